@@ -1,11 +1,66 @@
 use clap::{App, Arg};
 use gettextrs::gettext;
+use nom::types::CompleteStr;
 
 use std::path::PathBuf;
 
+use metadata::parse_timestamp;
+use ui::AmplitudeMode;
+
+const DEFAULT_RENDER_WIDTH: &str = "1920";
+const DEFAULT_RENDER_HEIGHT: &str = "270";
+const DEFAULT_RENDER_AMPLITUDE: &str = "peak";
+const DEFAULT_RENDER_DB_FLOOR: &str = "-60";
+
+pub struct RenderArguments {
+    pub output_file: PathBuf,
+    pub width: i32,
+    pub height: i32,
+    pub start: u64,
+    pub end: Option<u64>,
+    pub with_chapters: bool,
+    pub amplitude_mode: AmplitudeMode,
+    pub db_floor: f64,
+    pub is_lanes_layout: bool,
+}
+
 pub struct CommandLineArguments {
     pub input_file: Option<PathBuf>,
     pub disable_gl: bool,
+    pub render: Option<RenderArguments>,
+}
+
+fn parse_timestamp_arg(value: &str) -> Result<u64, String> {
+    match parse_timestamp(CompleteStr(value)) {
+        Ok((_, timestamp)) => Ok(timestamp.nano_total),
+        Err(_) => Err(gettext("Invalid timestamp \"{}\"").replacen("{}", value, 1)),
+    }
+}
+
+fn validate_timestamp(value: String) -> Result<(), String> {
+    parse_timestamp_arg(&value).map(|_| ())
+}
+
+fn parse_amplitude_mode(value: &str) -> AmplitudeMode {
+    match value {
+        "rms" => AmplitudeMode::LinearRms,
+        "db" => AmplitudeMode::Logarithmic,
+        _ => AmplitudeMode::LinearPeak,
+    }
+}
+
+fn validate_db_floor(value: String) -> Result<(), String> {
+    match value.parse::<i32>() {
+        Ok(db_floor) if db_floor < 0 => Ok(()),
+        _ => Err(gettext("Invalid dB floor \"{}\"").replacen("{}", &value, 1)),
+    }
+}
+
+fn validate_dimension(value: String) -> Result<(), String> {
+    match value.parse::<i32>() {
+        Ok(dimension) if dimension > 0 => Ok(()),
+        _ => Err(gettext("Invalid dimension \"{}\"").replacen("{}", &value, 1)),
+    }
 }
 
 pub fn handle_command_line() -> CommandLineArguments {
@@ -16,6 +71,15 @@ pub fn handle_command_line() -> CommandLineArguments {
 
     let disable_gl_arg = "DISABLE_GL";
     let input_arg = gettext("MEDIA");
+    let render_arg = gettext("IMAGE");
+    let width_arg = gettext("WIDTH");
+    let height_arg = gettext("HEIGHT");
+    let start_arg = gettext("START");
+    let end_arg = gettext("END");
+    let chapters_arg = "CHAPTERS";
+    let amplitude_arg = gettext("AMPLITUDE");
+    let db_floor_arg = gettext("DB_FLOOR");
+    let lanes_arg = "LANES";
 
     let matches = App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
@@ -29,6 +93,74 @@ pub fn handle_command_line() -> CommandLineArguments {
                 .long("disable-gl")
                 .help(&gettext("Disable video rendering hardware acceleration")),
         )
+        .arg(
+            Arg::with_name(&render_arg[..])
+                .long("render-waveform")
+                .takes_value(true)
+                .requires(&input_arg[..])
+                .help(&gettext(
+                    "Render the waveform of the media to a png or svg file and exit",
+                )),
+        )
+        .arg(
+            Arg::with_name(&width_arg[..])
+                .long("width")
+                .takes_value(true)
+                .default_value(DEFAULT_RENDER_WIDTH)
+                .validator(validate_dimension)
+                .help(&gettext("Width of the rendered waveform")),
+        )
+        .arg(
+            Arg::with_name(&height_arg[..])
+                .long("height")
+                .takes_value(true)
+                .default_value(DEFAULT_RENDER_HEIGHT)
+                .validator(validate_dimension)
+                .help(&gettext("Height of the rendered waveform")),
+        )
+        .arg(
+            Arg::with_name(&start_arg[..])
+                .long("start")
+                .takes_value(true)
+                .validator(validate_timestamp)
+                .help(&gettext("Start of the rendered waveform (e.g. 1:02.500)")),
+        )
+        .arg(
+            Arg::with_name(&end_arg[..])
+                .long("end")
+                .takes_value(true)
+                .validator(validate_timestamp)
+                .help(&gettext(
+                    "End of the rendered waveform (defaults to the media's end)",
+                )),
+        )
+        .arg(
+            Arg::with_name(chapters_arg)
+                .long("chapters")
+                .help(&gettext("Draw the chapters on the rendered waveform")),
+        )
+        .arg(
+            Arg::with_name(&amplitude_arg[..])
+                .long("amplitude")
+                .takes_value(true)
+                .possible_values(&["peak", "rms", "db"])
+                .default_value(DEFAULT_RENDER_AMPLITUDE)
+                .help(&gettext("Amplitude mode of the rendered waveform")),
+        )
+        .arg(
+            Arg::with_name(&db_floor_arg[..])
+                .long("db-floor")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .default_value(DEFAULT_RENDER_DB_FLOOR)
+                .validator(validate_db_floor)
+                .help(&gettext("Lowest level of the rendered waveform in dB mode (dBFS)")),
+        )
+        .arg(
+            Arg::with_name(lanes_arg)
+                .long("lanes")
+                .help(&gettext("Render each channel of the waveform in its own lane")),
+        )
         .arg(
             Arg::with_name(&input_arg[..])
                 .help(&gettext("Path to the input media file"))
@@ -36,10 +168,45 @@ pub fn handle_command_line() -> CommandLineArguments {
         )
         .get_matches();
 
+    // Values are checked by the validators
+    let render = matches
+        .value_of(render_arg.as_str())
+        .map(|output_file| RenderArguments {
+            output_file: output_file.into(),
+            width: matches
+                .value_of(width_arg.as_str())
+                .unwrap()
+                .parse()
+                .unwrap(),
+            height: matches
+                .value_of(height_arg.as_str())
+                .unwrap()
+                .parse()
+                .unwrap(),
+            start: matches
+                .value_of(start_arg.as_str())
+                .map_or(0, |start| parse_timestamp_arg(start).unwrap()),
+            end: matches
+                .value_of(end_arg.as_str())
+                .map(|end| parse_timestamp_arg(end).unwrap()),
+            with_chapters: matches.is_present(chapters_arg),
+            amplitude_mode: matches
+                .value_of(amplitude_arg.as_str())
+                .map(parse_amplitude_mode)
+                .unwrap(),
+            db_floor: matches
+                .value_of(db_floor_arg.as_str())
+                .unwrap()
+                .parse()
+                .unwrap(),
+            is_lanes_layout: matches.is_present(lanes_arg),
+        });
+
     CommandLineArguments {
         input_file: matches
             .value_of(input_arg.as_str())
             .map(|input_file| input_file.into()),
         disable_gl: matches.is_present(disable_gl_arg),
+        render,
     }
 }
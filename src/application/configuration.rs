@@ -8,6 +8,10 @@ use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
 use std::sync::RwLock;
 
+use media::SnapTarget;
+use metadata::{PregapMode, DEFAULT_NAMING_TEMPLATE};
+use ui::AmplitudeMode;
+
 use super::{SLD, TLD};
 
 const CONFIG_FILENAME: &str = "config.ron";
@@ -30,10 +34,134 @@ pub struct Media {
     pub last_path: Option<PathBuf>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Waveform {
+    pub is_snap_enabled: bool,
+    pub snap_target: SnapTarget,
+    pub snap_window_ms: u32,
+    pub amplitude_mode: AmplitudeMode,
+    pub db_floor: i32, // dBFS
+    pub is_lanes_layout: bool,
+}
+
+impl Default for Waveform {
+    fn default() -> Self {
+        Waveform {
+            is_snap_enabled: false,
+            snap_target: SnapTarget::default(),
+            snap_window_ms: 100,
+            amplitude_mode: AmplitudeMode::default(),
+            db_floor: -60,
+            is_lanes_layout: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Spectrogram {
+    pub is_enabled: bool,
+    pub window_size: u32, // samples
+    pub overlap: u32,     // % of the window
+    pub is_log_scale: bool,
+}
+
+impl Default for Spectrogram {
+    fn default() -> Self {
+        Spectrogram {
+            is_enabled: false,
+            window_size: 2048,
+            overlap: 75,
+            is_log_scale: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SilenceDetection {
+    pub threshold: i32, // dBFS
+    pub min_duration_ms: u32,
+    pub is_per_channel: bool,
+}
+
+impl Default for SilenceDetection {
+    fn default() -> Self {
+        SilenceDetection {
+            threshold: -50,
+            min_duration_ms: 1_500,
+            is_per_channel: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SceneDetection {
+    pub black_level: u32,   // % of the maximum luma
+    pub cut_threshold: u32, // % of luma histogram difference
+    pub min_interval_ms: u32,
+}
+
+impl Default for SceneDetection {
+    fn default() -> Self {
+        SceneDetection {
+            black_level: 8,
+            cut_threshold: 40,
+            min_interval_ms: 5_000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Split {
+    pub concurrency: u32,
+    pub naming_template: String,
+    pub is_m3u8_enabled: bool,
+    pub is_pls_enabled: bool,
+    pub is_xspf_enabled: bool,
+    pub is_loudness_enabled: bool,
+    pub fade_in_ms: u32,
+    pub fade_out_ms: u32,
+    pub is_silence_trimmed: bool,
+    pub silence_threshold: i32, // dBFS
+    pub pregap_mode: PregapMode,
+}
+
+impl Default for Split {
+    fn default() -> Self {
+        Split {
+            concurrency: 2,
+            naming_template: DEFAULT_NAMING_TEMPLATE.to_owned(),
+            is_m3u8_enabled: false,
+            is_pls_enabled: false,
+            is_xspf_enabled: false,
+            is_loudness_enabled: false,
+            fade_in_ms: 0,
+            fade_out_ms: 0,
+            is_silence_trimmed: false,
+            silence_threshold: -60,
+            pregap_mode: PregapMode::default(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 pub struct Config {
     pub ui: UI,
     pub media: Media,
+    #[serde(default)]
+    pub split: Split,
+    #[serde(default)]
+    pub silence_detection: SilenceDetection,
+    #[serde(default)]
+    pub scene_detection: SceneDetection,
+    #[serde(default)]
+    pub waveform: Waveform,
+    #[serde(default)]
+    pub spectrogram: Spectrogram,
 }
 
 pub struct GlobalConfig {
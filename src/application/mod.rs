@@ -3,7 +3,9 @@ use gio::prelude::*;
 use glib;
 use gtk;
 
-use ui::MainController;
+use std::path::Path;
+
+use ui::{MainController, WaveformRenderer};
 
 pub const TLD: &str = "org";
 pub const SLD: &str = "fengalin";
@@ -16,7 +18,7 @@ lazy_static! {
 }
 
 mod command_line;
-pub use self::command_line::{handle_command_line, CommandLineArguments};
+pub use self::command_line::{handle_command_line, CommandLineArguments, RenderArguments};
 
 mod configuration;
 pub use self::configuration::CONFIG;
@@ -24,6 +26,9 @@ pub use self::configuration::CONFIG;
 mod locale;
 pub use self::locale::init_locale;
 
+mod peaks_cache;
+pub use self::peaks_cache::{PeaksCacheKey, PEAKS_CACHE};
+
 fn register_resource(resource: &[u8]) {
     let gbytes = glib::Bytes::from(resource);
     gio::Resource::new_from_data(&gbytes)
@@ -56,3 +61,18 @@ pub fn run(is_gst_ok: bool, args: CommandLineArguments) {
 
     gtk_app.run(&[]);
 }
+
+pub fn render_waveform(input_file: &Path, args: &RenderArguments) -> Result<(), String> {
+    let renderer = WaveformRenderer {
+        width: args.width,
+        height: args.height,
+        start: args.start,
+        end: args.end,
+        with_chapters: args.with_chapters,
+        amplitude_mode: args.amplitude_mode,
+        db_floor: args.db_floor,
+        is_lanes_layout: args.is_lanes_layout,
+    };
+
+    renderer.render(input_file, &args.output_file)
+}
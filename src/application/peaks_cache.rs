@@ -0,0 +1,311 @@
+use directories::ProjectDirs;
+use gettextrs::gettext;
+use ron;
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::fs::{create_dir_all, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use media::Peaks;
+
+use super::{SLD, TLD};
+
+const PEAKS_DIRNAME: &str = "peaks";
+const INDEX_FILENAME: &str = "index.ron";
+const MAX_CACHE_SIZE: u64 = 512 * 1024 * 1024; // 512 MiB
+
+lazy_static! {
+    pub static ref PEAKS_CACHE: Mutex<PeaksCache> = Mutex::new(PeaksCache::new());
+}
+
+// Identifies the audio stream of a media file in the state it was analyzed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PeaksCacheKey {
+    path: PathBuf,
+    size: u64,
+    mtime: u64, // ns since UNIX epoch
+    stream_id: String,
+}
+
+impl PeaksCacheKey {
+    pub fn new(path: &Path, stream_id: &str) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+        Some(PeaksCacheKey {
+            path: path.to_owned(),
+            size: metadata.len(),
+            mtime: mtime.as_secs() * 1_000_000_000 + u64::from(mtime.subsec_nanos()),
+            stream_id: stream_id.to_owned(),
+        })
+    }
+
+    // One entry is kept per stream, whatever the state of the file
+    fn get_file_name(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.path.hash(&mut hasher);
+        self.stream_id.hash(&mut hasher);
+        format!("{:016x}.peaks", hasher.finish())
+    }
+
+    fn is_same_stream(&self, other: &PeaksCacheKey) -> bool {
+        self.path == other.path && self.stream_id == other.stream_id
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CacheEntry {
+    key: PeaksCacheKey,
+    file_name: String,
+    size: u64,
+    last_used: u64, // s since UNIX epoch
+}
+
+// Persists the peaks of previously analyzed audio streams
+// The least recently used entries are evicted when the cache exceeds its maximum size
+pub struct PeaksCache {
+    dir: PathBuf,
+    max_size: u64,
+    entries: Vec<CacheEntry>,
+}
+
+impl PeaksCache {
+    fn new() -> Self {
+        let project_dirs = ProjectDirs::from(TLD, SLD, env!("CARGO_PKG_NAME"));
+        PeaksCache::new_in(&project_dirs.cache_dir().join(PEAKS_DIRNAME), MAX_CACHE_SIZE)
+    }
+
+    fn new_in(dir: &Path, max_size: u64) -> Self {
+        if let Err(err) = create_dir_all(dir) {
+            error!(
+                "{}",
+                gettext("couldn't create peaks cache: {}").replacen("{}", &format!("{}", err), 1)
+            );
+        }
+
+        let entries = File::open(dir.join(INDEX_FILENAME))
+            .ok()
+            .and_then(|index_file| {
+                let entries: Result<Vec<CacheEntry>, ron::de::Error> =
+                    ron::de::from_reader(index_file);
+                entries
+                    .map_err(|err| warn!("couldn't load peaks cache index: {:?}", err))
+                    .ok()
+            })
+            .unwrap_or_else(Vec::new);
+
+        PeaksCache {
+            dir: dir.to_owned(),
+            max_size,
+            entries,
+        }
+    }
+
+    fn get_now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or(0)
+    }
+
+    // Returns the peaks of the stream if they were stored in the same state of the file
+    pub fn load(&mut self, key: &PeaksCacheKey) -> Option<Peaks> {
+        let idx = self.entries
+            .iter()
+            .position(|entry| entry.key.is_same_stream(key))?;
+
+        if self.entries[idx].key != *key {
+            debug!("peaks cache: {:?} changed", key.path);
+            self.remove(idx);
+            self.save_index();
+            return None;
+        }
+
+        let peaks = File::open(self.dir.join(&self.entries[idx].file_name))
+            .and_then(|peaks_file| Peaks::read(&mut BufReader::new(peaks_file)));
+        match peaks {
+            Ok(peaks) => {
+                self.entries[idx].last_used = PeaksCache::get_now();
+                self.save_index();
+                Some(peaks)
+            }
+            Err(err) => {
+                warn!("peaks cache: couldn't read peaks for {:?}: {}", key.path, err);
+                self.remove(idx);
+                self.save_index();
+                None
+            }
+        }
+    }
+
+    pub fn store(&mut self, key: &PeaksCacheKey, peaks: &Peaks) -> Result<(), String> {
+        if let Some(idx) = self.entries
+            .iter()
+            .position(|entry| entry.key.is_same_stream(key))
+        {
+            self.remove(idx);
+        }
+
+        let file_name = key.get_file_name();
+        let path = self.dir.join(&file_name);
+        File::create(&path)
+            .and_then(|peaks_file| {
+                let mut writer = BufWriter::new(peaks_file);
+                peaks.write(&mut writer)?;
+                writer.flush()
+            })
+            .map_err(|err| {
+                let _ = fs::remove_file(&path);
+                gettext("couldn't store peaks in cache: {}").replacen("{}", &format!("{}", err), 1)
+            })?;
+
+        let size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+        self.entries.push(CacheEntry {
+            key: key.clone(),
+            file_name,
+            size,
+            last_used: PeaksCache::get_now(),
+        });
+
+        self.evict();
+        self.save_index();
+
+        Ok(())
+    }
+
+    fn remove(&mut self, idx: usize) {
+        let entry = self.entries.remove(idx);
+        let _ = fs::remove_file(self.dir.join(&entry.file_name));
+    }
+
+    // Removes the least recently used entries until the cache fits in its maximum size
+    // Note: the most recent entry is kept even if it is larger than the cache
+    fn evict(&mut self) {
+        // Stable sort: entries used at the same time keep their insertion order
+        self.entries.sort_by_key(|entry| entry.last_used);
+
+        let mut total_size = self.entries.iter().map(|entry| entry.size).sum::<u64>();
+        while total_size > self.max_size && self.entries.len() > 1 {
+            total_size -= self.entries[0].size;
+            self.remove(0);
+        }
+    }
+
+    fn save_index(&self) {
+        let result = ron::ser::to_string(&self.entries)
+            .map_err(|err| format!("{:?}", err))
+            .and_then(|index_str| {
+                File::create(self.dir.join(INDEX_FILENAME))
+                    .and_then(|mut index_file| index_file.write_all(index_str.as_bytes()))
+                    .map_err(|err| format!("{}", err))
+            });
+
+        if let Err(err) = result {
+            error!(
+                "{}",
+                gettext("couldn't write peaks cache index: {}").replacen("{}", &err, 1)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use media::Peaks;
+
+    use super::{PeaksCache, PeaksCacheKey};
+
+    fn get_test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("media-toc-peaks-cache-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_media(path: &PathBuf, content: &[u8]) {
+        File::create(path).unwrap().write_all(content).unwrap();
+    }
+
+    fn get_peaks(frame_count: u64) -> Peaks {
+        let mut peaks = Peaks::new();
+        for index in 0..frame_count {
+            peaks.push_frame(index * 10_000_000, &[0.5]);
+        }
+        peaks.finish();
+        peaks
+    }
+
+    #[test]
+    fn invalidation() {
+        let dir = get_test_dir("invalidation");
+        let media_path = dir.join("media.ogg");
+        write_media(&media_path, b"audio");
+
+        let mut cache = PeaksCache::new_in(&dir.join("cache"), 1024 * 1024);
+        let key = PeaksCacheKey::new(&media_path, "audio_1").unwrap();
+        assert!(cache.load(&key).is_none());
+
+        let peaks = get_peaks(100);
+        cache.store(&key, &peaks).unwrap();
+        assert_eq!(
+            cache.load(&key).unwrap().get_duration(),
+            peaks.get_duration()
+        );
+
+        // The index is persisted
+        let mut cache = PeaksCache::new_in(&dir.join("cache"), 1024 * 1024);
+        assert!(cache.load(&key).is_some());
+        // Other stream in the same file
+        let other_key = PeaksCacheKey::new(&media_path, "audio_2").unwrap();
+        assert!(cache.load(&other_key).is_none());
+
+        // The file changes
+        write_media(&media_path, b"modified audio");
+        let modified_key = PeaksCacheKey::new(&media_path, "audio_1").unwrap();
+        assert!(cache.load(&modified_key).is_none());
+        assert!(cache.load(&key).is_none());
+        assert_eq!(fs::read_dir(dir.join("cache")).unwrap().count(), 1); // index only
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn eviction() {
+        let dir = get_test_dir("eviction");
+        let peaks = get_peaks(100);
+        let mut peaks_size = Vec::new();
+        peaks.write(&mut peaks_size).unwrap();
+
+        // Room for two entries
+        let mut cache = PeaksCache::new_in(&dir.join("cache"), 2 * peaks_size.len() as u64);
+        let keys = (0..3)
+            .map(|index| {
+                let media_path = dir.join(format!("media_{}.ogg", index));
+                write_media(&media_path, b"audio");
+                PeaksCacheKey::new(&media_path, "audio_1").unwrap()
+            })
+            .collect::<Vec<PeaksCacheKey>>();
+
+        cache.store(&keys[0], &peaks).unwrap();
+        cache.store(&keys[1], &peaks).unwrap();
+        cache.store(&keys[2], &peaks).unwrap();
+
+        // The least recently used entry is evicted
+        assert!(cache.load(&keys[0]).is_none());
+        assert!(cache.load(&keys[1]).is_some());
+        assert!(cache.load(&keys[2]).is_some());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -30,8 +30,10 @@ extern crate chrono;
 
 use gettextrs::gettext;
 
+use std::process;
+
 mod application;
-use application::{handle_command_line, init_locale, run};
+use application::{handle_command_line, init_locale, render_waveform, run};
 mod media;
 mod metadata;
 mod ui;
@@ -46,6 +48,22 @@ fn main() {
 
     let args = handle_command_line();
 
+    if let Some(ref render_args) = args.render {
+        // Rendering doesn't require a display
+        if let Err(err) = gstreamer::init() {
+            error!("{}: {}", gettext("Failed to initialize GStreamer"), err);
+            process::exit(1);
+        }
+
+        let input_file = args.input_file.as_ref().unwrap();
+        if let Err(err) = render_waveform(input_file, render_args) {
+            error!("{}", err);
+            process::exit(1);
+        }
+
+        return;
+    }
+
     if is_gtk_ok {
         run(gstreamer::init().is_ok(), args);
     } else {
@@ -0,0 +1,289 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use gettextrs::gettext;
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_audio as gst_audio;
+
+use glib;
+
+use std::error::Error;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use super::ContextMessage;
+
+const CONVERTER_NAME: &str = "analysis_converter";
+const SAMPLE_SIZE: usize = 8; // F64LE
+
+// Receives the decoded frames of the analyzed stream
+pub trait FramesHandler: Send + 'static {
+    fn set_caps(&mut self, caps: &gst::Caps);
+    fn push_buffer(&mut self, buffer: &gst::Buffer);
+
+    // Called when all the frames were pushed
+    fn finish(&mut self) {}
+}
+
+// Receives the frames of an audio stream, one value per channel in the range [-1, 1]
+pub trait AudioFramesHandler: Send + 'static {
+    // Called before the first frame and whenever the audio format changes
+    fn set_format(&mut self, _rate: u32, _channels: usize) {}
+    fn push_frame(&mut self, timestamp: u64, frame: &[f64]);
+
+    // Called when all the frames were pushed
+    fn finish(&mut self) {}
+}
+
+// Decodes the F64LE buffers of an audio stream
+// and hands their frames to an `AudioFramesHandler`
+pub struct AudioFramesDecoder<H: AudioFramesHandler> {
+    pub handler: H,
+    rate: u64,
+    channels: usize,
+}
+
+impl<H: AudioFramesHandler> AudioFramesDecoder<H> {
+    pub fn new(handler: H) -> Self {
+        AudioFramesDecoder {
+            handler,
+            rate: 0,
+            channels: 0,
+        }
+    }
+
+    // The caps to request to the `AnalysisPipeline`
+    pub fn get_caps() -> gst::Caps {
+        gst::Caps::new_simple(
+            "audio/x-raw",
+            &[("format", &"F64LE"), ("layout", &"interleaved")],
+        )
+    }
+}
+
+impl<H: AudioFramesHandler> FramesHandler for AudioFramesDecoder<H> {
+    fn set_caps(&mut self, caps: &gst::Caps) {
+        match gst_audio::AudioInfo::from_caps(caps) {
+            Some(audio_info) => {
+                self.rate = u64::from(audio_info.rate());
+                self.channels = audio_info.channels() as usize;
+                self.handler.set_format(audio_info.rate(), self.channels);
+            }
+            None => warn!("AudioFramesDecoder: unexpected caps {:?}", caps),
+        }
+    }
+
+    fn push_buffer(&mut self, buffer: &gst::Buffer) {
+        if self.rate == 0 || self.channels == 0 {
+            return;
+        }
+
+        let pts = match buffer.get_pts().nseconds() {
+            Some(pts) => pts,
+            None => return,
+        };
+        let map = match buffer.map_readable() {
+            Some(map) => map,
+            None => return,
+        };
+
+        let frame_size = SAMPLE_SIZE * self.channels;
+        let mut frame = vec![0f64; self.channels];
+        for (index, bytes) in map.as_slice().chunks(frame_size).enumerate() {
+            if bytes.len() < frame_size {
+                break;
+            }
+
+            for (channel, value) in frame.iter_mut().enumerate() {
+                *value = LittleEndian::read_f64(&bytes[channel * SAMPLE_SIZE..]);
+            }
+            self.handler
+                .push_frame(pts + index as u64 * 1_000_000_000 / self.rate, &frame);
+        }
+    }
+
+    fn finish(&mut self) {
+        self.handler.finish();
+    }
+}
+
+// Decodes a stream of a media and hands its frames to a `FramesHandler`
+// The other streams are decoded and dropped.
+// `ContextMessage::Eos` is sent when the whole stream is analyzed
+// and `ContextMessage::FailedToAnalyze` in case of an error.
+pub struct AnalysisPipeline {
+    pipeline: gst::Pipeline,
+    position_query: gst::query::Position<gst::Query>,
+    bus_watch_src: Option<glib::SourceId>,
+}
+
+impl AnalysisPipeline {
+    // The frames of the stream `stream_id` are converted to `caps` which must be
+    // raw audio or video caps
+    // `start_error` is the message sent if the analysis can't be started
+    pub fn new<H: FramesHandler>(
+        name: &str,
+        input_path: &Path,
+        stream_id: &str,
+        caps: gst::Caps,
+        handler: Arc<Mutex<H>>,
+        start_error: String,
+        ctx_tx: Sender<ContextMessage>,
+    ) -> Result<AnalysisPipeline, String> {
+        let mut this = AnalysisPipeline {
+            pipeline: gst::Pipeline::new(name),
+            position_query: gst::Query::new_position(gst::Format::Time),
+            bus_watch_src: None,
+        };
+
+        this.build_pipeline(input_path, stream_id.to_owned(), caps, handler);
+        this.register_bus_inspector(start_error, ctx_tx);
+
+        match this.pipeline.set_state(gst::State::Paused) {
+            gst::StateChangeReturn::Failure => Err(gettext("Could not set media in Paused mode")),
+            _ => Ok(this),
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+
+    pub fn get_position(&mut self) -> u64 {
+        self.pipeline.query(&mut self.position_query);
+        self.position_query.get_result().get_value() as u64
+    }
+
+    fn build_pipeline<H: FramesHandler>(
+        &mut self,
+        input_path: &Path,
+        stream_id: String,
+        caps: gst::Caps,
+        handler: Arc<Mutex<H>>,
+    ) {
+        let filesrc = gst::ElementFactory::make("filesrc", None).unwrap();
+        filesrc
+            .set_property("location", &gst::Value::from(input_path.to_str().unwrap()))
+            .unwrap();
+        let decodebin = gst::ElementFactory::make("decodebin", None).unwrap();
+
+        self.pipeline.add_many(&[&filesrc, &decodebin]).unwrap();
+
+        filesrc.link(&decodebin).unwrap();
+        decodebin.sync_state_with_parent().unwrap();
+
+        let is_audio = caps.get_structure(0).unwrap().get_name().starts_with("audio/");
+        let media_type = if is_audio { "audio/" } else { "video/" };
+
+        let pipeline_cb = self.pipeline.clone();
+        decodebin.connect_pad_added(move |_element, pad| {
+            let pad_caps = pad.get_current_caps().unwrap();
+            let name = pad_caps.get_structure(0).unwrap().get_name().to_owned();
+
+            let queue = gst::ElementFactory::make("queue", None).unwrap();
+            let fakesink = gst::ElementFactory::make("fakesink", None).unwrap();
+            pipeline_cb.add_many(&[&queue, &fakesink]).unwrap();
+            let queue_sink_pad = queue.get_static_pad("sink").unwrap();
+            assert_eq!(pad.link(&queue_sink_pad), gst::PadLinkReturn::Ok);
+
+            let is_analyzed_stream = name.starts_with(media_type)
+                && pipeline_cb.get_by_name(CONVERTER_NAME).is_none()
+                && pad.get_stream_id().map_or(false, |id| id == stream_id);
+            if is_analyzed_stream {
+                // Convert to the format expected by the handler
+                let mut elements = Vec::<gst::Element>::new();
+                if is_audio {
+                    elements.push(
+                        gst::ElementFactory::make("audioconvert", CONVERTER_NAME).unwrap(),
+                    );
+                } else {
+                    elements.push(
+                        gst::ElementFactory::make("videoconvert", CONVERTER_NAME).unwrap(),
+                    );
+                    elements.push(gst::ElementFactory::make("videoscale", None).unwrap());
+                }
+                let capsfilter = gst::ElementFactory::make("capsfilter", None).unwrap();
+                capsfilter.set_property("caps", &caps).unwrap();
+                elements.push(capsfilter);
+
+                for element in &elements {
+                    pipeline_cb.add(element).unwrap();
+                }
+                let mut chain = vec![&queue];
+                chain.extend(elements.iter());
+                chain.push(&fakesink);
+                gst::Element::link_many(&chain).unwrap();
+                for element in &elements {
+                    element.sync_state_with_parent().unwrap();
+                }
+
+                let handler = Arc::clone(&handler);
+                let fakesink_sink_pad = fakesink.get_static_pad("sink").unwrap();
+                let pad_probe_filter = gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM;
+                fakesink_sink_pad.add_probe(pad_probe_filter, move |_pad, probe_info| {
+                    if let Some(ref data) = probe_info.data {
+                        match *data {
+                            gst::PadProbeData::Buffer(ref buffer) => {
+                                handler.lock().unwrap().push_buffer(buffer);
+                            }
+                            gst::PadProbeData::Event(ref event) => match event.view() {
+                                gst::EventView::Caps(caps_event) => {
+                                    handler.lock().unwrap().set_caps(caps_event.get_caps());
+                                }
+                                gst::EventView::Eos(_) => handler.lock().unwrap().finish(),
+                                _ => (),
+                            },
+                            _ => (),
+                        }
+                    }
+                    gst::PadProbeReturn::Ok
+                });
+            } else {
+                queue.link(&fakesink).unwrap();
+            }
+
+            queue.sync_state_with_parent().unwrap();
+            fakesink.sync_state_with_parent().unwrap();
+        });
+    }
+
+    fn register_bus_inspector(&mut self, start_error: String, ctx_tx: Sender<ContextMessage>) {
+        let pipeline = self.pipeline.clone();
+        self.bus_watch_src = Some(self.pipeline.get_bus().unwrap().add_watch(move |_, msg| {
+            match msg.view() {
+                gst::MessageView::Eos(..) => {
+                    let _ = pipeline.set_state(gst::State::Null);
+                    ctx_tx.send(ContextMessage::Eos).unwrap();
+                }
+                gst::MessageView::Error(err) => {
+                    ctx_tx
+                        .send(ContextMessage::FailedToAnalyze(
+                            err.get_error().description().to_owned(),
+                        ))
+                        .unwrap();
+                }
+                gst::MessageView::AsyncDone(_) => {
+                    // Start analyzing
+                    if pipeline.set_state(gst::State::Playing) == gst::StateChangeReturn::Failure {
+                        ctx_tx
+                            .send(ContextMessage::FailedToAnalyze(start_error.clone()))
+                            .unwrap();
+                    }
+                }
+                _ => (),
+            }
+
+            glib::Continue(true)
+        }));
+    }
+}
+
+impl Drop for AnalysisPipeline {
+    fn drop(&mut self) {
+        if let Some(bus_watch_src) = self.bus_watch_src.take() {
+            glib::source_remove(bus_watch_src);
+        }
+    }
+}
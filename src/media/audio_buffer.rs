@@ -9,12 +9,30 @@ use sample::Sample;
 use byteorder::ByteOrder;
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 
+use std::cmp;
 use std::collections::vec_deque::VecDeque;
 use std::io::{Cursor, Read};
 
 #[cfg(test)]
 use gstreamer::ClockTime;
 
+// Duration of the blocks used to compare the energy of the samples when snapping
+const LOW_ENERGY_BLOCK_DURATION: u64 = 5_000_000; // 5 ms
+const ENERGY_TOLERANCE: f64 = 1e-9;
+
+// Where a position can be snapped
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SnapTarget {
+    LowEnergy,
+    ZeroCrossing,
+}
+
+impl Default for SnapTarget {
+    fn default() -> Self {
+        SnapTarget::LowEnergy
+    }
+}
+
 pub struct AudioBuffer {
     buffer_duration: u64,
     capacity: usize,
@@ -363,6 +381,42 @@ impl AudioBuffer {
         }
     }
 
+    // Returns the position of the `target` nearest to `position` within `window`
+    // or `None` if no target could be found in the available samples
+    pub fn get_snap_position(&self, position: u64, window: u64, target: SnapTarget) -> Option<u64> {
+        if self.sample_duration == 0 {
+            return None;
+        }
+
+        let sample = (position / self.sample_duration) as usize;
+        let sample_window = (window / self.sample_duration) as usize;
+        let lower = cmp::max(sample.saturating_sub(sample_window), self.lower);
+        let upper = cmp::min(sample + sample_window + 1, self.upper);
+        if sample < lower || sample >= upper {
+            return None;
+        }
+
+        // Note: samples are stored as `1.0 - value`
+        let frames = self.iter(lower, upper, 1)?;
+        let index = match target {
+            SnapTarget::LowEnergy => {
+                let energies = frames
+                    .map(|frame| frame.iter().map(|value| (1f64 - value).powi(2)).sum())
+                    .collect::<Vec<f64>>();
+                let block_len = (LOW_ENERGY_BLOCK_DURATION / self.sample_duration) as usize;
+                find_low_energy(&energies, sample - lower, block_len)
+            }
+            SnapTarget::ZeroCrossing => {
+                let values = frames
+                    .map(|frame| frame.iter().map(|value| 1f64 - value).sum())
+                    .collect::<Vec<f64>>();
+                find_zero_crossing(&values, sample - lower)
+            }
+        }?;
+
+        Some((lower + index) as u64 * self.sample_duration)
+    }
+
     #[cfg(test)]
     pub fn push_samples(&mut self, samples: &[i16], lower: usize, is_new_segment: bool) {
         let mut samples_u8 = Vec::with_capacity(samples.len() * 2 * self.channels);
@@ -402,6 +456,61 @@ impl AudioBuffer {
     }
 }
 
+// Returns the index of the block with the lowest mean energy
+// which is the nearest to `center`
+fn find_low_energy(energies: &[f64], center: usize, block_len: usize) -> Option<usize> {
+    if energies.is_empty() {
+        return None;
+    }
+
+    let half_block = cmp::max(block_len, 1) / 2;
+    let mut sums = Vec::with_capacity(energies.len() + 1);
+    sums.push(0f64);
+    for energy in energies {
+        let last_sum = sums[sums.len() - 1];
+        sums.push(last_sum + energy);
+    }
+
+    let mut lowest: Option<(f64, usize, usize)> = None; // (mean energy, distance, index)
+    for index in 0..energies.len() {
+        let first = index.saturating_sub(half_block);
+        let last = cmp::min(index + half_block + 1, energies.len());
+        let mean = (sums[last] - sums[first]) / (last - first) as f64;
+        let distance = if index > center {
+            index - center
+        } else {
+            center - index
+        };
+
+        // Note: the means are compared with a tolerance due to the rounding of the sums
+        let is_lower = lowest.map_or(true, |(lowest_mean, lowest_distance, _)| {
+            if (mean - lowest_mean).abs() < ENERGY_TOLERANCE {
+                distance < lowest_distance
+            } else {
+                mean < lowest_mean
+            }
+        });
+        if is_lower {
+            lowest = Some((mean, distance, index));
+        }
+    }
+
+    lowest.map(|(_, _, index)| index)
+}
+
+// Returns the index of the zero crossing which is the nearest to `center`
+fn find_zero_crossing(values: &[f64], center: usize) -> Option<usize> {
+    (1..values.len())
+        .filter(|&index| (values[index - 1] < 0f64) != (values[index] < 0f64))
+        .min_by_key(|&index| {
+            if index > center {
+                index - center
+            } else {
+                center - index
+            }
+        })
+}
+
 // Convert sample buffer to i16 on the fly
 type ConvertFn = fn(&mut Read) -> f64;
 macro_rules! to_positive_f64(
@@ -573,6 +682,8 @@ mod tests {
 
     use media::AudioBuffer;
 
+    use super::{find_low_energy, find_zero_crossing};
+
     const SAMPLE_RATE: u32 = 300;
 
     // Build a buffer with 2 channels in the specified range
@@ -783,4 +894,19 @@ mod tests {
         // check overlap between 4 & 5
         check_iter(&audio_buffer, 290, 310, 5, &vec![290, 295, 300, 305]);
     }
+
+    #[test]
+    fn snap() {
+        let values = [0.5, 0.2, -0.1, -0.3, -0.2, 0.1, 0.4, 0.3];
+        assert_eq!(find_zero_crossing(&values, 0), Some(2));
+        assert_eq!(find_zero_crossing(&values, 4), Some(5));
+        assert_eq!(find_zero_crossing(&values[..2], 0), None);
+
+        let energies = [0.5, 0.4, 0.1, 0.1, 0.3, 0.6, 0.1, 0.1, 0.1, 0.7];
+        // The quietest block is [6, 8]
+        assert_eq!(find_low_energy(&energies, 0, 3), Some(7));
+        // Without blocks, the nearest quiet sample is selected
+        assert_eq!(find_low_energy(&energies, 4, 1), Some(3));
+        assert_eq!(find_low_energy(&[], 0, 1), None);
+    }
 }
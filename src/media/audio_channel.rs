@@ -9,6 +9,7 @@ pub enum AudioChannelSide {
 }
 
 pub struct AudioChannel {
+    pub position: gst_audio::AudioChannelPosition,
     pub side: AudioChannelSide,
     pub factor: f64,
 }
@@ -49,6 +50,46 @@ impl AudioChannel {
             _ => (AudioChannelSide::NotLocalized, 0.7f64),
         };
 
-        AudioChannel { side, factor }
+        AudioChannel {
+            position: *position,
+            side,
+            factor,
+        }
+    }
+
+    // Short name of the channel, as displayed in the waveform lanes
+    pub fn get_label(&self) -> &'static str {
+        match self.position {
+            Position::Mono => "M",
+            Position::FrontLeft => "FL",
+            Position::FrontRight => "FR",
+            Position::FrontCenter => "FC",
+            Position::Lfe1 => "LFE",
+            Position::RearLeft => "RL",
+            Position::RearRight => "RR",
+            Position::FrontLeftOfCenter => "FLC",
+            Position::FrontRightOfCenter => "FRC",
+            Position::RearCenter => "RC",
+            Position::Lfe2 => "LFE2",
+            Position::SideLeft => "SL",
+            Position::SideRight => "SR",
+            Position::TopFrontLeft => "TFL",
+            Position::TopFrontRight => "TFR",
+            Position::TopFrontCenter => "TFC",
+            Position::TopCenter => "TC",
+            Position::TopRearLeft => "TRL",
+            Position::TopRearRight => "TRR",
+            Position::TopSideLeft => "TSL",
+            Position::TopSideRight => "TSR",
+            Position::TopRearCenter => "TRC",
+            Position::BottomFrontCenter => "BFC",
+            Position::BottomFrontLeft => "BFL",
+            Position::BottomFrontRight => "BFR",
+            Position::WideLeft => "WL",
+            Position::WideRight => "WR",
+            Position::SurroundLeft => "SurL",
+            Position::SurroundRight => "SurR",
+            _ => "?",
+        }
     }
 }
@@ -0,0 +1,119 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use gstreamer as gst;
+use gstreamer_audio as gst_audio;
+
+const SAMPLE_SIZE: usize = 4; // F32LE
+
+// Solo and mute states of the audio channels
+// Channels which were never toggled are neither muted nor soloed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelMix {
+    muted: Vec<bool>,
+    soloed: Vec<bool>,
+}
+
+impl ChannelMix {
+    pub fn is_muted(&self, channel: usize) -> bool {
+        self.muted.get(channel).cloned().unwrap_or(false)
+    }
+
+    pub fn is_soloed(&self, channel: usize) -> bool {
+        self.soloed.get(channel).cloned().unwrap_or(false)
+    }
+
+    pub fn toggle_mute(&mut self, channel: usize) {
+        ChannelMix::toggle(&mut self.muted, channel);
+    }
+
+    pub fn toggle_solo(&mut self, channel: usize) {
+        ChannelMix::toggle(&mut self.soloed, channel);
+    }
+
+    fn toggle(states: &mut Vec<bool>, channel: usize) {
+        if states.len() <= channel {
+            states.resize(channel + 1, false);
+        }
+        states[channel] = !states[channel];
+    }
+
+    // A channel is audible when it is not muted
+    // and either it is soloed or no channel is soloed
+    pub fn is_audible(&self, channel: usize) -> bool {
+        if self.is_muted(channel) {
+            false
+        } else {
+            self.is_soloed(channel) || !self.soloed.contains(&true)
+        }
+    }
+
+    // Returns true if all the channels are audible
+    pub fn is_passthrough(&self) -> bool {
+        !self.muted.contains(&true) && !self.soloed.contains(&true)
+    }
+
+    // Silences the channels which are not audible
+    // in a buffer of interleaved `F32LE` samples
+    pub fn apply(&self, buffer: &mut gst::Buffer, audio_info: &gst_audio::AudioInfo) {
+        if self.is_passthrough() {
+            return;
+        }
+
+        let channels = audio_info.channels() as usize;
+        let silenced = (0..channels)
+            .filter(|channel| !self.is_audible(*channel))
+            .collect::<Vec<usize>>();
+        if silenced.is_empty() {
+            return;
+        }
+
+        let mut map = match buffer.make_mut().map_writable() {
+            Some(map) => map,
+            None => return,
+        };
+        for frame in map.as_mut_slice().chunks_mut(SAMPLE_SIZE * channels) {
+            for channel in &silenced {
+                let offset = channel * SAMPLE_SIZE;
+                if offset + SAMPLE_SIZE <= frame.len() {
+                    LittleEndian::write_f32(&mut frame[offset..offset + SAMPLE_SIZE], 0f32);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChannelMix;
+
+    #[test]
+    fn audible() {
+        let mut mix = ChannelMix::default();
+        assert!(mix.is_passthrough());
+        assert!(mix.is_audible(0));
+        assert!(mix.is_audible(5));
+
+        mix.toggle_mute(1);
+        assert!(!mix.is_passthrough());
+        assert!(mix.is_audible(0));
+        assert!(!mix.is_audible(1));
+        assert!(mix.is_audible(2));
+
+        // Soloing a channel silences the others
+        mix.toggle_solo(2);
+        assert!(!mix.is_audible(0));
+        assert!(!mix.is_audible(1));
+        assert!(mix.is_audible(2));
+
+        // Mute takes precedence over solo
+        mix.toggle_solo(1);
+        assert!(!mix.is_audible(1));
+        assert!(mix.is_audible(2));
+
+        mix.toggle_solo(1);
+        mix.toggle_solo(2);
+        mix.toggle_mute(1);
+        assert!(mix.is_passthrough());
+        assert!(mix.is_audible(1));
+    }
+}
@@ -5,7 +5,7 @@ use std::mem;
 
 use std::sync::{Arc, Mutex};
 
-use super::{AudioBuffer, AudioChannel, SampleExtractor, QUEUE_SIZE_NS};
+use super::{AudioBuffer, AudioChannel, SampleExtractor, SnapTarget, QUEUE_SIZE_NS};
 
 const EXTRACTION_THRESHOLD: usize = 1024;
 
@@ -94,6 +94,15 @@ impl DoubleAudioBuffer {
         self.working_buffer.as_mut().unwrap().set_state(state);
     }
 
+    // Playback rate used to interpolate the position
+    pub fn set_rate(&mut self, rate: f64) {
+        {
+            let exposed_buffer_box = &mut *self.exposed_buffer_mtx.lock().unwrap();
+            exposed_buffer_box.set_rate(rate);
+        }
+        self.working_buffer.as_mut().unwrap().set_rate(rate);
+    }
+
     pub fn clean_samples(&mut self) {
         self.audio_buffer.clean_samples();
         // Also reset basetime
@@ -104,6 +113,12 @@ impl DoubleAudioBuffer {
         self.working_buffer.as_mut().unwrap().reset_basetime();
     }
 
+    // See `AudioBuffer::get_snap_position`
+    pub fn get_snap_position(&self, position: u64, window: u64, target: SnapTarget) -> Option<u64> {
+        self.audio_buffer
+            .get_snap_position(position, window, target)
+    }
+
     pub fn set_caps(&mut self, caps: &gst::CapsRef) {
         info!("Changing caps");
         let audio_info = gst_audio::AudioInfo::from_caps(caps).unwrap();
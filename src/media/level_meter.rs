@@ -0,0 +1,227 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use gstreamer as gst;
+use gstreamer_audio as gst_audio;
+
+use std::collections::VecDeque;
+
+use super::LoudnessMeter;
+
+const SAMPLE_SIZE: usize = 4; // F32LE
+// Levels are computed on periods of 50ms
+const PERIODS_PER_SECOND: u32 = 20;
+// Levels which are not consumed are discarded, e.g. when the UI is not refreshed
+const MAX_PENDING_LEVELS: usize = 200; // 10 s
+
+// Levels of the audio channels on a period ending at `position`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Levels {
+    pub position: u64,
+    // Sample peak and RMS per channel, 1.0 is full scale
+    pub peaks: Vec<f64>,
+    pub rms: Vec<f64>,
+    pub momentary: Option<f64>,  // LUFS
+    pub short_term: Option<f64>, // LUFS
+}
+
+// Measures the levels of the samples played
+// Levels are computed ahead of the playback position
+// and are retrieved when the position is reached
+#[derive(Default)]
+pub struct LevelMeter {
+    rate: u32,
+    channels: usize,
+    loudness: Option<LoudnessMeter>,
+
+    period_len: usize,
+    period_pos: usize,
+    peaks: Vec<f64>,
+    squares: Vec<f64>,
+
+    pending: VecDeque<Levels>,
+}
+
+impl LevelMeter {
+    // Discards the measurements in progress, e.g. after a seek
+    pub fn reset(&mut self) {
+        if self.rate > 0 {
+            self.loudness = Some(LoudnessMeter::new(self.rate, self.channels));
+        }
+        self.period_pos = 0;
+        self.clear_period();
+        self.pending.clear();
+    }
+
+    fn clear_period(&mut self) {
+        for peak in &mut self.peaks {
+            *peak = 0f64;
+        }
+        for square in &mut self.squares {
+            *square = 0f64;
+        }
+    }
+
+    fn init(&mut self, rate: u32, channels: usize) {
+        debug!("LevelMeter: init rate {}, channels {}", rate, channels);
+
+        self.rate = rate;
+        self.channels = channels;
+        self.period_len = (rate / PERIODS_PER_SECOND).max(1) as usize;
+        self.peaks = vec![0f64; channels];
+        self.squares = vec![0f64; channels];
+        self.reset();
+    }
+
+    // Pushes a buffer of interleaved `F32LE` samples
+    pub fn push_buffer(&mut self, buffer: &gst::Buffer, audio_info: &gst_audio::AudioInfo) {
+        let channels = audio_info.channels() as usize;
+        if audio_info.rate() != self.rate || channels != self.channels {
+            self.init(audio_info.rate(), channels);
+        }
+        if self.rate == 0 || self.channels == 0 {
+            return;
+        }
+
+        let pts = match buffer.get_pts().nseconds() {
+            Some(pts) => pts,
+            None => return,
+        };
+        let map = match buffer.map_readable() {
+            Some(map) => map,
+            None => return,
+        };
+
+        let frame_size = SAMPLE_SIZE * self.channels;
+        let mut frame = vec![0f64; self.channels];
+        for (index, bytes) in map.as_slice().chunks(frame_size).enumerate() {
+            if bytes.len() < frame_size {
+                break;
+            }
+
+            for (channel, value) in frame.iter_mut().enumerate() {
+                *value = f64::from(LittleEndian::read_f32(&bytes[channel * SAMPLE_SIZE..]));
+            }
+
+            let position = pts + (index as u64 + 1) * 1_000_000_000 / u64::from(self.rate);
+            self.push_frame(position, &frame);
+        }
+    }
+
+    fn push_frame(&mut self, position: u64, frame: &[f64]) {
+        for (channel, sample) in frame.iter().enumerate().take(self.channels) {
+            let abs_sample = sample.abs();
+            if abs_sample > self.peaks[channel] {
+                self.peaks[channel] = abs_sample;
+            }
+            self.squares[channel] += sample * sample;
+        }
+
+        if let Some(loudness) = self.loudness.as_mut() {
+            loudness.push_frame(frame);
+        }
+
+        self.period_pos += 1;
+        if self.period_pos < self.period_len {
+            return;
+        }
+
+        let period_len = self.period_len as f64;
+        let levels = Levels {
+            position,
+            peaks: self.peaks.clone(),
+            rms: self.squares
+                .iter()
+                .map(|square| (square / period_len).sqrt())
+                .collect(),
+            momentary: self.loudness
+                .as_ref()
+                .and_then(|loudness| loudness.get_momentary_loudness()),
+            short_term: self.loudness
+                .as_ref()
+                .and_then(|loudness| loudness.get_short_term_loudness()),
+        };
+
+        if self.pending.len() == MAX_PENDING_LEVELS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(levels);
+
+        self.period_pos = 0;
+        self.clear_period();
+    }
+
+    // Returns the most recent levels measured up to `position`
+    // Older levels are discarded
+    pub fn pop_levels(&mut self, position: u64) -> Option<Levels> {
+        let mut levels = None;
+        while self.pending
+            .front()
+            .map_or(false, |pending| pending.position <= position)
+        {
+            levels = self.pending.pop_front();
+        }
+
+        levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::LevelMeter;
+
+    const RATE: u32 = 48_000;
+
+    fn push_sine(meter: &mut LevelMeter, amplitudes: &[f64], duration_ms: u64) {
+        let mut frame = vec![0f64; amplitudes.len()];
+        let sample_nb = u64::from(RATE) * duration_ms / 1_000;
+        for index in 0..sample_nb {
+            let phase = 2f64 * PI * 1_000f64 * index as f64 / f64::from(RATE);
+            for (value, amplitude) in frame.iter_mut().zip(amplitudes) {
+                *value = amplitude * phase.sin();
+            }
+            let position = (index + 1) * 1_000_000_000 / u64::from(RATE);
+            meter.push_frame(position, &frame);
+        }
+    }
+
+    #[test]
+    fn peak_rms() {
+        let mut meter = LevelMeter::default();
+        meter.init(RATE, 2);
+        push_sine(&mut meter, &[0.5, 0.1], 1_000);
+
+        // Nothing measured before the first period
+        assert_eq!(meter.pop_levels(10_000_000), None);
+
+        let levels = meter.pop_levels(500_000_000).unwrap();
+        assert_eq!(levels.position, 500_000_000);
+        assert!((levels.peaks[0] - 0.5).abs() < 0.001);
+        assert!((levels.peaks[1] - 0.1).abs() < 0.001);
+        assert!((levels.rms[0] - 0.5 / 2f64.sqrt()).abs() < 0.001);
+        assert!((levels.rms[1] - 0.1 / 2f64.sqrt()).abs() < 0.001);
+        assert!(levels.momentary.is_some());
+        assert_eq!(levels.short_term, None);
+
+        // Previous levels are discarded
+        let levels = meter.pop_levels(1_000_000_000).unwrap();
+        assert_eq!(levels.position, 1_000_000_000);
+        assert_eq!(meter.pop_levels(1_000_000_000), None);
+    }
+
+    #[test]
+    fn reset() {
+        let mut meter = LevelMeter::default();
+        meter.init(RATE, 1);
+        push_sine(&mut meter, &[0.5], 1_000);
+
+        meter.reset();
+        assert_eq!(meter.pop_levels(1_000_000_000), None);
+
+        // The loudness is measured again from scratch
+        push_sine(&mut meter, &[0.5], 100);
+        let levels = meter.pop_levels(1_000_000_000).unwrap();
+        assert_eq!(levels.momentary, None);
+    }
+}
@@ -0,0 +1,163 @@
+use gettextrs::gettext;
+
+use std::mem;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use super::{AnalysisPipeline, AudioFramesDecoder, AudioFramesHandler, ContextMessage, Loudness,
+            LoudnessMeter, VolumeEnvelope};
+
+// Dispatches the decoded frames to the meter of the chapter they belong to
+// and detects the first and last audible frames of each chapter
+// Note: the fades are applied before measuring so that the gains
+// match the content of the split files
+struct ChaptersMeter {
+    boundaries: Vec<(u64, u64)>,
+    envelopes: Vec<VolumeEnvelope>,
+    meters: Vec<LoudnessMeter>,
+    silence_threshold: f64,
+    audible_bounds: Vec<Option<(u64, u64)>>,
+    current: usize,
+    rate: u64,
+    // The frame with the fades applied
+    frame: Vec<f64>,
+}
+
+impl ChaptersMeter {
+    fn new(boundaries: Vec<(u64, u64)>, fades: &[(u64, u64)], silence_threshold: f64) -> Self {
+        let audible_bounds = vec![None; boundaries.len()];
+        let envelopes = boundaries
+            .iter()
+            .zip(fades)
+            .map(|(&(start, end), &(fade_in, fade_out))| {
+                VolumeEnvelope::new(start, end, fade_in, fade_out)
+            })
+            .collect();
+        ChaptersMeter {
+            boundaries,
+            envelopes,
+            meters: Vec::new(),
+            silence_threshold,
+            audible_bounds,
+            current: 0,
+            rate: 0,
+            frame: Vec::new(),
+        }
+    }
+}
+
+impl AudioFramesHandler for ChaptersMeter {
+    fn set_format(&mut self, rate: u32, channels: usize) {
+        if !self.meters.is_empty() {
+            // Caps can be sent again with the same audio format
+            return;
+        }
+
+        self.rate = u64::from(rate);
+        self.frame = vec![0f64; channels];
+        self.meters = self.boundaries
+            .iter()
+            .map(|_| LoudnessMeter::new(rate, channels))
+            .collect();
+    }
+
+    fn push_frame(&mut self, timestamp: u64, frame: &[f64]) {
+        if self.meters.is_empty() {
+            return;
+        }
+
+        while self.current < self.boundaries.len() && timestamp >= self.boundaries[self.current].1
+        {
+            self.current += 1;
+        }
+        if self.current == self.boundaries.len() || timestamp < self.boundaries[self.current].0 {
+            // Not part of a chapter
+            return;
+        }
+
+        let gain = self.envelopes[self.current].get_gain(timestamp);
+        let mut is_audible = false;
+        for (value, &decoded) in self.frame.iter_mut().zip(frame) {
+            is_audible |= decoded.abs() > self.silence_threshold;
+            *value = decoded * gain;
+        }
+        self.meters[self.current].push_frame(&self.frame);
+
+        if is_audible {
+            let frame_end = timestamp + 1_000_000_000 / self.rate;
+            let bounds = &mut self.audible_bounds[self.current];
+            *bounds = Some(match *bounds {
+                Some((first, _)) => (first, frame_end),
+                None => (timestamp, frame_end),
+            });
+        }
+    }
+}
+
+// Decodes the audio stream in order to measure the loudness of the chapters
+// and to locate their leading and trailing silences
+pub struct LoudnessContext {
+    pipeline: AnalysisPipeline,
+    decoder: Arc<Mutex<AudioFramesDecoder<ChaptersMeter>>>,
+}
+
+impl LoudnessContext {
+    // `boundaries` are the start and end of each chapter, in order
+    // `fades` are the fade in and fade out durations of each chapter
+    // `silence_threshold` is the level under which a sample is silent in dBFS
+    pub fn new(
+        input_path: &Path,
+        stream_id: &str,
+        boundaries: Vec<(u64, u64)>,
+        fades: &[(u64, u64)],
+        silence_threshold: f64,
+        ctx_tx: Sender<ContextMessage>,
+    ) -> Result<LoudnessContext, String> {
+        info!(
+            "{}",
+            gettext("Measuring the loudness of {}...")
+                .replacen("{}", input_path.to_str().unwrap(), 1)
+        );
+
+        let decoder = Arc::new(Mutex::new(AudioFramesDecoder::new(ChaptersMeter::new(
+            boundaries,
+            fades,
+            10f64.powf(silence_threshold / 20f64),
+        ))));
+        let pipeline = AnalysisPipeline::new(
+            "loudness_pipeline",
+            input_path,
+            stream_id,
+            AudioFramesDecoder::<ChaptersMeter>::get_caps(),
+            Arc::clone(&decoder),
+            gettext("Failed to start measuring the loudness."),
+            ctx_tx,
+        )?;
+
+        Ok(LoudnessContext { pipeline, decoder })
+    }
+
+    pub fn cancel(&mut self) {
+        self.pipeline.cancel();
+    }
+
+    pub fn get_position(&mut self) -> u64 {
+        self.pipeline.get_position()
+    }
+
+    // Returns the loudness of each chapter and of the chapters as a whole
+    pub fn take_loudness(&mut self) -> (Vec<Option<Loudness>>, Option<Loudness>) {
+        let meters = mem::replace(&mut self.decoder.lock().unwrap().handler.meters, Vec::new());
+        (
+            meters.iter().map(|meter| meter.get_loudness()).collect(),
+            LoudnessMeter::get_global_loudness(&meters),
+        )
+    }
+
+    // Returns the timestamps of the first and last audible frames of each chapter
+    // or `None` if the chapter is silent
+    pub fn get_audible_bounds(&self) -> Vec<Option<(u64, u64)>> {
+        self.decoder.lock().unwrap().handler.audible_bounds.clone()
+    }
+}
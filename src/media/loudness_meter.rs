@@ -0,0 +1,347 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+// Loudness measurement according to ITU-R BS.1770-4 / EBU R128
+
+// Offset of the K-weighted loudness
+const LOUDNESS_OFFSET: f64 = -0.691;
+const ABSOLUTE_GATE: f64 = -70f64; // LUFS
+const RELATIVE_GATE: f64 = -10f64; // LU
+// Blocks of 400ms overlapping by 75%
+const SUB_BLOCKS_PER_BLOCK: usize = 4;
+const SUB_BLOCKS_PER_SECOND: u32 = 10;
+// The short-term loudness is measured on a sliding window of 3s
+const SUB_BLOCKS_PER_SHORT_TERM: usize = 30;
+
+// Target loudness for ReplayGain 2.0
+pub const REPLAYGAIN_TARGET: f64 = -18f64; // LUFS
+// Reference level associated with ReplayGain tags
+pub const REPLAYGAIN_REFERENCE_LEVEL: f64 = 89f64; // dB SPL
+// Target loudness for the Opus R128 gain tags
+pub const R128_TARGET: f64 = -23f64; // LUFS
+
+// Second order IIR filter
+#[derive(Clone, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+// The K-weighting filter: a high shelf followed by a high pass filter
+// Coefficients are computed for the actual sample rate (see libebur128)
+#[derive(Clone)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(rate: u32) -> Self {
+        let rate = f64::from(rate);
+
+        let f0 = 1_681.974_450_955_533;
+        let gain = 3.999_843_853_973_347;
+        let q = 0.707_175_236_955_419_6;
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20f64);
+        let vb = vh.powf(0.499_666_774_154_541_6);
+        let a0 = 1f64 + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2f64 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2f64 * (k * k - 1f64) / a0, (1f64 - k / q + k * k) / a0],
+            ..Biquad::default()
+        };
+
+        let f0 = 38.135_470_876_024_44;
+        let q = 0.500_327_037_323_877_3;
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1f64 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1f64, -2f64, 1f64],
+            a: [2f64 * (k * k - 1f64) / a0, (1f64 - k / q + k * k) / a0],
+            ..Biquad::default()
+        };
+
+        KWeighting { shelf, high_pass }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let x = self.shelf.process(x);
+        self.high_pass.process(x)
+    }
+}
+
+// Channels are assumed to be in the usual order, i.e. for 5.1:
+// front left, front right, center, LFE, surround left, surround right
+fn get_channel_weight(channel: usize, channels: usize) -> f64 {
+    if channels < 6 {
+        return 1f64;
+    }
+
+    match channel {
+        3 => 0f64,
+        4 | 5 => 1.41,
+        _ => 1f64,
+    }
+}
+
+fn energy_to_loudness(energy: f64) -> f64 {
+    LOUDNESS_OFFSET + 10f64 * energy.log10()
+}
+
+fn loudness_to_energy(loudness: f64) -> f64 {
+    10f64.powf((loudness - LOUDNESS_OFFSET) / 10f64)
+}
+
+// Computes the gated loudness of the given blocks energies
+fn get_gated_loudness<'a, I>(blocks: I) -> Option<f64>
+where
+    I: Iterator<Item = &'a f64> + Clone,
+{
+    let absolute_threshold = loudness_to_energy(ABSOLUTE_GATE);
+    let mean_above = |threshold: f64| {
+        let (sum, count) = blocks
+            .clone()
+            .filter(|energy| **energy > threshold)
+            .fold((0f64, 0usize), |(sum, count), energy| (sum + energy, count + 1));
+        if count > 0 {
+            Some(sum / count as f64)
+        } else {
+            None
+        }
+    };
+
+    let relative_threshold =
+        loudness_to_energy(energy_to_loudness(mean_above(absolute_threshold)?) + RELATIVE_GATE);
+    let threshold = if relative_threshold > absolute_threshold {
+        relative_threshold
+    } else {
+        absolute_threshold
+    };
+
+    mean_above(threshold).map(energy_to_loudness)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Loudness {
+    pub integrated: f64, // LUFS
+    pub peak: f64,       // sample peak, 1.0 is full scale
+}
+
+impl Loudness {
+    pub fn get_replaygain(&self) -> f64 {
+        REPLAYGAIN_TARGET - self.integrated
+    }
+
+    // Gain in Q7.8 format as defined in RFC 7845
+    pub fn get_r128_gain(&self) -> i16 {
+        let gain = ((R128_TARGET - self.integrated) * 256f64).round();
+        if gain > f64::from(i16::max_value()) {
+            i16::max_value()
+        } else if gain < f64::from(i16::min_value()) {
+            i16::min_value()
+        } else {
+            gain as i16
+        }
+    }
+}
+
+// Measures the integrated loudness and the sample peak of a stream
+// Samples are pushed as interleaved frames of `f64` in the range [-1.0, 1.0]
+pub struct LoudnessMeter {
+    filters: Vec<KWeighting>,
+    weights: Vec<f64>,
+    sub_block_len: usize,
+    sub_block_pos: usize,
+    sub_block_energy: f64,
+    // Energies of the most recent sub-blocks, enough for the short-term loudness
+    sub_blocks: VecDeque<f64>,
+    blocks: Vec<f64>,
+    peak: f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(rate: u32, channels: usize) -> Self {
+        LoudnessMeter {
+            filters: vec![KWeighting::new(rate); channels],
+            weights: (0..channels)
+                .map(|channel| get_channel_weight(channel, channels))
+                .collect(),
+            sub_block_len: (rate / SUB_BLOCKS_PER_SECOND) as usize,
+            sub_block_pos: 0,
+            sub_block_energy: 0f64,
+            sub_blocks: VecDeque::with_capacity(SUB_BLOCKS_PER_SHORT_TERM),
+            blocks: Vec::new(),
+            peak: 0f64,
+        }
+    }
+
+    pub fn push_frame(&mut self, frame: &[f64]) {
+        for (channel, sample) in frame.iter().enumerate().take(self.filters.len()) {
+            let abs_sample = sample.abs();
+            if abs_sample > self.peak {
+                self.peak = abs_sample;
+            }
+
+            let filtered = self.filters[channel].process(*sample);
+            self.sub_block_energy += self.weights[channel] * filtered * filtered;
+        }
+
+        self.sub_block_pos += 1;
+        if self.sub_block_pos < self.sub_block_len {
+            return;
+        }
+
+        if self.sub_blocks.len() == SUB_BLOCKS_PER_SHORT_TERM {
+            self.sub_blocks.pop_front();
+        }
+        self.sub_blocks.push_back(self.sub_block_energy);
+        self.sub_block_pos = 0;
+        self.sub_block_energy = 0f64;
+
+        if let Some(block_energy) = self.get_recent_energy(SUB_BLOCKS_PER_BLOCK) {
+            self.blocks.push(block_energy);
+        }
+    }
+
+    // Mean energy of the last `sub_block_count` sub-blocks
+    fn get_recent_energy(&self, sub_block_count: usize) -> Option<f64> {
+        if self.sub_blocks.len() < sub_block_count {
+            return None;
+        }
+
+        let sum = self.sub_blocks
+            .iter()
+            .skip(self.sub_blocks.len() - sub_block_count)
+            .sum::<f64>();
+        Some(sum / (sub_block_count * self.sub_block_len) as f64)
+    }
+
+    // Ungated loudness of the last 400ms in LUFS
+    pub fn get_momentary_loudness(&self) -> Option<f64> {
+        self.get_recent_energy(SUB_BLOCKS_PER_BLOCK).map(energy_to_loudness)
+    }
+
+    // Ungated loudness of the last 3s in LUFS
+    pub fn get_short_term_loudness(&self) -> Option<f64> {
+        self.get_recent_energy(SUB_BLOCKS_PER_SHORT_TERM).map(energy_to_loudness)
+    }
+
+    // Returns `None` if the stream is too short or too quiet to be measured
+    pub fn get_loudness(&self) -> Option<Loudness> {
+        get_gated_loudness(self.blocks.iter()).map(|integrated| Loudness {
+            integrated,
+            peak: self.peak,
+        })
+    }
+
+    // Returns the loudness of the streams as a whole, e.g. for an album
+    pub fn get_global_loudness(meters: &[LoudnessMeter]) -> Option<Loudness> {
+        let peak = meters
+            .iter()
+            .fold(0f64, |peak, meter| if meter.peak > peak { meter.peak } else { peak });
+        get_gated_loudness(meters.iter().flat_map(|meter| meter.blocks.iter()))
+            .map(|integrated| Loudness { integrated, peak })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{Loudness, LoudnessMeter};
+
+    const RATE: u32 = 48_000;
+
+    fn push_sine(meter: &mut LoudnessMeter, amplitude: f64, channels: usize, duration_s: u32) {
+        let mut frame = vec![0f64; channels];
+        for index in 0..RATE * duration_s {
+            let sample =
+                amplitude * (2f64 * PI * 1_000f64 * f64::from(index) / f64::from(RATE)).sin();
+            for value in &mut frame {
+                *value = sample;
+            }
+            meter.push_frame(&frame);
+        }
+    }
+
+    #[test]
+    fn sine_1khz() {
+        // EBU Tech 3341: a stereo 1kHz sine at -20 dBFS measures -20 LUFS
+        let mut meter = LoudnessMeter::new(RATE, 2);
+        push_sine(&mut meter, 0.1, 2, 20);
+        let loudness = meter.get_loudness().unwrap();
+        assert!((loudness.integrated + 20f64).abs() < 0.1);
+        assert!((loudness.peak - 0.1).abs() < 0.001);
+        assert!((loudness.get_replaygain() - 2f64).abs() < 0.1);
+        assert!((loudness.get_r128_gain() + 3 * 256).abs() < 26);
+    }
+
+    #[test]
+    fn momentary_short_term() {
+        let mut meter = LoudnessMeter::new(RATE, 2);
+        assert_eq!(meter.get_momentary_loudness(), None);
+
+        push_sine(&mut meter, 0.1, 2, 1);
+        assert!((meter.get_momentary_loudness().unwrap() + 20f64).abs() < 0.1);
+        // Not enough samples for the short-term window
+        assert_eq!(meter.get_short_term_loudness(), None);
+
+        push_sine(&mut meter, 0.1, 2, 2);
+        assert!((meter.get_short_term_loudness().unwrap() + 20f64).abs() < 0.1);
+
+        // The momentary loudness follows the level changes faster
+        push_sine(&mut meter, 0.01, 2, 1);
+        assert!((meter.get_momentary_loudness().unwrap() + 40f64).abs() < 0.1);
+        assert!(meter.get_short_term_loudness().unwrap() > -30f64);
+    }
+
+    #[test]
+    fn gating() {
+        let mut meter = LoudnessMeter::new(RATE, 2);
+        assert_eq!(meter.get_loudness(), None);
+
+        // Silence is discarded by the absolute gate
+        push_sine(&mut meter, 0f64, 2, 10);
+        assert_eq!(meter.get_loudness(), None);
+
+        push_sine(&mut meter, 0.1, 2, 10);
+        let loudness = meter.get_loudness().unwrap();
+        assert!((loudness.integrated + 20f64).abs() < 0.1);
+
+        // Quiet passages are discarded by the relative gate
+        // (only the blocks overlapping the transition are kept)
+        push_sine(&mut meter, 0.001, 2, 10);
+        let loudness = meter.get_loudness().unwrap();
+        assert!((loudness.integrated + 20f64).abs() < 0.2);
+    }
+
+    #[test]
+    fn global_loudness() {
+        let mut loud = LoudnessMeter::new(RATE, 2);
+        push_sine(&mut loud, 0.2, 2, 10);
+        let mut quiet = LoudnessMeter::new(RATE, 2);
+        push_sine(&mut quiet, 0.1, 2, 10);
+
+        let quiet_loudness = quiet.get_loudness().unwrap();
+        let meters = vec![loud, quiet];
+        let global: Loudness = LoudnessMeter::get_global_loudness(&meters).unwrap();
+        assert!(global.integrated > quiet_loudness.integrated);
+        assert!(global.integrated < meters[0].get_loudness().unwrap().integrated);
+        assert!((global.peak - 0.2).abs() < 0.001);
+    }
+}
@@ -1,27 +1,74 @@
+pub mod analysis_pipeline;
+pub use self::analysis_pipeline::{AnalysisPipeline, AudioFramesDecoder, AudioFramesHandler,
+                                  FramesHandler};
+
 pub mod audio_channel;
 pub use self::audio_channel::{AudioChannel, AudioChannelSide};
 
 pub mod audio_buffer;
-pub use self::audio_buffer::AudioBuffer;
+pub use self::audio_buffer::{AudioBuffer, SnapTarget};
+
+pub mod channel_mix;
+pub use self::channel_mix::ChannelMix;
 
 pub mod dbl_audio_buffer;
 pub use self::dbl_audio_buffer::DoubleAudioBuffer;
 
+pub mod level_meter;
+pub use self::level_meter::{LevelMeter, Levels};
+
+pub mod loudness_context;
+pub use self::loudness_context::LoudnessContext;
+
+pub mod loudness_meter;
+pub use self::loudness_meter::{Loudness, LoudnessMeter};
+
+pub mod opus_tags;
+pub use self::opus_tags::with_opus_tags;
+
+pub mod peaks;
+pub use self::peaks::Peaks;
+
+pub mod peaks_context;
+pub use self::peaks_context::PeaksContext;
+
 pub mod playback_context;
 pub use self::playback_context::{PlaybackContext, QUEUE_SIZE_NS};
 
+pub mod render_context;
+pub use self::render_context::{DecodedData, RenderContext};
+
 pub mod sample_extractor;
 pub use self::sample_extractor::SampleExtractor;
 
+pub mod scene_context;
+pub use self::scene_context::SceneContext;
+
+pub mod scene_detector;
+pub use self::scene_detector::{SceneCandidate, SceneChange, SceneDetector, Thumbnail};
+
+pub mod silence_context;
+pub use self::silence_context::SilenceContext;
+
+pub mod silence_detector;
+pub use self::silence_detector::SilenceDetector;
+
 pub mod splitter_context;
 pub use self::splitter_context::SplitterContext;
 
+pub mod stft;
+pub use self::stft::Stft;
+
 pub mod toc_setter_context;
 pub use self::toc_setter_context::TocSetterContext;
 
+pub mod volume_envelope;
+pub use self::volume_envelope::VolumeEnvelope;
+
 pub enum ContextMessage {
     AsyncDone,
     Eos,
+    FailedToAnalyze(String),
     FailedToOpenMedia(String),
     FailedToExport(String),
     InitDone,
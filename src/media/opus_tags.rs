@@ -0,0 +1,236 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use glib;
+use gstreamer as gst;
+
+// Opus streams hold their tags in the second header packet, `OpusTags`,
+// which uses the Vorbis comment layout (see RFC 7845, section 5.2)
+const MAGIC: &[u8] = b"OpusTags";
+const LEN_SIZE: usize = 4; // u32 LE
+
+// Vorbis comment fields for the tags which can be defined for a chapter
+// Note: fields follow the mapping used by GStreamer when re-encoding
+const FIELDS: &[(&str, &str)] = &[
+    ("title", "TITLE"),
+    ("title-sortname", "TITLESORT"),
+    ("album", "ALBUM"),
+    ("album-sortname", "ALBUMSORT"),
+    ("artist", "ARTIST"),
+    ("artist-sortname", "ARTISTSORT"),
+    ("album-artist", "ALBUMARTIST"),
+    ("album-artist-sortname", "ALBUMARTISTSORT"),
+    ("composer", "COMPOSER"),
+    ("composer-sortname", "COMPOSERSORT"),
+    ("conductor", "CONDUCTOR"),
+    ("performer", "PERFORMER"),
+    ("genre", "GENRE"),
+    ("comment", "COMMENT"),
+    ("description", "DESCRIPTION"),
+    ("isrc", "ISRC"),
+    ("copyright", "COPYRIGHT"),
+    ("license", "LICENSE"),
+    ("organization", "ORGANIZATION"),
+    ("publisher", "PUBLISHER"),
+    ("location", "LOCATION"),
+    ("contact", "CONTACT"),
+    ("version", "VERSION"),
+    ("datetime", "DATE"),
+    ("track-number", "TRACKNUMBER"),
+    ("track-count", "TRACKTOTAL"),
+    ("album-disc-number", "DISCNUMBER"),
+    ("album-disc-count", "DISCTOTAL"),
+    ("replaygain-track-gain", "REPLAYGAIN_TRACK_GAIN"),
+    ("replaygain-track-peak", "REPLAYGAIN_TRACK_PEAK"),
+    ("replaygain-album-gain", "REPLAYGAIN_ALBUM_GAIN"),
+    ("replaygain-album-peak", "REPLAYGAIN_ALBUM_PEAK"),
+];
+
+// Returns a copy of the Opus stream `caps` with an `OpusTags` header built from `tags`
+// `oggmux` writes the headers from the caps, so this is how the tags
+// can be set on a stream which is not re-encoded
+pub fn with_opus_tags(caps: &gst::CapsRef, tags: &gst::TagList) -> Option<gst::Caps> {
+    let (opus_head, opus_tags) = {
+        let stream_headers = caps.get_structure(0)?.get::<gst::Array>("streamheader")?;
+        let stream_headers = stream_headers.as_slice();
+        if stream_headers.len() != 2 {
+            // Expecting `OpusHead` and `OpusTags`
+            return None;
+        }
+
+        let opus_head = stream_headers[0].get::<gst::Buffer>()?;
+        let opus_tags = {
+            let original = stream_headers[1].get::<gst::Buffer>()?;
+            let map = original.map_readable()?;
+            build_opus_tags(map.as_slice(), tags)
+        };
+        (opus_head, opus_tags)
+    };
+
+    let mut buffer = gst::Buffer::with_size(opus_tags.len())?;
+    {
+        let buffer_mut = buffer.get_mut().unwrap();
+        buffer_mut.copy_from_slice(0, &opus_tags).ok()?;
+        buffer_mut.set_flags(gst::BufferFlags::HEADER);
+    }
+
+    let mut caps = caps.to_owned();
+    caps.get_mut()
+        .unwrap()
+        .get_mut_structure(0)
+        .unwrap()
+        .set("streamheader", &gst::Array::new(&[&opus_head, &buffer]));
+
+    Some(caps)
+}
+
+// Returns an `OpusTags` header holding `tags`
+// The vendor string is kept from `original`, the stream's `OpusTags` header
+pub fn build_opus_tags(original: &[u8], tags: &gst::TagList) -> Vec<u8> {
+    let comments = get_comments(tags);
+
+    let mut opus_tags = MAGIC.to_vec();
+    push_field(&mut opus_tags, get_vendor(original).unwrap_or(&[]));
+
+    let mut count = [0u8; LEN_SIZE];
+    LittleEndian::write_u32(&mut count, comments.len() as u32);
+    opus_tags.extend_from_slice(&count);
+    for comment in &comments {
+        push_field(&mut opus_tags, comment.as_bytes());
+    }
+
+    opus_tags
+}
+
+fn get_vendor(opus_tags: &[u8]) -> Option<&[u8]> {
+    if !opus_tags.starts_with(MAGIC) || opus_tags.len() < MAGIC.len() + LEN_SIZE {
+        return None;
+    }
+
+    let len = LittleEndian::read_u32(&opus_tags[MAGIC.len()..]) as usize;
+    let start = MAGIC.len() + LEN_SIZE;
+    opus_tags.get(start..start + len)
+}
+
+fn push_field(opus_tags: &mut Vec<u8>, field: &[u8]) {
+    let mut len = [0u8; LEN_SIZE];
+    LittleEndian::write_u32(&mut len, field.len() as u32);
+    opus_tags.extend_from_slice(&len);
+    opus_tags.extend_from_slice(field);
+}
+
+// Returns the Vorbis comments in the form `FIELD=value`
+fn get_comments(tags: &gst::TagList) -> Vec<String> {
+    let mut comments = Vec::new();
+
+    for &(tag_name, field) in FIELDS {
+        let mut idx = 0;
+        while let Some(value) = tags.get_index_generic(tag_name, idx) {
+            if let Some(value) = format_value(tag_name, value) {
+                comments.push(format!("{}={}", field, value));
+            }
+            idx += 1;
+        }
+    }
+
+    // Extended comments are already in the form `KEY=value`
+    comments.extend(
+        tags.iter_tag::<gst::tags::ExtendedComment>()
+            .map(|comment| comment.get().unwrap().to_owned())
+            .filter(|comment| comment.contains('=')),
+    );
+
+    comments
+}
+
+fn format_value(tag_name: &str, value: &glib::SendValue) -> Option<String> {
+    if let Some(value) = value.get::<String>() {
+        Some(value)
+    } else if let Some(value) = value.get::<u32>() {
+        Some(value.to_string())
+    } else if let Some(value) = value.get::<f64>() {
+        if tag_name.ends_with("-gain") {
+            Some(format!("{:.2} dB", value))
+        } else {
+            Some(format!("{:.6}", value))
+        }
+    } else {
+        value
+            .get::<gst::DateTime>()
+            .and_then(|date_time| date_time.to_iso8601_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{ByteOrder, LittleEndian};
+    use gstreamer as gst;
+
+    use super::*;
+
+    // Returns the vendor and the comments of an `OpusTags` header
+    fn parse_opus_tags(opus_tags: &[u8]) -> (String, Vec<String>) {
+        assert!(opus_tags.starts_with(MAGIC));
+        let mut offset = MAGIC.len();
+        let read_field = |offset: &mut usize| {
+            let len = LittleEndian::read_u32(&opus_tags[*offset..]) as usize;
+            *offset += LEN_SIZE;
+            let field = String::from_utf8(opus_tags[*offset..*offset + len].to_vec()).unwrap();
+            *offset += len;
+            field
+        };
+
+        let vendor = read_field(&mut offset);
+        let count = LittleEndian::read_u32(&opus_tags[offset..]);
+        offset += LEN_SIZE;
+        let comments = (0..count).map(|_| read_field(&mut offset)).collect();
+        assert_eq!(opus_tags.len(), offset);
+
+        (vendor, comments)
+    }
+
+    #[test]
+    fn opus_tags() {
+        gst::init().unwrap();
+
+        let mut original_tags = gst::TagList::new();
+        original_tags
+            .get_mut()
+            .unwrap()
+            .add::<gst::tags::Title>(&"Original", gst::TagMergeMode::Replace);
+        let original = build_opus_tags(&[], &original_tags);
+        let (vendor, comments) = parse_opus_tags(&original);
+        assert!(vendor.is_empty());
+        assert_eq!(vec!["TITLE=Original".to_owned()], comments);
+
+        // Forge an original header with a vendor string
+        let mut original = MAGIC.to_vec();
+        push_field(&mut original, b"libopus 1.2");
+        original.extend_from_slice(&[0u8; LEN_SIZE]);
+
+        let mut tags = gst::TagList::new();
+        {
+            let tags = tags.get_mut().unwrap();
+            tags.add::<gst::tags::Title>(&"Chapter 2", gst::TagMergeMode::Replace);
+            tags.add::<gst::tags::Artist>(&"Artist", gst::TagMergeMode::Replace);
+            tags.add::<gst::tags::TrackNumber>(&2u32, gst::TagMergeMode::Replace);
+            tags.add::<gst::tags::TrackCount>(&5u32, gst::TagMergeMode::Replace);
+            tags.add::<gst::tags::TrackGain>(&-3.5f64, gst::TagMergeMode::Replace);
+            tags.add::<gst::tags::ExtendedComment>(&"MOOD=calm", gst::TagMergeMode::Append);
+            tags.add::<gst::tags::ExtendedComment>(&"no key", gst::TagMergeMode::Append);
+        }
+
+        let (vendor, comments) = parse_opus_tags(&build_opus_tags(&original, &tags));
+        assert_eq!("libopus 1.2", vendor);
+        assert_eq!(
+            vec![
+                "TITLE=Chapter 2".to_owned(),
+                "ARTIST=Artist".to_owned(),
+                "TRACKNUMBER=2".to_owned(),
+                "TRACKTOTAL=5".to_owned(),
+                "REPLAYGAIN_TRACK_GAIN=-3.50 dB".to_owned(),
+                "MOOD=calm".to_owned(),
+            ],
+            comments
+        );
+    }
+}
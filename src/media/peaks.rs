@@ -0,0 +1,429 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use std::io;
+use std::io::{Read, Write};
+
+// Duration of the periods at the finest resolution
+pub const PEAK_DURATION: u64 = 50_000_000; // 50 ms
+// Each resolution is `LEVEL_FACTOR` times coarser than the previous one
+const LEVEL_FACTOR: usize = 8;
+const LEVEL_COUNT: usize = 4;
+
+// Header of the serialized peaks
+const MAGIC: &[u8; 4] = b"MTPK";
+const FORMAT_VERSION: u32 = 1;
+// Prevents huge allocations when reading corrupted peaks (48 h at the finest resolution)
+const MAX_PEAK_COUNT: u64 = 48 * 3_600 * 1_000_000_000 / PEAK_DURATION;
+
+// Minimum, maximum and RMS of the samples in a period
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    pub min: f64,
+    pub max: f64,
+    pub rms: f64,
+}
+
+impl Peak {
+    fn merge(&mut self, other: &Peak) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+}
+
+#[derive(Default)]
+struct PeakAccumulator {
+    min: f64,
+    max: f64,
+    sum_squares: f64,
+    count: usize,
+}
+
+impl PeakAccumulator {
+    fn push(&mut self, min: f64, max: f64, square: f64) {
+        if self.count == 0 {
+            self.min = min;
+            self.max = max;
+        } else {
+            self.min = self.min.min(min);
+            self.max = self.max.max(max);
+        }
+        self.sum_squares += square;
+        self.count += 1;
+    }
+
+    fn take(&mut self) -> Peak {
+        let peak = if self.count > 0 {
+            Peak {
+                min: self.min,
+                max: self.max,
+                rms: (self.sum_squares / self.count as f64).sqrt(),
+            }
+        } else {
+            Peak {
+                min: 0f64,
+                max: 0f64,
+                rms: 0f64,
+            }
+        };
+        *self = PeakAccumulator::default();
+        peak
+    }
+}
+
+// Peaks of a whole audio stream at several resolutions
+// Samples are pushed as interleaved frames of `f64` in the range [-1.0, 1.0]
+pub struct Peaks {
+    levels: Vec<Vec<Peak>>,
+    // Period being accumulated for each level
+    pending: Vec<PeakAccumulator>,
+    pending_idx: Option<u64>,
+    is_complete: bool,
+}
+
+impl Peaks {
+    pub fn new() -> Self {
+        Peaks {
+            levels: (0..LEVEL_COUNT).map(|_| Vec::new()).collect(),
+            pending: (0..LEVEL_COUNT).map(|_| PeakAccumulator::default()).collect(),
+            pending_idx: None,
+            is_complete: false,
+        }
+    }
+
+    fn get_level_duration(level: usize) -> u64 {
+        PEAK_DURATION * (LEVEL_FACTOR.pow(level as u32) as u64)
+    }
+
+    pub fn push_frame(&mut self, timestamp: u64, frame: &[f64]) {
+        if frame.is_empty() {
+            return;
+        }
+
+        let idx = timestamp / PEAK_DURATION;
+        match self.pending_idx {
+            Some(pending_idx) if idx > pending_idx => {
+                self.flush_pending(0);
+                // Fill the holes in the stream, if any
+                for _ in pending_idx + 1..idx {
+                    self.flush_pending(0);
+                }
+                self.pending_idx = Some(idx);
+            }
+            None => {
+                // Keep the periods aligned with the beginning of the stream
+                for _ in 0..idx {
+                    self.flush_pending(0);
+                }
+                self.pending_idx = Some(idx);
+            }
+            _ => (),
+        }
+
+        let accumulator = &mut self.pending[0];
+        for &value in frame {
+            accumulator.push(value, value, value * value);
+        }
+    }
+
+    fn flush_pending(&mut self, level: usize) {
+        let peak = self.pending[level].take();
+        self.levels[level].push(peak);
+
+        if level + 1 < LEVEL_COUNT {
+            self.pending[level + 1].push(peak.min, peak.max, peak.rms * peak.rms);
+            if self.levels[level].len() % LEVEL_FACTOR == 0 {
+                self.flush_pending(level + 1);
+            }
+        }
+    }
+
+    // Flushes the last periods
+    pub fn finish(&mut self) {
+        if self.is_complete {
+            return;
+        }
+
+        if self.pending_idx.is_some() {
+            self.flush_pending(0);
+            for level in 1..LEVEL_COUNT {
+                if self.pending[level].count > 0 {
+                    let peak = self.pending[level].take();
+                    self.levels[level].push(peak);
+                    if level + 1 < LEVEL_COUNT {
+                        self.pending[level + 1].push(peak.min, peak.max, peak.rms * peak.rms);
+                    }
+                }
+            }
+        }
+        self.is_complete = true;
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.is_complete
+    }
+
+    // Returns the duration for which the peaks are available
+    pub fn get_duration(&self) -> u64 {
+        self.levels[0].len() as u64 * PEAK_DURATION
+    }
+
+    // Returns the peak of the period [`start`, `end`[
+    // using the coarsest resolution which fits in the period
+    pub fn get_peak(&self, start: u64, end: u64) -> Option<Peak> {
+        let period = end.saturating_sub(start);
+        let level = (0..LEVEL_COUNT)
+            .rev()
+            .find(|&level| Peaks::get_level_duration(level) <= period)
+            .unwrap_or(0);
+        let level_duration = Peaks::get_level_duration(level);
+        let peaks = &self.levels[level];
+
+        let first_idx = (start / level_duration) as usize;
+        if first_idx >= peaks.len() {
+            return None;
+        }
+        let end_idx = (end.saturating_sub(1) / level_duration) as usize + 1;
+        let others = &peaks[first_idx + 1..end_idx.min(peaks.len()).max(first_idx + 1)];
+
+        let mut peak = peaks[first_idx];
+        if !others.is_empty() {
+            let mut sum_squares = peak.rms * peak.rms;
+            for other in others {
+                peak.merge(other);
+                sum_squares += other.rms * other.rms;
+            }
+            peak.rms = (sum_squares / (others.len() + 1) as f64).sqrt();
+        }
+
+        Some(peak)
+    }
+
+    // Serializes complete peaks in a compact binary form
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if !self.is_complete {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "peaks are not complete",
+            ));
+        }
+
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(FORMAT_VERSION)?;
+        writer.write_u32::<LittleEndian>(LEVEL_COUNT as u32)?;
+        for level in &self.levels {
+            writer.write_u64::<LittleEndian>(level.len() as u64)?;
+            for peak in level {
+                writer.write_f32::<LittleEndian>(peak.min as f32)?;
+                writer.write_f32::<LittleEndian>(peak.max as f32)?;
+                writer.write_f32::<LittleEndian>(peak.rms as f32)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Peaks> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC
+            || reader.read_u32::<LittleEndian>()? != FORMAT_VERSION
+            || reader.read_u32::<LittleEndian>()? as usize != LEVEL_COUNT
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected peaks format",
+            ));
+        }
+
+        let mut levels: Vec<Vec<Peak>> = Vec::with_capacity(LEVEL_COUNT);
+        for _ in 0..LEVEL_COUNT {
+            let count = reader.read_u64::<LittleEndian>()?;
+            // Each level is `LEVEL_FACTOR` times shorter than the previous one
+            let expected_count = levels.last().map(|prev_level| {
+                (prev_level.len() as u64 + LEVEL_FACTOR as u64 - 1) / LEVEL_FACTOR as u64
+            });
+            let is_consistent = expected_count.map_or(true, |expected| count == expected);
+            if count > MAX_PEAK_COUNT || !is_consistent {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unexpected peaks count",
+                ));
+            }
+
+            let mut level = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                level.push(Peak {
+                    min: f64::from(reader.read_f32::<LittleEndian>()?),
+                    max: f64::from(reader.read_f32::<LittleEndian>()?),
+                    rms: f64::from(reader.read_f32::<LittleEndian>()?),
+                });
+            }
+            levels.push(level);
+        }
+
+        let pending_idx = if levels[0].is_empty() {
+            None
+        } else {
+            Some(levels[0].len() as u64 - 1)
+        };
+        Ok(Peaks {
+            levels,
+            pending: (0..LEVEL_COUNT).map(|_| PeakAccumulator::default()).collect(),
+            pending_idx,
+            is_complete: true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::{Peak, Peaks, LEVEL_FACTOR, PEAK_DURATION};
+
+    const FRAMES_PER_PEAK: u64 = 10;
+    const FRAME_DURATION: u64 = PEAK_DURATION / FRAMES_PER_PEAK;
+
+    fn push_peaks(peaks: &mut Peaks, timestamp: &mut u64, level: f64, count: u64) {
+        for index in 0..count * FRAMES_PER_PEAK {
+            // Alternate the sign of the samples as for a waveform
+            let sign = if index % 2 == 0 { 1f64 } else { -1f64 };
+            peaks.push_frame(*timestamp, &[sign * level, sign * level / 2f64]);
+            *timestamp += FRAME_DURATION;
+        }
+    }
+
+    #[test]
+    fn levels() {
+        let mut peaks = Peaks::new();
+        let mut timestamp = 0;
+
+        push_peaks(&mut peaks, &mut timestamp, 0.5, LEVEL_FACTOR as u64);
+        push_peaks(&mut peaks, &mut timestamp, 0.8, 2);
+        peaks.finish();
+        assert!(peaks.is_complete());
+        assert_eq!(
+            peaks.get_duration(),
+            (LEVEL_FACTOR as u64 + 2) * PEAK_DURATION
+        );
+
+        // Finest resolution
+        let peak = peaks.get_peak(0, PEAK_DURATION).unwrap();
+        assert_eq!((peak.min, peak.max), (-0.5, 0.5));
+        assert!((peak.rms - (0.3125f64 / 2f64).sqrt()).abs() < 1e-9);
+        // Shorter than the finest resolution
+        assert_eq!(peaks.get_peak(10, 20), Some(peak));
+
+        // Coarser resolution
+        let second_level = peaks
+            .get_peak(0, LEVEL_FACTOR as u64 * PEAK_DURATION)
+            .unwrap();
+        assert_eq!((second_level.min, second_level.max), (-0.5, 0.5));
+        assert!((second_level.rms - peak.rms).abs() < 1e-9);
+
+        let peak = peaks
+            .get_peak(PEAK_DURATION, (LEVEL_FACTOR as u64 + 2) * PEAK_DURATION)
+            .unwrap();
+        assert_eq!((peak.min, peak.max), (-0.8, 0.8));
+
+        // After the end of the stream
+        assert_eq!(
+            peaks.get_peak(
+                (LEVEL_FACTOR as u64 + 2) * PEAK_DURATION,
+                (LEVEL_FACTOR as u64 + 3) * PEAK_DURATION
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn holes() {
+        let mut peaks = Peaks::new();
+        // The stream starts after 2 periods
+        let mut timestamp = 2 * PEAK_DURATION;
+        push_peaks(&mut peaks, &mut timestamp, 0.5, 1);
+        // One period is missing
+        timestamp += PEAK_DURATION;
+        push_peaks(&mut peaks, &mut timestamp, 0.5, 1);
+        peaks.finish();
+
+        let silence = Peak {
+            min: 0f64,
+            max: 0f64,
+            rms: 0f64,
+        };
+        assert_eq!(peaks.get_duration(), 5 * PEAK_DURATION);
+        assert_eq!(peaks.get_peak(0, PEAK_DURATION), Some(silence));
+        assert_eq!(peaks.get_peak(3 * PEAK_DURATION, 4 * PEAK_DURATION), Some(silence));
+        assert_eq!(
+            peaks
+                .get_peak(4 * PEAK_DURATION, 5 * PEAK_DURATION)
+                .map(|peak| peak.max),
+            Some(0.5)
+        );
+    }
+
+    #[test]
+    fn serialization() {
+        let mut peaks = Peaks::new();
+        let mut timestamp = 0;
+        push_peaks(&mut peaks, &mut timestamp, 0.5, LEVEL_FACTOR as u64 + 3);
+
+        // Incomplete peaks are not serialized
+        let mut buffer = Vec::new();
+        assert!(peaks.write(&mut buffer).is_err());
+
+        peaks.finish();
+        peaks.write(&mut buffer).unwrap();
+
+        let read_peaks = Peaks::read(&mut &buffer[..]).unwrap();
+        assert!(read_peaks.is_complete());
+        assert_eq!(read_peaks.get_duration(), peaks.get_duration());
+        for &(start, end) in &[
+            (0, PEAK_DURATION),
+            (PEAK_DURATION, 10 * PEAK_DURATION),
+            (0, 20 * PEAK_DURATION),
+        ] {
+            let peak = peaks.get_peak(start, end).unwrap();
+            let read_peak = read_peaks.get_peak(start, end).unwrap();
+            assert!((peak.max - read_peak.max).abs() < 1e-6);
+            assert!((peak.rms - read_peak.rms).abs() < 1e-6);
+        }
+
+        // Corrupted data
+        buffer[0] = b'X';
+        assert!(Peaks::read(&mut &buffer[..]).is_err());
+        assert!(Peaks::read(&mut &buffer[..8]).is_err());
+    }
+
+    #[test]
+    fn corrupted_header() {
+        let mut peaks = Peaks::new();
+        let mut timestamp = 0;
+        push_peaks(&mut peaks, &mut timestamp, 0.5, LEVEL_FACTOR as u64 + 3);
+        peaks.finish();
+        let mut buffer = Vec::new();
+        peaks.write(&mut buffer).unwrap();
+
+        // The count of the first level follows the magic, the version and the level count
+        const COUNT_OFFSET: usize = 12;
+        // min, max & rms as `f32`
+        const PEAK_SIZE: usize = 12;
+
+        // Huge count which must not be allocated
+        let mut corrupted = buffer.clone();
+        LittleEndian::write_u64(&mut corrupted[COUNT_OFFSET..], u64::max_value());
+        assert!(Peaks::read(&mut &corrupted[..]).is_err());
+
+        // Count of the second level not matching the first level
+        let mut corrupted = buffer.clone();
+        let second_count_offset = COUNT_OFFSET + 8 + (LEVEL_FACTOR + 3) * PEAK_SIZE;
+        LittleEndian::write_u64(&mut corrupted[second_count_offset..], 3);
+        assert!(Peaks::read(&mut &corrupted[..]).is_err());
+
+        // Count exceeding the data
+        let mut corrupted = buffer;
+        LittleEndian::write_u64(&mut corrupted[COUNT_OFFSET..], 1_000);
+        assert!(Peaks::read(&mut &corrupted[..]).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use gettextrs::gettext;
+
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use super::{AnalysisPipeline, AudioFramesDecoder, AudioFramesHandler, ContextMessage, Peaks};
+
+struct PeaksHandler {
+    peaks: Arc<Mutex<Peaks>>,
+}
+
+impl AudioFramesHandler for PeaksHandler {
+    fn push_frame(&mut self, timestamp: u64, frame: &[f64]) {
+        self.peaks.lock().unwrap().push_frame(timestamp, frame);
+    }
+
+    fn finish(&mut self) {
+        self.peaks.lock().unwrap().finish();
+    }
+}
+
+// Decodes the whole audio stream in order to compute its peaks
+pub struct PeaksContext {
+    pipeline: AnalysisPipeline,
+    peaks: Arc<Mutex<Peaks>>,
+}
+
+impl PeaksContext {
+    pub fn new(
+        input_path: &Path,
+        stream_id: &str,
+        ctx_tx: Sender<ContextMessage>,
+    ) -> Result<PeaksContext, String> {
+        info!(
+            "{}",
+            gettext("Computing the audio overview of {}...")
+                .replacen("{}", input_path.to_str().unwrap(), 1)
+        );
+
+        let peaks = Arc::new(Mutex::new(Peaks::new()));
+        let pipeline = AnalysisPipeline::new(
+            "peaks_pipeline",
+            input_path,
+            stream_id,
+            AudioFramesDecoder::<PeaksHandler>::get_caps(),
+            Arc::new(Mutex::new(AudioFramesDecoder::new(PeaksHandler {
+                peaks: Arc::clone(&peaks),
+            }))),
+            gettext("Failed to start computing the audio overview."),
+            ctx_tx,
+        )?;
+
+        Ok(PeaksContext { pipeline, peaks })
+    }
+
+    pub fn cancel(&mut self) {
+        self.pipeline.cancel();
+    }
+
+    // The peaks are updated while the stream is decoded
+    pub fn get_peaks(&self) -> Arc<Mutex<Peaks>> {
+        Arc::clone(&self.peaks)
+    }
+}
@@ -1,6 +1,7 @@
 use gettextrs::gettext;
 
 use gstreamer as gst;
+use gstreamer_audio as gst_audio;
 
 use gstreamer::prelude::*;
 use gstreamer::{BinExt, ClockTime, GstObjectExt, PadExt};
@@ -16,7 +17,7 @@ use std::sync::{Arc, Mutex, RwLock};
 use application::CONFIG;
 use metadata::MediaInfo;
 
-use super::{ContextMessage, DoubleAudioBuffer};
+use super::{ChannelMix, ContextMessage, DoubleAudioBuffer, LevelMeter};
 
 // Buffer size in ns for queues
 // This is the max duration that queues can hold
@@ -40,6 +41,9 @@ pub struct PlaybackContext {
     decodebin: gst::Element,
     position_query: gst::query::Position<gst::Query>,
     dbl_audio_buffer_mtx: Arc<Mutex<DoubleAudioBuffer>>,
+    channel_mix: Arc<RwLock<ChannelMix>>,
+    level_meter: Arc<Mutex<LevelMeter>>,
+    rate: f64,
 
     pub info: Arc<RwLock<MediaInfo>>,
 }
@@ -70,10 +74,19 @@ impl PlaybackContext {
             decodebin: gst::ElementFactory::make("decodebin3", "decodebin").unwrap(),
             position_query: gst::Query::new_position(gst::Format::Time),
             dbl_audio_buffer_mtx: Arc::clone(dbl_audio_buffer_mtx),
+            channel_mix: Arc::new(RwLock::new(ChannelMix::default())),
+            level_meter: Arc::new(Mutex::new(LevelMeter::default())),
+            rate: 1f64,
 
             info: Arc::new(RwLock::new(MediaInfo::new(path))),
         };
 
+        // The samples extraction keeps the rate of the previous media otherwise
+        this.dbl_audio_buffer_mtx
+            .lock()
+            .expect("PlaybackContext::new: couldn't lock dbl_audio_buffer_mtx")
+            .set_rate(this.rate);
+
         this.pipeline.add(&this.decodebin).unwrap();
         this.build_pipeline(path, video_sink, &ctx_tx);
         this.register_bus_inspector(&ctx_tx);
@@ -148,13 +161,22 @@ impl PlaybackContext {
     }
 
     pub fn seek(&self, position: u64, accurate: bool) {
+        self.level_meter.lock().unwrap().reset();
+
         let flags = gst::SeekFlags::FLUSH | if accurate {
             gst::SeekFlags::ACCURATE
         } else {
             gst::SeekFlags::KEY_UNIT
         };
         self.pipeline
-            .seek_simple(flags, ClockTime::from(position))
+            .seek(
+                self.rate,
+                flags,
+                gst::SeekType::Set,
+                ClockTime::from(position),
+                gst::SeekType::None,
+                ClockTime::none(),
+            )
             .ok()
             .unwrap();
     }
@@ -166,10 +188,11 @@ impl PlaybackContext {
             .lock()
             .expect("PlaybackContext::play: couldn't lock dbl_audio_buffer_mtx")
             .ignore_eos();
+        self.level_meter.lock().unwrap().reset();
 
         self.pipeline
             .seek(
-                1f64,
+                self.rate,
                 gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
                 gst::SeekType::Set,
                 ClockTime::from(start),
@@ -184,6 +207,29 @@ impl PlaybackContext {
         }
     }
 
+    // Solo and mute states applied to the audio channels during playback
+    pub fn set_channel_mix(&self, channel_mix: &ChannelMix) {
+        *self.channel_mix.write().unwrap() = channel_mix.clone();
+    }
+
+    pub fn get_rate(&self) -> f64 {
+        self.rate
+    }
+
+    // The playback rate is applied with the next seek
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+        self.dbl_audio_buffer_mtx
+            .lock()
+            .expect("PlaybackContext::set_rate: couldn't lock dbl_audio_buffer_mtx")
+            .set_rate(rate);
+    }
+
+    // Levels of the audio channels, measured during playback
+    pub fn get_level_meter(&self) -> Arc<Mutex<LevelMeter>> {
+        Arc::clone(&self.level_meter)
+    }
+
     pub fn select_streams(&self, stream_ids: &[Arc<str>]) {
         let stream_id_vec: Vec<&str> = stream_ids.iter().map(|id| id.as_ref()).collect();
         let select_streams_evt = gst::Event::new_select_streams(&stream_id_vec).build();
@@ -283,6 +329,8 @@ impl PlaybackContext {
         // Prepare pad configuration callback
         let pipeline_clone = self.pipeline.clone();
         let dbl_audio_buffer_mtx = Arc::clone(&self.dbl_audio_buffer_mtx);
+        let channel_mix = Arc::clone(&self.channel_mix);
+        let level_meter = Arc::clone(&self.level_meter);
         let video_sink = video_sink.clone();
         let ctx_tx_mtx = Arc::new(Mutex::new(ctx_tx.clone()));
         self.decodebin
@@ -296,6 +344,8 @@ impl PlaybackContext {
                         src_pad,
                         &audio_sink,
                         &dbl_audio_buffer_mtx,
+                        &channel_mix,
+                        &level_meter,
                         &ctx_tx_mtx,
                     );
                 } else if name.starts_with("video_") {
@@ -318,6 +368,8 @@ impl PlaybackContext {
         src_pad: &gst::Pad,
         audio_sink: &gst::Element,
         dbl_audio_buffer_mtx: &Arc<Mutex<DoubleAudioBuffer>>,
+        channel_mix: &Arc<RwLock<ChannelMix>>,
+        level_meter: &Arc<Mutex<LevelMeter>>,
         ctx_tx_mtx: &Arc<Mutex<Sender<ContextMessage>>>,
     ) {
         let playback_queue = gst::ElementFactory::make("queue", "audio_playback_queue").unwrap();
@@ -325,15 +377,61 @@ impl PlaybackContext {
 
         let playback_convert =
             gst::ElementFactory::make("audioconvert", "playback_audioconvert").unwrap();
+        // Mix the channels on float samples, keeping the channel layout of the stream
+        // and convert back to a format suitable for the sink
+        let playback_channel_mix =
+            gst::ElementFactory::make("capsfilter", "playback_channel_mix").unwrap();
+        playback_channel_mix
+            .set_property(
+                "caps",
+                &gst::Caps::new_simple(
+                    "audio/x-raw",
+                    &[("format", &"F32LE"), ("layout", &"interleaved")],
+                ),
+            )
+            .unwrap();
+        let playback_mix_convert =
+            gst::ElementFactory::make("audioconvert", "playback_mix_audioconvert").unwrap();
+        // Preserve the pitch when the playback rate is changed
+        let playback_scaletempo = gst::ElementFactory::make("scaletempo", "playback_scaletempo");
+        if playback_scaletempo.is_none() {
+            warn!("scaletempo not found: the pitch won't be preserved at other playback rates");
+        }
         let playback_resample =
             gst::ElementFactory::make("audioresample", "playback_audioresample").unwrap();
         let playback_sink_pad = playback_queue.get_static_pad("sink").unwrap();
-        let playback_elements = &[
-            &playback_queue,
-            &playback_convert,
+        let mut playback_elements = vec![&playback_queue, &playback_convert, &playback_channel_mix];
+        if let Some(ref playback_scaletempo) = playback_scaletempo {
+            playback_elements.push(playback_scaletempo);
+        }
+        playback_elements.extend_from_slice(&[
+            &playback_mix_convert,
             &playback_resample,
             audio_sink,
-        ];
+        ]);
+
+        let channel_mix = Arc::clone(channel_mix);
+        let level_meter = Arc::clone(level_meter);
+        let channel_mix_src_pad = playback_channel_mix.get_static_pad("src").unwrap();
+        channel_mix_src_pad.add_probe(gst::PadProbeType::BUFFER, move |pad, probe_info| {
+            if let Some(gst::PadProbeData::Buffer(ref mut buffer)) = probe_info.data {
+                let audio_info = pad
+                    .get_current_caps()
+                    .and_then(|caps| gst_audio::AudioInfo::from_caps(&caps));
+                if let Some(audio_info) = audio_info {
+                    {
+                        let channel_mix = channel_mix.read().unwrap();
+                        if !channel_mix.is_passthrough() {
+                            channel_mix.apply(buffer, &audio_info);
+                        }
+                    }
+
+                    // Measure the levels of the channels actually heard
+                    level_meter.lock().unwrap().push_buffer(buffer, &audio_info);
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
 
         let waveform_queue = gst::ElementFactory::make("queue2", "waveform_queue").unwrap();
         PlaybackContext::setup_queue(&waveform_queue);
@@ -345,11 +443,11 @@ impl PlaybackContext {
             let waveform_elements = &[&waveform_queue, &waveform_sink];
             let tee = gst::ElementFactory::make("tee", "audio_tee").unwrap();
             let mut elements = vec![&tee];
-            elements.extend_from_slice(playback_elements);
+            elements.extend_from_slice(&playback_elements);
             elements.extend_from_slice(waveform_elements);
             pipeline.add_many(elements.as_slice()).unwrap();
 
-            gst::Element::link_many(playback_elements).unwrap();
+            gst::Element::link_many(&playback_elements).unwrap();
             gst::Element::link_many(waveform_elements).unwrap();
 
             let tee_sink = tee.get_static_pad("sink").unwrap();
@@ -0,0 +1,237 @@
+use gettextrs::gettext;
+
+use gstreamer as gst;
+use gstreamer::ClockTime;
+use gstreamer::prelude::*;
+
+use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::time::Duration;
+
+const PREROLL_TIMEOUT: u64 = 10; // 10 s
+// Limits the decoded buffers pending rendering
+const DATA_CHANNEL_SIZE: usize = 64;
+const BUS_CHECK_PERIOD: u64 = 100; // 100 ms
+
+// Data received by the audio sink
+pub enum DecodedData {
+    Caps(gst::Caps),
+    Segment(gst::Segment),
+    Buffer(gst::Buffer),
+    Eos,
+}
+
+// Decodes the first audio stream of a media without a main loop
+// so that it can be rendered offscreen
+pub struct RenderContext {
+    pipeline: gst::Pipeline,
+    is_forwarding: Arc<AtomicBool>,
+    data_rx: Option<Receiver<DecodedData>>,
+
+    pub duration: u64,
+    pub toc: Option<gst::Toc>,
+}
+
+impl RenderContext {
+    pub fn new(input_path: &Path) -> Result<RenderContext, String> {
+        info!(
+            "{}",
+            gettext("Decoding {}...").replacen("{}", input_path.to_str().unwrap(), 1)
+        );
+
+        let (data_tx, data_rx) = sync_channel(DATA_CHANNEL_SIZE);
+        let mut this = RenderContext {
+            pipeline: gst::Pipeline::new("render_pipeline"),
+            is_forwarding: Arc::new(AtomicBool::new(false)),
+            data_rx: Some(data_rx),
+            duration: 0,
+            toc: None,
+        };
+
+        this.build_pipeline(input_path, data_tx);
+        this.preroll()?;
+
+        Ok(this)
+    }
+
+    // Starts decoding the range [start, end] (up to the end of the stream if `end` is `None`)
+    pub fn start(&mut self, start: u64, end: Option<u64>) -> Result<(), String> {
+        // Data received from now on result from the seek
+        self.is_forwarding.store(true, Ordering::SeqCst);
+
+        let (end_type, end) = match end {
+            Some(end) => (gst::SeekType::Set, ClockTime::from(end)),
+            None => (gst::SeekType::None, ClockTime::none()),
+        };
+        self.pipeline
+            .seek(
+                1f64,
+                gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
+                gst::SeekType::Set,
+                ClockTime::from(start),
+                end_type,
+                end,
+            )
+            .map_err(|_| gettext("Failed to seek in the media"))?;
+
+        match self.pipeline.set_state(gst::State::Playing) {
+            gst::StateChangeReturn::Failure => Err(gettext("Failed to start decoding the media")),
+            _ => Ok(()),
+        }
+    }
+
+    // Blocks until the audio sink receives new data
+    pub fn pop_data(&mut self) -> Result<DecodedData, String> {
+        loop {
+            let data = self.data_rx
+                .as_ref()
+                .unwrap()
+                .recv_timeout(Duration::from_millis(BUS_CHECK_PERIOD));
+            match data {
+                Ok(data) => return Ok(data),
+                Err(RecvTimeoutError::Timeout) => self.check_errors()?,
+                Err(RecvTimeoutError::Disconnected) => return Ok(DecodedData::Eos),
+            }
+        }
+    }
+
+    fn preroll(&mut self) -> Result<(), String> {
+        if self.pipeline.set_state(gst::State::Paused) == gst::StateChangeReturn::Failure {
+            self.check_errors()?;
+            return Err(gettext("Could not set media in Paused mode"));
+        }
+
+        let (state_change, _, _) = self.pipeline
+            .get_state(ClockTime::from_seconds(PREROLL_TIMEOUT));
+        self.check_errors()?;
+        if state_change == gst::StateChangeReturn::Failure {
+            return Err(gettext("Could not set media in Paused mode"));
+        }
+
+        if self.pipeline.get_by_name("audioconvert").is_none() {
+            return Err(gettext("No audio stream found"));
+        }
+
+        let mut duration_query = gst::Query::new_duration(gst::Format::Time);
+        if self.pipeline.query(&mut duration_query) {
+            self.duration = duration_query.get_result().get_value() as u64;
+        }
+
+        Ok(())
+    }
+
+    // Handles the messages posted on the bus, returns the first error if any
+    fn check_errors(&mut self) -> Result<(), String> {
+        let bus = self.pipeline.get_bus().unwrap();
+        while let Some(msg) = bus.pop() {
+            match msg.view() {
+                gst::MessageView::Error(err) => {
+                    return Err(err.get_error().description().to_owned());
+                }
+                gst::MessageView::Toc(msg_toc) => {
+                    let (toc, _updated) = msg_toc.get_toc();
+                    if toc.get_scope() == gst::TocScope::Global {
+                        self.toc = Some(toc);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn build_pipeline(&mut self, input_path: &Path, data_tx: SyncSender<DecodedData>) {
+        let filesrc = gst::ElementFactory::make("filesrc", None).unwrap();
+        filesrc
+            .set_property("location", &gst::Value::from(input_path.to_str().unwrap()))
+            .unwrap();
+        let decodebin = gst::ElementFactory::make("decodebin", None).unwrap();
+
+        self.pipeline.add_many(&[&filesrc, &decodebin]).unwrap();
+
+        filesrc.link(&decodebin).unwrap();
+        decodebin.sync_state_with_parent().unwrap();
+
+        let pipeline_cb = self.pipeline.clone();
+        let is_forwarding = Arc::clone(&self.is_forwarding);
+        decodebin.connect_pad_added(move |_element, pad| {
+            let caps = pad.get_current_caps().unwrap();
+            let name = caps.get_structure(0).unwrap().get_name().to_owned();
+
+            let queue = gst::ElementFactory::make("queue", None).unwrap();
+            let fakesink = gst::ElementFactory::make("fakesink", None).unwrap();
+            pipeline_cb.add_many(&[&queue, &fakesink]).unwrap();
+            let queue_sink_pad = queue.get_static_pad("sink").unwrap();
+            assert_eq!(pad.link(&queue_sink_pad), gst::PadLinkReturn::Ok);
+
+            // Only the first audio stream is rendered
+            if name.starts_with("audio/") && pipeline_cb.get_by_name("audioconvert").is_none() {
+                let audio_conv = gst::ElementFactory::make("audioconvert", "audioconvert").unwrap();
+                let capsfilter = gst::ElementFactory::make("capsfilter", None).unwrap();
+                capsfilter
+                    .set_property(
+                        "caps",
+                        &gst::Caps::new_simple(
+                            "audio/x-raw",
+                            &[("format", &"S16LE"), ("layout", &"interleaved")],
+                        ),
+                    )
+                    .unwrap();
+                pipeline_cb.add_many(&[&audio_conv, &capsfilter]).unwrap();
+                gst::Element::link_many(&[&queue, &audio_conv, &capsfilter, &fakesink]).unwrap();
+                audio_conv.sync_state_with_parent().unwrap();
+                capsfilter.sync_state_with_parent().unwrap();
+
+                let is_forwarding = Arc::clone(&is_forwarding);
+                let data_tx = data_tx.clone();
+                let fakesink_sink_pad = fakesink.get_static_pad("sink").unwrap();
+                let pad_probe_filter = gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_DOWNSTREAM;
+                fakesink_sink_pad.add_probe(pad_probe_filter, move |_pad, probe_info| {
+                    // Caps are not sent again after the seek so they are always forwarded
+                    // while the data received during preroll are ignored
+                    let is_forwarding = is_forwarding.load(Ordering::SeqCst);
+                    let data = match probe_info.data {
+                        Some(gst::PadProbeData::Buffer(ref buffer)) if is_forwarding => {
+                            Some(DecodedData::Buffer(buffer.clone()))
+                        }
+                        Some(gst::PadProbeData::Event(ref event)) => match event.view() {
+                            gst::EventView::Caps(caps_event) => {
+                                Some(DecodedData::Caps(caps_event.get_caps().to_owned()))
+                            }
+                            _ if !is_forwarding => None,
+                            gst::EventView::Segment(segment_event) => {
+                                Some(DecodedData::Segment(segment_event.get_segment().clone()))
+                            }
+                            gst::EventView::Eos(_) => Some(DecodedData::Eos),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    if let Some(data) = data {
+                        // The receiver is dropped when the rendering is interrupted
+                        let _ = data_tx.send(data);
+                    }
+
+                    gst::PadProbeReturn::Ok
+                });
+            } else {
+                queue.link(&fakesink).unwrap();
+            }
+
+            queue.sync_state_with_parent().unwrap();
+            fakesink.sync_state_with_parent().unwrap();
+        });
+    }
+}
+
+impl Drop for RenderContext {
+    fn drop(&mut self) {
+        // Unblock the streaming thread before stopping the pipeline
+        self.data_rx.take();
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
@@ -12,6 +12,8 @@ pub struct SampleExtractionState {
     audio_ref: Option<gst::Element>,
     pub basetime: Option<(u64, u64)>, // (base_time, base_frame_time)
     pub last_pos: u64,
+    // Playback rate used to interpolate the position between frames
+    pub rate: f64,
 }
 
 impl SampleExtractionState {
@@ -23,6 +25,7 @@ impl SampleExtractionState {
             audio_ref: None,
             basetime: None,
             last_pos: 0,
+            rate: 1f64,
         }
     }
 
@@ -34,6 +37,7 @@ impl SampleExtractionState {
         self.audio_ref = None;
         self.basetime = None;
         self.last_pos = 0;
+        // The rate is kept as it is controlled by the playback
     }
 }
 
@@ -59,6 +63,12 @@ pub trait SampleExtractor: Send {
         self.get_extraction_state_mut().basetime = None;
     }
 
+    fn set_rate(&mut self, rate: f64) {
+        let state = self.get_extraction_state_mut();
+        state.rate = rate;
+        state.basetime = None;
+    }
+
     fn new_segment(&mut self) {
         self.get_extraction_state_mut().basetime = None;
     }
@@ -85,7 +95,9 @@ pub trait SampleExtractor: Send {
         let position = match state.state {
             gst::State::Playing => {
                 if let Some(&(base_time, base_frame_time)) = state.basetime.as_ref() {
-                    (frame_time - base_frame_time) * 1_000 + base_time
+                    // frame_time is in µs and the stream advances `rate` times faster
+                    ((frame_time - base_frame_time) as f64 * 1_000f64 * state.rate) as u64
+                        + base_time
                 } else {
                     let mut query = gst::Query::new_position(gst::Format::Time);
                     if state.audio_ref.as_ref().unwrap().query(&mut query) {
@@ -122,3 +134,76 @@ pub trait SampleExtractor: Send {
     // and position.
     fn refresh(&mut self, audio_buffer: &AudioBuffer);
 }
+
+#[cfg(test)]
+mod tests {
+    use gstreamer as gst;
+
+    use std::any::Any;
+
+    use media::{AudioBuffer, AudioChannel};
+
+    use super::{SampleExtractionState, SampleExtractor};
+
+    struct TestExtractor {
+        state: SampleExtractionState,
+    }
+
+    impl SampleExtractor for TestExtractor {
+        fn as_mut_any(&mut self) -> &mut Any {
+            self
+        }
+        fn as_any(&self) -> &Any {
+            self
+        }
+        fn get_extraction_state(&self) -> &SampleExtractionState {
+            &self.state
+        }
+        fn get_extraction_state_mut(&mut self) -> &mut SampleExtractionState {
+            &mut self.state
+        }
+
+        fn cleanup(&mut self) {
+            self.state.cleanup();
+        }
+
+        fn set_channels(&mut self, _channels: &[AudioChannel]) {}
+        fn set_sample_duration(&mut self, per_sample: u64, per_1000_samples: f64) {
+            self.state.sample_duration = per_sample;
+            self.state.duration_per_1000_samples = per_1000_samples;
+        }
+        fn get_lower(&self) -> usize {
+            0
+        }
+        fn get_requested_sample_window(&self) -> Option<usize> {
+            None
+        }
+        fn switch_to_paused(&mut self) {}
+        fn update_concrete_state(&mut self, _other: &mut SampleExtractor) {}
+        fn extract_samples(&mut self, _audio_buffer: &AudioBuffer) {}
+        fn refresh(&mut self, _audio_buffer: &AudioBuffer) {}
+    }
+
+    #[test]
+    fn current_sample_with_rate() {
+        let mut extractor = TestExtractor {
+            state: SampleExtractionState::new(),
+        };
+        extractor.set_sample_duration(1_000, 1_000_000f64);
+        extractor.set_state(gst::State::Playing);
+        extractor.set_rate(2f64);
+        // (base_time in ns, base_frame_time in µs)
+        extractor.get_extraction_state_mut().basetime = Some((1_000_000, 500));
+
+        // 1ms later, the stream advanced twice as much
+        assert_eq!(extractor.get_current_sample(1_500), (3_000_000, 3_000));
+
+        extractor.set_rate(0.5f64);
+        extractor.get_extraction_state_mut().basetime = Some((3_000_000, 1_500));
+        assert_eq!(extractor.get_current_sample(3_500), (4_000_000, 4_000));
+
+        // The rate is still applied after the extractor is reused
+        extractor.cleanup();
+        assert_eq!(extractor.get_extraction_state().rate, 0.5f64);
+    }
+}
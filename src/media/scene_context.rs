@@ -0,0 +1,115 @@
+use gettextrs::gettext;
+
+use gstreamer as gst;
+
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use super::{AnalysisPipeline, ContextMessage, FramesHandler, SceneCandidate, SceneDetector};
+
+// Frames are analyzed at a reduced resolution
+const ANALYSIS_WIDTH: i32 = 160;
+
+struct FramesDispatcher {
+    detector: SceneDetector,
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+impl FramesHandler for FramesDispatcher {
+    fn set_caps(&mut self, caps: &gst::Caps) {
+        let structure = caps.get_structure(0).unwrap();
+        match (structure.get::<i32>("width"), structure.get::<i32>("height")) {
+            (Some(width), Some(height)) if width > 0 && height > 0 => {
+                self.width = width as usize;
+                self.height = height as usize;
+                // GStreamer aligns the rows of packed RGB frames on 4 bytes
+                self.stride = (self.width * 3 + 3) & !3;
+            }
+            _ => warn!("SceneContext: unexpected caps {:?}", caps),
+        }
+    }
+
+    fn push_buffer(&mut self, buffer: &gst::Buffer) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+
+        let pts = match buffer.get_pts().nseconds() {
+            Some(pts) => pts,
+            None => return,
+        };
+        let map = match buffer.map_readable() {
+            Some(map) => map,
+            None => return,
+        };
+
+        self.detector
+            .push_frame(pts, map.as_slice(), self.width, self.height, self.stride);
+    }
+}
+
+// Decodes the video stream in order to find the black frames and the cuts
+pub struct SceneContext {
+    pipeline: AnalysisPipeline,
+    dispatcher: Arc<Mutex<FramesDispatcher>>,
+}
+
+impl SceneContext {
+    // See `SceneDetector` for the detection parameters
+    pub fn new(
+        input_path: &Path,
+        stream_id: &str,
+        detector: SceneDetector,
+        ctx_tx: Sender<ContextMessage>,
+    ) -> Result<SceneContext, String> {
+        info!(
+            "{}",
+            gettext("Detecting scene changes in {}...")
+                .replacen("{}", input_path.to_str().unwrap(), 1)
+        );
+
+        let dispatcher = Arc::new(Mutex::new(FramesDispatcher {
+            detector,
+            width: 0,
+            height: 0,
+            stride: 0,
+        }));
+        // The height is chosen by videoscale so as to keep the aspect ratio
+        let pipeline = AnalysisPipeline::new(
+            "scene_pipeline",
+            input_path,
+            stream_id,
+            gst::Caps::new_simple(
+                "video/x-raw",
+                &[
+                    ("format", &"RGB"),
+                    ("width", &ANALYSIS_WIDTH),
+                    ("pixel-aspect-ratio", &gst::Fraction::new(1, 1)),
+                ],
+            ),
+            Arc::clone(&dispatcher),
+            gettext("Failed to start detecting scene changes."),
+            ctx_tx,
+        )?;
+
+        Ok(SceneContext {
+            pipeline,
+            dispatcher,
+        })
+    }
+
+    pub fn cancel(&mut self) {
+        self.pipeline.cancel();
+    }
+
+    pub fn get_position(&mut self) -> u64 {
+        self.pipeline.get_position()
+    }
+
+    pub fn take_candidates(&self) -> Vec<SceneCandidate> {
+        self.dispatcher.lock().unwrap().detector.take_candidates()
+    }
+}
@@ -0,0 +1,207 @@
+const HISTOGRAM_BINS: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SceneChange {
+    // First frame after a sequence of black frames
+    BlackFrames,
+    // First frame of a shot
+    Cut,
+}
+
+// Packed RGB pixels of the first frame of a scene
+pub struct Thumbnail {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+pub struct SceneCandidate {
+    pub position: u64,
+    pub change: SceneChange,
+    pub thumbnail: Thumbnail,
+}
+
+// Detects the black frames and the hard cuts which could start a chapter
+// Frames are pushed as RGB pixels, preferably at a reduced resolution
+pub struct SceneDetector {
+    black_threshold: f64,
+    cut_threshold: f64,
+    min_interval: u64,
+    has_content: bool,
+    is_in_black: bool,
+    prev_histogram: Option<Vec<f64>>,
+    last_position: Option<u64>,
+    candidates: Vec<SceneCandidate>,
+}
+
+impl SceneDetector {
+    // `black_threshold` is the mean luma under which a frame is black in the range [0.0, 1.0]
+    // `cut_threshold` is the minimum difference between the luma histograms
+    // of two consecutive frames to detect a cut in the range [0.0, 1.0]
+    // `min_interval` is the minimum duration between two candidates in ns
+    pub fn new(black_threshold: f64, cut_threshold: f64, min_interval: u64) -> Self {
+        SceneDetector {
+            black_threshold,
+            cut_threshold,
+            min_interval,
+            has_content: false,
+            is_in_black: false,
+            prev_histogram: None,
+            last_position: None,
+            candidates: Vec::new(),
+        }
+    }
+
+    // `stride` is the number of bytes between the start of two rows in `data`
+    pub fn push_frame(
+        &mut self,
+        timestamp: u64,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+    ) {
+        if width == 0 || height == 0 || data.len() < (height - 1) * stride + width * 3 {
+            return;
+        }
+
+        let mut histogram = vec![0f64; HISTOGRAM_BINS];
+        let mut luma_sum = 0f64;
+        for row in 0..height {
+            let row_data = &data[row * stride..row * stride + width * 3];
+            for pixel in row_data.chunks(3) {
+                let luma = (0.299 * f64::from(pixel[0]) + 0.587 * f64::from(pixel[1])
+                    + 0.114 * f64::from(pixel[2])) / 255f64;
+                luma_sum += luma;
+                let bin = ((luma * HISTOGRAM_BINS as f64) as usize).min(HISTOGRAM_BINS - 1);
+                histogram[bin] += 1f64;
+            }
+        }
+        let pixel_count = (width * height) as f64;
+        for value in &mut histogram {
+            *value /= pixel_count;
+        }
+
+        if luma_sum / pixel_count <= self.black_threshold {
+            self.is_in_black = true;
+        } else {
+            let change = if self.is_in_black {
+                // Leading black frames don't separate two chapters
+                if self.has_content {
+                    Some(SceneChange::BlackFrames)
+                } else {
+                    None
+                }
+            } else {
+                self.prev_histogram.as_ref().and_then(|prev_histogram| {
+                    let difference = prev_histogram
+                        .iter()
+                        .zip(histogram.iter())
+                        .map(|(prev, cur)| (prev - cur).abs())
+                        .sum::<f64>() / 2f64;
+                    if difference >= self.cut_threshold {
+                        Some(SceneChange::Cut)
+                    } else {
+                        None
+                    }
+                })
+            };
+
+            if let Some(change) = change {
+                let is_far_enough = self.last_position
+                    .map_or(true, |last_position| timestamp >= last_position + self.min_interval);
+                if is_far_enough {
+                    let mut thumbnail = Vec::with_capacity(width * height * 3);
+                    for row in 0..height {
+                        thumbnail.extend_from_slice(&data[row * stride..row * stride + width * 3]);
+                    }
+
+                    self.candidates.push(SceneCandidate {
+                        position: timestamp,
+                        change,
+                        thumbnail: Thumbnail {
+                            width,
+                            height,
+                            data: thumbnail,
+                        },
+                    });
+                    self.last_position = Some(timestamp);
+                }
+            }
+
+            self.has_content = true;
+            self.is_in_black = false;
+        }
+
+        self.prev_histogram = Some(histogram);
+    }
+
+    pub fn take_candidates(&mut self) -> Vec<SceneCandidate> {
+        self.candidates.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SceneChange, SceneDetector};
+
+    const FRAME_DURATION: u64 = 40_000_000; // 25 fps
+    const WIDTH: usize = 4;
+    const HEIGHT: usize = 2;
+    const STRIDE: usize = 12;
+
+    // Pushes `count` frames, the left half with `left` level, the right half with `right` level
+    fn push_frames(
+        detector: &mut SceneDetector,
+        timestamp: &mut u64,
+        left: u8,
+        right: u8,
+        count: u64,
+    ) {
+        let mut frame = Vec::with_capacity(HEIGHT * STRIDE);
+        for _ in 0..HEIGHT {
+            for column in 0..WIDTH {
+                let level = if column < WIDTH / 2 { left } else { right };
+                frame.extend_from_slice(&[level, level, level]);
+            }
+        }
+
+        for _ in 0..count {
+            detector.push_frame(*timestamp, &frame, WIDTH, HEIGHT, STRIDE);
+            *timestamp += FRAME_DURATION;
+        }
+    }
+
+    #[test]
+    fn black_frames_and_cuts() {
+        let mut detector = SceneDetector::new(0.05, 0.4, 10 * FRAME_DURATION);
+        let mut timestamp = 0;
+
+        // Leading black frames
+        push_frames(&mut detector, &mut timestamp, 0, 0, 10);
+        push_frames(&mut detector, &mut timestamp, 100, 200, 20);
+        // Black frames
+        push_frames(&mut detector, &mut timestamp, 0, 5, 5);
+        push_frames(&mut detector, &mut timestamp, 100, 200, 20);
+        // Cut
+        push_frames(&mut detector, &mut timestamp, 220, 30, 5);
+        // Cut too close from previous one
+        push_frames(&mut detector, &mut timestamp, 50, 150, 5);
+        // Slight change
+        push_frames(&mut detector, &mut timestamp, 51, 151, 20);
+
+        let candidates = detector.take_candidates();
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|candidate| (candidate.position / FRAME_DURATION, candidate.change))
+                .collect::<Vec<(u64, SceneChange)>>(),
+            vec![(35, SceneChange::BlackFrames), (55, SceneChange::Cut)]
+        );
+
+        let thumbnail = &candidates[1].thumbnail;
+        assert_eq!((thumbnail.width, thumbnail.height), (WIDTH, HEIGHT));
+        assert_eq!(&thumbnail.data[..3], &[220, 220, 220]);
+        assert_eq!(&thumbnail.data[9..12], &[30, 30, 30]);
+    }
+}
@@ -0,0 +1,61 @@
+use gettextrs::gettext;
+
+use std::path::Path;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use super::{AnalysisPipeline, AudioFramesDecoder, AudioFramesHandler, ContextMessage,
+            SilenceDetector};
+
+impl AudioFramesHandler for SilenceDetector {
+    fn push_frame(&mut self, timestamp: u64, frame: &[f64]) {
+        SilenceDetector::push_frame(self, timestamp, frame);
+    }
+}
+
+// Decodes the audio stream in order to find the gaps between tracks
+pub struct SilenceContext {
+    pipeline: AnalysisPipeline,
+    decoder: Arc<Mutex<AudioFramesDecoder<SilenceDetector>>>,
+}
+
+impl SilenceContext {
+    // See `SilenceDetector` for the detection parameters
+    pub fn new(
+        input_path: &Path,
+        stream_id: &str,
+        detector: SilenceDetector,
+        ctx_tx: Sender<ContextMessage>,
+    ) -> Result<SilenceContext, String> {
+        info!(
+            "{}",
+            gettext("Detecting silences in {}...")
+                .replacen("{}", input_path.to_str().unwrap(), 1)
+        );
+
+        let decoder = Arc::new(Mutex::new(AudioFramesDecoder::new(detector)));
+        let pipeline = AnalysisPipeline::new(
+            "silence_pipeline",
+            input_path,
+            stream_id,
+            AudioFramesDecoder::<SilenceDetector>::get_caps(),
+            Arc::clone(&decoder),
+            gettext("Failed to start detecting silences."),
+            ctx_tx,
+        )?;
+
+        Ok(SilenceContext { pipeline, decoder })
+    }
+
+    pub fn cancel(&mut self) {
+        self.pipeline.cancel();
+    }
+
+    pub fn get_boundary_candidates(&self) -> Vec<u64> {
+        self.decoder
+            .lock()
+            .unwrap()
+            .handler
+            .get_boundary_candidates()
+    }
+}
@@ -0,0 +1,196 @@
+// Duration of the windows on which the RMS level is computed
+const WINDOW_DURATION: u64 = 10_000_000; // 10 ms
+
+// Detects the silent regions of a stream which could separate two tracks
+// Samples are pushed as interleaved frames of `f64` in the range [-1.0, 1.0]
+// and the level is the RMS computed on consecutive windows of `WINDOW_DURATION`
+pub struct SilenceDetector {
+    threshold: f64,
+    min_duration: u64,
+    is_per_channel: bool,
+    first_timestamp: Option<u64>,
+    window_start: Option<u64>,
+    window_squares: Vec<f64>, // sum of the squared samples for each channel
+    window_frames: usize,
+    silence_start: Option<u64>,
+    gaps: Vec<(u64, u64)>,
+}
+
+impl SilenceDetector {
+    // `threshold` is the level under which a window is silent in dBFS
+    // `min_duration` is the minimum duration of a gap in ns
+    // When `is_per_channel` is true, a window is silent if all its channels are silent,
+    // otherwise the power of the channels is averaged before the level is compared
+    // with the threshold
+    pub fn new(threshold: f64, min_duration: u64, is_per_channel: bool) -> Self {
+        SilenceDetector {
+            threshold: 10f64.powf(threshold / 20f64),
+            min_duration,
+            is_per_channel,
+            first_timestamp: None,
+            window_start: None,
+            window_squares: Vec::new(),
+            window_frames: 0,
+            silence_start: None,
+            gaps: Vec::new(),
+        }
+    }
+
+    // Compares the RMS level of the current window with the threshold
+    fn is_window_silent(&self) -> bool {
+        if self.window_frames == 0 || self.window_squares.is_empty() {
+            return true;
+        }
+
+        // Compare the mean squares in order to avoid computing the square roots
+        let max_squares = self.threshold * self.threshold * self.window_frames as f64;
+        if self.is_per_channel {
+            self.window_squares
+                .iter()
+                .all(|squares| *squares <= max_squares)
+        } else {
+            self.window_squares.iter().sum::<f64>() / self.window_squares.len() as f64
+                <= max_squares
+        }
+    }
+
+    pub fn push_frame(&mut self, timestamp: u64, frame: &[f64]) {
+        if self.first_timestamp.is_none() {
+            self.first_timestamp = Some(timestamp);
+        }
+
+        match self.window_start {
+            Some(window_start) if timestamp < window_start + WINDOW_DURATION => (),
+            Some(window_start) => {
+                self.close_window(window_start);
+                self.window_start = Some(timestamp);
+            }
+            None => self.window_start = Some(timestamp),
+        }
+
+        if self.window_squares.len() != frame.len() {
+            self.window_squares = vec![0f64; frame.len()];
+        }
+        for (squares, value) in self.window_squares.iter_mut().zip(frame) {
+            *squares += value * value;
+        }
+        self.window_frames += 1;
+    }
+
+    fn close_window(&mut self, window_start: u64) {
+        if self.is_window_silent() {
+            if self.silence_start.is_none() {
+                self.silence_start = Some(window_start);
+            }
+        } else if let Some(silence_start) = self.silence_start.take() {
+            // Leading silence is not a gap between two tracks
+            if Some(silence_start) != self.first_timestamp
+                && window_start - silence_start >= self.min_duration
+            {
+                self.gaps.push((silence_start, window_start));
+            }
+        }
+
+        for squares in &mut self.window_squares {
+            *squares = 0f64;
+        }
+        self.window_frames = 0;
+    }
+
+    // Returns the silent regions found between audible regions so far
+    // Note: a trailing silence is not a gap between two tracks
+    #[cfg(test)]
+    pub fn get_gaps(&self) -> &[(u64, u64)] {
+        &self.gaps
+    }
+
+    // Returns the midpoints of the gaps, where chapters boundaries could be defined
+    pub fn get_boundary_candidates(&self) -> Vec<u64> {
+        self.gaps
+            .iter()
+            .map(|&(start, end)| start + (end - start) / 2)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SilenceDetector;
+
+    const FRAME_DURATION: u64 = 1_000_000;
+
+    // Pushes `count` frames with the given `levels` for each channel
+    fn push_frames(detector: &mut SilenceDetector, timestamp: &mut u64, levels: &[f64], count: u64) {
+        for index in 0..count {
+            // Alternate the sign of the samples as for a waveform
+            let sign = if index % 2 == 0 { 1f64 } else { -1f64 };
+            let frame = levels.iter().map(|level| sign * level).collect::<Vec<f64>>();
+            detector.push_frame(*timestamp, &frame);
+            *timestamp += FRAME_DURATION;
+        }
+    }
+
+    #[test]
+    fn gaps() {
+        let mut detector = SilenceDetector::new(-40f64, 20 * FRAME_DURATION, true);
+        let mut timestamp = 0;
+
+        // Leading silence
+        push_frames(&mut detector, &mut timestamp, &[0f64, 0f64], 20);
+        push_frames(&mut detector, &mut timestamp, &[0.5, 0.5], 10);
+        // Too short
+        push_frames(&mut detector, &mut timestamp, &[0.001, 0f64], 10);
+        push_frames(&mut detector, &mut timestamp, &[0.5, 0.5], 10);
+        // Gap
+        push_frames(&mut detector, &mut timestamp, &[0.001, 0.001], 30);
+        push_frames(&mut detector, &mut timestamp, &[0f64, 0.5], 10);
+        // Trailing silence
+        push_frames(&mut detector, &mut timestamp, &[0f64, 0f64], 20);
+
+        assert_eq!(
+            detector.get_gaps(),
+            &[(50 * FRAME_DURATION, 80 * FRAME_DURATION)]
+        );
+        assert_eq!(detector.get_boundary_candidates(), vec![65 * FRAME_DURATION]);
+    }
+
+    #[test]
+    fn isolated_peaks() {
+        // A peak doesn't interrupt a silence when the window's level is low enough
+        let mut detector = SilenceDetector::new(-40f64, 20 * FRAME_DURATION, true);
+        let mut timestamp = 0;
+
+        push_frames(&mut detector, &mut timestamp, &[0.5, 0.5], 10);
+        push_frames(&mut detector, &mut timestamp, &[0f64, 0f64], 15);
+        push_frames(&mut detector, &mut timestamp, &[0.02, 0.02], 1);
+        push_frames(&mut detector, &mut timestamp, &[0f64, 0f64], 14);
+        push_frames(&mut detector, &mut timestamp, &[0.5, 0.5], 20);
+
+        assert_eq!(
+            detector.get_gaps(),
+            &[(10 * FRAME_DURATION, 40 * FRAME_DURATION)]
+        );
+    }
+
+    #[test]
+    fn summed_channels() {
+        let mut per_channel = SilenceDetector::new(-40f64, 10 * FRAME_DURATION, true);
+        let mut summed = SilenceDetector::new(-40f64, 10 * FRAME_DURATION, false);
+        for detector in &mut [&mut per_channel, &mut summed] {
+            let mut timestamp = 0;
+            push_frames(detector, &mut timestamp, &[0.5, 0.5], 20);
+            // Channels in opposite phase are not silent
+            push_frames(detector, &mut timestamp, &[0.5, -0.5], 20);
+            push_frames(detector, &mut timestamp, &[0.5, 0.5], 20);
+            // A quiet channel is averaged with the silent one
+            push_frames(detector, &mut timestamp, &[0.001, 0.013], 20);
+            push_frames(detector, &mut timestamp, &[0.5, 0.5], 20);
+        }
+
+        assert!(per_channel.get_gaps().is_empty());
+        assert_eq!(
+            summed.get_gaps(),
+            &[(60 * FRAME_DURATION, 80 * FRAME_DURATION)]
+        );
+    }
+}
@@ -3,29 +3,116 @@ use gettextrs::gettext;
 use gstreamer as gst;
 use gstreamer::ClockTime;
 use gstreamer::prelude::*;
+use gstreamer_audio as gst_audio;
 
 use glib;
 
+use std::cmp;
+use std::collections::HashSet;
 use std::error::Error;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
 
-use metadata::Format;
+use metadata::{Format, SplitOptions};
 
-use super::ContextMessage;
+use super::{with_opus_tags, ContextMessage, VolumeEnvelope};
+
+// Tolerance when checking the duration of an existing output
+const DURATION_TOLERANCE: u64 = 1_000_000_000; // 1s
+// Duration decoded at the end of an existing output to check its integrity
+const TAIL_CHECK_DURATION: u64 = 2_000_000_000; // 2s
+const CHECK_TIMEOUT: u64 = 5; // 5 s
+
+// AAC encoders by order of preference
+const AAC_ENCODERS: [&str; 3] = ["fdkaacenc", "avenc_aac", "voaacenc"];
+
+// Progress of the chapter extraction in passthrough mode
+enum CutState {
+    Preroll,
+    Seeking,
+    Cutting,
+}
+
+// Actual boundaries of the frames kept in passthrough mode
+#[derive(Default)]
+struct CutBounds {
+    first: Option<u64>,
+    last: Option<u64>,
+}
 
 pub struct SplitterContext {
     pipeline: gst::Pipeline,
     position_query: gst::query::Position<gst::Query>,
 
+    output_path: PathBuf,
     format: Format,
+    is_passthrough: bool,
     chapter: gst::TocEntry,
+    cut_bounds: Arc<Mutex<CutBounds>>,
+    bus_watch_src: Option<glib::SourceId>,
 }
 
 impl SplitterContext {
+    fn get_aac_encoder_name() -> Option<&'static str> {
+        AAC_ENCODERS
+            .iter()
+            .find(|name| gst::ElementFactory::find(name).is_some())
+            .cloned()
+    }
+
+    fn check_mp4mux() -> Result<(), String> {
+        gst::ElementFactory::make("mp4mux", None).map_or(
+            Err(gettext(
+                "Missing `mp4mux`\ncheck your gst-plugins-good install",
+            )),
+            |_| Ok(()),
+        )
+    }
+
+    fn check_matroskamux() -> Result<(), String> {
+        gst::ElementFactory::make("matroskamux", None).map_or(
+            Err(gettext(
+                "Missing `matroskamux`\ncheck your gst-plugins-good install",
+            )),
+            |_| Ok(()),
+        )
+    }
+
+    fn check_oggmux() -> Result<(), String> {
+        gst::ElementFactory::make("oggmux", None).map_or(
+            Err(gettext(
+                "Missing `oggmux`\ncheck your gst-plugins-good install",
+            )),
+            |_| Ok(()),
+        )
+    }
+
     pub fn check_requirements(format: Format) -> Result<(), String> {
         match format {
+            Format::AAC => Self::get_aac_encoder_name()
+                .map_or(
+                    Err(gettext(
+                        "Missing an AAC encoder\ncheck your gst-plugins-bad or gst-libav install",
+                    )),
+                    |_| Ok(()),
+                )
+                .and_then(|_| Self::check_mp4mux()),
+            Format::AIFF => gst::ElementFactory::make("aiffmux", None).map_or(
+                Err(gettext(
+                    "Missing `aiffmux`\ncheck your gst-plugins-bad install",
+                )),
+                |_| Ok(()),
+            ),
+            Format::ALAC => gst::ElementFactory::make("avenc_alac", None)
+                .map_or(
+                    Err(gettext(
+                        "Missing `avenc_alac`\ncheck your gst-libav install",
+                    )),
+                    |_| Ok(()),
+                )
+                .and_then(|_| Self::check_mp4mux()),
             Format::Flac => gst::ElementFactory::make("flacenc", None).map_or(
                 Err(gettext(
                     "Missing `flacenc`\ncheck your gst-plugins-good install",
@@ -38,6 +125,32 @@ impl SplitterContext {
                 )),
                 |_| Ok(()),
             ),
+            Format::WavPack => gst::ElementFactory::make("wavpackenc", None)
+                .map_or(
+                    Err(gettext(
+                        "Missing `wavpackenc`\ncheck your gst-plugins-good install",
+                    )),
+                    |_| Ok(()),
+                )
+                .and_then(|_| {
+                    gst::ElementFactory::make("apev2mux", None).map_or(
+                        Err(gettext(
+                            "Missing `apev2mux`\ncheck your gst-plugins-good install",
+                        )),
+                        |_| Ok(()),
+                    )
+                }),
+            // Note: the video encoder is only required when a video stream is exported
+            // (see `check_video_requirements`)
+            Format::Matroska => Self::check_matroskamux()
+                .and_then(|_| {
+                    gst::ElementFactory::make("vorbisenc", None).map_or(
+                        Err(gettext(
+                            "Missing `vorbisenc`\ncheck your gst-plugins-base install",
+                        )),
+                        |_| Ok(()),
+                    )
+                }),
             Format::Opus => gst::ElementFactory::make("opusenc", None)
                 .map_or(
                     Err(gettext(
@@ -90,28 +203,118 @@ impl SplitterContext {
         }
     }
 
+    // Returns false if the split files for `format` can't hold the chapters tags
+    pub fn are_tags_supported(format: Format) -> bool {
+        match format {
+            Format::AIFF => gst::ElementFactory::make("aiffmux", None)
+                .map_or(false, |aiffmux| aiffmux.dynamic_cast::<gst::TagSetter>().is_ok()),
+            _ => true,
+        }
+    }
+
+    // Video streams are re-encoded when splitting to Matroska
+    pub fn check_video_requirements() -> Result<(), String> {
+        gst::ElementFactory::make("x264enc", None).map_or(
+            Err(gettext(
+                "Missing `x264enc`\ncheck your gst-plugins-ugly install",
+            )),
+            |_| Ok(()),
+        )
+    }
+
+    // Returns the format which can hold the stream described by `caps`
+    // without re-encoding it, if any
+    pub fn get_passthrough_format(caps: &gst::Caps) -> Option<Format> {
+        let structure = caps.get_structure(0)?;
+        match structure.get_name() {
+            "audio/mpeg" => match structure.get::<i32>("mpegversion") {
+                Some(1) => {
+                    let layer = structure.get::<i32>("layer");
+                    if layer.map_or(true, |layer| layer == 3) {
+                        Some(Format::MP3)
+                    } else {
+                        None
+                    }
+                }
+                Some(2) | Some(4) => Some(Format::AAC),
+                _ => None,
+            },
+            "audio/x-alac" => Some(Format::ALAC),
+            "audio/x-opus" => Some(Format::Opus),
+            "audio/x-vorbis" => Some(Format::Vorbis),
+            _ => None,
+        }
+    }
+
+    pub fn check_passthrough_requirements(format: Format) -> Result<(), String> {
+        gst::ElementFactory::make("parsebin", None)
+            .map_or(
+                Err(gettext(
+                    "Missing `parsebin`\ncheck your gst-plugins-base install",
+                )),
+                |_| Ok(()),
+            )
+            .and_then(|_| match format {
+                Format::AAC | Format::ALAC => Self::check_mp4mux(),
+                Format::Matroska => Self::check_matroskamux(),
+                Format::Opus => Self::check_oggmux(),
+                Format::Vorbis => gst::ElementFactory::make("vorbistag", None)
+                    .map_or(
+                        Err(gettext(
+                            "Missing `vorbistag`\ncheck your gst-plugins-base install",
+                        )),
+                        |_| Ok(()),
+                    )
+                    .and_then(|_| Self::check_oggmux()),
+                Format::MP3 => gst::ElementFactory::make("id3v2mux", None).map_or(
+                    Err(gettext(
+                        "Missing `id3v2mux`\ncheck your gst-plugins-good install",
+                    )),
+                    |_| Ok(()),
+                ),
+                _ => panic!(
+                    "SplitterContext::check_passthrough_requirements unsupported format: {:?}",
+                    format
+                ),
+            })
+    }
+
+    // Note: fades are only applied when the audio stream is re-encoded
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     pub fn new(
         input_path: &Path,
         output_path: &Path,
-        stream_id: &str,
+        streams: HashSet<String>,
         format: Format,
+        is_passthrough: bool,
         chapter: gst::TocEntry,
+        options: &SplitOptions,
         ctx_tx: Sender<ContextMessage>,
     ) -> Result<SplitterContext, String> {
         info!(
             "{}",
             gettext("Splitting {}...").replacen("{}", output_path.to_str().unwrap(), 1)
         );
-        debug!("stream id {}", &stream_id);
+        debug!("streams {:?}", &streams);
 
         let mut this = SplitterContext {
             pipeline: gst::Pipeline::new("pipeline"),
             position_query: gst::Query::new_position(gst::Format::Time),
+            output_path: output_path.to_owned(),
             format,
+            is_passthrough,
             chapter,
+            cut_bounds: Arc::new(Mutex::new(CutBounds::default())),
+            bus_watch_src: None,
         };
 
-        this.build_pipeline(input_path, output_path, stream_id);
+        if format == Format::Matroska {
+            this.build_matroska_pipeline(input_path, output_path, streams);
+        } else if is_passthrough {
+            this.build_passthrough_pipeline(input_path, output_path, streams);
+        } else {
+            this.build_pipeline(input_path, output_path, streams, options);
+        }
         this.register_bus_inspector(ctx_tx);
 
         match this.pipeline.set_state(gst::State::Paused) {
@@ -120,12 +323,152 @@ impl SplitterContext {
         }
     }
 
+    // Stops the split and removes the partial output
+    pub fn cancel(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+        if self.output_path.exists() {
+            let _ = fs::remove_file(&self.output_path).map_err(|err| {
+                warn!(
+                    "{}",
+                    gettext("Failed to remove {}: {}")
+                        .replacen("{}", self.output_path.to_str().unwrap(), 1)
+                        .replacen("{}", &err.to_string(), 1)
+                );
+            });
+        }
+    }
+
+    // Checks whether `output_path` holds the result of a previous split:
+    // its duration must match the expected duration and its tail must decode
+    // Note: this blocks until the file is checked, so it must not be called
+    // from the UI thread
+    pub fn is_output_complete(output_path: &Path, expected_duration: u64) -> bool {
+        if !output_path.exists() {
+            return false;
+        }
+
+        let pipeline = gst::Pipeline::new(None);
+        let filesrc = gst::ElementFactory::make("filesrc", None).unwrap();
+        filesrc
+            .set_property("location", &gst::Value::from(output_path.to_str().unwrap()))
+            .unwrap();
+        let decodebin = gst::ElementFactory::make("decodebin", None).unwrap();
+        pipeline.add_many(&[&filesrc, &decodebin]).unwrap();
+        filesrc.link(&decodebin).unwrap();
+
+        // End of the last decoded buffer
+        let decoded_end = Arc::new(Mutex::new(0u64));
+        let decoded_end_cb = Arc::clone(&decoded_end);
+        let pipeline_cb = pipeline.clone();
+        decodebin.connect_pad_added(move |_element, pad| {
+            let fakesink = gst::ElementFactory::make("fakesink", None).unwrap();
+            // Decode the tail as fast as possible
+            fakesink
+                .set_property("sync", &gst::Value::from(&false))
+                .unwrap();
+            pipeline_cb.add(&fakesink).unwrap();
+            let fakesink_sink_pad = fakesink.get_static_pad("sink").unwrap();
+            let _ = pad.link(&fakesink_sink_pad);
+
+            let decoded_end = Arc::clone(&decoded_end_cb);
+            fakesink_sink_pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, probe_info| {
+                if let Some(gst::PadProbeData::Buffer(ref buffer)) = probe_info.data {
+                    if let Some(pts) = buffer.get_pts().nseconds() {
+                        let end = pts + buffer.get_duration().nseconds().unwrap_or(0);
+                        let mut decoded_end = decoded_end.lock().unwrap();
+                        *decoded_end = cmp::max(*decoded_end, end);
+                    }
+                }
+                gst::PadProbeReturn::Ok
+            });
+
+            fakesink.sync_state_with_parent().unwrap();
+        });
+
+        let is_complete = Self::check_output(&pipeline, expected_duration)
+            .map_or(false, |duration| {
+                *decoded_end.lock().unwrap() + DURATION_TOLERANCE >= duration
+            });
+        let _ = pipeline.set_state(gst::State::Null);
+
+        is_complete
+    }
+
+    // Returns the duration of the output if it matches `expected_duration`
+    // and if its tail could be decoded up to the end of stream
+    fn check_output(pipeline: &gst::Pipeline, expected_duration: u64) -> Option<u64> {
+        let _ = pipeline.set_state(gst::State::Paused);
+        let (state_change, _, _) = pipeline.get_state(ClockTime::from_seconds(CHECK_TIMEOUT));
+        if state_change == gst::StateChangeReturn::Failure {
+            return None;
+        }
+
+        let mut duration_query = gst::Query::new_duration(gst::Format::Time);
+        if !pipeline.query(&mut duration_query) {
+            return None;
+        }
+        let duration = duration_query.get_result().get_value() as u64;
+
+        // Cuts at keyframes can make the output slightly longer than expected,
+        // while a shorter or much longer file results from an interrupted
+        // or a different split
+        let difference = if duration > expected_duration {
+            duration - expected_duration
+        } else {
+            expected_duration - duration
+        };
+        if difference > DURATION_TOLERANCE {
+            return None;
+        }
+
+        pipeline
+            .seek_simple(
+                gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT,
+                ClockTime::from(duration.saturating_sub(TAIL_CHECK_DURATION)),
+            )
+            .ok()?;
+        if pipeline.set_state(gst::State::Playing) == gst::StateChangeReturn::Failure {
+            return None;
+        }
+
+        let bus = pipeline.get_bus().unwrap();
+        loop {
+            let msg = bus.timed_pop(ClockTime::from_seconds(CHECK_TIMEOUT))?;
+            match msg.view() {
+                gst::MessageView::Eos(..) => return Some(duration),
+                gst::MessageView::Error(..) => return None,
+                _ => (),
+            }
+        }
+    }
+
     pub fn get_position(&mut self) -> u64 {
         self.pipeline.query(&mut self.position_query);
         self.position_query.get_result().get_value() as u64
     }
 
-    fn build_pipeline(&mut self, input_path: &Path, output_path: &Path, stream_id: &str) {
+    // Returns the differences between the boundaries of the frames actually
+    // kept and the requested chapter boundaries (passthrough mode only)
+    pub fn get_drift(&self) -> Option<(i64, i64)> {
+        if !self.is_passthrough {
+            return None;
+        }
+
+        let (start, end) = self.chapter.get_start_stop_times().unwrap();
+        let cut_bounds = self.cut_bounds.lock().unwrap();
+        match (cut_bounds.first, cut_bounds.last) {
+            (Some(first), Some(last)) => Some((first as i64 - start, last as i64 - end)),
+            _ => None,
+        }
+    }
+
+    fn build_pipeline(
+        &mut self,
+        input_path: &Path,
+        output_path: &Path,
+        streams: HashSet<String>,
+        options: &SplitOptions,
+    ) {
         /* There are multiple showstoppers to implementing something ideal
          * to export splitted chapters with audio and video (and subtitles):
          * 1. matroska-mux drops seek events explicitely (a message states: "discard for now").
@@ -145,7 +488,10 @@ impl SplitterContext {
          *
          * Until I design a GUI for the user to select which stream to export to which codec,
          * current solution is to keep only the audio track and to save it as a flac file
-         * which matches the initial purpose of this application */
+         * which matches the initial purpose of this application.
+         *
+         * Note: see `build_matroska_pipeline` for a workaround to issue 1 which allows
+         * splitting to Matroska with all the selected streams. */
 
         // Input
         let filesrc = gst::ElementFactory::make("filesrc", None).unwrap();
@@ -161,8 +507,20 @@ impl SplitterContext {
 
         // Audio encoder
         let audio_enc = match self.format {
+            Format::AAC => {
+                let encoder_name = Self::get_aac_encoder_name().unwrap();
+                let aac_enc = gst::ElementFactory::make(encoder_name, None).unwrap();
+                if encoder_name == "avenc_aac" {
+                    // libav's native encoder used to be flagged as experimental
+                    aac_enc.set_property_from_str("compliance", "experimental");
+                }
+                aac_enc
+            }
+            Format::AIFF => gst::ElementFactory::make("aiffmux", None).unwrap(),
+            Format::ALAC => gst::ElementFactory::make("avenc_alac", None).unwrap(),
             Format::Flac => gst::ElementFactory::make("flacenc", None).unwrap(),
             Format::Wave => gst::ElementFactory::make("wavenc", None).unwrap(),
+            Format::WavPack => gst::ElementFactory::make("wavpackenc", None).unwrap(),
             Format::Opus => gst::ElementFactory::make("opusenc", None).unwrap(),
             Format::Vorbis => gst::ElementFactory::make("vorbisenc", None).unwrap(),
             Format::MP3 => gst::ElementFactory::make("lamemp3enc", None).unwrap(),
@@ -237,18 +595,43 @@ impl SplitterContext {
 
         // add a muxer when required
         let (tag_setter, audio_muxer) = match self.format {
-            Format::Flac | Format::Wave => (audio_enc.clone(), audio_enc.clone()),
+            Format::Flac | Format::Wave => (Some(audio_enc.clone()), audio_enc.clone()),
+            // Note: depending on its version, `aiffmux` might not support tags
+            // (see `are_tags_supported`)
+            Format::AIFF => (
+                audio_enc
+                    .clone()
+                    .dynamic_cast::<gst::TagSetter>()
+                    .ok()
+                    .map(|_| audio_enc.clone()),
+                audio_enc.clone(),
+            ),
+            Format::WavPack => {
+                // WavPack files hold their tags in an APEv2 footer
+                let apev2_muxer = gst::ElementFactory::make("apev2mux", None).unwrap();
+                self.pipeline.add(&apev2_muxer).unwrap();
+                audio_enc.link(&apev2_muxer).unwrap();
+                (Some(apev2_muxer.clone()), apev2_muxer)
+            }
+            Format::AAC | Format::ALAC => {
+                // `mp4mux` writes the tags as iTunes metadata atoms,
+                // including the cover art and the track number / count
+                let mp4_muxer = gst::ElementFactory::make("mp4mux", None).unwrap();
+                self.pipeline.add(&mp4_muxer).unwrap();
+                audio_enc.link(&mp4_muxer).unwrap();
+                (Some(mp4_muxer.clone()), mp4_muxer)
+            }
             Format::Opus | Format::Vorbis => {
                 let ogg_muxer = gst::ElementFactory::make("oggmux", None).unwrap();
                 self.pipeline.add(&ogg_muxer).unwrap();
                 audio_enc.link(&ogg_muxer).unwrap();
-                (audio_enc.clone(), ogg_muxer)
+                (Some(audio_enc.clone()), ogg_muxer)
             }
             Format::MP3 => {
                 let id3v2_muxer = gst::ElementFactory::make("id3v2mux", None).unwrap();
                 self.pipeline.add(&id3v2_muxer).unwrap();
                 audio_enc.link(&id3v2_muxer).unwrap();
-                (id3v2_muxer.clone(), id3v2_muxer)
+                (Some(id3v2_muxer.clone()), id3v2_muxer)
             }
             _ => panic!(
                 "SplitterContext::build_pipeline unsupported format: {:?}",
@@ -256,9 +639,11 @@ impl SplitterContext {
             ),
         };
 
-        if let Some(tags) = self.chapter.get_tags() {
-            let tag_setter = tag_setter.clone().dynamic_cast::<gst::TagSetter>().unwrap();
-            tag_setter.merge_tags(&tags, gst::TagMergeMode::ReplaceAll);
+        if let Some(tag_setter) = tag_setter {
+            if let Some(tags) = self.chapter.get_tags() {
+                let tag_setter = tag_setter.dynamic_cast::<gst::TagSetter>().unwrap();
+                tag_setter.merge_tags(&tags, gst::TagMergeMode::ReplaceAll);
+            }
         }
 
         // Output sink
@@ -271,8 +656,18 @@ impl SplitterContext {
         audio_muxer.link(&outsink).unwrap();
         outsink.sync_state_with_parent().unwrap();
 
+        let envelope = if options.has_fades() {
+            Some(VolumeEnvelope::new(
+                start as u64,
+                end as u64,
+                options.fade_in,
+                options.fade_out,
+            ))
+        } else {
+            None
+        };
+
         let pipeline_cb = self.pipeline.clone();
-        let stream_id = stream_id.to_owned();
         decodebin.connect_pad_added(move |_element, pad| {
             let caps = pad.get_current_caps().unwrap();
             let structure = caps.get_structure(0).unwrap();
@@ -286,13 +681,59 @@ impl SplitterContext {
             let queue_src_pad = queue.get_static_pad("src").unwrap();
 
             if name.starts_with("audio/") && pipeline_cb.get_by_name("audioconvert").is_none()
-                && stream_id
-                    == pad.get_stream_id()
-                        .expect("SplitterContext::build_pipeline no stream_id for audio src pad")
+                && streams.contains(&pad.get_stream_id()
+                    .expect("SplitterContext::build_pipeline no stream_id for audio src pad"))
             {
                 let audio_conv = gst::ElementFactory::make("audioconvert", "audioconvert").unwrap();
                 pipeline_cb.add(&audio_conv).unwrap();
-                gst::Element::link_many(&[&queue, &audio_conv, &audio_enc]).unwrap();
+                match envelope {
+                    Some(envelope) => {
+                        // Apply the fades on float samples
+                        // and convert back to a format suitable for the encoder
+                        let capsfilter = gst::ElementFactory::make("capsfilter", None).unwrap();
+                        capsfilter
+                            .set_property(
+                                "caps",
+                                &gst::Caps::new_simple(
+                                    "audio/x-raw",
+                                    &[("format", &"F32LE"), ("layout", &"interleaved")],
+                                ),
+                            )
+                            .unwrap();
+                        let enc_conv = gst::ElementFactory::make("audioconvert", None).unwrap();
+                        pipeline_cb.add_many(&[&capsfilter, &enc_conv]).unwrap();
+                        gst::Element::link_many(&[
+                            &queue,
+                            &audio_conv,
+                            &capsfilter,
+                            &enc_conv,
+                            &audio_enc,
+                        ]).unwrap();
+
+                        let capsfilter_src_pad = capsfilter.get_static_pad("src").unwrap();
+                        capsfilter_src_pad.add_probe(
+                            gst::PadProbeType::BUFFER,
+                            move |pad, probe_info| {
+                                if let Some(gst::PadProbeData::Buffer(ref mut buffer)) =
+                                    probe_info.data
+                                {
+                                    let audio_info = pad.get_current_caps()
+                                        .and_then(|caps| gst_audio::AudioInfo::from_caps(&caps));
+                                    if let Some(audio_info) = audio_info {
+                                        envelope.apply(buffer, &audio_info);
+                                    }
+                                }
+                                gst::PadProbeReturn::Ok
+                            },
+                        );
+
+                        capsfilter.sync_state_with_parent().unwrap();
+                        enc_conv.sync_state_with_parent().unwrap();
+                    }
+                    None => {
+                        gst::Element::link_many(&[&queue, &audio_conv, &audio_enc]).unwrap();
+                    }
+                }
                 audio_conv.sync_state_with_parent().unwrap();
                 audio_enc.sync_state_with_parent().unwrap();
             } else {
@@ -308,10 +749,451 @@ impl SplitterContext {
         });
     }
 
+    fn build_passthrough_pipeline(
+        &mut self,
+        input_path: &Path,
+        output_path: &Path,
+        streams: HashSet<String>,
+    ) {
+        // Demux and parse the audio stream without decoding it.
+        // The frames are then pushed as is to a muxer for the original codec.
+        let filesrc = gst::ElementFactory::make("filesrc", None).unwrap();
+        filesrc
+            .set_property("location", &gst::Value::from(input_path.to_str().unwrap()))
+            .unwrap();
+        let parsebin = gst::ElementFactory::make("parsebin", None).unwrap();
+
+        self.pipeline.add_many(&[&filesrc, &parsebin]).unwrap();
+
+        filesrc.link(&parsebin).unwrap();
+        parsebin.sync_state_with_parent().unwrap();
+
+        // Optional element setting the tags and optional element to insert before the muxer
+        let (tag_setter, audio_tagger, audio_muxer) = match self.format {
+            Format::AAC | Format::ALAC => {
+                // `mp4mux` writes the tags as iTunes metadata atoms
+                let mp4_muxer = gst::ElementFactory::make("mp4mux", None).unwrap();
+                (Some(mp4_muxer.clone()), None, mp4_muxer)
+            }
+            Format::Opus => {
+                // `opusparse` is not a tag setter and there is no equivalent to `vorbistag`
+                // => the `OpusTags` header is replaced in the caps (see `with_opus_tags`)
+                let ogg_muxer = gst::ElementFactory::make("oggmux", None).unwrap();
+                (None, None, ogg_muxer)
+            }
+            Format::Vorbis => {
+                // `oggmux` uses the stream headers from the caps,
+                // `vorbistag` rewrites the comment header with the chapter's tags
+                let vorbis_tagger = gst::ElementFactory::make("vorbistag", None).unwrap();
+                let ogg_muxer = gst::ElementFactory::make("oggmux", None).unwrap();
+                (Some(vorbis_tagger.clone()), Some(vorbis_tagger), ogg_muxer)
+            }
+            Format::MP3 => {
+                let id3v2_muxer = gst::ElementFactory::make("id3v2mux", None).unwrap();
+                (Some(id3v2_muxer.clone()), None, id3v2_muxer)
+            }
+            _ => panic!(
+                "SplitterContext::build_passthrough_pipeline unsupported format: {:?}",
+                self.format
+            ),
+        };
+
+        if let Some(tag_setter) = tag_setter {
+            if let Some(tags) = self.chapter.get_tags() {
+                let tag_setter = tag_setter.dynamic_cast::<gst::TagSetter>().unwrap();
+                tag_setter.merge_tags(&tags, gst::TagMergeMode::ReplaceAll);
+            }
+        }
+        let opus_tags = if self.format == Format::Opus {
+            Some(self.chapter.get_tags().unwrap_or_else(gst::TagList::new))
+        } else {
+            None
+        };
+        if let Some(ref audio_tagger) = audio_tagger {
+            self.pipeline.add(audio_tagger).unwrap();
+        }
+
+        // Output sink
+        let outsink = gst::ElementFactory::make("filesink", "filesink").unwrap();
+        outsink
+            .set_property("location", &gst::Value::from(output_path.to_str().unwrap()))
+            .unwrap();
+
+        self.pipeline.add_many(&[&audio_muxer, &outsink]).unwrap();
+        audio_muxer.link(&outsink).unwrap();
+        outsink.sync_state_with_parent().unwrap();
+
+        let (start, end) = self.chapter.get_start_stop_times().unwrap();
+        let (start, end) = (start as u64, end as u64);
+
+        let pipeline_cb = self.pipeline.clone();
+        let cut_bounds = Arc::clone(&self.cut_bounds);
+        parsebin.connect_pad_added(move |_element, pad| {
+            let is_selected_stream = pipeline_cb.get_by_name("passthrough_queue").is_none()
+                && streams.contains(&pad.get_stream_id().expect(
+                    "SplitterContext::build_passthrough_pipeline no stream_id for src pad",
+                ));
+
+            let queue = if is_selected_stream {
+                gst::ElementFactory::make("queue", "passthrough_queue").unwrap()
+            } else {
+                gst::ElementFactory::make("queue", None).unwrap()
+            };
+            pipeline_cb.add(&queue).unwrap();
+            let queue_sink_pad = queue.get_static_pad("sink").unwrap();
+            assert_eq!(pad.link(&queue_sink_pad), gst::PadLinkReturn::Ok);
+            queue.sync_state_with_parent().unwrap();
+            let queue_src_pad = queue.get_static_pad("src").unwrap();
+
+            if !is_selected_stream {
+                let fakesink = gst::ElementFactory::make("fakesink", None).unwrap();
+                pipeline_cb.add(&fakesink).unwrap();
+                let fakesink_sink_pad = fakesink.get_static_pad("sink").unwrap();
+                assert_eq!(
+                    queue_src_pad.link(&fakesink_sink_pad),
+                    gst::PadLinkReturn::Ok
+                );
+                fakesink.sync_state_with_parent().unwrap();
+                return;
+            }
+
+            // Drop the upstream Tags & TOC
+            queue_src_pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, |_pad, probe_info| {
+                if let Some(ref data) = probe_info.data {
+                    if let gst::PadProbeData::Event(ref event) = *data {
+                        match event.view() {
+                            gst::EventView::Tag(ref _tag) => return gst::PadProbeReturn::Drop,
+                            gst::EventView::Toc(ref _toc) => return gst::PadProbeReturn::Drop,
+                            _ => (),
+                        }
+                    }
+                }
+                gst::PadProbeReturn::Ok
+            });
+
+            if let Some(ref opus_tags) = opus_tags {
+                // Replace the `OpusTags` header from the stream with the chapter's tags
+                let opus_tags = opus_tags.clone();
+                queue_src_pad.add_probe(
+                    gst::PadProbeType::EVENT_DOWNSTREAM,
+                    move |_pad, probe_info| {
+                        let caps = match probe_info.data {
+                            Some(gst::PadProbeData::Event(ref event)) => match event.view() {
+                                gst::EventView::Caps(caps_event) => {
+                                    with_opus_tags(caps_event.get_caps(), &opus_tags)
+                                }
+                                _ => None,
+                            },
+                            _ => None,
+                        };
+                        if let Some(caps) = caps {
+                            probe_info.data =
+                                Some(gst::PadProbeData::Event(gst::Event::new_caps(&caps).build()));
+                        }
+                        gst::PadProbeReturn::Ok
+                    },
+                );
+            }
+
+            // Same principle as for the encoding pipeline: drop the preroll buffers,
+            // seek to the chapter and wait for the first buffer of the new segment.
+            // Since frames can't be split, the seek snaps to the frame preceding the start
+            // and frames are kept when their middle lies within the chapter boundaries.
+            // This guarantees that successive chapters don't share any frame.
+            let pipeline = pipeline_cb.clone();
+            let cut_bounds = Arc::clone(&cut_bounds);
+            let cut_state = Arc::new(Mutex::new(CutState::Preroll));
+            queue_src_pad.add_probe(gst::PadProbeType::BUFFER, move |_pad, probe_info| {
+                if let Some(ref data) = probe_info.data {
+                    if let gst::PadProbeData::Buffer(ref buffer) = *data {
+                        let is_discont = buffer.get_flags() & gst::BufferFlags::DISCONT
+                            == gst::BufferFlags::DISCONT;
+
+                        let mut cut_state = cut_state.lock().unwrap();
+                        match *cut_state {
+                            CutState::Preroll => {
+                                let _res = pipeline
+                                    .seek(
+                                        1f64,
+                                        gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT
+                                            | gst::SeekFlags::SNAP_BEFORE,
+                                        gst::SeekType::Set,
+                                        ClockTime::from(start),
+                                        gst::SeekType::Set,
+                                        ClockTime::from(end),
+                                    )
+                                    .map_err(|_| {
+                                        error!("{}", gettext("Failed to intialize the split"));
+                                    });
+                                *cut_state = CutState::Seeking;
+                                return gst::PadProbeReturn::Drop;
+                            }
+                            CutState::Seeking => {
+                                if !is_discont {
+                                    return gst::PadProbeReturn::Drop;
+                                }
+                                *cut_state = CutState::Cutting;
+                            }
+                            CutState::Cutting => (),
+                        }
+
+                        let pts = match buffer.get_pts().nseconds() {
+                            Some(pts) => pts,
+                            None => return gst::PadProbeReturn::Ok,
+                        };
+                        let duration = buffer.get_duration().nseconds().unwrap_or(0);
+                        let middle = pts + duration / 2;
+                        if middle < start || middle >= end {
+                            return gst::PadProbeReturn::Drop;
+                        }
+
+                        let mut cut_bounds = cut_bounds.lock().unwrap();
+                        cut_bounds.first.get_or_insert(pts);
+                        cut_bounds.last = Some(pts + duration);
+                    }
+                }
+                gst::PadProbeReturn::Ok
+            });
+
+            let branch_src_pad = match audio_tagger {
+                Some(ref audio_tagger) => {
+                    queue.link(audio_tagger).unwrap();
+                    audio_tagger.sync_state_with_parent().unwrap();
+                    audio_tagger.get_static_pad("src").unwrap()
+                }
+                None => queue_src_pad,
+            };
+
+            let muxer_sink_pad = audio_muxer
+                .get_compatible_pad(&branch_src_pad, None)
+                .unwrap();
+            assert_eq!(branch_src_pad.link(&muxer_sink_pad), gst::PadLinkReturn::Ok);
+            audio_muxer.sync_state_with_parent().unwrap();
+        });
+    }
+
+    fn build_matroska_pipeline(
+        &mut self,
+        input_path: &Path,
+        output_path: &Path,
+        streams: HashSet<String>,
+    ) {
+        // `matroskamux` drops the seek events (see `build_pipeline`), so we can't seek
+        // the pipeline. Instead, the seek is sent upstream from one of the queues
+        // which bypasses the muxer. Buffers are dropped on each stream until the flush
+        // resulting from the seek is received.
+        // In passthrough mode, streams are only parsed and the seek snaps to the keyframe
+        // preceding the chapter start. Otherwise, streams are decoded and re-encoded
+        // so that the boundaries are accurate.
+        let filesrc = gst::ElementFactory::make("filesrc", None).unwrap();
+        filesrc
+            .set_property("location", &gst::Value::from(input_path.to_str().unwrap()))
+            .unwrap();
+        let demux = if self.is_passthrough {
+            gst::ElementFactory::make("parsebin", None).unwrap()
+        } else {
+            gst::ElementFactory::make("decodebin", None).unwrap()
+        };
+
+        self.pipeline.add_many(&[&filesrc, &demux]).unwrap();
+
+        filesrc.link(&demux).unwrap();
+        demux.sync_state_with_parent().unwrap();
+
+        let muxer = gst::ElementFactory::make("matroskamux", None).unwrap();
+        muxer
+            .set_property("writing-app", &gst::Value::from("media-toc"))
+            .unwrap();
+
+        if let Some(tags) = self.chapter.get_tags() {
+            let tag_setter = muxer.clone().dynamic_cast::<gst::TagSetter>().unwrap();
+            tag_setter.merge_tags(&tags, gst::TagMergeMode::ReplaceAll);
+        }
+        let toc_setter = muxer.clone().dynamic_cast::<gst::TocSetter>().unwrap();
+        toc_setter.set_toc(&self.get_chapter_toc());
+
+        let outsink = gst::ElementFactory::make("filesink", "filesink").unwrap();
+        outsink
+            .set_property("location", &gst::Value::from(output_path.to_str().unwrap()))
+            .unwrap();
+
+        self.pipeline.add_many(&[&muxer, &outsink]).unwrap();
+        muxer.link(&outsink).unwrap();
+        outsink.sync_state_with_parent().unwrap();
+
+        let (start, end) = self.chapter.get_start_stop_times().unwrap();
+        let seek_flags = if self.is_passthrough {
+            gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT | gst::SeekFlags::SNAP_BEFORE
+        } else {
+            gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE
+        };
+
+        let is_passthrough = self.is_passthrough;
+        let pipeline_cb = self.pipeline.clone();
+        #[cfg_attr(feature = "cargo-clippy", allow(mutex_atomic))]
+        let seek_done = Arc::new(Mutex::new(false));
+        demux.connect_pad_added(move |_element, pad| {
+            let queue = gst::ElementFactory::make("queue", None).unwrap();
+            pipeline_cb.add(&queue).unwrap();
+            let queue_sink_pad = queue.get_static_pad("sink").unwrap();
+            assert_eq!(pad.link(&queue_sink_pad), gst::PadLinkReturn::Ok);
+            queue.sync_state_with_parent().unwrap();
+            let queue_src_pad = queue.get_static_pad("src").unwrap();
+
+            if !streams.contains(&pad.get_stream_id()
+                .expect("SplitterContext::build_matroska_pipeline no stream_id for src pad"))
+            {
+                let fakesink = gst::ElementFactory::make("fakesink", None).unwrap();
+                pipeline_cb.add(&fakesink).unwrap();
+                let fakesink_sink_pad = fakesink.get_static_pad("sink").unwrap();
+                assert_eq!(
+                    queue_src_pad.link(&fakesink_sink_pad),
+                    gst::PadLinkReturn::Ok
+                );
+                fakesink.sync_state_with_parent().unwrap();
+                return;
+            }
+
+            // Drop the upstream Tags & TOC
+            queue_src_pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, |_pad, probe_info| {
+                if let Some(ref data) = probe_info.data {
+                    if let gst::PadProbeData::Event(ref event) = *data {
+                        match event.view() {
+                            gst::EventView::Tag(ref _tag) => return gst::PadProbeReturn::Drop,
+                            gst::EventView::Toc(ref _toc) => return gst::PadProbeReturn::Drop,
+                            _ => (),
+                        }
+                    }
+                }
+                gst::PadProbeReturn::Ok
+            });
+
+            #[cfg_attr(feature = "cargo-clippy", allow(mutex_atomic))]
+            let is_flushed = Arc::new(Mutex::new(false));
+            let seek_done = Arc::clone(&seek_done);
+            let queue_cb = queue.clone();
+            queue_src_pad.add_probe(
+                gst::PadProbeType::BUFFER | gst::PadProbeType::EVENT_FLUSH,
+                move |_pad, probe_info| {
+                    if let Some(ref data) = probe_info.data {
+                        match *data {
+                            gst::PadProbeData::Buffer(_) => {
+                                // Don't keep the lock while seeking: the other streams
+                                // receive the flush events from the seeking thread
+                                let must_seek = {
+                                    let mut seek_done = seek_done.lock().unwrap();
+                                    let must_seek = !*seek_done;
+                                    *seek_done = true;
+                                    must_seek
+                                };
+
+                                if must_seek {
+                                    let seek = gst::Event::new_seek(
+                                        1f64,
+                                        seek_flags,
+                                        gst::SeekType::Set,
+                                        ClockTime::from(start as u64),
+                                        gst::SeekType::Set,
+                                        ClockTime::from(end as u64),
+                                    ).build();
+                                    if !queue_cb.send_event(seek) {
+                                        // FIXME: feedback to the user using the UI channel
+                                        error!("{}", gettext("Failed to intialize the split"));
+                                    }
+                                } else if *is_flushed.lock().unwrap() {
+                                    return gst::PadProbeReturn::Ok;
+                                }
+                            }
+                            gst::PadProbeData::Event(ref event) => {
+                                if let gst::EventView::FlushStop(_) = event.view() {
+                                    if *seek_done.lock().unwrap() {
+                                        *is_flushed.lock().unwrap() = true;
+                                    }
+                                }
+                                return gst::PadProbeReturn::Ok;
+                            }
+                            _ => return gst::PadProbeReturn::Ok,
+                        }
+                    }
+                    gst::PadProbeReturn::Drop
+                },
+            );
+
+            let branch_src_pad = if is_passthrough {
+                queue_src_pad
+            } else {
+                let caps = pad.get_current_caps().unwrap();
+                let name = caps.get_structure(0).unwrap().get_name().to_owned();
+                let converter_encoder = if name.starts_with("audio/") {
+                    Some((
+                        gst::ElementFactory::make("audioconvert", None).unwrap(),
+                        gst::ElementFactory::make("vorbisenc", None).unwrap(),
+                    ))
+                } else if name.starts_with("video/") {
+                    Some((
+                        gst::ElementFactory::make("videoconvert", None).unwrap(),
+                        gst::ElementFactory::make("x264enc", None).unwrap(),
+                    ))
+                } else {
+                    // subtitles can be muxed as is
+                    None
+                };
+
+                match converter_encoder {
+                    Some((converter, encoder)) => {
+                        pipeline_cb.add_many(&[&converter, &encoder]).unwrap();
+                        gst::Element::link_many(&[&queue, &converter, &encoder]).unwrap();
+                        converter.sync_state_with_parent().unwrap();
+                        encoder.sync_state_with_parent().unwrap();
+                        encoder.get_static_pad("src").unwrap()
+                    }
+                    None => queue_src_pad,
+                }
+            };
+
+            let muxer_sink_pad = muxer.get_compatible_pad(&branch_src_pad, None).unwrap();
+            assert_eq!(branch_src_pad.link(&muxer_sink_pad), gst::PadLinkReturn::Ok);
+            muxer.sync_state_with_parent().unwrap();
+        });
+    }
+
+    // Builds a TOC with a single entry for the chapter
+    // so that each split file holds its own chapter
+    fn get_chapter_toc(&self) -> gst::Toc {
+        let (start, end) = self.chapter.get_start_stop_times().unwrap();
+
+        let mut chapter = gst::TocEntry::new(gst::TocEntryType::Chapter, "01");
+        chapter
+            .get_mut()
+            .unwrap()
+            .set_start_stop_times(0, end - start);
+
+        let title = self.chapter.get_tags().and_then(|tags| {
+            tags.get::<gst::tags::Title>()
+                .map(|tag| tag.get().unwrap().to_owned())
+        });
+        if let Some(title) = title {
+            let mut tag_list = gst::TagList::new();
+            tag_list
+                .get_mut()
+                .unwrap()
+                .add::<gst::tags::Title>(&title.as_str(), gst::TagMergeMode::Replace);
+            chapter.get_mut().unwrap().set_tags(tag_list);
+        }
+
+        let mut edition = gst::TocEntry::new(gst::TocEntryType::Edition, "");
+        edition.get_mut().unwrap().append_sub_entry(chapter);
+
+        let mut toc = gst::Toc::new(gst::TocScope::Global);
+        toc.get_mut().unwrap().append_entry(edition);
+        toc
+    }
+
     // Uses ctx_tx to notify the UI controllers
-    fn register_bus_inspector(&self, ctx_tx: Sender<ContextMessage>) {
+    // Note: the bus watch is removed when the context is dropped
+    fn register_bus_inspector(&mut self, ctx_tx: Sender<ContextMessage>) {
         let pipeline = self.pipeline.clone();
-        self.pipeline.get_bus().unwrap().add_watch(move |_, msg| {
+        self.bus_watch_src = Some(self.pipeline.get_bus().unwrap().add_watch(move |_, msg| {
             match msg.view() {
                 gst::MessageView::Eos(..) => {
                     if pipeline.set_state(gst::State::Null) == gst::StateChangeReturn::Failure {
@@ -322,7 +1204,6 @@ impl SplitterContext {
                             .unwrap();
                     }
                     ctx_tx.send(ContextMessage::Eos).unwrap();
-                    return glib::Continue(false);
                 }
                 gst::MessageView::Error(err) => {
                     ctx_tx
@@ -330,7 +1211,6 @@ impl SplitterContext {
                             err.get_error().description().to_owned(),
                         ))
                         .unwrap();
-                    return glib::Continue(false);
                 }
                 gst::MessageView::AsyncDone(_) => {
                     // Start splitting
@@ -346,6 +1226,14 @@ impl SplitterContext {
             }
 
             glib::Continue(true)
-        });
+        }));
+    }
+}
+
+impl Drop for SplitterContext {
+    fn drop(&mut self) {
+        if let Some(bus_watch_src) = self.bus_watch_src.take() {
+            glib::source_remove(bus_watch_src);
+        }
     }
 }
@@ -0,0 +1,164 @@
+use std::f64::consts::PI;
+
+// Level assigned to the frequency bins without energy
+pub const MIN_LEVEL: f64 = -120f64; // dBFS
+
+// Computes the spectrum of successive windows of samples (Short Time Fourier Transform)
+// Samples are expected in the range [-1.0, 1.0]
+pub struct Stft {
+    window_size: usize,
+    window: Vec<f64>,
+    cos_table: Vec<f64>,
+    sin_table: Vec<f64>,
+    bit_reversed: Vec<usize>,
+    // The peak magnitude of a full scale sinusoid
+    full_scale: f64,
+    real: Vec<f64>,
+    imag: Vec<f64>,
+}
+
+impl Stft {
+    // `window_size` must be a power of 2
+    pub fn new(window_size: usize) -> Self {
+        assert!(window_size >= 2 && window_size.is_power_of_two());
+
+        let size_f = window_size as f64;
+        // Hann window
+        let window = (0..window_size)
+            .map(|index| 0.5f64 - 0.5f64 * (2f64 * PI * index as f64 / size_f).cos())
+            .collect::<Vec<f64>>();
+        let full_scale = window.iter().sum::<f64>() / 2f64;
+
+        let half_size = window_size / 2;
+        let cos_table = (0..half_size)
+            .map(|index| (2f64 * PI * index as f64 / size_f).cos())
+            .collect();
+        let sin_table = (0..half_size)
+            .map(|index| (2f64 * PI * index as f64 / size_f).sin())
+            .collect();
+
+        let bits = window_size.trailing_zeros();
+        let bit_reversed = (0..window_size)
+            .map(|index| {
+                let mut reversed = 0;
+                for bit in 0..bits {
+                    if index & (1 << bit) != 0 {
+                        reversed |= 1 << (bits - 1 - bit);
+                    }
+                }
+                reversed
+            })
+            .collect();
+
+        Stft {
+            window_size,
+            window,
+            cos_table,
+            sin_table,
+            bit_reversed,
+            full_scale,
+            real: vec![0f64; window_size],
+            imag: vec![0f64; window_size],
+        }
+    }
+
+    pub fn get_window_size(&self) -> usize {
+        self.window_size
+    }
+
+    // Bins range from 0 Hz to the Nyquist frequency
+    pub fn get_bin_count(&self) -> usize {
+        self.window_size / 2 + 1
+    }
+
+    // Returns the level in dBFS of each frequency bin for the window in `samples`
+    // Missing samples at the end of the window are considered silent
+    pub fn compute(&mut self, samples: &[f64]) -> Vec<f64> {
+        for index in 0..self.window_size {
+            let value = samples.get(index).cloned().unwrap_or(0f64);
+            let target = self.bit_reversed[index];
+            self.real[target] = value * self.window[index];
+            self.imag[target] = 0f64;
+        }
+
+        // In place radix-2 FFT
+        let mut size = 2;
+        while size <= self.window_size {
+            let half_size = size / 2;
+            let table_step = self.window_size / size;
+            let mut start = 0;
+            while start < self.window_size {
+                for offset in 0..half_size {
+                    let cos = self.cos_table[offset * table_step];
+                    let sin = self.sin_table[offset * table_step];
+                    let even = start + offset;
+                    let odd = even + half_size;
+
+                    let odd_real = self.real[odd] * cos + self.imag[odd] * sin;
+                    let odd_imag = self.imag[odd] * cos - self.real[odd] * sin;
+                    self.real[odd] = self.real[even] - odd_real;
+                    self.imag[odd] = self.imag[even] - odd_imag;
+                    self.real[even] += odd_real;
+                    self.imag[even] += odd_imag;
+                }
+                start += size;
+            }
+            size *= 2;
+        }
+
+        (0..self.get_bin_count())
+            .map(|bin| {
+                let magnitude = (self.real[bin].powi(2) + self.imag[bin].powi(2)).sqrt();
+                if magnitude > 0f64 {
+                    (20f64 * (magnitude / self.full_scale).log10()).max(MIN_LEVEL)
+                } else {
+                    MIN_LEVEL
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{Stft, MIN_LEVEL};
+
+    #[test]
+    fn sinusoid() {
+        let window_size = 1024;
+        let mut stft = Stft::new(window_size);
+        assert_eq!(stft.get_bin_count(), 513);
+
+        assert!(
+            stft.compute(&vec![0f64; window_size])
+                .iter()
+                .all(|level| *level == MIN_LEVEL)
+        );
+
+        for &(bin, amplitude) in &[(32usize, 1f64), (100, 0.5f64)] {
+            let samples = (0..window_size)
+                .map(|index| {
+                    amplitude * (2f64 * PI * (bin * index) as f64 / window_size as f64).sin()
+                })
+                .collect::<Vec<f64>>();
+            let levels = stft.compute(&samples);
+
+            let (max_bin, max_level) = levels
+                .iter()
+                .enumerate()
+                .fold((0, MIN_LEVEL), |(max_bin, max_level), (bin, level)| {
+                    if *level > max_level {
+                        (bin, *level)
+                    } else {
+                        (max_bin, max_level)
+                    }
+                });
+            assert_eq!(max_bin, bin);
+            assert!((max_level - 20f64 * amplitude.log10()).abs() < 0.01f64);
+            // Leakage is limited to the neighbour bins
+            assert!(levels[bin + 3] < -60f64);
+        }
+    }
+}
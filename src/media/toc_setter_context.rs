@@ -9,7 +9,8 @@ use glib::ObjectExt;
 
 use std::collections::HashSet;
 use std::error::Error;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
 
 use super::ContextMessage;
@@ -18,6 +19,7 @@ pub struct TocSetterContext {
     pipeline: gst::Pipeline,
     muxer: Option<gst::Element>,
     position_query: gst::query::Position<gst::Query>,
+    output_path: PathBuf,
 }
 
 impl TocSetterContext {
@@ -54,6 +56,7 @@ impl TocSetterContext {
             pipeline: gst::Pipeline::new("pipeline"),
             muxer: None,
             position_query: gst::Query::new_position(gst::Format::Time),
+            output_path: output_path.to_owned(),
         };
 
         this.build_pipeline(input_path, output_path, streams);
@@ -76,6 +79,21 @@ impl TocSetterContext {
         }
     }
 
+    // Stops the export and removes the partial output
+    pub fn cancel(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+        if self.output_path.exists() {
+            let _ = fs::remove_file(&self.output_path).map_err(|err| {
+                warn!(
+                    "{}",
+                    gettext("Failed to remove {}: {}")
+                        .replacen("{}", self.output_path.to_str().unwrap(), 1)
+                        .replacen("{}", &err.to_string(), 1)
+                );
+            });
+        }
+    }
+
     pub fn get_position(&mut self) -> u64 {
         self.pipeline.query(&mut self.position_query);
         self.position_query.get_result().get_value() as u64
@@ -0,0 +1,100 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use gstreamer as gst;
+use gstreamer_audio as gst_audio;
+
+const SAMPLE_SIZE: usize = 4; // F32LE
+
+// Applies a linear fade-in and fade-out to the samples of a chapter
+#[derive(Clone, Copy, Debug)]
+pub struct VolumeEnvelope {
+    start: u64,
+    end: u64,
+    fade_in: u64,
+    fade_out: u64,
+}
+
+impl VolumeEnvelope {
+    pub fn new(start: u64, end: u64, fade_in: u64, fade_out: u64) -> Self {
+        // Fades can't exceed the chapter's duration
+        let duration = end.saturating_sub(start);
+        VolumeEnvelope {
+            start,
+            end,
+            fade_in: if fade_in < duration { fade_in } else { duration },
+            fade_out: if fade_out < duration { fade_out } else { duration },
+        }
+    }
+
+    // Returns the gain in the range [0.0, 1.0] at the given timestamp
+    pub fn get_gain(&self, timestamp: u64) -> f64 {
+        let mut gain = 1f64;
+        if self.fade_in > 0 && timestamp < self.start + self.fade_in {
+            gain = timestamp.saturating_sub(self.start) as f64 / self.fade_in as f64;
+        }
+        if self.fade_out > 0 && timestamp + self.fade_out > self.end {
+            let fade_out_gain = self.end.saturating_sub(timestamp) as f64 / self.fade_out as f64;
+            if fade_out_gain < gain {
+                gain = fade_out_gain;
+            }
+        }
+        gain
+    }
+
+    // Applies the envelope to a buffer of interleaved `F32LE` samples
+    pub fn apply(&self, buffer: &mut gst::Buffer, audio_info: &gst_audio::AudioInfo) {
+        let pts = match buffer.get_pts().nseconds() {
+            Some(pts) => pts,
+            None => return,
+        };
+        let rate = u64::from(audio_info.rate());
+        let frame_size = SAMPLE_SIZE * audio_info.channels() as usize;
+        if rate == 0 || frame_size == 0 {
+            return;
+        }
+
+        let frame_count = buffer.get_size() / frame_size;
+        let buffer_end = pts + frame_count as u64 * 1_000_000_000 / rate;
+        if pts >= self.start + self.fade_in && buffer_end + self.fade_out <= self.end {
+            // Buffer is out of the fades
+            return;
+        }
+
+        let mut map = match buffer.make_mut().map_writable() {
+            Some(map) => map,
+            None => return,
+        };
+        for (index, frame) in map.as_mut_slice().chunks_mut(frame_size).enumerate() {
+            let gain = self.get_gain(pts + index as u64 * 1_000_000_000 / rate) as f32;
+            for sample in frame.chunks_mut(SAMPLE_SIZE) {
+                let value = LittleEndian::read_f32(sample);
+                LittleEndian::write_f32(sample, value * gain);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VolumeEnvelope;
+
+    #[test]
+    fn gain() {
+        let envelope = VolumeEnvelope::new(1_000, 11_000, 1_000, 2_000);
+        assert_eq!(envelope.get_gain(0), 0f64);
+        assert_eq!(envelope.get_gain(1_000), 0f64);
+        assert_eq!(envelope.get_gain(1_500), 0.5);
+        assert_eq!(envelope.get_gain(2_000), 1f64);
+        assert_eq!(envelope.get_gain(9_000), 1f64);
+        assert_eq!(envelope.get_gain(10_000), 0.5);
+        assert_eq!(envelope.get_gain(11_000), 0f64);
+
+        let no_fades = VolumeEnvelope::new(1_000, 11_000, 0, 0);
+        assert_eq!(no_fades.get_gain(1_000), 1f64);
+        assert_eq!(no_fades.get_gain(11_000), 1f64);
+
+        // Fades overlap on short chapters
+        let short = VolumeEnvelope::new(0, 1_000, 2_000, 2_000);
+        assert_eq!(short.get_gain(500), 0.5);
+    }
+}
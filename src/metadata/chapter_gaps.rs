@@ -0,0 +1,140 @@
+use gstreamer as gst;
+
+use std::cmp;
+
+use super::chapter_tags::get_extended_comment;
+use super::split_options::remove_extended_comments;
+
+// Gaps are stored in the chapters tags as extended comments in the form `KEY=duration`
+// with the duration in nanoseconds
+// Audio located before the start of the chapter (Cue Sheet INDEX 00)
+pub const PREGAP_KEY: &str = "MEDIA_TOC_PREGAP";
+// Silences which are not part of the media (Cue Sheet PREGAP & POSTGAP)
+pub const SILENT_PREGAP_KEY: &str = "MEDIA_TOC_SILENT_PREGAP";
+pub const SILENT_POSTGAP_KEY: &str = "MEDIA_TOC_SILENT_POSTGAP";
+pub const GAP_KEYS: [&str; 3] = [PREGAP_KEY, SILENT_PREGAP_KEY, SILENT_POSTGAP_KEY];
+
+// Where to put the pregap of a chapter when splitting
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PregapMode {
+    AppendToPrevious,
+    PrependToCurrent,
+    Discard,
+}
+
+impl Default for PregapMode {
+    fn default() -> Self {
+        PregapMode::AppendToPrevious
+    }
+}
+
+// Returns the duration of the gap or 0 if the chapter has no such gap
+pub fn get_chapter_gap(chapter: &gst::TocEntry, key: &str) -> u64 {
+    chapter.get_tags().map_or(0, |tags| get_gap(&tags, key))
+}
+
+// Returns the duration of the gap defined in the chapter's `tags` or 0
+pub fn get_gap(tags: &gst::TagList, key: &str) -> u64 {
+    get_extended_comment(tags, key)
+        .and_then(|gap| gap.parse::<u64>().ok())
+        .unwrap_or(0)
+}
+
+pub fn add_chapter_gap(tags: &mut gst::TagListRef, key: &str, gap: u64) {
+    if gap > 0 {
+        tags.add::<gst::tags::ExtendedComment>(
+            &format!("{}={}", key, gap).as_str(),
+            gst::TagMergeMode::Append,
+        );
+    }
+}
+
+// Replaces the gap, removing it if `gap` is 0
+pub fn set_chapter_gap(tags: &mut gst::TagListRef, key: &str, gap: u64) {
+    remove_extended_comments(tags, &[key]);
+    add_chapter_gap(tags, key, gap);
+}
+
+// Returns the pregap of a chapter when its start moves from `start` to `new_start`
+// The pregap keeps starting at the same position, but it can't extend
+// before the start of the previous chapter (`prev_start`)
+pub fn get_moved_pregap(start: u64, pregap: u64, new_start: u64, prev_start: u64) -> u64 {
+    if pregap == 0 {
+        return 0;
+    }
+
+    let pregap_start = cmp::max(start.saturating_sub(pregap), prev_start);
+    new_start.saturating_sub(pregap_start)
+}
+
+// Computes the boundaries of the chapters to split from their
+// `(start, end, pregap)` depending on the `mode`
+// Note: chapters are contiguous and the pregap of a chapter is part of the previous
+// chapter, which corresponds to `AppendToPrevious`. The pregap of the first chapter
+// (the hidden track) is only kept with `PrependToCurrent`.
+pub fn get_split_boundaries(mode: PregapMode, chapters: &[(u64, u64, u64)]) -> Vec<(u64, u64)> {
+    chapters
+        .iter()
+        .enumerate()
+        .map(|(index, &(start, end, pregap))| {
+            if mode == PregapMode::AppendToPrevious {
+                return (start, end);
+            }
+
+            let start = if mode == PregapMode::PrependToCurrent {
+                start.saturating_sub(pregap)
+            } else {
+                start
+            };
+
+            let end = match chapters.get(index + 1) {
+                Some(&(next_start, _, next_pregap)) => {
+                    let next_pregap_start = next_start.saturating_sub(next_pregap);
+                    if next_pregap_start < end && next_pregap_start > start {
+                        next_pregap_start
+                    } else {
+                        end
+                    }
+                }
+                None => end,
+            };
+
+            (start, end)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_moved_pregap, get_split_boundaries, PregapMode};
+
+    const CHAPTERS: [(u64, u64, u64); 3] = [(2, 10, 2), (10, 20, 3), (20, 30, 0)];
+
+    #[test]
+    fn pregap_modes() {
+        assert_eq!(
+            get_split_boundaries(PregapMode::AppendToPrevious, &CHAPTERS),
+            vec![(2, 10), (10, 20), (20, 30)]
+        );
+        assert_eq!(
+            get_split_boundaries(PregapMode::PrependToCurrent, &CHAPTERS),
+            vec![(0, 7), (7, 20), (20, 30)]
+        );
+        assert_eq!(
+            get_split_boundaries(PregapMode::Discard, &CHAPTERS),
+            vec![(2, 7), (10, 20), (20, 30)]
+        );
+    }
+
+    #[test]
+    fn moved_pregap() {
+        // The pregap of the chapter starting at 10 starts at 7
+        assert_eq!(get_moved_pregap(10, 3, 12, 2), 5);
+        assert_eq!(get_moved_pregap(10, 3, 8, 2), 1);
+        assert_eq!(get_moved_pregap(10, 3, 6, 2), 0);
+        // The pregap can't extend before the previous chapter
+        assert_eq!(get_moved_pregap(10, 3, 12, 9), 3);
+        // No pregap
+        assert_eq!(get_moved_pregap(10, 0, 12, 2), 0);
+    }
+}
@@ -0,0 +1,278 @@
+use gettextrs::gettext;
+use glib::{StaticType, ToSendValue};
+use gstreamer as gst;
+
+use super::split_options::{parse_extended_comment, remove_extended_comments, FADE_IN_KEY,
+                           FADE_OUT_KEY, SPLIT_OPTION_KEYS, TRIM_SILENCE_KEY};
+
+// Chapters tags which are always proposed for edition
+// Note: the title is edited in the chapters list
+// and the split options are stored as extended comments (see `split_options`).
+// Other string tags can be added by name (see `is_editable_tag`)
+const DEFAULT_TAG_NAMES: &[&str] = &[
+    "artist",
+    "artist-sortname",
+    "album-artist",
+    "album-artist-sortname",
+    "composer",
+    "composer-sortname",
+    "conductor",
+    "performer",
+    "interpreted-by",
+    "genre",
+    "comment",
+    "isrc",
+    "copyright",
+    "license",
+    "publisher",
+    "organization",
+    "keywords",
+    "description",
+    "location",
+    "homepage",
+    "version",
+    "contact",
+];
+
+const TITLE_TAG_NAME: &str = "title";
+const EXTENDED_COMMENT_TAG_NAME: &str = "extended-comment";
+
+// Returns the name and the label of the tags proposed by default
+pub fn get_editable_tags() -> Vec<(&'static str, String)> {
+    DEFAULT_TAG_NAMES
+        .iter()
+        .chain(SPLIT_OPTION_KEYS.iter())
+        .map(|tag_name| (*tag_name, get_tag_label(tag_name)))
+        .collect()
+}
+
+pub fn get_tag_label(tag_name: &str) -> String {
+    match tag_name {
+        "artist" => gettext("Artist"),
+        "artist-sortname" => gettext("Artist (sort name)"),
+        "album-artist" => gettext("Album artist"),
+        "album-artist-sortname" => gettext("Album artist (sort name)"),
+        "composer" => gettext("Composer"),
+        "composer-sortname" => gettext("Composer (sort name)"),
+        "conductor" => gettext("Conductor"),
+        "performer" => gettext("Performer"),
+        "interpreted-by" => gettext("Interpreted by"),
+        "genre" => gettext("Genre"),
+        "comment" => gettext("Comment"),
+        "isrc" => gettext("ISRC"),
+        "copyright" => gettext("Copyright"),
+        "license" => gettext("License"),
+        "publisher" => gettext("Publisher"),
+        "organization" => gettext("Organization"),
+        "keywords" => gettext("Keywords"),
+        "description" => gettext("Description"),
+        "location" => gettext("Location"),
+        "homepage" => gettext("Homepage"),
+        "version" => gettext("Version"),
+        "contact" => gettext("Contact"),
+        FADE_IN_KEY => gettext("Split: fade in (ms)"),
+        FADE_OUT_KEY => gettext("Split: fade out (ms)"),
+        TRIM_SILENCE_KEY => gettext("Split: trim silence (yes/no)"),
+        _ => gst::tags::tag_get_nick(tag_name)
+            .map_or_else(|| tag_name.to_owned(), |nick| nick.to_owned()),
+    }
+}
+
+// Any registered GStreamer tag holding a string can be edited,
+// except for the title and the extended comments which are handled separately
+pub fn is_editable_tag(tag_name: &str) -> bool {
+    if SPLIT_OPTION_KEYS.contains(&tag_name) {
+        return true;
+    }
+
+    tag_name != TITLE_TAG_NAME && tag_name != EXTENDED_COMMENT_TAG_NAME
+        && gst::tags::tag_exists(tag_name)
+        && gst::tags::tag_get_type(tag_name) == String::static_type()
+}
+
+// Returns the names of the editable tags defined in `tags`
+pub fn get_tag_names(tags: &gst::TagList) -> Vec<String> {
+    (0..tags.n_tags())
+        .map(|idx| tags.nth_tag_name(idx as u32).to_owned())
+        .filter(|tag_name| is_editable_tag(tag_name))
+        .collect()
+}
+
+pub fn get_extended_comment(tags: &gst::TagList, key: &str) -> Option<String> {
+    tags.iter_tag::<gst::tags::ExtendedComment>()
+        .filter_map(|comment| {
+            parse_extended_comment(comment.get().unwrap())
+                .and_then(|(comment_key, value)| {
+                    if comment_key == key {
+                        Some(value.to_owned())
+                    } else {
+                        None
+                    }
+                })
+        })
+        .next()
+}
+
+pub fn get_tag_string(tags: &gst::TagList, tag_name: &str) -> Option<String> {
+    if SPLIT_OPTION_KEYS.contains(&tag_name) {
+        get_extended_comment(tags, tag_name)
+    } else {
+        tags.get_index_generic(tag_name, 0)
+            .and_then(|value| value.get::<String>())
+    }
+}
+
+// Returns a copy of `tags` with the tag set to `value` or removed if `value` is empty
+pub fn with_tag_string(
+    tags: &gst::TagList,
+    tag_name: &str,
+    value: &str,
+) -> Result<gst::TagList, String> {
+    if !is_editable_tag(tag_name) {
+        return Err(gettext("Can't edit tag {}").replacen("{}", tag_name, 1));
+    }
+
+    let mut new_tags = tags.copy();
+    {
+        let new_tags = new_tags.get_mut().unwrap();
+        if SPLIT_OPTION_KEYS.contains(&tag_name) {
+            // Split options share the extended comment tag with other comments
+            remove_extended_comments(new_tags, &[tag_name]);
+            if !value.is_empty() {
+                new_tags.add::<gst::tags::ExtendedComment>(
+                    &format!("{}={}", tag_name, value).as_str(),
+                    gst::TagMergeMode::Append,
+                );
+            }
+        } else if value.is_empty() {
+            new_tags.remove_generic(tag_name);
+        } else {
+            new_tags
+                .add_generic(tag_name, &value.to_send_value(), gst::TagMergeMode::Replace)
+                .map_err(|_| gettext("Can't edit tag {}").replacen("{}", tag_name, 1))?;
+        }
+    }
+
+    Ok(new_tags)
+}
+
+// Sets the editable tags defined in `chapter_tags` to `tags`,
+// replacing the values inherited from the media
+pub fn merge_chapter_tags(tags: &mut gst::TagListRef, chapter_tags: &gst::TagList) {
+    for tag_name in get_tag_names(chapter_tags) {
+        if SPLIT_OPTION_KEYS.contains(&tag_name.as_str()) {
+            continue;
+        }
+
+        if let Some(value) = chapter_tags.get_index_generic(&tag_name, 0) {
+            let res = tags.add_generic(&tag_name, value, gst::TagMergeMode::Replace);
+            if res.is_err() {
+                warn!("merge_chapter_tags couldn't set tag {}", tag_name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gstreamer as gst;
+
+    use super::*;
+
+    #[test]
+    fn edit_tags() {
+        gst::init().unwrap();
+
+        let mut tags = gst::TagList::new();
+        tags.get_mut()
+            .unwrap()
+            .add::<gst::tags::Title>(&"Track", gst::TagMergeMode::Replace);
+
+        let tags = with_tag_string(&tags, "artist", "Artist").unwrap();
+        let tags = with_tag_string(&tags, "composer", "Composer").unwrap();
+        assert_eq!(Some("Artist".to_owned()), get_tag_string(&tags, "artist"));
+        assert_eq!(Some("Composer".to_owned()), get_tag_string(&tags, "composer"));
+
+        let tags = with_tag_string(&tags, "artist", "").unwrap();
+        assert_eq!(None, get_tag_string(&tags, "artist"));
+        assert_eq!(Some("Composer".to_owned()), get_tag_string(&tags, "composer"));
+        assert_eq!(
+            Some("Track"),
+            tags.get_index::<gst::tags::Title>(0)
+                .map(|value| value.get().unwrap())
+        );
+
+        let mut track_tags = gst::TagList::new();
+        {
+            let track_tags = track_tags.get_mut().unwrap();
+            track_tags.add::<gst::tags::Composer>(&"Album Composer", gst::TagMergeMode::Replace);
+            track_tags.add::<gst::tags::Genre>(&"Album Genre", gst::TagMergeMode::Replace);
+            merge_chapter_tags(track_tags, &tags);
+        }
+        assert_eq!(Some("Composer".to_owned()), get_tag_string(&track_tags, "composer"));
+        assert_eq!(Some("Album Genre".to_owned()), get_tag_string(&track_tags, "genre"));
+    }
+
+    #[test]
+    fn edit_other_tags() {
+        gst::init().unwrap();
+
+        let mut tags = gst::TagList::new();
+        {
+            let tags = tags.get_mut().unwrap();
+            tags.add::<gst::tags::Title>(&"Track", gst::TagMergeMode::Replace);
+            tags.add::<gst::tags::Duration>(&(5 * gst::SECOND), gst::TagMergeMode::Replace);
+        }
+
+        assert!(is_editable_tag("lyrics"));
+        assert!(!is_editable_tag("title"));
+        assert!(!is_editable_tag("duration"));
+        assert!(!is_editable_tag("not-a-tag"));
+        assert!(with_tag_string(&tags, "not-a-tag", "value").is_err());
+
+        let tags = with_tag_string(&tags, "lyrics", "Lyrics").unwrap();
+        let tags = with_tag_string(&tags, "artist", "Artist").unwrap();
+        assert_eq!(Some("Lyrics".to_owned()), get_tag_string(&tags, "lyrics"));
+        assert_eq!(vec!["lyrics".to_owned(), "artist".to_owned()], get_tag_names(&tags));
+
+        // Removing a tag keeps the others untouched
+        let tags = with_tag_string(&tags, "artist", "").unwrap();
+        assert_eq!(None, get_tag_string(&tags, "artist"));
+        assert_eq!(Some("Lyrics".to_owned()), get_tag_string(&tags, "lyrics"));
+        assert_eq!(
+            Some(5 * gst::SECOND),
+            tags.get_index::<gst::tags::Duration>(0)
+                .map(|value| value.get().unwrap())
+        );
+        assert_eq!(1, tags.get_size::<gst::tags::Title>());
+
+        let mut track_tags = gst::TagList::new();
+        merge_chapter_tags(track_tags.get_mut().unwrap(), &tags);
+        assert_eq!(Some("Lyrics".to_owned()), get_tag_string(&track_tags, "lyrics"));
+        assert_eq!(0, track_tags.get_size::<gst::tags::Duration>());
+    }
+
+    #[test]
+    fn edit_split_options() {
+        gst::init().unwrap();
+
+        let tags = with_tag_string(&gst::TagList::new(), "artist", "Artist").unwrap();
+        let tags = with_tag_string(&tags, FADE_IN_KEY, "500").unwrap();
+        let tags = with_tag_string(&tags, TRIM_SILENCE_KEY, "yes").unwrap();
+        assert_eq!(Some("500".to_owned()), get_tag_string(&tags, FADE_IN_KEY));
+        assert_eq!(Some("yes".to_owned()), get_tag_string(&tags, TRIM_SILENCE_KEY));
+
+        let tags = with_tag_string(&tags, FADE_IN_KEY, "1000").unwrap();
+        assert_eq!(Some("1000".to_owned()), get_tag_string(&tags, FADE_IN_KEY));
+        assert_eq!(2, tags.iter_tag::<gst::tags::ExtendedComment>().count());
+
+        let tags = with_tag_string(&tags, TRIM_SILENCE_KEY, "").unwrap();
+        assert_eq!(None, get_tag_string(&tags, TRIM_SILENCE_KEY));
+        assert_eq!(Some("Artist".to_owned()), get_tag_string(&tags, "artist"));
+
+        // Split options are not copied to the tracks
+        let mut track_tags = gst::TagList::new();
+        merge_chapter_tags(track_tags.get_mut().unwrap(), &tags);
+        assert_eq!(None, get_tag_string(&track_tags, FADE_IN_KEY));
+    }
+}
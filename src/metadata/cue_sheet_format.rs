@@ -1,12 +1,16 @@
 use gettextrs::gettext;
 use gstreamer as gst;
 
-use std::io::Write;
+use std::io::{Read, Write};
 
-use super::{get_default_chapter_title, MediaInfo, Timestamp, TocVisitor, Writer};
+use super::chapter_gaps::{PREGAP_KEY, SILENT_POSTGAP_KEY, SILENT_PREGAP_KEY};
+use super::{add_chapter_gap, get_chapter_gap, get_default_chapter_title, get_tag_string,
+            MediaInfo, Reader, TocVisitor, Writer};
 
 static EXTENSION: &'static str = "cue";
 
+const FRAMES_PER_SECOND: u64 = 75;
+
 pub struct CueSheetFormat {}
 
 impl CueSheetFormat {
@@ -19,6 +23,239 @@ impl CueSheetFormat {
     }
 }
 
+// FILE type as defined by the Cue Sheet specifications
+fn get_file_type(info: &MediaInfo) -> &'static str {
+    let audio_codec = info.get_audio_codec()
+        .map_or_else(String::new, |audio_codec| audio_codec.to_lowercase());
+    if audio_codec.find("mp3").is_some() {
+        return "MP3";
+    }
+
+    let is_aiff = audio_codec.find("aiff").is_some()
+        || info.get_container()
+            .map_or(false, |container| container.to_lowercase().find("aiff").is_some())
+        || info.path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(false, |extension| {
+                let extension = extension.to_lowercase();
+                extension == "aif" || extension == "aiff" || extension == "aifc"
+            });
+
+    if is_aiff {
+        "AIFF"
+    } else {
+        "WAVE"
+    }
+}
+
+// Formats a duration as `mm:ss:ff` with 75 frames per second
+fn format_cue_time(nano: u64) -> String {
+    let frames = (nano as f64 * FRAMES_PER_SECOND as f64 / 1_000_000_000f64).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / (FRAMES_PER_SECOND * 60),
+        (frames / FRAMES_PER_SECOND) % 60,
+        frames % FRAMES_PER_SECOND
+    )
+}
+
+fn parse_cue_time(value: &str) -> Option<u64> {
+    let mut parts = value.split(':').map(|part| part.parse::<u64>().ok());
+    let minutes = parts.next()??;
+    let seconds = parts.next()??;
+    let frames = parts.next()??;
+    if parts.next().is_some() || seconds >= 60 || frames >= FRAMES_PER_SECOND {
+        return None;
+    }
+
+    Some(((minutes * 60 + seconds) * FRAMES_PER_SECOND + frames) * 1_000_000_000 / FRAMES_PER_SECOND)
+}
+
+// Removes the quotes around a text value
+fn parse_text(value: &str) -> String {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        value[1..value.len() - 1].to_owned()
+    } else {
+        value.to_owned()
+    }
+}
+
+// A track read from a Cue Sheet
+#[derive(Debug, Default, PartialEq)]
+struct CueTrack {
+    nb: usize,
+    title: Option<String>,
+    performer: Option<String>,
+    songwriter: Option<String>,
+    isrc: Option<String>,
+    index_00: Option<u64>,
+    index_01: Option<u64>,
+    silent_pregap: u64,
+    silent_postgap: u64,
+}
+
+fn parse_tracks(content: &str) -> Result<Vec<CueTrack>, String> {
+    let mut tracks = Vec::<CueTrack>::new();
+    let mut file_count = 0;
+
+    for (line_idx, line) in content.lines().enumerate() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let command = parts.next().unwrap().to_uppercase();
+        let value = parts.next().unwrap_or("").trim();
+
+        let invalid_line = || {
+            let msg = gettext("invalid {} at line {}")
+                .replacen("{}", &command, 1)
+                .replacen("{}", &format!("{}", line_idx + 1), 1);
+            error!("{}", msg);
+            msg
+        };
+
+        match command.as_str() {
+            "FILE" => {
+                file_count += 1;
+                if file_count > 1 {
+                    let msg = gettext("Cue Sheets with multiple files are not supported");
+                    error!("{}", msg);
+                    return Err(msg);
+                }
+            }
+            "TRACK" => {
+                let nb = value
+                    .split_whitespace()
+                    .next()
+                    .and_then(|nb| nb.parse::<usize>().ok())
+                    .ok_or_else(&invalid_line)?;
+                tracks.push(CueTrack {
+                    nb,
+                    ..CueTrack::default()
+                });
+            }
+            // Disc level commands
+            _ if tracks.is_empty() => (),
+            "TITLE" => tracks.last_mut().unwrap().title = Some(parse_text(value)),
+            "PERFORMER" => tracks.last_mut().unwrap().performer = Some(parse_text(value)),
+            "SONGWRITER" => tracks.last_mut().unwrap().songwriter = Some(parse_text(value)),
+            "ISRC" => tracks.last_mut().unwrap().isrc = Some(parse_text(value)),
+            "INDEX" => {
+                let mut index_parts = value.split_whitespace();
+                let index_nb = index_parts
+                    .next()
+                    .and_then(|nb| nb.parse::<usize>().ok())
+                    .ok_or_else(&invalid_line)?;
+                let time = index_parts
+                    .next()
+                    .and_then(parse_cue_time)
+                    .ok_or_else(&invalid_line)?;
+                let track = tracks.last_mut().unwrap();
+                match index_nb {
+                    0 => track.index_00 = Some(time),
+                    1 => track.index_01 = Some(time),
+                    // Subindices are not supported
+                    _ => (),
+                }
+            }
+            "PREGAP" => {
+                tracks.last_mut().unwrap().silent_pregap =
+                    parse_cue_time(value).ok_or_else(&invalid_line)?;
+            }
+            "POSTGAP" => {
+                tracks.last_mut().unwrap().silent_postgap =
+                    parse_cue_time(value).ok_or_else(&invalid_line)?;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(tracks)
+}
+
+fn new_chapter(track: &CueTrack, start: u64, end: u64) -> gst::TocEntry {
+    let mut chapter =
+        gst::TocEntry::new(gst::TocEntryType::Chapter, &format!("{:02}", track.nb));
+    chapter
+        .get_mut()
+        .unwrap()
+        .set_start_stop_times(start as i64, end as i64);
+
+    let mut tags = gst::TagList::new();
+    {
+        let tags = tags.get_mut().unwrap();
+        if let Some(ref title) = track.title {
+            tags.add::<gst::tags::Title>(&title.as_str(), gst::TagMergeMode::Replace);
+        }
+        if let Some(ref performer) = track.performer {
+            tags.add::<gst::tags::Artist>(&performer.as_str(), gst::TagMergeMode::Replace);
+        }
+        if let Some(ref songwriter) = track.songwriter {
+            tags.add::<gst::tags::Composer>(&songwriter.as_str(), gst::TagMergeMode::Replace);
+        }
+        if let Some(ref isrc) = track.isrc {
+            tags.add::<gst::tags::ISRC>(&isrc.as_str(), gst::TagMergeMode::Replace);
+        }
+
+        let pregap = track.index_00.map_or(0, |index_00| start.saturating_sub(index_00));
+        add_chapter_gap(tags, PREGAP_KEY, pregap);
+        add_chapter_gap(tags, SILENT_PREGAP_KEY, track.silent_pregap);
+        add_chapter_gap(tags, SILENT_POSTGAP_KEY, track.silent_postgap);
+    }
+    chapter.get_mut().unwrap().set_tags(tags);
+
+    chapter
+}
+
+impl Reader for CueSheetFormat {
+    fn read(&self, info: &MediaInfo, source: &mut Read) -> Result<Option<gst::Toc>, String> {
+        let mut content = Vec::new();
+        source.read_to_end(&mut content).map_err(|_| {
+            let msg = gettext("unexpected error reading Cue Sheet file.");
+            error!("{}", msg);
+            msg
+        })?;
+        // Cue Sheets are often encoded using legacy code pages
+        let tracks = parse_tracks(&String::from_utf8_lossy(&content))?;
+        if tracks.is_empty() {
+            return Ok(None);
+        }
+
+        let mut toc_edition = gst::TocEntry::new(gst::TocEntryType::Edition, "");
+        for (index, track) in tracks.iter().enumerate() {
+            let start = track.index_01.ok_or_else(|| {
+                let msg = gettext("missing INDEX 01 for track {}").replacen(
+                    "{}",
+                    &format!("{}", track.nb),
+                    1,
+                );
+                error!("{}", msg);
+                msg
+            })?;
+            // Chapters are contiguous, the pregap of the next track
+            // is part of the current chapter
+            let end = tracks
+                .get(index + 1)
+                .and_then(|next_track| next_track.index_01)
+                .unwrap_or(info.duration);
+            let end = if end > start { end } else { start };
+
+            toc_edition
+                .get_mut()
+                .unwrap()
+                .append_sub_entry(new_chapter(track, start, end));
+        }
+
+        let mut toc = gst::Toc::new(gst::TocScope::Global);
+        toc.get_mut().unwrap().append_entry(toc_edition);
+        Ok(Some(toc))
+    }
+}
+
 macro_rules! write_fmt(
     ($dest:ident, $fmt:expr, $( $item:expr ),*) => {
         $dest.write_fmt(format_args!($fmt, $( $item ),*)).map_err(|_| {
@@ -47,23 +284,11 @@ impl Writer for CueSheetFormat {
             write_fmt!(destination, "PERFORMER \"{}\"\n", artist);
         }
 
-        let audio_codec = match info.get_audio_codec() {
-            Some(audio_codec) => {
-                if audio_codec.to_lowercase().find("mp3").is_some() {
-                    "MP3"
-                } else if audio_codec.to_lowercase().find("aiff").is_some() {
-                    "AIFF"
-                } else {
-                    "WAVE"
-                }
-            }
-            None => "WAVE",
-        };
         write_fmt!(
             destination,
             "FILE \"{}\" {}\n",
             info.get_file_name(),
-            audio_codec
+            get_file_type(info)
         );
 
         let mut index = 0;
@@ -93,20 +318,112 @@ impl Writer for CueSheetFormat {
                 .unwrap_or_else(get_default_chapter_title);
             write_fmt!(destination, "    PERFORMER \"{}\"\n", &artist);
 
+            if let Some(tags) = chapter.get_tags() {
+                if let Some(composer) = get_tag_string(&tags, "composer") {
+                    write_fmt!(destination, "    SONGWRITER \"{}\"\n", &composer);
+                }
+                if let Some(isrc) = get_tag_string(&tags, "isrc") {
+                    write_fmt!(destination, "    ISRC {}\n", &isrc);
+                }
+            }
+
+            let silent_pregap = get_chapter_gap(&chapter, SILENT_PREGAP_KEY);
+            if silent_pregap > 0 {
+                write_fmt!(destination, "    PREGAP {}\n", format_cue_time(silent_pregap));
+            }
+
             if let Some((start, _end)) = chapter.get_start_stop_times() {
-                let start_ts = Timestamp::from_nano(start as u64);
-                write_fmt!(
-                    destination,
-                    "    INDEX 01 {:02}:{:02}:{:02}\n",
-                    start_ts.h * 60 + start_ts.m,
-                    start_ts.s,
-                    (((start_ts.ms * 1_000 + start_ts.us) * 1_000 + start_ts.nano) as f64
-                        / 1_000_000_000f64 * 75f64)
-                        .round() // frame nb (75 frames/s for Cue Sheets)
-                );
+                let start = start as u64;
+                let pregap = get_chapter_gap(&chapter, PREGAP_KEY);
+                if pregap > 0 {
+                    write_fmt!(
+                        destination,
+                        "    INDEX 00 {}\n",
+                        format_cue_time(start.saturating_sub(pregap))
+                    );
+                }
+                write_fmt!(destination, "    INDEX 01 {}\n", format_cue_time(start));
+            }
+
+            let silent_postgap = get_chapter_gap(&chapter, SILENT_POSTGAP_KEY);
+            if silent_postgap > 0 {
+                write_fmt!(destination, "    POSTGAP {}\n", format_cue_time(silent_postgap));
             }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use gstreamer as gst;
+    use std::path::Path;
+
+    use super::{format_cue_time, get_file_type, parse_cue_time, parse_tracks, CueTrack};
+    use metadata::MediaInfo;
+
+    #[test]
+    fn file_type() {
+        gst::init().unwrap();
+
+        assert_eq!(get_file_type(&MediaInfo::new(Path::new("test.aiff"))), "AIFF");
+        assert_eq!(get_file_type(&MediaInfo::new(Path::new("test.AIF"))), "AIFF");
+        assert_eq!(get_file_type(&MediaInfo::new(Path::new("test.wav"))), "WAVE");
+    }
+
+    #[test]
+    fn cue_time() {
+        assert_eq!(parse_cue_time("01:02:15"), Some(62_200_000_000));
+        assert_eq!(parse_cue_time("00:00:75"), None);
+        assert_eq!(parse_cue_time("00:00"), None);
+        assert_eq!(format_cue_time(62_200_000_000), "01:02:15");
+        // Rounding to the next second
+        assert_eq!(format_cue_time(1_999_999_999), "00:02:00");
+    }
+
+    #[test]
+    fn tracks() {
+        let tracks = parse_tracks(
+            "REM GENRE Rock\n\
+             PERFORMER \"Artist\"\n\
+             TITLE \"Album\"\n\
+             FILE \"album.wav\" WAVE\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   TITLE \"Hidden\"\n\
+             \x20   INDEX 00 00:00:00\n\
+             \x20   INDEX 01 00:02:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   TITLE \"Second\"\n\
+             \x20   PERFORMER \"Guest\"\n\
+             \x20   PREGAP 00:01:00\n\
+             \x20   INDEX 01 00:12:00\n\
+             \x20   POSTGAP 00:00:30\n",
+        ).unwrap();
+
+        assert_eq!(
+            tracks,
+            vec![
+                CueTrack {
+                    nb: 1,
+                    title: Some("Hidden".to_owned()),
+                    index_00: Some(0),
+                    index_01: Some(2_000_000_000),
+                    ..CueTrack::default()
+                },
+                CueTrack {
+                    nb: 2,
+                    title: Some("Second".to_owned()),
+                    performer: Some("Guest".to_owned()),
+                    index_01: Some(12_000_000_000),
+                    silent_pregap: 1_000_000_000,
+                    silent_postgap: 400_000_000,
+                    ..CueTrack::default()
+                },
+            ]
+        );
+
+        assert!(parse_tracks("FILE \"a.wav\" WAVE\nFILE \"b.wav\" WAVE\n").is_err());
+        assert!(parse_tracks("TRACK 01 AUDIO\nINDEX 01 00:61:00\n").is_err());
+    }
+}
@@ -8,14 +8,17 @@ impl Factory {
     pub fn get_extensions() -> Vec<(&'static str, Format)> {
         let mut result = Vec::<(&'static str, Format)>::new();
 
-        // Only MKVMergeTextFormat implemented for Read ATM
+        // Only MKVMergeTextFormat & CueSheetFormat implemented for Read ATM
         result.push((MKVMergeTextFormat::get_extension(), Format::MKVMergeText));
+        result.push((CueSheetFormat::get_extension(), Format::CueSheet));
 
         result
     }
 
     pub fn get_extension(format: &Format, is_audio_only: bool) -> &'static str {
         match *format {
+            Format::AAC | Format::ALAC => "m4a",
+            Format::AIFF => "aiff",
             Format::CueSheet => CueSheetFormat::get_extension(),
             Format::Flac => "flac",
             Format::Matroska => {
@@ -30,11 +33,13 @@ impl Factory {
             Format::Opus => "opus",
             Format::Vorbis => "oga",
             Format::Wave => "wave",
+            Format::WavPack => "wv",
         }
     }
 
     pub fn get_reader(format: &Format) -> Box<Reader> {
         match *format {
+            Format::CueSheet => CueSheetFormat::new_as_boxed(),
             Format::MKVMergeText => MKVMergeTextFormat::new_as_boxed(),
             format => unimplemented!("Reader for {:?}", format),
         }
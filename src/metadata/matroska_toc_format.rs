@@ -3,6 +3,7 @@ use glib::Cast;
 use gstreamer as gst;
 use gstreamer::{TagSetterExt, TocSetterExt};
 
+use super::split_options::strip_internal_tags;
 use super::{Exporter, MediaInfo};
 
 static EXTENSION: &'static str = "toc.mkv";
@@ -41,7 +42,8 @@ impl Exporter for MatroskaTocFormat {
                 .dynamic_cast::<gst::TocSetter>()
                 .expect("MatroskaTocFormat::export muxer is not a TocSetter");
 
-            toc_setter.set_toc(toc);
+            // Split options and gaps are specific to media-toc
+            toc_setter.set_toc(&strip_internal_tags(toc));
         }
     }
 }
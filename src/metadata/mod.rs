@@ -1,3 +1,11 @@
+pub mod chapter_gaps;
+pub use self::chapter_gaps::{add_chapter_gap, get_chapter_gap, get_gap, get_moved_pregap,
+                             get_split_boundaries, set_chapter_gap, PregapMode};
+
+pub mod chapter_tags;
+pub use self::chapter_tags::{get_editable_tags, get_tag_label, get_tag_names, get_tag_string,
+                             is_editable_tag, merge_chapter_tags, with_tag_string};
+
 pub mod cue_sheet_format;
 pub use self::cue_sheet_format::CueSheetFormat;
 
@@ -16,6 +24,16 @@ pub use self::media_info::{get_default_chapter_title, MediaInfo, Stream, Streams
 pub mod mkvmerge_text_format;
 pub use self::mkvmerge_text_format::MKVMergeTextFormat;
 
+pub mod naming_template;
+pub use self::naming_template::{deduplicate_names, get_tag_value, NamingTemplate, TemplateValue,
+                                DEFAULT_NAMING_TEMPLATE};
+
+pub mod playlist;
+pub use self::playlist::{Playlist, PlaylistFormat, PlaylistTrack};
+
+pub mod split_options;
+pub use self::split_options::SplitOptions;
+
 pub mod timestamp;
 pub use self::timestamp::{parse_timestamp, Timestamp};
 
@@ -24,6 +42,9 @@ pub use self::toc_visitor::{TocVisit, TocVisitor};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Format {
+    AAC,
+    AIFF,
+    ALAC,
     CueSheet,
     Flac,
     Matroska,
@@ -32,4 +53,5 @@ pub enum Format {
     Opus,
     Vorbis,
     Wave,
+    WavPack,
 }
@@ -0,0 +1,431 @@
+use gettextrs::gettext;
+use gstreamer as gst;
+
+use std::collections::HashSet;
+
+pub const DEFAULT_NAMING_TEMPLATE: &str =
+    "[{artist} - ][{album} - ][{track:02}. ]{title}[ ({language})]";
+
+// Characters which are not allowed in file names on FAT & NTFS
+const ILLEGAL_CHARS: [char; 8] = ['<', '>', ':', '"', '\\', '|', '?', '*'];
+const RESERVED_NAMES: [&str; 4] = ["CON", "PRN", "AUX", "NUL"];
+const PATH_SEPARATOR: char = '/';
+
+pub enum TemplateValue {
+    Text(String),
+    Number(u64),
+    // Each item is a distinct level in the hierarchy
+    Path(Vec<String>),
+}
+
+impl TemplateValue {
+    // Returns `None` if the value is empty
+    fn format(&self, padding: Option<&Padding>) -> Option<String> {
+        let formatted = match *self {
+            TemplateValue::Text(ref text) => {
+                let text = text.trim();
+                match (padding, text.parse::<u64>()) {
+                    (Some(padding), Ok(number)) => padding.pad(number),
+                    _ => sanitize_value(text),
+                }
+            }
+            TemplateValue::Number(number) => match padding {
+                Some(padding) => padding.pad(number),
+                None => format!("{}", number),
+            },
+            TemplateValue::Path(ref items) => items
+                .iter()
+                .map(|item| sanitize_value(item.trim()))
+                .filter(|item| !item.is_empty())
+                .collect::<Vec<String>>()
+                .join(&PATH_SEPARATOR.to_string()),
+        };
+
+        if formatted.is_empty() {
+            None
+        } else {
+            Some(formatted)
+        }
+    }
+}
+
+struct Padding {
+    width: usize,
+    with_zeros: bool,
+}
+
+impl Padding {
+    fn pad(&self, number: u64) -> String {
+        if self.with_zeros {
+            format!("{:01$}", number, self.width)
+        } else {
+            format!("{:>1$}", number, self.width)
+        }
+    }
+}
+
+enum Segment {
+    Literal(String),
+    Placeholder {
+        name: String,
+        padding: Option<Padding>,
+    },
+    // Rendered only if all the placeholders it contains are defined
+    Conditional(Vec<Segment>),
+}
+
+// A template to build the path of a split file, e.g.:
+// `{albumartist}/{album}/[{track:02} ]{title}`
+// - `{name}` is replaced by the value of the placeholder `name`.
+// - `{name:02}` pads a number with zeros up to 2 digits.
+// - `[...]` is rendered only if all the placeholders it contains are defined.
+// - `/` separates directories.
+// - `\` escapes the following character.
+pub struct NamingTemplate {
+    segments: Vec<Segment>,
+}
+
+impl NamingTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        if template.trim().is_empty() {
+            return Err(gettext("The template is empty"));
+        }
+
+        // Last item holds the segments of the innermost conditional
+        let mut stack = vec![Vec::<Segment>::new()];
+        let mut literal = String::new();
+
+        let mut chars = template.chars().enumerate();
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, c)) => literal.push(c),
+                    None => return Err(gettext("Unexpected end of template after `\\`")),
+                },
+                '{' => {
+                    push_literal(stack.last_mut().unwrap(), &mut literal);
+
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => placeholder.push(c),
+                            None => {
+                                return Err(gettext("Missing `}` for `{` at position {}")
+                                    .replacen("{}", &format!("{}", position + 1), 1))
+                            }
+                        }
+                    }
+
+                    let placeholder = parse_placeholder(&placeholder, position)?;
+                    stack.last_mut().unwrap().push(placeholder);
+                }
+                '[' => {
+                    push_literal(stack.last_mut().unwrap(), &mut literal);
+                    stack.push(Vec::new());
+                }
+                ']' => {
+                    if stack.len() == 1 {
+                        return Err(gettext("Unexpected `]` at position {}").replacen(
+                            "{}",
+                            &format!("{}", position + 1),
+                            1,
+                        ));
+                    }
+
+                    let mut segments = stack.pop().unwrap();
+                    push_literal(&mut segments, &mut literal);
+                    stack.last_mut().unwrap().push(Segment::Conditional(segments));
+                }
+                '}' => {
+                    return Err(gettext("Unexpected `}` at position {}").replacen(
+                        "{}",
+                        &format!("{}", position + 1),
+                        1,
+                    ))
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if stack.len() > 1 {
+            return Err(gettext("Missing `]` at the end of the template"));
+        }
+
+        let mut segments = stack.pop().unwrap();
+        push_literal(&mut segments, &mut literal);
+
+        Ok(NamingTemplate { segments })
+    }
+
+    // Returns a relative path with `/` as the directories separator
+    // or an empty `String` if nothing could be rendered
+    pub fn render<F>(&self, resolve: F) -> String
+    where
+        F: Fn(&str) -> Option<TemplateValue>,
+    {
+        let mut rendered = String::new();
+        render_segments(&self.segments, &resolve, &mut rendered);
+
+        rendered
+            .split(PATH_SEPARATOR)
+            .map(sanitize_component)
+            .filter(|component| !component.is_empty())
+            .collect::<Vec<String>>()
+            .join(&PATH_SEPARATOR.to_string())
+    }
+}
+
+fn push_literal(segments: &mut Vec<Segment>, literal: &mut String) {
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal.clone()));
+        literal.clear();
+    }
+}
+
+fn parse_placeholder(placeholder: &str, position: usize) -> Result<Segment, String> {
+    let mut parts = placeholder.splitn(2, ':');
+    let name = parts.next().unwrap().trim().to_lowercase();
+
+    let is_name_valid = !name.is_empty()
+        && name.chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    if !is_name_valid {
+        return Err(gettext("Invalid placeholder name `{}` at position {}")
+            .replacen("{}", &name, 1)
+            .replacen("{}", &format!("{}", position + 1), 1));
+    }
+
+    let padding = match parts.next().map(|spec| spec.trim()) {
+        Some(spec) => match spec.parse::<usize>() {
+            Ok(width) => Some(Padding {
+                width,
+                with_zeros: spec.starts_with('0'),
+            }),
+            Err(_) => {
+                return Err(gettext("Invalid width `{}` at position {}")
+                    .replacen("{}", spec, 1)
+                    .replacen("{}", &format!("{}", position + 1), 1))
+            }
+        },
+        None => None,
+    };
+
+    Ok(Segment::Placeholder { name, padding })
+}
+
+// Returns `true` if all the placeholders were defined
+fn render_segments<F>(segments: &[Segment], resolve: &F, output: &mut String) -> bool
+where
+    F: Fn(&str) -> Option<TemplateValue>,
+{
+    let mut is_complete = true;
+    for segment in segments {
+        match *segment {
+            Segment::Literal(ref text) => output.push_str(text),
+            Segment::Placeholder {
+                ref name,
+                ref padding,
+            } => match resolve(name).and_then(|value| value.format(padding.as_ref())) {
+                Some(value) => output.push_str(&value),
+                None => is_complete = false,
+            },
+            Segment::Conditional(ref segments) => {
+                let mut conditional = String::new();
+                if render_segments(segments, resolve, &mut conditional) {
+                    output.push_str(&conditional);
+                }
+            }
+        }
+    }
+
+    is_complete
+}
+
+// Values can't introduce directories
+fn sanitize_value(value: &str) -> String {
+    value.replace(PATH_SEPARATOR, "_").replace('\\', "_")
+}
+
+fn sanitize_component(component: &str) -> String {
+    let mut sanitized = component
+        .chars()
+        .map(|c| {
+            if c.is_control() || ILLEGAL_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .trim_start()
+        // Windows doesn't support names ending with a dot or a space
+        .trim_end_matches(|c| c == '.' || c == ' ')
+        .to_owned();
+
+    let is_reserved = {
+        let stem = sanitized.split('.').next().unwrap().to_uppercase();
+        RESERVED_NAMES.contains(&stem.as_str())
+            || ((stem.starts_with("COM") || stem.starts_with("LPT")) && stem.len() == 4
+                && stem.chars().last().unwrap().is_digit(10))
+    };
+    if is_reserved {
+        sanitized.push('_');
+    }
+
+    sanitized
+}
+
+// Appends `-N` to the names which were already rendered for a previous chapter
+// so that the split files don't overwrite each other
+// Note: names are compared regardless of case for case insensitive file systems
+pub fn deduplicate_names(names: &mut [String]) {
+    let mut used = names
+        .iter()
+        .map(|name| name.to_lowercase())
+        .collect::<HashSet<String>>();
+    let mut seen = HashSet::<String>::new();
+
+    for name in names.iter_mut() {
+        if seen.insert(name.to_lowercase()) {
+            continue;
+        }
+
+        let mut index = 2;
+        loop {
+            let candidate = format!("{}-{}", name, index);
+            if used.insert(candidate.to_lowercase()) {
+                seen.insert(candidate.to_lowercase());
+                *name = candidate;
+                break;
+            }
+            index += 1;
+        }
+    }
+}
+
+// Tags can be referred to by their GStreamer name, e.g. `album-artist`,
+// or by a simpler form, e.g. `albumartist` or `album_artist`
+fn get_tag_name(name: &str) -> String {
+    match name {
+        "albumartist" => "album-artist".to_owned(),
+        "albumartistsort" => "musicbrainz-albumartistsortname".to_owned(),
+        "disc" | "discnumber" => "album-disc-number".to_owned(),
+        "discs" | "disccount" => "album-disc-count".to_owned(),
+        "tracknumber" => "track-number".to_owned(),
+        "trackcount" => "track-count".to_owned(),
+        _ => name.replace('_', "-"),
+    }
+}
+
+pub fn get_tag_value(tags: &gst::TagList, name: &str) -> Option<TemplateValue> {
+    let value = tags.get_index_generic(&get_tag_name(name), 0)?;
+
+    if let Some(text) = value.get::<String>() {
+        Some(TemplateValue::Text(text))
+    } else if let Some(number) = value.get::<u32>() {
+        Some(TemplateValue::Number(u64::from(number)))
+    } else if let Some(number) = value.get::<u64>() {
+        Some(TemplateValue::Number(number))
+    } else if let Some(number) = value.get::<f64>() {
+        Some(TemplateValue::Text(format!("{}", number)))
+    } else if let Some(date_time) = value.get::<gst::DateTime>() {
+        Some(TemplateValue::Number(date_time.get_year() as u64))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deduplicate_names, NamingTemplate, TemplateValue, DEFAULT_NAMING_TEMPLATE};
+
+    fn resolve(name: &str) -> Option<TemplateValue> {
+        match name {
+            "artist" => Some(TemplateValue::Text("AC/DC".to_owned())),
+            "album" => Some(TemplateValue::Text("Live: Vol. 1".to_owned())),
+            "track" => Some(TemplateValue::Number(3)),
+            "title" => Some(TemplateValue::Text("Who? Me...".to_owned())),
+            "chapter_path" => Some(TemplateValue::Path(vec![
+                "Part 1".to_owned(),
+                "Side A".to_owned(),
+            ])),
+            "empty" => Some(TemplateValue::Text(" ".to_owned())),
+            _ => None,
+        }
+    }
+
+    fn render(template: &str) -> String {
+        NamingTemplate::parse(template).unwrap().render(resolve)
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(render("{track:02}. {title}"), "03. Who_ Me");
+        assert_eq!(render("#{track:3} {TITLE}"), "#  3 Who_ Me");
+        assert_eq!(render("{artist} - {unknown}{title}"), "AC_DC - Who_ Me");
+        assert_eq!(render("\\{{track}\\}"), "{3}");
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_eq!(
+            render(DEFAULT_NAMING_TEMPLATE),
+            "AC_DC - Live_ Vol. 1 - 03. Who_ Me"
+        );
+        assert_eq!(render("[{unknown} - ]{title}"), "Who_ Me");
+        assert_eq!(render("[{empty} - ]{title}"), "Who_ Me");
+        assert_eq!(render("[{artist}[ ({unknown})] - ]{title}"), "AC_DC - Who_ Me");
+    }
+
+    #[test]
+    fn directories() {
+        assert_eq!(
+            render("{artist}/{album}/{track:02} {title}"),
+            "AC_DC/Live_ Vol. 1/03 Who_ Me"
+        );
+        assert_eq!(
+            render("{unknown}/{chapter_path}/{title}"),
+            "Part 1/Side A/Who_ Me"
+        );
+        assert_eq!(render("../{title}"), "Who_ Me");
+        assert_eq!(render("nul/{title}"), "nul_/Who_ Me");
+    }
+
+    #[test]
+    fn duplicates() {
+        // The same album for all the chapters
+        let mut names = vec![
+            render("{album}"),
+            render("{album}"),
+            "Live_ Vol. 1-2".to_owned(),
+            render("{album}"),
+            render("{title}"),
+            "who_ me".to_owned(),
+        ];
+        deduplicate_names(&mut names);
+        assert_eq!(
+            names,
+            vec![
+                "Live_ Vol. 1",
+                "Live_ Vol. 1-3",
+                "Live_ Vol. 1-2",
+                "Live_ Vol. 1-4",
+                "Who_ Me",
+                "who_ me-2",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(NamingTemplate::parse("").is_err());
+        assert!(NamingTemplate::parse("{title").is_err());
+        assert!(NamingTemplate::parse("title}").is_err());
+        assert!(NamingTemplate::parse("[{title}").is_err());
+        assert!(NamingTemplate::parse("{title}]").is_err());
+        assert!(NamingTemplate::parse("{}").is_err());
+        assert!(NamingTemplate::parse("{track:x}").is_err());
+        assert!(NamingTemplate::parse("{title}\\").is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use gettextrs::gettext;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaylistFormat {
+    M3U8,
+    PLS,
+    XSPF,
+}
+
+impl PlaylistFormat {
+    pub fn get_extension(&self) -> &'static str {
+        match *self {
+            PlaylistFormat::M3U8 => "m3u8",
+            PlaylistFormat::PLS => "pls",
+            PlaylistFormat::XSPF => "xspf",
+        }
+    }
+}
+
+pub struct PlaylistTrack {
+    pub path: PathBuf,
+    pub title: String,
+    pub artist: Option<String>,
+    pub duration: u64,
+}
+
+impl PlaylistTrack {
+    // Tracks are usually displayed as `Artist - Title` in playlists
+    fn get_display_title(&self) -> String {
+        match self.artist {
+            Some(ref artist) => format!("{} - {}", artist, self.title),
+            None => self.title.clone(),
+        }
+    }
+
+    fn get_duration_s(&self) -> u64 {
+        (self.duration + 500_000_000) / 1_000_000_000
+    }
+}
+
+// A list of tracks, paths are written relative to the directory of the playlist
+pub struct Playlist {
+    base_dir: PathBuf,
+    tracks: Vec<PlaylistTrack>,
+}
+
+macro_rules! write_fmt(
+    ($dest:ident, $fmt:expr, $( $item:expr ),*) => {
+        $dest.write_fmt(format_args!($fmt, $( $item ),*)).map_err(|_| {
+            let msg = gettext("Failed to write playlist");
+            error!("{}", msg);
+            msg
+        })?;
+    };
+);
+
+impl Playlist {
+    pub fn new(base_dir: &Path) -> Self {
+        Playlist {
+            base_dir: base_dir.to_owned(),
+            tracks: Vec::new(),
+        }
+    }
+
+    pub fn add_track(&mut self, track: PlaylistTrack) {
+        self.tracks.push(track);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    fn get_relative_path(&self, track: &PlaylistTrack) -> String {
+        track
+            .path
+            .strip_prefix(&self.base_dir)
+            .unwrap_or(&track.path)
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    fn get_location(&self, track: &PlaylistTrack) -> String {
+        let path = self.get_relative_path(track);
+        if Path::new(&path).is_absolute() {
+            format!("file://{}", encode_uri_path(&path))
+        } else {
+            encode_uri_path(&path)
+        }
+    }
+
+    pub fn write(&self, format: PlaylistFormat, destination: &mut Write) -> Result<(), String> {
+        match format {
+            PlaylistFormat::M3U8 => self.write_m3u8(destination),
+            PlaylistFormat::PLS => self.write_pls(destination),
+            PlaylistFormat::XSPF => self.write_xspf(destination),
+        }
+    }
+
+    fn write_m3u8(&self, destination: &mut Write) -> Result<(), String> {
+        write_fmt!(destination, "{}\n", "#EXTM3U");
+        for track in &self.tracks {
+            write_fmt!(
+                destination,
+                "#EXTINF:{},{}\n{}\n",
+                track.get_duration_s(),
+                track.get_display_title(),
+                self.get_relative_path(track)
+            );
+        }
+
+        Ok(())
+    }
+
+    fn write_pls(&self, destination: &mut Write) -> Result<(), String> {
+        write_fmt!(destination, "{}\n", "[playlist]");
+        for (index, track) in self.tracks.iter().enumerate() {
+            let nb = index + 1;
+            write_fmt!(
+                destination,
+                "File{}={}\nTitle{}={}\nLength{}={}\n",
+                nb,
+                self.get_relative_path(track),
+                nb,
+                track.get_display_title(),
+                nb,
+                track.get_duration_s()
+            );
+        }
+        write_fmt!(
+            destination,
+            "NumberOfEntries={}\nVersion=2\n",
+            self.tracks.len()
+        );
+
+        Ok(())
+    }
+
+    fn write_xspf(&self, destination: &mut Write) -> Result<(), String> {
+        write_fmt!(
+            destination,
+            "{}\n{}\n  <trackList>\n",
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>",
+            "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">"
+        );
+        for (index, track) in self.tracks.iter().enumerate() {
+            write_fmt!(
+                destination,
+                "    <track>\n      <location>{}</location>\n      <title>{}</title>\n",
+                escape_xml(&self.get_location(track)),
+                escape_xml(&track.title)
+            );
+            if let Some(ref artist) = track.artist {
+                write_fmt!(destination, "      <creator>{}</creator>\n", escape_xml(artist));
+            }
+            write_fmt!(
+                destination,
+                "      <trackNum>{}</trackNum>\n      <duration>{}</duration>\n    </track>\n",
+                index + 1,
+                track.duration / 1_000_000
+            );
+        }
+        write_fmt!(destination, "{}\n", "  </trackList>\n</playlist>");
+
+        Ok(())
+    }
+}
+
+// XSPF locations are URIs
+fn encode_uri_path(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded += &format!("%{:02X}", byte),
+        }
+    }
+    encoded
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Playlist, PlaylistFormat, PlaylistTrack};
+
+    fn get_playlist() -> Playlist {
+        let mut playlist = Playlist::new(Path::new("/music"));
+        playlist.add_track(PlaylistTrack {
+            path: Path::new("/music/Album/01. Intro.flac").to_owned(),
+            title: "Intro".to_owned(),
+            artist: Some("Me & You".to_owned()),
+            duration: 61_600_000_000,
+        });
+        playlist.add_track(PlaylistTrack {
+            path: Path::new("/other/02. Outro.flac").to_owned(),
+            title: "Outro".to_owned(),
+            artist: None,
+            duration: 30_000_000_000,
+        });
+        playlist
+    }
+
+    fn write(format: PlaylistFormat) -> String {
+        let mut output = Vec::<u8>::new();
+        get_playlist().write(format, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn m3u8() {
+        assert_eq!(
+            write(PlaylistFormat::M3U8),
+            "#EXTM3U\n\
+             #EXTINF:62,Me & You - Intro\nAlbum/01. Intro.flac\n\
+             #EXTINF:30,Outro\n/other/02. Outro.flac\n",
+        );
+    }
+
+    #[test]
+    fn pls() {
+        assert_eq!(
+            write(PlaylistFormat::PLS),
+            "[playlist]\n\
+             File1=Album/01. Intro.flac\nTitle1=Me & You - Intro\nLength1=62\n\
+             File2=/other/02. Outro.flac\nTitle2=Outro\nLength2=30\n\
+             NumberOfEntries=2\nVersion=2\n",
+        );
+    }
+
+    #[test]
+    fn xspf() {
+        let xspf = write(PlaylistFormat::XSPF);
+        assert!(xspf.contains("<location>Album/01.%20Intro.flac</location>"));
+        assert!(xspf.contains("<location>file:///other/02.%20Outro.flac</location>"));
+        assert!(xspf.contains("<creator>Me &amp; You</creator>"));
+        assert!(xspf.contains("<trackNum>2</trackNum>\n      <duration>30000</duration>"));
+        assert!(xspf.ends_with("</trackList>\n</playlist>\n"));
+    }
+}
@@ -0,0 +1,236 @@
+use gettextrs::gettext;
+use gstreamer as gst;
+
+use super::chapter_gaps::GAP_KEYS;
+
+// Chapters can override the split options using extended comments
+// in the form `KEY=value`
+pub const FADE_IN_KEY: &str = "MEDIA_TOC_FADE_IN"; // ms
+pub const FADE_OUT_KEY: &str = "MEDIA_TOC_FADE_OUT"; // ms
+pub const TRIM_SILENCE_KEY: &str = "MEDIA_TOC_TRIM_SILENCE"; // yes / no
+pub const SPLIT_OPTION_KEYS: [&str; 3] = [FADE_IN_KEY, FADE_OUT_KEY, TRIM_SILENCE_KEY];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SplitOptions {
+    pub fade_in: u64,  // ns
+    pub fade_out: u64, // ns
+    pub trim_silence: bool,
+}
+
+impl SplitOptions {
+    pub fn new(fade_in_ms: u32, fade_out_ms: u32, trim_silence: bool) -> Self {
+        SplitOptions {
+            fade_in: u64::from(fade_in_ms) * 1_000_000,
+            fade_out: u64::from(fade_out_ms) * 1_000_000,
+            trim_silence,
+        }
+    }
+
+    pub fn has_fades(&self) -> bool {
+        self.fade_in > 0 || self.fade_out > 0
+    }
+
+    // Returns the options with the overrides defined in the chapter's tags
+    pub fn with_overrides(&self, tags: &gst::TagList) -> Self {
+        let mut options = *self;
+
+        for comment in tags.iter_tag::<gst::tags::ExtendedComment>() {
+            let comment = comment.get().unwrap();
+            let (key, value) = match parse_extended_comment(comment) {
+                Some(key_value) => key_value,
+                None => continue,
+            };
+
+            let is_valid = match key {
+                FADE_IN_KEY => parse_duration_ms(value).map(|fade_in| options.fade_in = fade_in),
+                FADE_OUT_KEY => {
+                    parse_duration_ms(value).map(|fade_out| options.fade_out = fade_out)
+                }
+                TRIM_SILENCE_KEY => parse_bool(value).map(|trim| options.trim_silence = trim),
+                _ => Some(()),
+            }.is_some();
+
+            if !is_valid {
+                warn!(
+                    "{}",
+                    gettext("Ignoring invalid split option `{}`").replacen("{}", comment, 1)
+                );
+            }
+        }
+
+        options
+    }
+}
+
+// Removes the extended comments defining one of the `keys`
+pub fn remove_extended_comments(tags: &mut gst::TagListRef, keys: &[&str]) {
+    let comments = tags.iter_tag::<gst::tags::ExtendedComment>()
+        .map(|comment| comment.get().unwrap().to_owned())
+        .collect::<Vec<String>>();
+
+    tags.remove::<gst::tags::ExtendedComment>();
+    for comment in &comments {
+        let is_removed =
+            parse_extended_comment(comment).map_or(false, |(key, _)| keys.contains(&key));
+        if !is_removed {
+            tags.add::<gst::tags::ExtendedComment>(&comment.as_str(), gst::TagMergeMode::Append);
+        }
+    }
+}
+
+// Returns a copy of `toc` without the split options and the gaps
+// which are only meaningful to media-toc
+pub fn strip_internal_tags(toc: &gst::Toc) -> gst::Toc {
+    let keys = SPLIT_OPTION_KEYS
+        .iter()
+        .chain(GAP_KEYS.iter())
+        .cloned()
+        .collect::<Vec<&str>>();
+
+    let mut stripped_toc = gst::Toc::new(toc.get_scope());
+    {
+        let stripped_toc = stripped_toc.get_mut().unwrap();
+        if let Some(tags) = toc.get_tags() {
+            stripped_toc.set_tags(tags);
+        }
+        for entry in toc.get_entries() {
+            stripped_toc.append_entry(strip_entry(&entry, &keys));
+        }
+    }
+    stripped_toc
+}
+
+fn strip_entry(entry: &gst::TocEntry, keys: &[&str]) -> gst::TocEntry {
+    let mut stripped_entry = gst::TocEntry::new(entry.get_entry_type(), &entry.get_uid());
+    {
+        let stripped_entry = stripped_entry.get_mut().unwrap();
+        if let Some((start, stop)) = entry.get_start_stop_times() {
+            stripped_entry.set_start_stop_times(start, stop);
+        }
+        if let Some((loop_type, repeat_count)) = entry.get_loop() {
+            stripped_entry.set_loop(loop_type, repeat_count);
+        }
+        if let Some(mut tags) = entry.get_tags() {
+            remove_extended_comments(tags.make_mut(), keys);
+            stripped_entry.set_tags(tags);
+        }
+        for sub_entry in entry.get_sub_entries() {
+            stripped_entry.append_sub_entry(strip_entry(&sub_entry, keys));
+        }
+    }
+    stripped_entry
+}
+
+// Splits an extended comment in the form `KEY=value`
+pub fn parse_extended_comment(comment: &str) -> Option<(&str, &str)> {
+    let mut parts = comment.splitn(2, '=');
+    let key = parts.next()?.trim();
+    let value = parts.next()?.trim();
+    if key.is_empty() {
+        None
+    } else {
+        Some((key, value))
+    }
+}
+
+fn parse_duration_ms(value: &str) -> Option<u64> {
+    value.parse::<u64>().ok().map(|ms| ms * 1_000_000)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" | "true" | "1" => Some(true),
+        "no" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gstreamer as gst;
+
+    use super::*;
+
+    #[test]
+    fn overrides() {
+        gst::init().unwrap();
+
+        let defaults = SplitOptions::new(500, 1_000, false);
+        assert!(defaults.has_fades());
+        assert!(!SplitOptions::new(0, 0, true).has_fades());
+
+        let mut tags = gst::TagList::new();
+        assert_eq!(defaults, defaults.with_overrides(&tags));
+
+        {
+            let tags = tags.get_mut().unwrap();
+            for comment in &[
+                "MEDIA_TOC_FADE_IN=0",
+                "MEDIA_TOC_FADE_OUT=invalid",
+                "MEDIA_TOC_TRIM_SILENCE=Yes",
+                "REPLAYGAIN_TRACK_GAIN=-3.2 dB",
+            ] {
+                tags.add::<gst::tags::ExtendedComment>(comment, gst::TagMergeMode::Append);
+            }
+        }
+
+        let options = defaults.with_overrides(&tags);
+        assert_eq!(options.fade_in, 0);
+        assert_eq!(options.fade_out, 1_000_000_000);
+        assert!(options.trim_silence);
+    }
+
+    #[test]
+    fn strip_toc() {
+        gst::init().unwrap();
+
+        let mut tags = gst::TagList::new();
+        {
+            let tags = tags.get_mut().unwrap();
+            tags.add::<gst::tags::Title>(&"Chapter", gst::TagMergeMode::Replace);
+            tags.add::<gst::tags::ExtendedComment>(
+                &"MEDIA_TOC_FADE_IN=500",
+                gst::TagMergeMode::Append,
+            );
+            for comment in &[
+                "MEDIA_TOC_PREGAP=2000000000",
+                "MEDIA_TOC_SILENT_PREGAP=1000000000",
+                "MEDIA_TOC_SILENT_POSTGAP=500000000",
+            ] {
+                tags.add::<gst::tags::ExtendedComment>(comment, gst::TagMergeMode::Append);
+            }
+            tags.add::<gst::tags::ExtendedComment>(&"KEPT=value", gst::TagMergeMode::Append);
+        }
+
+        let mut chapter = gst::TocEntry::new(gst::TocEntryType::Chapter, "1");
+        {
+            let chapter = chapter.get_mut().unwrap();
+            chapter.set_start_stop_times(1, 2);
+            chapter.set_tags(tags);
+        }
+        let mut edition = gst::TocEntry::new(gst::TocEntryType::Edition, "edition");
+        edition.get_mut().unwrap().append_sub_entry(chapter);
+        let mut toc = gst::Toc::new(gst::TocScope::Global);
+        toc.get_mut().unwrap().append_entry(edition);
+
+        let stripped_toc = strip_internal_tags(&toc);
+        let chapter = &stripped_toc.get_entries()[0].get_sub_entries()[0];
+        assert_eq!(Some((1, 2)), chapter.get_start_stop_times());
+        let tags = chapter.get_tags().unwrap();
+        assert_eq!(
+            Some("Chapter"),
+            tags.get::<gst::tags::Title>().map(|value| value.get().unwrap())
+        );
+        let comments = tags.iter_tag::<gst::tags::ExtendedComment>()
+            .map(|comment| comment.get().unwrap().to_owned())
+            .collect::<Vec<String>>();
+        assert_eq!(vec!["KEPT=value".to_owned()], comments);
+
+        // The original TOC is left untouched
+        let chapter = &toc.get_entries()[0].get_sub_entries()[0];
+        assert_eq!(
+            5,
+            chapter.get_tags().unwrap().iter_tag::<gst::tags::ExtendedComment>().count()
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use cairo;
 use gdk;
-use gdk::{Cursor, CursorType, FrameClockExt, WindowExt};
+use gdk::{Cursor, CursorType, FrameClockExt, ModifierType, ScrollDirection, WindowExt};
 use gio;
 use gio::prelude::*;
 use glib;
@@ -17,18 +17,26 @@ use std::rc::Rc;
 
 use std::sync::{Arc, Mutex};
 
-use media::{DoubleAudioBuffer, PlaybackContext, SampleExtractor, QUEUE_SIZE_NS};
+use application::CONFIG;
+
+use media::{ChannelMix, DoubleAudioBuffer, PlaybackContext, SampleExtractor, SnapTarget,
+            QUEUE_SIZE_NS};
 
 use metadata::{MediaInfo, Timestamp};
 
-use super::{ChaptersBoundaries, DoubleWaveformBuffer, MainController, WaveformBuffer,
-            BACKGROUND_COLOR};
+use super::{get_amplitude_ratio, AmplitudeMode, ChannelLane, ChaptersBoundaries,
+            DoubleWaveformBuffer, MainController, MeterController, SilenceController,
+            SpectrogramSettings, WaveformBuffer, BACKGROUND_COLOR};
 
 const BUFFER_DURATION: u64 = 60_000_000_000; // 60 s
 const MIN_REQ_DURATION: f64 = 1_953_125f64; // 2 ms / 1000 px
 const MAX_REQ_DURATION: f64 = 32_000_000_000f64; // 32 s / 1000 px
 const INIT_REQ_DURATION: f64 = 4_000_000_000f64; // 4 s / 1000 px
 const STEP_REQ_DURATION: f64 = 2f64;
+// Zoom factor applied for each step of the mouse wheel
+const SCROLL_ZOOM_FACTOR: f64 = 1.25f64;
+// Ratio of the window width moved for each step of the mouse wheel
+const SCROLL_PAN_RATIO: f64 = 0.1f64;
 
 const SEEK_STEP_DURATION_DIVISOR: u64 = 2;
 
@@ -38,8 +46,17 @@ const OTHER_UI_REFRESH_PERIOD: u64 = 50_000_000; // 50 ms
 // Range playback
 const MIN_RANGE_DURATION: u64 = 100_000_000; // 100 ms
 
+const SELECTION_COLOR: (f64, f64, f64, f64) = (0.5f64, 0.6f64, 1f64, 0.25f64);
+
 const HOUR_IN_NANO: u64 = 3_600_000_000_000;
 
+// Levels marked on the amplitude ruler
+const DB_RULER_LEVELS: [i32; 11] = [0, -3, -6, -12, -18, -24, -36, -48, -60, -80, -100];
+const DB_RULER_TICK_WIDTH: f64 = 5f64;
+
+const MUTE_TEXT: &str = "M";
+const SOLO_TEXT: &str = "S";
+
 // Use this text to compute the largest text box for the waveform boundaries
 // This is required to position the labels in such a way that they don't
 // move constantly depending on the digits width
@@ -54,6 +71,28 @@ pub enum ControllerState {
     MovingBoundary(u64),
     Playing,
     Paused,
+    SelectingRange(u64), // position where the selection started
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ChannelToggle {
+    Mute,
+    Solo,
+}
+
+// Area of the waveform where a click toggles the state of a channel
+struct ChannelButton {
+    channel: usize,
+    toggle: ChannelToggle,
+    x: f64,
+    y: f64,
+    size: f64,
+}
+
+impl ChannelButton {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && x <= self.x + self.size && y >= self.y && y <= self.y + self.size
+    }
 }
 
 pub struct AudioController {
@@ -62,6 +101,21 @@ pub struct AudioController {
     drawingarea: gtk::DrawingArea,
     zoom_in_btn: gtk::ToolButton,
     zoom_out_btn: gtk::ToolButton,
+    follow_btn: gtk::ToggleToolButton,
+    zoom_gesture: gtk::GestureZoom,
+    loop_selection_btn: gtk::ToolButton,
+    selection_chapter_btn: gtk::ToolButton,
+    export_selection_btn: gtk::ToolButton,
+    snap_btn: gtk::ToggleToolButton,
+    snap_target_combo: gtk::ComboBoxText,
+    snap_window_spin: gtk::SpinButton,
+    amplitude_mode_combo: gtk::ComboBoxText,
+    db_floor_spin: gtk::SpinButton,
+    lanes_chk: gtk::CheckButton,
+    spectrogram_btn: gtk::ToggleToolButton,
+    spectrogram_window_combo: gtk::ComboBoxText,
+    spectrogram_overlap_combo: gtk::ComboBoxText,
+    spectrogram_log_scale_chk: gtk::CheckButton,
     ref_lbl: gtk::Label,
 
     font_family: Option<(String)>,
@@ -79,6 +133,7 @@ pub struct AudioController {
     playback_needs_refresh: bool,
 
     requested_duration: f64,
+    pinch_start: Option<(f64, f64)>, // (requested_duration, anchor x)
     seek_step: u64,
     current_position: u64,
     last_other_ui_refresh: u64,
@@ -87,6 +142,18 @@ pub struct AudioController {
     sample_duration: u64,
     sample_step: f64,
     boundaries: Rc<RefCell<ChaptersBoundaries>>,
+    // Levels meter, refreshed with the waveform
+    meter_ctrl: Rc<RefCell<MeterController>>,
+    // Position where the boundary being moved is snapped
+    snap_position: Option<u64>,
+    selection: Option<(u64, u64)>,
+    amplitude_mode: AmplitudeMode,
+    db_floor: f64,
+    channel_mix: ChannelMix,
+    channel_buttons: Vec<ChannelButton>,
+
+    // Proposed chapters boundaries
+    silence_ctrl: Rc<RefCell<SilenceController>>,
 
     waveform_mtx: Arc<Mutex<Box<SampleExtractor>>>,
     pub dbl_buffer_mtx: Arc<Mutex<DoubleAudioBuffer>>,
@@ -101,16 +168,48 @@ impl AudioController {
     pub fn new(
         builder: &gtk::Builder,
         boundaries: Rc<RefCell<ChaptersBoundaries>>,
+        meter_ctrl: Rc<RefCell<MeterController>>,
+        silence_ctrl: Rc<RefCell<SilenceController>>,
     ) -> Rc<RefCell<Self>> {
         let dbl_buffer_mtx = DoubleWaveformBuffer::new_mutex(BUFFER_DURATION);
         let waveform_mtx = dbl_buffer_mtx.lock().unwrap().get_exposed_buffer_mtx();
 
+        let drawingarea: gtk::DrawingArea = builder.get_object("audio-drawingarea").unwrap();
+        let zoom_gesture = gtk::GestureZoom::new(&drawingarea);
+
         let this = Rc::new(RefCell::new(AudioController {
             window: builder.get_object("application-window").unwrap(),
             container: builder.get_object("audio-container").unwrap(),
-            drawingarea: builder.get_object("audio-drawingarea").unwrap(),
+            drawingarea,
             zoom_in_btn: builder.get_object("audio_zoom_in-toolbutton").unwrap(),
             zoom_out_btn: builder.get_object("audio_zoom_out-toolbutton").unwrap(),
+            follow_btn: builder.get_object("audio_follow-toggletoolbutton").unwrap(),
+            zoom_gesture,
+            loop_selection_btn: builder
+                .get_object("audio_loop_selection-toolbutton")
+                .unwrap(),
+            selection_chapter_btn: builder
+                .get_object("audio_selection_chapter-toolbutton")
+                .unwrap(),
+            export_selection_btn: builder
+                .get_object("audio_export_selection-toolbutton")
+                .unwrap(),
+            snap_btn: builder.get_object("audio_snap-toggletoolbutton").unwrap(),
+            snap_target_combo: builder.get_object("waveform_snap_target-combo").unwrap(),
+            snap_window_spin: builder.get_object("waveform_snap_window-spin").unwrap(),
+            amplitude_mode_combo: builder
+                .get_object("waveform_amplitude_mode-combo")
+                .unwrap(),
+            db_floor_spin: builder.get_object("waveform_db_floor-spin").unwrap(),
+            lanes_chk: builder.get_object("waveform_lanes-check").unwrap(),
+            spectrogram_btn: builder
+                .get_object("audio_spectrogram-toggletoolbutton")
+                .unwrap(),
+            spectrogram_window_combo: builder.get_object("spectrogram_window-combo").unwrap(),
+            spectrogram_overlap_combo: builder.get_object("spectrogram_overlap-combo").unwrap(),
+            spectrogram_log_scale_chk: builder
+                .get_object("spectrogram_log_scale-check")
+                .unwrap(),
             ref_lbl: builder.get_object("title-caption").unwrap(),
 
             font_family: None,
@@ -128,6 +227,7 @@ impl AudioController {
             playback_needs_refresh: false,
 
             requested_duration: INIT_REQ_DURATION,
+            pinch_start: None,
             seek_step: INIT_REQ_DURATION as u64 / SEEK_STEP_DURATION_DIVISOR,
 
             current_position: 0,
@@ -137,6 +237,15 @@ impl AudioController {
             sample_duration: 0,
             sample_step: 0f64,
             boundaries,
+            meter_ctrl,
+            silence_ctrl,
+            snap_position: None,
+            selection: None,
+            amplitude_mode: CONFIG.read().unwrap().waveform.amplitude_mode,
+            db_floor: f64::from(CONFIG.read().unwrap().waveform.db_floor),
+            channel_mix: ChannelMix::default(),
+            channel_buttons: Vec::new(),
+
 
             waveform_mtx,
             dbl_buffer_mtx,
@@ -216,22 +325,81 @@ impl AudioController {
                 if 1 == event_button.get_button() {
                     // left button
                     let mut this = this_clone.borrow_mut();
-                    if let ControllerState::MovingBoundary(_boundary) = this.state {
-                        this.state = ControllerState::Paused;
-
-                        match this.get_boundary_at(event_button.get_position().0) {
-                            Some(_boundary) => {
-                                this.set_cursor(CursorType::SbHDoubleArrow);
-                            }
-                            None => {
-                                this.reset_cursor();
+                    match this.state {
+                        ControllerState::MovingBoundary(_boundary) => {
+                            this.state = ControllerState::Paused;
+                            this.snap_position = None;
+                            this.redraw();
+
+                            match this.get_boundary_at(event_button.get_position().0) {
+                                Some(_boundary) => {
+                                    this.set_cursor(CursorType::SbHDoubleArrow);
+                                }
+                                None => {
+                                    this.reset_cursor();
+                                }
                             }
                         }
+                        ControllerState::SelectingRange(_start) => {
+                            this.state = ControllerState::Paused;
+                            // Discard selections too short to be heard
+                            let selection = match this.selection {
+                                Some((start, end)) if end - start >= MIN_RANGE_DURATION => {
+                                    Some((start, end))
+                                }
+                                _ => None,
+                            };
+                            this.set_selection(selection);
+                        }
+                        _ => (),
                     }
                 }
                 Inhibit(true)
             });
 
+        // Scroll in drawing_area: zoom while Ctrl is pressed, move the window otherwise
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        this.drawingarea.connect_scroll_event(move |_, event_scroll| {
+            let refill_position = this_clone.borrow_mut().scroll(event_scroll);
+            if let Some(position) = refill_position {
+                // Samples are only decoded around the playback position
+                main_ctrl_clone.borrow_mut().seek(position, true); // accurate (slow)
+            }
+            Inhibit(true)
+        });
+
+        // Pinch in drawing_area
+        let this_clone = Rc::clone(this_rc);
+        this.zoom_gesture.connect_begin(move |gesture, _sequence| {
+            let mut this = this_clone.borrow_mut();
+            let anchor_x = gesture
+                .get_bounding_box_center()
+                .map_or(this.area_width / 2f64, |(x, _y)| x);
+            this.pinch_start = Some((this.requested_duration, anchor_x));
+        });
+        let this_clone = Rc::clone(this_rc);
+        this.zoom_gesture.connect_scale_changed(move |_, scale| {
+            let mut this = this_clone.borrow_mut();
+            if let Some((requested_duration, anchor_x)) = this.pinch_start {
+                if scale > 0f64 {
+                    this.zoom_at(requested_duration / scale, anchor_x);
+                }
+            }
+        });
+        let this_clone = Rc::clone(this_rc);
+        this.zoom_gesture.connect_end(move |_, _sequence| {
+            this_clone.borrow_mut().pinch_start = None;
+        });
+
+        let this_clone = Rc::clone(this_rc);
+        this.follow_btn.connect_toggled(move |toggle_btn| {
+            // The AudioController is already borrowed when it moves the window itself
+            if let Ok(mut this) = this_clone.try_borrow_mut() {
+                this.follow_playhead_toggled(toggle_btn.get_active());
+            }
+        });
+
         // Register Zoom in action
         let zoom_in = gio::SimpleAction::new("zoom_in", None);
         gtk_app.add_action(&zoom_in);
@@ -297,6 +465,160 @@ impl AudioController {
             main_ctrl.seek(seek_pos, true); // accurate (slow)
         });
         gtk_app.set_accels_for_action("app.step_back", &["Left"]);
+
+        // Register Loop selection action
+        let loop_selection = gio::SimpleAction::new("loop_selection", None);
+        gtk_app.add_action(&loop_selection);
+        let this_clone = Rc::clone(&this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        loop_selection.connect_activate(move |_, _| {
+            let (selection, current_position) = {
+                let this = this_clone.borrow();
+                (this.selection, this.current_position)
+            };
+            if let Some((start, end)) = selection {
+                main_ctrl_clone
+                    .borrow_mut()
+                    .loop_range(start, end, current_position);
+            }
+        });
+        gtk_app.set_accels_for_action("app.loop_selection", &["<Shift>r"]);
+
+        // Register Add selection chapter action
+        let add_selection_chapter = gio::SimpleAction::new("add_selection_chapter", None);
+        gtk_app.add_action(&add_selection_chapter);
+        let this_clone = Rc::clone(&this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        add_selection_chapter.connect_activate(move |_, _| {
+            let selection = this_clone.borrow().selection;
+            if let Some((start, end)) = selection {
+                main_ctrl_clone.borrow_mut().add_range_chapter(start, end);
+                this_clone.borrow().redraw();
+            }
+        });
+
+        // Register Export selection action
+        let export_selection = gio::SimpleAction::new("export_selection", None);
+        gtk_app.add_action(&export_selection);
+        let this_clone = Rc::clone(&this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        export_selection.connect_activate(move |_, _| {
+            let selection = this_clone.borrow().selection;
+            if let Some((start, end)) = selection {
+                main_ctrl_clone.borrow_mut().export_range(start, end);
+            }
+        });
+        gtk_app.set_accels_for_action("app.export_selection", &["<Ctrl>e"]);
+
+        {
+            let config = CONFIG.read().unwrap();
+            this.snap_btn.set_active(config.waveform.is_snap_enabled);
+            this.snap_target_combo
+                .set_active_id(get_snap_target_id(config.waveform.snap_target));
+            this.snap_window_spin
+                .set_value(f64::from(config.waveform.snap_window_ms));
+        }
+        this.snap_btn.connect_toggled(|toggle_btn| {
+            CONFIG.write().unwrap().waveform.is_snap_enabled = toggle_btn.get_active();
+        });
+        this.snap_target_combo.connect_changed(|combo| {
+            if let Some(snap_target) = combo.get_active_id().and_then(|id| get_snap_target(&id)) {
+                CONFIG.write().unwrap().waveform.snap_target = snap_target;
+            }
+        });
+        this.snap_window_spin.connect_value_changed(|spin_btn| {
+            CONFIG.write().unwrap().waveform.snap_window_ms = spin_btn.get_value_as_int() as u32;
+        });
+
+        this.amplitude_mode_combo
+            .set_active_id(get_amplitude_mode_id(this.amplitude_mode));
+        this.db_floor_spin.set_value(this.db_floor);
+        this.update_amplitude_mode();
+
+        let this_clone = Rc::clone(this_rc);
+        this.amplitude_mode_combo.connect_changed(move |combo| {
+            if let Some(amplitude_mode) = combo
+                .get_active_id()
+                .and_then(|id| get_amplitude_mode(&id))
+            {
+                CONFIG.write().unwrap().waveform.amplitude_mode = amplitude_mode;
+                let mut this = this_clone.borrow_mut();
+                this.amplitude_mode = amplitude_mode;
+                this.amplitude_mode_changed();
+            }
+        });
+        let this_clone = Rc::clone(this_rc);
+        this.db_floor_spin.connect_value_changed(move |spin_btn| {
+            let db_floor = spin_btn.get_value_as_int();
+            CONFIG.write().unwrap().waveform.db_floor = db_floor;
+            let mut this = this_clone.borrow_mut();
+            this.db_floor = f64::from(db_floor);
+            this.amplitude_mode_changed();
+        });
+
+        this.lanes_chk
+            .set_active(CONFIG.read().unwrap().waveform.is_lanes_layout);
+        this.update_lanes_layout();
+
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        this.lanes_chk.connect_toggled(move |check_btn| {
+            let is_lanes_layout = check_btn.get_active();
+            CONFIG.write().unwrap().waveform.is_lanes_layout = is_lanes_layout;
+            if !is_lanes_layout {
+                // The mute and solo buttons are only available in the lanes layout
+                AudioController::reset_channel_mix(&this_clone, &main_ctrl_clone);
+            }
+            this_clone.borrow_mut().lanes_layout_changed();
+        });
+
+        {
+            let config = CONFIG.read().unwrap();
+            this.spectrogram_btn
+                .set_active(config.spectrogram.is_enabled);
+            this.spectrogram_window_combo
+                .set_active_id(&config.spectrogram.window_size.to_string());
+            this.spectrogram_overlap_combo
+                .set_active_id(&config.spectrogram.overlap.to_string());
+            this.spectrogram_log_scale_chk
+                .set_active(config.spectrogram.is_log_scale);
+        }
+        this.update_spectrogram_settings();
+
+        let this_clone = Rc::clone(this_rc);
+        this.spectrogram_btn.connect_toggled(move |toggle_btn| {
+            CONFIG.write().unwrap().spectrogram.is_enabled = toggle_btn.get_active();
+            this_clone.borrow_mut().spectrogram_settings_changed();
+        });
+        let this_clone = Rc::clone(this_rc);
+        this.spectrogram_window_combo.connect_changed(move |combo| {
+            if let Some(window_size) = combo.get_active_id().and_then(|id| id.parse().ok()) {
+                CONFIG.write().unwrap().spectrogram.window_size = window_size;
+                this_clone.borrow_mut().spectrogram_settings_changed();
+            }
+        });
+        let this_clone = Rc::clone(this_rc);
+        this.spectrogram_overlap_combo.connect_changed(move |combo| {
+            if let Some(overlap) = combo.get_active_id().and_then(|id| id.parse().ok()) {
+                CONFIG.write().unwrap().spectrogram.overlap = overlap;
+                this_clone.borrow_mut().spectrogram_settings_changed();
+            }
+        });
+        let this_clone = Rc::clone(this_rc);
+        this.spectrogram_log_scale_chk
+            .connect_toggled(move |check_btn| {
+                CONFIG.write().unwrap().spectrogram.is_log_scale = check_btn.get_active();
+                this_clone.borrow_mut().spectrogram_settings_changed();
+            });
+    }
+
+    // Returns the range of positions displayed in the waveform
+    pub fn get_visible_range(&self) -> Option<(u64, u64)> {
+        if self.state == ControllerState::Disabled || self.last_visible_pos == 0 {
+            None
+        } else {
+            Some((self.first_visible_pos, self.last_visible_pos))
+        }
     }
 
     pub fn redraw(&self) {
@@ -307,6 +629,9 @@ impl AudioController {
         self.state = ControllerState::Disabled;
         self.zoom_in_btn.set_sensitive(false);
         self.zoom_out_btn.set_sensitive(false);
+        self.follow_btn.set_sensitive(false);
+        self.follow_btn.set_active(true);
+        self.pinch_start = None;
         self.reset_cursor();
         self.playback_needs_refresh = false;
         self.dbl_buffer_mtx.lock().unwrap().cleanup();
@@ -318,6 +643,10 @@ impl AudioController {
         self.last_visible_pos = 0;
         self.sample_duration = 0;
         self.sample_step = 0f64;
+        self.channel_mix = ChannelMix::default();
+        self.channel_buttons.clear();
+        self.update_channel_mix();
+        self.set_selection(None);
         // AudioController accesses self.boundaries as readonly
         // clearing it is under the responsiblity of ChapterTreeManager
         self.update_conditions();
@@ -325,6 +654,8 @@ impl AudioController {
     }
 
     pub fn new_media(&mut self, context: &PlaybackContext) {
+        context.set_channel_mix(&self.channel_mix);
+
         let is_audio_selected = {
             let info = context.info.read().unwrap();
             self.streams_changed(&info);
@@ -349,6 +680,7 @@ impl AudioController {
             debug!("streams_changed audio selected");
             self.zoom_in_btn.set_sensitive(true);
             self.zoom_out_btn.set_sensitive(true);
+            self.follow_btn.set_sensitive(true);
             self.container.show();
         } else {
             debug!("streams_changed audio not selected");
@@ -356,6 +688,16 @@ impl AudioController {
         }
     }
 
+    fn set_selection(&mut self, selection: Option<(u64, u64)>) {
+        self.selection = selection;
+
+        let has_selection = selection.is_some();
+        self.loop_selection_btn.set_sensitive(has_selection);
+        self.selection_chapter_btn.set_sensitive(has_selection);
+        self.export_selection_btn.set_sensitive(has_selection);
+        self.redraw();
+    }
+
     pub fn get_seek_back_1st_position(&self, target: u64) -> Option<u64> {
         let (lower_pos, upper_pos, half_window_duration) = {
             let waveform_grd = self.waveform_mtx.lock().unwrap();
@@ -464,6 +806,7 @@ impl AudioController {
                     }
 
                     this.redraw();
+                    this.meter_ctrl.borrow_mut().tick(this.current_position);
                     glib::Continue(true)
                 }),
         );
@@ -518,6 +861,47 @@ impl AudioController {
         range.next().map(|(boundary, _chapters)| *boundary)
     }
 
+    // Returns the position where a boundary moved to `position` should be snapped
+    fn get_snap_position(&self, position: u64) -> Option<u64> {
+        let (snap_target, snap_window) = {
+            let config = CONFIG.read().unwrap();
+            if !config.waveform.is_snap_enabled {
+                return None;
+            }
+            (
+                config.waveform.snap_target,
+                u64::from(config.waveform.snap_window_ms) * 1_000_000,
+            )
+        };
+
+        self.dbl_buffer_mtx
+            .lock()
+            .unwrap()
+            .get_snap_position(position, snap_window, snap_target)
+    }
+
+    fn get_candidate_at(&self, x: f64) -> Option<u64> {
+        let position = self.get_position_at(x)?;
+
+        let delta = if self.sample_step > 0f64 {
+            self.sample_step as u64 * self.sample_duration * 2
+        } else {
+            1
+        };
+
+        let lower_bound = if position >= delta {
+            position - delta
+        } else {
+            0
+        };
+        self.silence_ctrl
+            .borrow()
+            .get_candidates()
+            .iter()
+            .find(|&&candidate| candidate >= lower_bound && candidate <= position + delta)
+            .cloned()
+    }
+
     fn adjust_waveform_text_width(&mut self, cr: &cairo::Context) {
         match self.font_family {
             Some(ref family) => {
@@ -549,6 +933,17 @@ impl AudioController {
     }
 
     fn update_conditions(&mut self) {
+        // When the window doesn't follow the playhead, zoom around its center
+        let zoom_anchor_x = if self.follow_btn.get_active() {
+            None
+        } else {
+            Some(self.area_width / 2f64)
+        };
+        self.update_conditions_around(zoom_anchor_x);
+    }
+
+    // See `WaveformBuffer::update_conditions` for `zoom_anchor_x`
+    fn update_conditions_around(&mut self, zoom_anchor_x: Option<f64>) {
         if self.state != ControllerState::Disabled {
             debug!(
                 "update_conditions {}, {}x{}",
@@ -565,17 +960,375 @@ impl AudioController {
                     self.requested_duration,
                     self.area_width as i32,
                     self.area_height as i32,
+                    zoom_anchor_x,
                 );
             }
             self.refresh();
         }
     }
 
+    // Returns the position to seek to when the window is moved past the available samples
+    fn scroll(&mut self, event_scroll: &gdk::EventScroll) -> Option<u64> {
+        if self.state == ControllerState::Disabled {
+            return None;
+        }
+
+        let (delta_x, delta_y) = match event_scroll.get_direction() {
+            ScrollDirection::Up => (0f64, -1f64),
+            ScrollDirection::Down => (0f64, 1f64),
+            ScrollDirection::Left => (-1f64, 0f64),
+            ScrollDirection::Right => (1f64, 0f64),
+            ScrollDirection::Smooth => event_scroll.get_delta(),
+            _ => return None,
+        };
+
+        if event_scroll
+            .get_state()
+            .contains(ModifierType::CONTROL_MASK)
+        {
+            // scrolling up zooms in
+            let requested_duration = self.requested_duration * SCROLL_ZOOM_FACTOR.powf(delta_y);
+            self.zoom_at(requested_duration, event_scroll.get_position().0);
+            None
+        } else {
+            // both the vertical wheel and the horizontal scroll move the window
+            self.pan((delta_x + delta_y) * SCROLL_PAN_RATIO * self.area_width);
+            self.refresh();
+
+            if self.state == ControllerState::Paused {
+                self.waveform_mtx
+                    .lock()
+                    .unwrap()
+                    .as_any()
+                    .downcast_ref::<WaveformBuffer>()
+                    .unwrap()
+                    .get_panned_refill_position()
+            } else {
+                None
+            }
+        }
+    }
+
+    // Zoom to `requested_duration` keeping the sample at `anchor_x` in place
+    // or the cursor when the window follows the playhead
+    fn zoom_at(&mut self, requested_duration: f64, anchor_x: f64) {
+        if self.state == ControllerState::Disabled {
+            return;
+        }
+
+        self.requested_duration = requested_duration
+            .max(MIN_REQ_DURATION)
+            .min(MAX_REQ_DURATION);
+        self.seek_step = self.requested_duration as u64 / SEEK_STEP_DURATION_DIVISOR;
+
+        let zoom_anchor_x = if self.follow_btn.get_active() {
+            None
+        } else {
+            Some(anchor_x)
+        };
+        self.update_conditions_around(zoom_anchor_x);
+    }
+
+    // Move the window by `delta_x` pixels, which stops following the playhead
+    fn pan(&mut self, delta_x: f64) {
+        // follow_btn's handler won't be able to borrow self
+        // so the window is updated below
+        self.follow_btn.set_active(false);
+
+        self.waveform_mtx
+            .lock()
+            .unwrap()
+            .as_mut_any()
+            .downcast_mut::<WaveformBuffer>()
+            .unwrap()
+            .pan(delta_x);
+    }
+
+    fn follow_playhead_toggled(&mut self, is_following: bool) {
+        {
+            let waveform_grd = &mut *self.waveform_mtx.lock().unwrap();
+            let waveform_buffer = waveform_grd
+                .as_mut_any()
+                .downcast_mut::<WaveformBuffer>()
+                .unwrap();
+            if is_following {
+                waveform_buffer.follow_playhead();
+            } else {
+                waveform_buffer.pan(0f64);
+            }
+        }
+
+        if self.state != ControllerState::Disabled {
+            self.refresh();
+        }
+    }
+
+    fn update_amplitude_mode(&self) {
+        self.waveform_mtx
+            .lock()
+            .unwrap()
+            .as_mut_any()
+            .downcast_mut::<WaveformBuffer>()
+            .unwrap()
+            .update_amplitude_mode(self.amplitude_mode, self.db_floor);
+    }
+
+    fn amplitude_mode_changed(&mut self) {
+        self.update_amplitude_mode();
+        if self.state != ControllerState::Disabled {
+            self.refresh();
+        }
+    }
+
+    fn update_lanes_layout(&self) {
+        let is_lanes_layout = CONFIG.read().unwrap().waveform.is_lanes_layout;
+        self.waveform_mtx
+            .lock()
+            .unwrap()
+            .as_mut_any()
+            .downcast_mut::<WaveformBuffer>()
+            .unwrap()
+            .update_lanes_layout(is_lanes_layout);
+    }
+
+    fn lanes_layout_changed(&mut self) {
+        self.update_lanes_layout();
+        if self.state != ControllerState::Disabled {
+            self.refresh();
+        }
+    }
+
+    fn update_channel_mix(&self) {
+        self.waveform_mtx
+            .lock()
+            .unwrap()
+            .as_mut_any()
+            .downcast_mut::<WaveformBuffer>()
+            .unwrap()
+            .update_channel_mix(&self.channel_mix);
+    }
+
+    // Mutes or solos `channel` both in the waveform and in the playback
+    fn toggle_channel(
+        this_rc: &Rc<RefCell<AudioController>>,
+        main_ctrl: &Rc<RefCell<MainController>>,
+        channel: usize,
+        toggle: ChannelToggle,
+    ) {
+        let channel_mix = {
+            let mut this = this_rc.borrow_mut();
+            match toggle {
+                ChannelToggle::Mute => this.channel_mix.toggle_mute(channel),
+                ChannelToggle::Solo => this.channel_mix.toggle_solo(channel),
+            }
+            this.update_channel_mix();
+            this.refresh();
+            this.channel_mix.clone()
+        };
+
+        main_ctrl.borrow().set_channel_mix(&channel_mix);
+    }
+
+    fn reset_channel_mix(
+        this_rc: &Rc<RefCell<AudioController>>,
+        main_ctrl: &Rc<RefCell<MainController>>,
+    ) {
+        {
+            let mut this = this_rc.borrow_mut();
+            if this.channel_mix == ChannelMix::default() {
+                return;
+            }
+            this.channel_mix = ChannelMix::default();
+            this.update_channel_mix();
+        }
+
+        main_ctrl.borrow().set_channel_mix(&ChannelMix::default());
+    }
+
+    fn get_channel_button_at(&self, x: f64, y: f64) -> Option<(usize, ChannelToggle)> {
+        self.channel_buttons
+            .iter()
+            .find(|button| button.contains(x, y))
+            .map(|button| (button.channel, button.toggle))
+    }
+
+    fn update_spectrogram_settings(&self) {
+        let (is_visible, settings) = {
+            let config = CONFIG.read().unwrap();
+            (
+                config.spectrogram.is_enabled,
+                SpectrogramSettings::new(
+                    config.spectrogram.window_size as usize,
+                    config.spectrogram.overlap as usize,
+                    config.spectrogram.is_log_scale,
+                ),
+            )
+        };
+
+        self.waveform_mtx
+            .lock()
+            .unwrap()
+            .as_mut_any()
+            .downcast_mut::<WaveformBuffer>()
+            .unwrap()
+            .set_spectrogram_settings(is_visible, settings);
+    }
+
+    fn spectrogram_settings_changed(&mut self) {
+        self.update_spectrogram_settings();
+        if self.state != ControllerState::Disabled {
+            self.refresh();
+        }
+    }
+
     pub fn refresh(&mut self) {
         self.dbl_buffer_mtx.lock().unwrap().refresh();
         self.redraw();
     }
 
+    // Draw the level marks of the amplitude on the right side
+    // of the lane centered on `center_y`
+    // Shades the selected range
+    fn draw_selection(&self, cr: &cairo::Context, last_visible_pos: u64) {
+        let (start, end) = match self.selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        if self.sample_duration == 0 || end < self.first_visible_pos || start > last_visible_pos {
+            return;
+        }
+
+        let start = start.max(self.first_visible_pos);
+        let end = end.min(last_visible_pos);
+        let start_x = ((start - self.first_visible_pos) / self.sample_duration) as f64
+            / self.sample_step;
+        let end_x =
+            ((end - self.first_visible_pos) / self.sample_duration) as f64 / self.sample_step;
+
+        cr.set_source_rgba(
+            SELECTION_COLOR.0,
+            SELECTION_COLOR.1,
+            SELECTION_COLOR.2,
+            SELECTION_COLOR.3,
+        );
+        cr.rectangle(start_x, 0f64, (end_x - start_x).max(1f64), self.area_height);
+        cr.fill();
+    }
+
+    fn draw_db_ruler(&self, cr: &cairo::Context, center_y: f64, half_height: f64) {
+        let tick_x = self.area_width - DB_RULER_TICK_WIDTH;
+        // Don't overlap with the positions at the top of the waveform
+        let min_text_y = self.twice_font_size + self.font_size;
+
+        cr.set_source_rgb(0.6f64, 0.6f64, 0.6f64);
+        cr.set_line_width(1f64);
+
+        let mut prev_offset: Option<f64> = None;
+        for level in DB_RULER_LEVELS.iter() {
+            let level = f64::from(*level);
+            if self.amplitude_mode == AmplitudeMode::Logarithmic && level < self.db_floor {
+                break;
+            }
+
+            let ratio =
+                get_amplitude_ratio(10f64.powf(level / 20f64), self.amplitude_mode, self.db_floor);
+            let offset = ratio * half_height;
+            if prev_offset.map_or(false, |prev_offset| prev_offset - offset < self.font_size) {
+                // Too close to the previous mark
+                continue;
+            }
+            prev_offset = Some(offset);
+
+            for y in &[center_y - offset, center_y + offset] {
+                cr.move_to(tick_x, *y);
+                cr.line_to(self.area_width, *y);
+                cr.stroke();
+            }
+
+            let text_y = center_y - offset + self.half_font_size;
+            if text_y >= min_text_y {
+                let text = format!("{}", level);
+                cr.move_to(tick_x - 2f64 - cr.text_extents(&text).width, text_y);
+                cr.show_text(&text);
+            }
+        }
+    }
+
+    // Draw the separations and the labels of the channel lanes
+    // along with the buttons to mute and solo each channel
+    fn draw_channel_lanes(&mut self, cr: &cairo::Context, lanes: &[ChannelLane]) {
+        self.channel_buttons.clear();
+        if lanes.is_empty() {
+            return;
+        }
+
+        let button_size = self.font_size + 4f64;
+        let label_width = lanes
+            .iter()
+            .map(|lane| cr.text_extents(lane.label).width)
+            .fold(0f64, f64::max);
+        // Don't overlap with the positions at the top of the waveform
+        let min_y = self.twice_font_size + 4f64;
+
+        cr.set_line_width(1f64);
+        for lane in lanes {
+            if lane.top > 0f64 {
+                cr.set_source_rgb(0.4f64, 0.4f64, 0.4f64);
+                cr.move_to(0f64, lane.top.floor() + 0.5f64);
+                cr.line_to(self.area_width, lane.top.floor() + 0.5f64);
+                cr.stroke();
+            }
+
+            let y = (lane.top + (lane.height - button_size) / 2f64)
+                .max(min_y)
+                .floor();
+            let text_y = y + button_size - 3f64;
+
+            cr.set_source_rgb(0.8f64, 0.8f64, 0.8f64);
+            cr.move_to(4f64, text_y);
+            cr.show_text(lane.label);
+
+            let mut x = 8f64 + label_width;
+            for &(toggle, text, is_active, color) in &[
+                (
+                    ChannelToggle::Mute,
+                    MUTE_TEXT,
+                    self.channel_mix.is_muted(lane.channel),
+                    (0.9f64, 0.3f64, 0.2f64),
+                ),
+                (
+                    ChannelToggle::Solo,
+                    SOLO_TEXT,
+                    self.channel_mix.is_soloed(lane.channel),
+                    (0.9f64, 0.8f64, 0.2f64),
+                ),
+            ] {
+                cr.rectangle(x + 0.5f64, y + 0.5f64, button_size - 1f64, button_size - 1f64);
+                if is_active {
+                    cr.set_source_rgb(color.0, color.1, color.2);
+                    cr.fill();
+                    cr.set_source_rgb(BACKGROUND_COLOR.0, BACKGROUND_COLOR.1, BACKGROUND_COLOR.2);
+                } else {
+                    cr.set_source_rgb(0.6f64, 0.6f64, 0.6f64);
+                    cr.stroke();
+                }
+
+                let text_width = cr.text_extents(text).width;
+                cr.move_to(x + (button_size - text_width) / 2f64, text_y);
+                cr.show_text(text);
+
+                self.channel_buttons.push(ChannelButton {
+                    channel: lane.channel,
+                    toggle,
+                    x,
+                    y,
+                    size: button_size,
+                });
+                x += button_size + 2f64;
+            }
+        }
+    }
+
     fn draw(
         &mut self,
         main_ctrl: &Rc<RefCell<MainController>>,
@@ -591,7 +1344,7 @@ impl AudioController {
             return Inhibit(false);
         }
 
-        let (current_position, image_positions) = {
+        let (current_position, image_positions, is_spectrogram_visible, channel_lanes) = {
             let waveform_grd = &mut *self.waveform_mtx.lock().unwrap();
             let waveform_buffer = waveform_grd
                 .as_mut_any()
@@ -599,6 +1352,12 @@ impl AudioController {
                 .unwrap();
 
             self.playback_needs_refresh = waveform_buffer.playback_needs_refresh;
+            let is_spectrogram_visible = waveform_buffer.is_spectrogram_visible();
+            let channel_lanes = if is_spectrogram_visible {
+                Vec::new()
+            } else {
+                waveform_buffer.get_channel_lanes()
+            };
 
             let (current_position, image_opt) =
                 waveform_buffer.get_image(da.get_frame_clock().unwrap().get_frame_time() as u64);
@@ -607,7 +1366,12 @@ impl AudioController {
                     cr.set_source_surface(image, -image_positions.first.x, 0f64);
                     cr.paint();
 
-                    (current_position, image_positions)
+                    (
+                        current_position,
+                        image_positions,
+                        is_spectrogram_visible,
+                        channel_lanes,
+                    )
                 }
                 None => {
                     debug!("draw no image");
@@ -622,8 +1386,23 @@ impl AudioController {
         self.sample_step = image_positions.sample_step;
 
         cr.scale(1f64, 1f64);
-        cr.set_source_rgb(1f64, 1f64, 0f64);
+        if let Some(last_pos) = image_positions.last {
+            self.draw_selection(cr, last_pos.timestamp);
+        }
         self.adjust_waveform_text_width(cr);
+        if !is_spectrogram_visible {
+            if channel_lanes.is_empty() {
+                let half_height = (self.area_height / 2f64).floor();
+                self.draw_db_ruler(cr, half_height, half_height);
+            } else {
+                for lane in &channel_lanes {
+                    let half_height = lane.height / 2f64;
+                    self.draw_db_ruler(cr, lane.top + half_height, half_height);
+                }
+            }
+        }
+        self.draw_channel_lanes(cr, &channel_lanes);
+        cr.set_source_rgb(1f64, 1f64, 0f64);
 
         // first position
         let first_text = Timestamp::format(self.first_visible_pos, false);
@@ -686,6 +1465,39 @@ impl AudioController {
                     }
                 }
             }
+
+            // Draw in-range proposed boundaries
+            let first_visible_pos = self.first_visible_pos;
+            let last_visible_pos = last_pos.timestamp;
+            let silence_ctrl = self.silence_ctrl.borrow();
+            let candidate_range = silence_ctrl.get_candidates().iter().filter(|&&candidate| {
+                candidate >= first_visible_pos && candidate <= last_visible_pos
+            });
+
+            cr.set_source_rgb(1f64, 0.6f64, 0.2f64);
+            cr.set_dash(&[4f64, 4f64], 0f64);
+            for candidate in candidate_range {
+                let x = ((candidate - first_visible_pos) / image_positions.sample_duration) as f64
+                    / image_positions.sample_step;
+                cr.move_to(x, boundary_y0);
+                cr.line_to(x, self.area_height);
+                cr.stroke();
+            }
+            cr.set_dash(&[], 0f64);
+
+            // Draw the snap hint
+            if let Some(snap_position) = self.snap_position {
+                if snap_position >= first_visible_pos && snap_position <= last_visible_pos {
+                    let x = ((snap_position - first_visible_pos) / image_positions.sample_duration)
+                        as f64 / image_positions.sample_step;
+                    cr.set_source_rgb(0.3f64, 1f64, 0.3f64);
+                    cr.move_to(x - 5f64, boundary_y0 - 6f64);
+                    cr.line_to(x + 5f64, boundary_y0 - 6f64);
+                    cr.line_to(x, boundary_y0);
+                    cr.close_path();
+                    cr.fill();
+                }
+            }
         }
 
         if let Some(current_x) = image_positions.current {
@@ -728,6 +1540,7 @@ impl AudioController {
                 }
                 ControllerState::Paused => true,
                 ControllerState::MovingBoundary(_boundary) => true,
+                ControllerState::SelectingRange(_start) => true,
                 _ => false,
             }
         };
@@ -751,7 +1564,7 @@ impl AudioController {
         main_ctrl: &Rc<RefCell<MainController>>,
         event_motion: &gdk::EventMotion,
     ) {
-        let (state, x) = {
+        let (state, x, y) = {
             let this = this_rc.borrow();
 
             let state = this.state.clone();
@@ -759,32 +1572,69 @@ impl AudioController {
                 return;
             }
 
-            let (x, _y) = event_motion.get_position();
-            (state, x)
+            let (x, y) = event_motion.get_position();
+            (state, x, y)
         };
 
         match state {
             ControllerState::Paused => {
                 let mut this = this_rc.borrow_mut();
 
+                if this.get_channel_button_at(x, y).is_some() {
+                    this.set_cursor(CursorType::Hand2);
+                    return;
+                }
+
                 match this.get_boundary_at(x) {
                     Some(_boundary) => this.set_cursor(CursorType::SbHDoubleArrow),
-                    None => this.reset_cursor(),
+                    None => match this.get_candidate_at(x) {
+                        Some(_candidate) => this.set_cursor(CursorType::Plus),
+                        None => this.reset_cursor(),
+                    },
                 };
             }
             ControllerState::MovingBoundary(boundary) => {
-                let position = match this_rc.borrow().get_position_at(x) {
-                    Some(position) => position,
-                    None => return,
+                let (position, snap_position) = {
+                    let this = this_rc.borrow();
+                    let position = match this.get_position_at(x) {
+                        Some(position) => position,
+                        None => return,
+                    };
+
+                    // Snapping is temporarily disabled while Shift is pressed
+                    let snap_position = if event_motion.get_state().contains(ModifierType::SHIFT_MASK)
+                    {
+                        None
+                    } else {
+                        this.get_snap_position(position)
+                    };
+                    (snap_position.unwrap_or(position), snap_position)
                 };
 
-                if main_ctrl
+                let has_moved = main_ctrl
                     .borrow_mut()
-                    .move_chapter_boundary(boundary, position)
-                {
-                    // boundary has moved
-                    let mut this = this_rc.borrow_mut();
+                    .move_chapter_boundary(boundary, position);
+
+                let mut this = this_rc.borrow_mut();
+                if has_moved {
                     this.state = ControllerState::MovingBoundary(position);
+                }
+                if has_moved || this.snap_position != snap_position {
+                    this.snap_position = snap_position;
+                    this.redraw();
+                }
+            }
+            ControllerState::SelectingRange(start) => {
+                let mut this = this_rc.borrow_mut();
+                let position = match this.get_position_at(x) {
+                    Some(position) => position,
+                    // Keep selecting up to the edges when the pointer leaves the waveform
+                    None if x < 0f64 => this.first_visible_pos,
+                    None => this.last_visible_pos,
+                };
+                let selection = Some((start.min(position), start.max(position)));
+                if this.selection != selection {
+                    this.selection = selection;
                     this.redraw();
                 }
             }
@@ -800,6 +1650,15 @@ impl AudioController {
         match event_button.get_button() {
             1 => {
                 // left button
+                let channel_button = {
+                    let (x, y) = event_button.get_position();
+                    this_rc.borrow().get_channel_button_at(x, y)
+                };
+                if let Some((channel, toggle)) = channel_button {
+                    AudioController::toggle_channel(this_rc, main_ctrl, channel, toggle);
+                    return;
+                }
+
                 let (position_opt, state) = {
                     let this = this_rc.borrow();
                     (
@@ -808,35 +1667,75 @@ impl AudioController {
                     )
                 };
                 if let Some(position) = position_opt {
+                    let mut accepted_candidate = None;
                     let must_seek = match state {
                         ControllerState::Paused => {
                             let mut this = this_rc.borrow_mut();
-                            this.get_boundary_at(event_button.get_position().0).map_or(
-                                true,
-                                |boundary| {
+                            let x = event_button.get_position().0;
+                            let boundary_opt = this.get_boundary_at(x);
+                            if boundary_opt.is_none()
+                                && event_button
+                                    .get_state()
+                                    .contains(ModifierType::SHIFT_MASK)
+                            {
+                                // Shift + drag => select a range
+                                // (on a boundary, Shift disables the snapping instead)
+                                this.state = ControllerState::SelectingRange(position);
+                                this.set_selection(None);
+                                this.selection = Some((position, position));
+                                return;
+                            }
+
+                            if this.selection.is_some() {
+                                this.set_selection(None);
+                            }
+
+                            match boundary_opt {
+                                Some(boundary) => {
                                     this.state = ControllerState::MovingBoundary(boundary);
                                     false
-                                },
-                            )
+                                }
+                                None => {
+                                    accepted_candidate = this.get_candidate_at(x);
+                                    accepted_candidate.is_none()
+                                }
+                            }
                         }
                         _ => true,
                     };
 
-                    if must_seek {
+                    if let Some(candidate) = accepted_candidate {
+                        let silence_ctrl = Rc::clone(&this_rc.borrow().silence_ctrl);
+                        SilenceController::accept_candidates(
+                            &silence_ctrl,
+                            main_ctrl,
+                            &[candidate],
+                        );
+                    } else if must_seek {
                         main_ctrl.borrow_mut().seek(position, true); // accurate (slow)
                     }
                 }
             }
             3 => {
                 // right button => segment playback
-                let (position_opt, current_position, last_pos) = {
+                let (position_opt, current_position, last_pos, selection) = {
                     let this = this_rc.borrow();
                     (
                         this.get_position_at(event_button.get_position().0),
                         this.current_position,
                         this.last_visible_pos,
+                        this.selection,
                     )
                 };
+                if let (Some(position), Some((start, end))) = (position_opt, selection) {
+                    if position >= start && position <= end {
+                        // play the selection
+                        main_ctrl
+                            .borrow_mut()
+                            .play_range(start, end, current_position);
+                        return;
+                    }
+                }
                 if let Some(start_pos) = position_opt {
                     // get a reasonable range so that we can still hear
                     // something even when there are few samples in current window
@@ -850,3 +1749,35 @@ impl AudioController {
         }
     }
 }
+
+fn get_amplitude_mode_id(amplitude_mode: AmplitudeMode) -> &'static str {
+    match amplitude_mode {
+        AmplitudeMode::LinearPeak => "peak",
+        AmplitudeMode::LinearRms => "rms",
+        AmplitudeMode::Logarithmic => "db",
+    }
+}
+
+fn get_amplitude_mode(id: &str) -> Option<AmplitudeMode> {
+    match id {
+        "peak" => Some(AmplitudeMode::LinearPeak),
+        "rms" => Some(AmplitudeMode::LinearRms),
+        "db" => Some(AmplitudeMode::Logarithmic),
+        _ => None,
+    }
+}
+
+fn get_snap_target_id(snap_target: SnapTarget) -> &'static str {
+    match snap_target {
+        SnapTarget::LowEnergy => "low_energy",
+        SnapTarget::ZeroCrossing => "zero_crossing",
+    }
+}
+
+fn get_snap_target(id: &str) -> Option<SnapTarget> {
+    match id {
+        "low_energy" => Some(SnapTarget::LowEnergy),
+        "zero_crossing" => Some(SnapTarget::ZeroCrossing),
+        _ => None,
+    }
+}
@@ -0,0 +1,164 @@
+use gettextrs::gettext;
+use glib::StaticType;
+use gstreamer as gst;
+
+use gtk;
+use gtk::prelude::*;
+
+use metadata::{get_editable_tags, get_tag_label, get_tag_names, get_tag_string, is_editable_tag};
+
+const TAG_NAME_COL: u32 = 0;
+const LABEL_COL: u32 = 1;
+const VALUE_COL: u32 = 2;
+
+// Lists the editable tags of the selected chapter
+// Tags other than the default ones are appended when defined
+// in the chapter or when added by name using `new_tag_entry`
+pub struct ChapterTagsEditor {
+    store: gtk::ListStore,
+    treeview: gtk::TreeView,
+    value_col: gtk::TreeViewColumn,
+    pub value_renderer: gtk::CellRendererText,
+    pub new_tag_entry: gtk::Entry,
+    default_count: usize,
+}
+
+impl ChapterTagsEditor {
+    pub fn new(treeview: gtk::TreeView, new_tag_entry: gtk::Entry) -> Self {
+        let store = gtk::ListStore::new(&[
+            String::static_type(),
+            String::static_type(),
+            String::static_type(),
+        ]);
+        let default_tags = get_editable_tags();
+        let default_count = default_tags.len();
+        for (tag_name, label) in default_tags {
+            store.insert_with_values(
+                None,
+                &[TAG_NAME_COL, LABEL_COL, VALUE_COL],
+                &[&tag_name.to_owned(), &label, &String::new()],
+            );
+        }
+        treeview.set_model(Some(&store));
+
+        let col = gtk::TreeViewColumn::new();
+        col.set_title(&gettext("Tag"));
+        let renderer = gtk::CellRendererText::new();
+        col.pack_start(&renderer, true);
+        col.add_attribute(&renderer, "text", LABEL_COL as i32);
+        treeview.append_column(&col);
+
+        let col = gtk::TreeViewColumn::new();
+        col.set_title(&gettext("Value"));
+        let value_renderer = gtk::CellRendererText::new();
+        value_renderer.set_property_editable(true);
+        col.pack_start(&value_renderer, true);
+        col.add_attribute(&value_renderer, "text", VALUE_COL as i32);
+        col.set_expand(true);
+        treeview.append_column(&col);
+
+        ChapterTagsEditor {
+            store,
+            treeview,
+            value_col: col,
+            value_renderer,
+            new_tag_entry,
+            default_count,
+        }
+    }
+
+    // Displays the tags of the selected chapter if any
+    pub fn set_tags(&self, tags: Option<&gst::TagList>) {
+        self.treeview.set_sensitive(tags.is_some());
+        self.new_tag_entry.set_sensitive(tags.is_some());
+
+        // Remove the tags added for the previously selected chapter
+        if let Some(iter) = self.store.iter_nth_child(None, self.default_count as i32) {
+            while self.store.remove(&iter) {}
+        }
+        if let Some(tags) = tags {
+            for tag_name in get_tag_names(tags) {
+                if self.find_tag(&tag_name).is_none() {
+                    self.append_tag(&tag_name);
+                }
+            }
+        }
+
+        if let Some(iter) = self.store.get_iter_first() {
+            loop {
+                let value = tags.and_then(|tags| {
+                    get_tag_string(tags, &self.get_tag_name_at(&iter))
+                });
+                self.store.set_value(
+                    &iter,
+                    VALUE_COL,
+                    &gtk::Value::from(value.as_ref().map_or("", |value| value.as_str())),
+                );
+
+                if !self.store.iter_next(&iter) {
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn get_tag_name(&self, tree_path: &gtk::TreePath) -> Option<String> {
+        self.store
+            .get_iter(tree_path)
+            .map(|iter| self.get_tag_name_at(&iter))
+    }
+
+    // Updates the displayed value
+    pub fn set_value(&self, tree_path: &gtk::TreePath, value: &str) {
+        if let Some(iter) = self.store.get_iter(tree_path) {
+            self.store
+                .set_value(&iter, VALUE_COL, &gtk::Value::from(value));
+        }
+    }
+
+    // Adds a row for `tag_name` if needed and starts editing its value
+    pub fn add_tag(&self, tag_name: &str) -> Result<(), String> {
+        if !is_editable_tag(tag_name) {
+            return Err(gettext("Can't edit tag {}").replacen("{}", tag_name, 1));
+        }
+
+        let iter = match self.find_tag(tag_name) {
+            Some(iter) => iter,
+            None => self.append_tag(tag_name),
+        };
+        if let Some(tree_path) = self.store.get_path(&iter) {
+            self.treeview
+                .set_cursor(&tree_path, Some(&self.value_col), true);
+        }
+
+        Ok(())
+    }
+
+    fn append_tag(&self, tag_name: &str) -> gtk::TreeIter {
+        self.store.insert_with_values(
+            None,
+            &[TAG_NAME_COL, LABEL_COL, VALUE_COL],
+            &[&tag_name.to_owned(), &get_tag_label(tag_name), &String::new()],
+        )
+    }
+
+    fn find_tag(&self, tag_name: &str) -> Option<gtk::TreeIter> {
+        let iter = self.store.get_iter_first()?;
+        loop {
+            if self.get_tag_name_at(&iter) == tag_name {
+                return Some(iter);
+            }
+
+            if !self.store.iter_next(&iter) {
+                return None;
+            }
+        }
+    }
+
+    fn get_tag_name_at(&self, iter: &gtk::TreeIter) -> String {
+        self.store
+            .get_value(iter, TAG_NAME_COL as i32)
+            .get::<String>()
+            .unwrap()
+    }
+}
@@ -2,12 +2,15 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use gettextrs::gettext;
+use glib::StaticType;
 use gstreamer as gst;
 
 use gtk;
 use gtk::prelude::*;
 
-use metadata::{get_default_chapter_title, Timestamp, TocVisitor};
+use metadata::chapter_gaps::PREGAP_KEY;
+use metadata::{get_default_chapter_title, get_gap, get_moved_pregap, set_chapter_gap,
+               with_tag_string, Timestamp, TocVisitor};
 
 use super::ChaptersBoundaries;
 
@@ -16,6 +19,8 @@ const END_COL: u32 = 1;
 const TITLE_COL: u32 = 2;
 const START_STR_COL: u32 = 3;
 const END_STR_COL: u32 = 4;
+const TAGS_COL: u32 = 5;
+const LOUDNESS_STR_COL: u32 = 6;
 
 pub struct ChapterEntry<'a> {
     store: &'a gtk::TreeStore,
@@ -56,6 +61,10 @@ impl<'a> ChapterEntry<'a> {
         Timestamp::from_nano(ChapterEntry::get_end(self.store, self.iter))
     }
 
+    pub fn tags(&self) -> gst::TagList {
+        ChapterEntry::get_tags(self.store, self.iter)
+    }
+
     pub fn as_toc_entry(&self) -> gst::TocEntry {
         let mut toc_entry = gst::TocEntry::new(
             gst::TocEntryType::Chapter,
@@ -66,10 +75,10 @@ impl<'a> ChapterEntry<'a> {
             .unwrap()
             .set_start_stop_times(self.start() as i64, self.end() as i64);
 
-        let mut tag_list = gst::TagList::new();
+        // The title is edited in the title column
+        let mut tag_list = self.tags();
         tag_list
-            .get_mut()
-            .unwrap()
+            .make_mut()
             .add::<gst::tags::Title>(&self.title().as_str(), gst::TagMergeMode::Replace);
         toc_entry.get_mut().unwrap().set_tags(tag_list);
 
@@ -107,6 +116,13 @@ impl<'a> ChapterEntry<'a> {
             .get::<String>()
             .unwrap()
     }
+
+    pub fn get_tags(store: &gtk::TreeStore, iter: &gtk::TreeIter) -> gst::TagList {
+        store
+            .get_value(iter, TAGS_COL as i32)
+            .get::<gst::TagList>()
+            .unwrap_or_else(gst::TagList::new)
+    }
 }
 
 pub struct ChapterTreeManager {
@@ -118,9 +134,17 @@ pub struct ChapterTreeManager {
 }
 
 impl ChapterTreeManager {
-    pub fn new(store: gtk::TreeStore, boundaries: Rc<RefCell<ChaptersBoundaries>>) -> Self {
+    pub fn new(boundaries: Rc<RefCell<ChaptersBoundaries>>) -> Self {
         ChapterTreeManager {
-            store,
+            store: gtk::TreeStore::new(&[
+                u64::static_type(),
+                u64::static_type(),
+                String::static_type(),
+                String::static_type(),
+                String::static_type(),
+                gst::TagList::static_type(),
+                String::static_type(),
+            ]),
             iter: None,
             selected_iter: None,
             title_renderer: None,
@@ -134,6 +158,7 @@ impl ChapterTreeManager {
             Some(self.add_column(treeview, &gettext("Title"), TITLE_COL, true, true));
         self.add_column(treeview, &gettext("Start"), START_STR_COL, false, false);
         self.add_column(treeview, &gettext("End"), END_STR_COL, false, false);
+        self.add_column(treeview, &gettext("Loudness"), LOUDNESS_STR_COL, false, false);
     }
 
     fn add_column(
@@ -209,6 +234,52 @@ impl ChapterTreeManager {
         }
     }
 
+    pub fn get_selected_tags(&self) -> Option<gst::TagList> {
+        self.selected_iter
+            .as_ref()
+            .map(|selected_iter| ChapterEntry::get_tags(&self.store, selected_iter))
+    }
+
+    // Sets the tag for the chapter starting at `start` or removes it if `value` is empty
+    pub fn set_chapter_tag(
+        &mut self,
+        start: u64,
+        tag_name: &str,
+        value: &str,
+    ) -> Result<(), String> {
+        if let Some(iter) = self.find_chapter(start) {
+            let tags = with_tag_string(
+                &ChapterEntry::get_tags(&self.store, &iter),
+                tag_name,
+                value,
+            )?;
+            self.store.set_value(&iter, TAGS_COL, &gtk::Value::from(&tags));
+        }
+
+        Ok(())
+    }
+
+    // Displays the loudness of the chapter starting at `start`
+    pub fn set_loudness(&self, start: u64, loudness: &str) {
+        if let Some(iter) = self.find_chapter(start) {
+            self.store
+                .set_value(&iter, LOUDNESS_STR_COL, &gtk::Value::from(loudness));
+        }
+    }
+
+    fn find_chapter(&self, start: u64) -> Option<gtk::TreeIter> {
+        let iter = self.store.get_iter_first()?;
+        loop {
+            if ChapterEntry::get_start(&self.store, &iter) == start {
+                return Some(iter);
+            }
+
+            if !self.store.iter_next(&iter) {
+                return None;
+            }
+        }
+    }
+
     pub fn replace_with(&mut self, toc: &Option<gst::Toc>) {
         self.clear();
 
@@ -226,23 +297,28 @@ impl ChapterTreeManager {
                     let start = start as u64;
                     let end = end as u64;
 
-                    let title = chapter
-                        .get_tags()
-                        .and_then(|tags| {
-                            tags.get::<gst::tags::Title>()
-                                .map(|tag| tag.get().unwrap().to_owned())
-                        })
+                    let tags = chapter.get_tags().unwrap_or_else(gst::TagList::new);
+                    let title = tags.get::<gst::tags::Title>()
+                        .map(|tag| tag.get().unwrap().to_owned())
                         .unwrap_or_else(get_default_chapter_title);
                     let iter = self.store.insert_with_values(
                         None,
                         None,
-                        &[START_COL, END_COL, TITLE_COL, START_STR_COL, END_STR_COL],
+                        &[
+                            START_COL,
+                            END_COL,
+                            TITLE_COL,
+                            START_STR_COL,
+                            END_STR_COL,
+                            TAGS_COL,
+                        ],
                         &[
                             &start,
                             &end,
                             &title,
                             &Timestamp::format(start, false),
                             &Timestamp::format(end, false),
+                            &tags,
                         ],
                     );
 
@@ -371,8 +447,8 @@ impl ChapterTreeManager {
                     // to match the start of the newly added chapter
                     self.store.set(
                         &selected_iter,
-                        &[END_COL, END_STR_COL],
-                        &[&position, &Timestamp::format(position, false)],
+                        &[END_COL, END_STR_COL, LOUDNESS_STR_COL],
+                        &[&position, &Timestamp::format(position, false), &""],
                     );
                     let new_iter = self.store.insert_after(None, &selected_iter);
                     (new_iter, current_end, current_end_str)
@@ -427,13 +503,21 @@ impl ChapterTreeManager {
         let default_title = get_default_chapter_title();
         self.store.set(
             &new_iter,
-            &[TITLE_COL, START_COL, START_STR_COL, END_COL, END_STR_COL],
+            &[
+                TITLE_COL,
+                START_COL,
+                START_STR_COL,
+                END_COL,
+                END_STR_COL,
+                TAGS_COL,
+            ],
             &[
                 &default_title,
                 &position,
                 &Timestamp::format(position, false),
                 &end,
                 &end_str,
+                &gst::TagList::new(),
             ],
         );
 
@@ -447,6 +531,66 @@ impl ChapterTreeManager {
         Some(new_iter)
     }
 
+    // Adds a chapter starting at `position`, splitting the chapter containing it if any
+    // When there is no chapter yet, the chapter preceding the boundary is also added
+    // Returns false if a chapter already starts at `position`
+    // Note: the selection is lost, use `update_position` to restore it
+    pub fn add_boundary(&mut self, position: u64, duration: u64) -> bool {
+        if self.store.get_iter_first().is_none() && position > 0 {
+            self.selected_iter = None;
+            self.iter = None;
+            self.add_chapter(0, duration);
+        }
+
+        let mut containing_iter = None;
+        let mut next_iter = None;
+        self.for_each(None, |chapter| {
+            if position < chapter.start() {
+                next_iter = Some(chapter.iter.clone());
+                false
+            } else if position < chapter.end() {
+                containing_iter = Some(chapter.iter.clone());
+                false
+            } else {
+                true // keep going
+            }
+        });
+
+        if let Some(ref containing_iter) = containing_iter {
+            if ChapterEntry::get_start(&self.store, containing_iter) == position {
+                return false;
+            }
+        }
+
+        // `add_chapter` splits the selected chapter
+        // or inserts the new chapter before `iter` or after the last chapter
+        self.selected_iter = containing_iter;
+        self.iter = next_iter;
+        let is_added = self.add_chapter(position, duration).is_some();
+
+        self.selected_iter = None;
+        self.rewind();
+        is_added
+    }
+
+    // Adds a chapter spanning [start, end] when there is no chapter yet
+    // Returns false if the store already contains chapters
+    // Note: the selection is lost, use `update_position` to restore it
+    pub fn add_single_chapter(&mut self, start: u64, end: u64) -> bool {
+        if self.store.get_iter_first().is_some() {
+            return false;
+        }
+
+        // Without any chapter, `add_chapter` ends the new chapter at the given duration
+        self.selected_iter = None;
+        self.iter = None;
+        let is_added = self.add_chapter(start, end).is_some();
+
+        self.selected_iter = None;
+        self.rewind();
+        is_added
+    }
+
     // Returns an iter on the chapter which should be selected, if any
     pub fn remove_selected_chapter(&mut self) -> Option<gtk::TreeIter> {
         match self.selected_iter.take() {
@@ -460,8 +604,8 @@ impl ChapterTreeManager {
                         // => update its end with the end of currently selected chapter
                         self.store.set(
                             &prev_iter,
-                            &[END_COL, END_STR_COL],
-                            &[&selected_end, &selected_chapter.end_str()],
+                            &[END_COL, END_STR_COL, LOUDNESS_STR_COL],
+                            &[&selected_end, &selected_chapter.end_str(), &""],
                         );
 
                         (selected_end, Some(prev_iter))
@@ -532,18 +676,32 @@ impl ChapterTreeManager {
 
         if to_position != boundary {
             // do the actual move
+            if let Some(next_iter) = next_iter.as_ref() {
+                // The pregap of the next chapter is located in the previous chapter
+                let mut tags = ChapterEntry::get_tags(&self.store, next_iter);
+                let pregap = get_gap(&tags, PREGAP_KEY);
+                if pregap > 0 {
+                    let prev_start = prev_iter.as_ref().map_or(0, |prev_iter| {
+                        ChapterEntry::get_start(&self.store, prev_iter)
+                    });
+                    let pregap = get_moved_pregap(boundary, pregap, to_position, prev_start);
+                    set_chapter_gap(tags.make_mut(), PREGAP_KEY, pregap);
+                    self.store
+                        .set_value(next_iter, TAGS_COL, &gtk::Value::from(&tags));
+                }
+            }
             if let Some(prev_iter) = prev_iter {
                 self.store.set(
                     &prev_iter,
-                    &[END_COL, END_STR_COL],
-                    &[&to_position, &Timestamp::format(to_position, false)],
+                    &[END_COL, END_STR_COL, LOUDNESS_STR_COL],
+                    &[&to_position, &Timestamp::format(to_position, false), &""],
                 );
             }
             if let Some(next_iter) = next_iter {
                 self.store.set(
                     &next_iter,
-                    &[START_COL, START_STR_COL],
-                    &[&to_position, &Timestamp::format(to_position, false)],
+                    &[START_COL, START_STR_COL, LOUDNESS_STR_COL],
+                    &[&to_position, &Timestamp::format(to_position, false), &""],
                 );
             }
 
@@ -84,6 +84,11 @@ impl ExportController {
         let this_clone = Rc::clone(this_rc);
         let main_ctrl_clone = Rc::clone(main_ctrl);
         this.export_btn.connect_clicked(move |_| {
+            if this_clone.borrow().listener_src.is_some() {
+                this_clone.borrow_mut().cancel();
+                return;
+            }
+
             let this_clone = Rc::clone(&this_clone);
             main_ctrl_clone
                 .borrow_mut()
@@ -187,6 +192,17 @@ impl ExportController {
         }
     }
 
+    fn cancel(&mut self) {
+        if let Some(mut toc_setter_ctx) = self.toc_setter_ctx.take() {
+            toc_setter_ctx.cancel();
+        }
+
+        self.remove_listener();
+        self.switch_to_available();
+        self.restore_context();
+        self.show_info(&gettext("Export cancelled"));
+    }
+
     fn build_context(&mut self, export_path: &Path, streams: HashSet<String>) {
         let (ctx_tx, ui_rx) = channel();
 
@@ -221,11 +237,11 @@ impl ExportController {
     }
 
     fn switch_to_busy(&self) {
-        // TODO: allow cancelling export
         self.base.switch_to_busy();
 
         self.export_list.set_sensitive(false);
-        self.export_btn.set_sensitive(false);
+        // Export button is used to cancel the export
+        self.export_btn.set_label(&gettext("Cancel"));
     }
 
     fn switch_to_available(&self) {
@@ -233,6 +249,7 @@ impl ExportController {
 
         self.export_progress_bar.set_fraction(0f64);
         self.export_list.set_sensitive(true);
+        self.export_btn.set_label(&gettext("Export"));
         self.export_btn.set_sensitive(true);
     }
 
@@ -280,6 +297,10 @@ impl ExportController {
                     }
                     FailedToExport(error) => {
                         keep_going = false;
+                        // Remove the partial output
+                        if let Some(mut toc_setter_ctx) = this.toc_setter_ctx.take() {
+                            toc_setter_ctx.cancel();
+                        }
                         let message =
                             gettext("Failed to export media. {}").replacen("{}", &error, 1);
                         this.show_error(&message);
@@ -15,7 +15,8 @@ use media::PlaybackContext;
 use metadata;
 use metadata::{MediaInfo, Timestamp};
 
-use super::{ChapterTreeManager, ChaptersBoundaries, ControllerState, ImageSurface, MainController};
+use super::{ChapterTagsEditor, ChapterTreeManager, ChaptersBoundaries, ControllerState,
+            ImageSurface, MainController};
 
 const GO_TO_PREV_CHAPTER_THRESHOLD: u64 = 1_000_000_000; // 1 s
 
@@ -46,6 +47,9 @@ pub struct InfoController {
     thumbnail: Option<cairo::ImageSurface>,
 
     chapter_manager: ChapterTreeManager,
+    chapter_tags_editor: ChapterTagsEditor,
+    // Start of the chapter and name of the tag being edited
+    edited_tag: Option<(u64, String)>,
 
     duration: u64,
     repeat_chapter: bool,
@@ -58,13 +62,15 @@ impl InfoController {
         builder: &gtk::Builder,
         boundaries: Rc<RefCell<ChaptersBoundaries>>,
     ) -> Rc<RefCell<Self>> {
-        let mut chapter_manager = ChapterTreeManager::new(
-            builder.get_object("chapters-tree-store").unwrap(),
-            boundaries,
-        );
+        let mut chapter_manager = ChapterTreeManager::new(boundaries);
         let chapter_treeview: gtk::TreeView = builder.get_object("chapter-treeview").unwrap();
         chapter_manager.init_treeview(&chapter_treeview);
 
+        let chapter_tags_editor = ChapterTagsEditor::new(
+            builder.get_object("chapter_tags-treeview").unwrap(),
+            builder.get_object("chapter_tags-new_tag-entry").unwrap(),
+        );
+
         // need a RefCell because the callbacks will use immutable versions of ac
         // when the UI controllers will get a mutable version from time to time
         let this_rc = Rc::new(RefCell::new(InfoController {
@@ -90,6 +96,8 @@ impl InfoController {
             thumbnail: None,
 
             chapter_manager,
+            chapter_tags_editor,
+            edited_tag: None,
 
             duration: 0,
             repeat_chapter: false,
@@ -197,6 +205,86 @@ impl InfoController {
             });
         }
 
+        // Chapter tag edition started
+        // Note: the selected chapter can change before the edition completes
+        // (e.g. during playback), so the edited chapter is recorded here
+        let this_clone = Rc::clone(this_rc);
+        this.chapter_tags_editor
+            .value_renderer
+            .connect_editing_started(move |_, _, tree_path| {
+                // Note: edition can also be started programmatically
+                // while the controller is borrowed (see `new_tag_entry`)
+                if let Ok(mut this) = this_clone.try_borrow_mut() {
+                    let edited_tag = this.chapter_tags_editor
+                        .get_tag_name(&tree_path)
+                        .and_then(|tag_name| this.get_edited_tag(tag_name));
+                    this.edited_tag = edited_tag;
+                }
+            });
+
+        let this_clone = Rc::clone(this_rc);
+        this.chapter_tags_editor
+            .value_renderer
+            .connect_editing_canceled(move |_| {
+                this_clone.borrow_mut().edited_tag = None;
+            });
+
+        // Chapter tag modified
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        let this_clone = Rc::clone(this_rc);
+        this.chapter_tags_editor
+            .value_renderer
+            .connect_edited(move |_, tree_path, value| {
+                let mut this = this_clone.borrow_mut();
+                let (start, tag_name) = match this.edited_tag.take() {
+                    Some(edited_tag) => edited_tag,
+                    None => return,
+                };
+
+                let value = value.trim();
+                match this.chapter_manager.set_chapter_tag(start, &tag_name, value) {
+                    Ok(()) => {
+                        let is_selected = this.chapter_manager
+                            .get_selected_iter()
+                            .map_or(false, |iter| {
+                                this.chapter_manager.get_chapter_at_iter(&iter).start() == start
+                            });
+                        if is_selected {
+                            this.chapter_tags_editor.set_value(&tree_path, value);
+                        }
+                    }
+                    Err(err) => main_ctrl_clone
+                        .borrow()
+                        .show_message(gtk::MessageType::Warning, &err),
+                }
+            });
+
+        // Add a tag by name to the selected chapter
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        let this_clone = Rc::clone(this_rc);
+        this.chapter_tags_editor
+            .new_tag_entry
+            .connect_activate(move |entry| {
+                let tag_name = entry.get_text().unwrap_or_default();
+                let tag_name = tag_name.trim();
+                if tag_name.is_empty() {
+                    return;
+                }
+
+                let mut this = this_clone.borrow_mut();
+                let res = this.chapter_tags_editor.add_tag(tag_name);
+                match res {
+                    Ok(()) => {
+                        let edited_tag = this.get_edited_tag(tag_name.to_owned());
+                        this.edited_tag = edited_tag;
+                        entry.set_text("");
+                    }
+                    Err(err) => main_ctrl_clone
+                        .borrow()
+                        .show_message(gtk::MessageType::Warning, &err),
+                }
+            });
+
         // Register add chapter action
         let add_chapter = gio::SimpleAction::new("add_chapter", None);
         gtk_app.add_action(&add_chapter);
@@ -425,6 +513,7 @@ impl InfoController {
                 // position is not in any chapter
                 self.del_chapter_btn.set_sensitive(false),
         }
+        self.update_chapter_tags();
 
         if self.thumbnail.is_some() {
             self.drawingarea.show();
@@ -441,6 +530,18 @@ impl InfoController {
             .set_label(info.get_video_codec().unwrap_or(&EMPTY_REPLACEMENT));
     }
 
+    // Returns the start of the selected chapter along with `tag_name`
+    fn get_edited_tag(&self, tag_name: String) -> Option<(u64, String)> {
+        self.chapter_manager
+            .get_selected_iter()
+            .map(|iter| (self.chapter_manager.get_chapter_at_iter(&iter).start(), tag_name))
+    }
+
+    fn update_chapter_tags(&self) {
+        self.chapter_tags_editor
+            .set_tags(self.chapter_manager.get_selected_tags().as_ref());
+    }
+
     fn update_marks(&self) {
         self.timeline_scale.clear_marks();
 
@@ -459,7 +560,9 @@ impl InfoController {
         self.video_codec_lbl.set_text("");
         self.duration_lbl.set_text("00:00.000");
         self.thumbnail = None;
+        self.edited_tag = None;
         self.chapter_manager.clear();
+        self.update_chapter_tags();
         self.add_chapter_btn.set_sensitive(false);
         self.del_chapter_btn.set_sensitive(false);
         self.timeline_scale.clear_marks();
@@ -521,6 +624,7 @@ impl InfoController {
                         self.del_chapter_btn.set_sensitive(false);
                     },
             }
+            self.update_chapter_tags();
         }
     }
 
@@ -559,9 +663,59 @@ impl InfoController {
             self.chapter_treeview.get_selection().select_iter(&new_iter);
             self.update_marks();
             self.del_chapter_btn.set_sensitive(true);
+            self.update_chapter_tags();
         }
     }
 
+    // Adds chapters boundaries at the given positions, e.g. the proposed boundaries
+    // Returns the number of boundaries actually added
+    pub fn add_chapter_boundaries(&mut self, boundaries: &[u64], position: u64) -> usize {
+        let duration = self.duration;
+        let mut added_count = 0;
+        for &boundary in boundaries {
+            if boundary > 0 && boundary < duration
+                && self.chapter_manager.add_boundary(boundary, duration)
+            {
+                added_count += 1;
+            }
+        }
+
+        if added_count > 0 {
+            self.chapters_added(position);
+        }
+
+        added_count
+    }
+
+    // Adds a chapter spanning [start, end]
+    // When chapters are already defined, they are split at `start` and `end`
+    pub fn add_range_chapter(&mut self, start: u64, end: u64, position: u64) {
+        if start >= end || end > self.duration {
+            return;
+        }
+
+        if self.chapter_manager.add_single_chapter(start, end) {
+            self.chapters_added(position);
+        } else {
+            self.add_chapter_boundaries(&[start, end], position);
+        }
+    }
+
+    fn chapters_added(&mut self, position: u64) {
+        // Restore the selection according to current position
+        self.chapter_treeview.get_selection().unselect_all();
+        self.chapter_manager.update_position(position);
+        match self.chapter_manager.get_selected_iter() {
+            Some(current_iter) => {
+                self.chapter_treeview.get_selection().select_iter(&current_iter);
+                self.del_chapter_btn.set_sensitive(true);
+            }
+            None => self.del_chapter_btn.set_sensitive(false),
+        }
+        self.update_marks();
+        self.update_chapter_tags();
+    }
+
     pub fn remove_chapter(&mut self) {
         match self.chapter_manager.remove_selected_chapter() {
             Some(new_iter) => self.chapter_treeview.get_selection().select_iter(&new_iter),
@@ -572,6 +726,14 @@ impl InfoController {
         }
 
         self.update_marks();
+        self.update_chapter_tags();
+    }
+
+    // `loudness` holds the start of the chapters and their formatted loudness
+    pub fn show_chapters_loudness(&self, loudness: &[(u64, String)]) {
+        for &(start, ref value) in loudness {
+            self.chapter_manager.set_loudness(start, value);
+        }
     }
 
     pub fn export_chapters(&self, context: &mut PlaybackContext) {
@@ -21,13 +21,15 @@ use gtk::prelude::*;
 
 use application::{APP_ID, APP_PATH, CONFIG};
 use media::ContextMessage::*;
-use media::{ContextMessage, PlaybackContext};
+use media::{ChannelMix, ContextMessage, PlaybackContext};
 
 use super::{AudioController, ChaptersBoundaries, ExportController, InfoController,
-            PerspectiveController, SplitController, StreamsController, VideoController};
+            MeterController, OverviewController, PerspectiveController, SceneController,
+            SilenceController, SplitController, StreamsController, VideoController};
 
 const PAUSE_ICON: &str = "media-playback-pause-symbolic";
 const PLAYBACK_ICON: &str = "media-playback-start-symbolic";
+const PLAYBACK_RATES: [f64; 7] = [0.5f64, 0.75f64, 1f64, 1.25f64, 1.5f64, 1.75f64, 2f64];
 
 #[derive(PartialEq)]
 pub enum ControllerState {
@@ -55,6 +57,7 @@ pub struct MainController {
     open_btn: gtk::Button,
     playback_paned: gtk::Paned,
     play_pause_btn: gtk::ToolButton,
+    playback_rate_btn: gtk::Button,
     info_bar_revealer: gtk::Revealer,
     info_bar: gtk::InfoBar,
     info_bar_lbl: gtk::Label,
@@ -63,7 +66,11 @@ pub struct MainController {
     video_ctrl: VideoController,
     info_ctrl: Rc<RefCell<InfoController>>,
     audio_ctrl: Rc<RefCell<AudioController>>,
+    meter_ctrl: Rc<RefCell<MeterController>>,
+    overview_ctrl: Rc<RefCell<OverviewController>>,
     export_ctrl: Rc<RefCell<ExportController>>,
+    scene_ctrl: Rc<RefCell<SceneController>>,
+    silence_ctrl: Rc<RefCell<SilenceController>>,
     split_ctrl: Rc<RefCell<SplitController>>,
     streams_ctrl: Rc<RefCell<StreamsController>>,
 
@@ -71,6 +78,8 @@ pub struct MainController {
     take_context_cb: Option<Box<FnMut(PlaybackContext)>>,
     missing_plugins: HashSet<String>,
     state: ControllerState,
+    // Range played again each time its end is reached
+    looping_range: Option<(u64, u64)>,
 
     this_opt: Option<Rc<RefCell<MainController>>>,
     keep_going: bool,
@@ -84,6 +93,8 @@ impl MainController {
         window.set_application(gtk_app);
 
         let chapters_boundaries = Rc::new(RefCell::new(ChaptersBoundaries::new()));
+        let meter_ctrl = MeterController::new(&builder);
+        let silence_ctrl = SilenceController::new(&builder, Rc::clone(&chapters_boundaries));
 
         let this = Rc::new(RefCell::new(MainController {
             window,
@@ -91,6 +102,7 @@ impl MainController {
             open_btn: builder.get_object("open-btn").unwrap(),
             playback_paned: builder.get_object("playback-paned").unwrap(),
             play_pause_btn: builder.get_object("play_pause-toolbutton").unwrap(),
+            playback_rate_btn: builder.get_object("playback_rate-button").unwrap(),
             info_bar_revealer: builder.get_object("info_bar-revealer").unwrap(),
             info_bar: builder.get_object("info_bar").unwrap(),
             info_bar_lbl: builder.get_object("info_bar-lbl").unwrap(),
@@ -98,8 +110,17 @@ impl MainController {
             perspective_ctrl: PerspectiveController::new(&builder),
             video_ctrl: VideoController::new(&builder, disable_gl),
             info_ctrl: InfoController::new(&builder, Rc::clone(&chapters_boundaries)),
-            audio_ctrl: AudioController::new(&builder, chapters_boundaries),
+            audio_ctrl: AudioController::new(
+                &builder,
+                Rc::clone(&chapters_boundaries),
+                Rc::clone(&meter_ctrl),
+                Rc::clone(&silence_ctrl),
+            ),
+            meter_ctrl,
+            overview_ctrl: OverviewController::new(&builder, chapters_boundaries),
             export_ctrl: ExportController::new(&builder),
+            scene_ctrl: SceneController::new(&builder),
+            silence_ctrl,
             split_ctrl: SplitController::new(&builder),
             streams_ctrl: StreamsController::new(&builder),
 
@@ -107,6 +128,7 @@ impl MainController {
             take_context_cb: None,
             missing_plugins: HashSet::<String>::new(),
             state: ControllerState::Stopped,
+            looping_range: None,
 
             this_opt: None,
             keep_going: true,
@@ -165,7 +187,11 @@ impl MainController {
                 );
                 InfoController::register_callbacks(&this_mut.info_ctrl, gtk_app, &this);
                 AudioController::register_callbacks(&this_mut.audio_ctrl, gtk_app, &this);
+                MeterController::register_callbacks(&this_mut.meter_ctrl);
+                OverviewController::register_callbacks(&this_mut.overview_ctrl, &this);
                 ExportController::register_callbacks(&this_mut.export_ctrl, &this);
+                SceneController::register_callbacks(&this_mut.scene_ctrl, gtk_app, &this);
+                SilenceController::register_callbacks(&this_mut.silence_ctrl, gtk_app, &this);
                 SplitController::register_callbacks(&this_mut.split_ctrl, &this);
                 StreamsController::register_callbacks(&this_mut.streams_ctrl, &this);
 
@@ -214,6 +240,47 @@ impl MainController {
 
                 this_mut.play_pause_btn.set_sensitive(true);
 
+                // Register playback rate actions
+                let slower = gio::SimpleAction::new("slower", None);
+                gtk_app.add_action(&slower);
+                let this_rc = Rc::clone(&this);
+                slower.connect_activate(move |_, _| {
+                    let mut this = this_rc.borrow_mut();
+                    if let Some(rate) = this.context.as_ref().and_then(|context| {
+                        let rate = context.get_rate();
+                        PLAYBACK_RATES
+                            .iter()
+                            .rev()
+                            .find(|&&step| step < rate)
+                            .cloned()
+                    }) {
+                        this.set_playback_rate(rate);
+                    }
+                });
+                gtk_app.set_accels_for_action("app.slower", &["bracketleft"]);
+
+                let faster = gio::SimpleAction::new("faster", None);
+                gtk_app.add_action(&faster);
+                let this_rc = Rc::clone(&this);
+                faster.connect_activate(move |_, _| {
+                    let mut this = this_rc.borrow_mut();
+                    if let Some(rate) = this.context.as_ref().and_then(|context| {
+                        let rate = context.get_rate();
+                        PLAYBACK_RATES.iter().find(|&&step| step > rate).cloned()
+                    }) {
+                        this.set_playback_rate(rate);
+                    }
+                });
+                gtk_app.set_accels_for_action("app.faster", &["bracketright"]);
+
+                let normal_speed = gio::SimpleAction::new("normal_speed", None);
+                gtk_app.add_action(&normal_speed);
+                let this_rc = Rc::clone(&this);
+                normal_speed.connect_activate(move |_, _| {
+                    this_rc.borrow_mut().set_playback_rate(1f64);
+                });
+                gtk_app.set_accels_for_action("app.normal_speed", &["equal"]);
+
                 // Register Close info bar action
                 let close_info_bar = gio::SimpleAction::new("close_info_bar", None);
                 gtk_app.add_action(&close_info_bar);
@@ -301,6 +368,10 @@ impl MainController {
         self.info_bar_revealer.set_reveal_child(true);
     }
 
+    pub fn show_chapters_loudness(&self, loudness: &[(u64, String)]) {
+        self.info_ctrl.borrow().show_chapters_loudness(loudness);
+    }
+
     pub fn play_pause(&mut self) {
         let mut context = match self.context.take() {
             Some(context) => context,
@@ -320,6 +391,12 @@ impl MainController {
                     self.context = Some(context);
                 }
                 gst::State::Playing => {
+                    if let ControllerState::PlayingRange(pos_to_restore) = self.state {
+                        self.context = Some(context);
+                        self.stop_play_range(pos_to_restore);
+                        return;
+                    }
+
                     context.pause().unwrap();
                     self.play_pause_btn.set_icon_name(PLAYBACK_ICON);
                     self.state = ControllerState::Paused;
@@ -344,6 +421,29 @@ impl MainController {
             .move_chapter_boundary(boundary, to_position)
     }
 
+    // Returns the number of boundaries actually added
+    pub fn add_chapter_boundaries(&mut self, boundaries: &[u64]) -> usize {
+        if self.context.is_none() {
+            return 0;
+        }
+
+        let position = self.get_position();
+        self.info_ctrl
+            .borrow_mut()
+            .add_chapter_boundaries(boundaries, position)
+    }
+
+    pub fn add_range_chapter(&mut self, start: u64, end: u64) {
+        if self.context.is_none() {
+            return;
+        }
+
+        let position = self.get_position();
+        self.info_ctrl
+            .borrow_mut()
+            .add_range_chapter(start, end, position);
+    }
+
     pub fn seek(&mut self, position: u64, accurate: bool) {
         let mut must_sync_ctrl = false;
         let mut seek_pos = position;
@@ -404,6 +504,7 @@ impl MainController {
             self.info_ctrl.borrow_mut().seek(seek_pos, &self.state);
             self.audio_ctrl.borrow_mut().seek(seek_pos);
         }
+        self.meter_ctrl.borrow_mut().reset();
 
         self.context.as_ref().unwrap().seek(seek_pos, accurate);
     }
@@ -418,6 +519,61 @@ impl MainController {
         }
     }
 
+    // Plays the range [start, end] repeatedly until playback is paused
+    pub fn loop_range(&mut self, start: u64, end: u64, pos_to_restore: u64) {
+        self.play_range(start, end, pos_to_restore);
+        if let ControllerState::PlayingRange(_) = self.state {
+            self.looping_range = Some((start, end));
+        }
+    }
+
+    // End of range => pause and seek back to `pos_to_restore`
+    fn stop_play_range(&mut self, pos_to_restore: u64) {
+        self.looping_range = None;
+        self.context.as_ref().unwrap().pause().unwrap();
+        self.state = ControllerState::Paused;
+        self.audio_ctrl.borrow_mut().stop_play_range();
+        self.seek(pos_to_restore, true); // accurate
+    }
+
+    // Exports the range [start, end] using the settings of the split perspective
+    pub fn export_range(&mut self, start: u64, end: u64) {
+        if self.context.is_none() || !self.split_ctrl.borrow_mut().set_range(start, end) {
+            return;
+        }
+
+        let split_cb = SplitController::get_split_cb(&self.split_ctrl);
+        self.request_context(split_cb);
+    }
+
+    // The new rate is applied by seeking to the current position
+    pub fn set_playback_rate(&mut self, rate: f64) {
+        match self.context.as_ref() {
+            Some(context) if (context.get_rate() - rate).abs() > ::std::f64::EPSILON => (),
+            _ => return,
+        }
+
+        match self.state {
+            ControllerState::Paused | ControllerState::Playing => {
+                self.context.as_mut().unwrap().set_rate(rate);
+                let position = self.get_position();
+                self.seek(position, true);
+            }
+            ControllerState::Ready => {
+                // Don't start playing
+                let position = self.get_position();
+                let context = self.context.as_mut().unwrap();
+                context.set_rate(rate);
+                context.seek(position, true);
+            }
+            // Applied when playback restarts from the beginning
+            ControllerState::EOS => self.context.as_mut().unwrap().set_rate(rate),
+            _ => return,
+        }
+
+        self.playback_rate_btn.set_label(&format!("{}×", rate));
+    }
+
     pub fn get_position(&mut self) -> u64 {
         self.context.as_mut().unwrap().get_position()
     }
@@ -429,7 +585,19 @@ impl MainController {
     pub fn refresh_info(&mut self, position: u64) {
         match self.state {
             ControllerState::Seeking { .. } => (),
-            _ => self.info_ctrl.borrow_mut().tick(position, false),
+            _ => {
+                self.info_ctrl.borrow_mut().tick(position, false);
+                let visible_range = self.audio_ctrl.borrow().get_visible_range();
+                self.overview_ctrl
+                    .borrow_mut()
+                    .tick(position, visible_range);
+            }
+        }
+    }
+
+    pub fn set_channel_mix(&self, channel_mix: &ChannelMix) {
+        if let Some(context) = self.context.as_ref() {
+            context.set_channel_mix(channel_mix);
         }
     }
 
@@ -448,7 +616,10 @@ impl MainController {
             let info = context.info.read().unwrap();
             self.audio_ctrl.borrow_mut().streams_changed(&info);
             self.info_ctrl.borrow().streams_changed(&info);
+            self.overview_ctrl.borrow_mut().streams_changed(&info);
             self.perspective_ctrl.borrow().streams_changed(&info);
+            self.scene_ctrl.borrow_mut().streams_changed(&info);
+            self.silence_ctrl.borrow_mut().streams_changed(&info);
             self.split_ctrl.borrow_mut().streams_changed(&info);
             self.video_ctrl.streams_changed(&info);
         }
@@ -473,6 +644,7 @@ impl MainController {
             context.pause().unwrap();
         };
 
+        self.looping_range = None;
         self.take_context_cb = Some(callback);
         if self.state == ControllerState::Playing || self.state == ControllerState::EOS {
             self.state = ControllerState::PendingTakeContext;
@@ -566,9 +738,13 @@ impl MainController {
                             .set_subtitle(Some(context.info.read().unwrap().file_name.as_str()));
 
                         this.audio_ctrl.borrow_mut().new_media(&context);
+                        this.meter_ctrl.borrow_mut().new_media(&context);
                         this.export_ctrl.borrow_mut().new_media();
                         this.info_ctrl.borrow_mut().new_media(&context);
+                        this.overview_ctrl.borrow_mut().new_media(&context);
                         this.perspective_ctrl.borrow().new_media(&context);
+                        this.scene_ctrl.borrow_mut().new_media(&context);
+                        this.silence_ctrl.borrow_mut().new_media(&context);
                         this.split_ctrl.borrow_mut().new_media(&context);
                         this.streams_ctrl.borrow_mut().new_media(&context);
                         this.video_ctrl.new_media(&context);
@@ -603,11 +779,12 @@ impl MainController {
                         let mut this = this_rc.borrow_mut();
                         match this.state {
                             ControllerState::PlayingRange(pos_to_restore) => {
-                                // end of range => pause and seek back to pos_to_restore
-                                this.context.as_ref().unwrap().pause().unwrap();
-                                this.state = ControllerState::Paused;
-                                this.audio_ctrl.borrow_mut().stop_play_range();
-                                this.seek(pos_to_restore, true); // accurate
+                                match this.looping_range {
+                                    Some((start, end)) => {
+                                        this.context.as_ref().unwrap().seek_range(start, end)
+                                    }
+                                    None => this.stop_play_range(pos_to_restore),
+                                }
                             }
                             _ => {
                                 this.play_pause_btn.set_icon_name(PLAYBACK_ICON);
@@ -713,16 +890,22 @@ impl MainController {
 
         self.info_ctrl.borrow_mut().cleanup();
         self.audio_ctrl.borrow_mut().cleanup();
+        self.meter_ctrl.borrow_mut().cleanup();
+        self.overview_ctrl.borrow_mut().cleanup();
         self.video_ctrl.cleanup();
         self.export_ctrl.borrow_mut().cleanup();
+        self.scene_ctrl.borrow_mut().cleanup();
+        self.silence_ctrl.borrow_mut().cleanup();
         self.split_ctrl.borrow_mut().cleanup();
         self.streams_ctrl.borrow_mut().cleanup();
         self.perspective_ctrl.borrow().cleanup();
         self.header_bar.set_subtitle("");
+        self.playback_rate_btn.set_label("1×");
 
         let (ctx_tx, ui_rx) = channel();
 
         self.state = ControllerState::Stopped;
+        self.looping_range = None;
         self.missing_plugins.clear();
         self.keep_going = true;
         self.register_listener(LISTENER_PERIOD, ui_rx);
@@ -0,0 +1,202 @@
+use cairo;
+use gtk;
+use gtk::prelude::*;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use media::{LevelMeter, Levels, PlaybackContext};
+
+use super::BACKGROUND_COLOR;
+
+// Lowest level displayed on the channels bars
+const DB_FLOOR: f64 = -60f64; // dBFS
+// Lowest loudness displayed on the loudness bars
+const LUFS_FLOOR: f64 = -60f64; // LUFS
+// Reference level for the loudness as defined in EBU R128
+const LUFS_TARGET: f64 = -23f64; // LUFS
+const PEAK_HOLD_DURATION: u64 = 2_000_000_000; // 2 s
+
+const FONT_SIZE: f64 = 10f64;
+const BAR_SPACING: f64 = 2f64;
+const RMS_COLOR: (f64, f64, f64) = (0.2f64, 0.8f64, 0.2f64);
+const PEAK_COLOR: (f64, f64, f64) = (0.6f64, 0.9f64, 0.4f64);
+const HOLD_COLOR: (f64, f64, f64) = (1f64, 0.8f64, 0.2f64);
+const CLIPPING_COLOR: (f64, f64, f64) = (1f64, 0f64, 0f64);
+const LOUDNESS_COLOR: (f64, f64, f64) = (0.5f64, 0.6f64, 1f64);
+
+fn amplitude_to_db(amplitude: f64) -> f64 {
+    if amplitude > 0f64 {
+        20f64 * amplitude.log10()
+    } else {
+        DB_FLOOR
+    }
+}
+
+// Ratio of the bar height for `level` in the range [floor, 0]
+fn get_level_ratio(level: f64, floor: f64) -> f64 {
+    (1f64 - level / floor).max(0f64).min(1f64)
+}
+
+// Displays the peak and RMS levels of the channels with a peak hold
+// and the EBU R128 momentary and short-term loudness of the audio being played
+pub struct MeterController {
+    drawingarea: gtk::DrawingArea,
+
+    level_meter: Option<Arc<Mutex<LevelMeter>>>,
+    levels: Option<Levels>,
+    // Highest peak of each channel and the position when it was reached
+    peak_holds: Vec<(f64, u64)>,
+}
+
+impl MeterController {
+    pub fn new(builder: &gtk::Builder) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(MeterController {
+            drawingarea: builder.get_object("meter-drawingarea").unwrap(),
+
+            level_meter: None,
+            levels: None,
+            peak_holds: Vec::new(),
+        }))
+    }
+
+    pub fn register_callbacks(this_rc: &Rc<RefCell<Self>>) {
+        let this = this_rc.borrow();
+
+        // draw
+        let this_clone = Rc::clone(this_rc);
+        this.drawingarea
+            .connect_draw(move |drawingarea, cairo_ctx| {
+                this_clone.borrow().draw(drawingarea, cairo_ctx)
+            });
+    }
+
+    pub fn new_media(&mut self, context: &PlaybackContext) {
+        self.level_meter = Some(context.get_level_meter());
+        self.reset();
+    }
+
+    pub fn cleanup(&mut self) {
+        self.level_meter = None;
+        self.reset();
+    }
+
+    // Clears the levels displayed, e.g. after a seek
+    pub fn reset(&mut self) {
+        self.levels = None;
+        self.peak_holds.clear();
+        self.drawingarea.queue_draw();
+    }
+
+    pub fn tick(&mut self, position: u64) {
+        let levels = match self.level_meter {
+            Some(ref level_meter) => match level_meter.lock().unwrap().pop_levels(position) {
+                Some(levels) => levels,
+                None => return,
+            },
+            None => return,
+        };
+
+        self.peak_holds.resize(levels.peaks.len(), (0f64, 0));
+        for (peak_hold, peak) in self.peak_holds.iter_mut().zip(levels.peaks.iter()) {
+            if *peak >= peak_hold.0
+                || levels.position > peak_hold.1 + PEAK_HOLD_DURATION
+                || levels.position < peak_hold.1
+            {
+                *peak_hold = (*peak, levels.position);
+            }
+        }
+
+        self.levels = Some(levels);
+        self.drawingarea.queue_draw();
+    }
+
+    fn draw(&self, drawingarea: &gtk::DrawingArea, cr: &cairo::Context) -> Inhibit {
+        let allocation = drawingarea.get_allocation();
+        let width = f64::from(allocation.width);
+        let height = f64::from(allocation.height);
+
+        cr.set_source_rgb(BACKGROUND_COLOR.0, BACKGROUND_COLOR.1, BACKGROUND_COLOR.2);
+        cr.paint();
+
+        let levels = match self.levels {
+            Some(ref levels) if !levels.peaks.is_empty() => levels,
+            _ => return Inhibit(true),
+        };
+
+        cr.set_font_size(FONT_SIZE);
+        // Leave room for the loudness values
+        let bars_height = height - 2.6f64 * FONT_SIZE;
+        if bars_height <= 0f64 {
+            return Inhibit(true);
+        }
+
+        // One bar per channel, a space and the momentary & short-term loudness bars
+        let bar_width = (width - BAR_SPACING) / (levels.peaks.len() + 3) as f64 - BAR_SPACING;
+        let get_y = |ratio: f64| bars_height * (1f64 - ratio);
+
+        let mut x = BAR_SPACING;
+        for (channel, (peak, rms)) in levels.peaks.iter().zip(levels.rms.iter()).enumerate() {
+            let peak_y = get_y(get_level_ratio(amplitude_to_db(*peak), DB_FLOOR));
+            cr.set_source_rgb(PEAK_COLOR.0, PEAK_COLOR.1, PEAK_COLOR.2);
+            cr.rectangle(x, peak_y, bar_width, bars_height - peak_y);
+            cr.fill();
+
+            let rms_y = get_y(get_level_ratio(amplitude_to_db(*rms), DB_FLOOR));
+            cr.set_source_rgb(RMS_COLOR.0, RMS_COLOR.1, RMS_COLOR.2);
+            cr.rectangle(x, rms_y, bar_width, bars_height - rms_y);
+            cr.fill();
+
+            if let Some(&(peak_hold, _)) = self.peak_holds.get(channel) {
+                if peak_hold >= 1f64 {
+                    cr.set_source_rgb(CLIPPING_COLOR.0, CLIPPING_COLOR.1, CLIPPING_COLOR.2);
+                } else {
+                    cr.set_source_rgb(HOLD_COLOR.0, HOLD_COLOR.1, HOLD_COLOR.2);
+                }
+                let hold_y = get_y(get_level_ratio(amplitude_to_db(peak_hold), DB_FLOOR));
+                cr.rectangle(x, hold_y.min(bars_height - 2f64), bar_width, 2f64);
+                cr.fill();
+            }
+
+            x += bar_width + BAR_SPACING;
+        }
+
+        x += bar_width + BAR_SPACING;
+        let loudness_x = x;
+        cr.set_source_rgb(LOUDNESS_COLOR.0, LOUDNESS_COLOR.1, LOUDNESS_COLOR.2);
+        for loudness in &[levels.momentary, levels.short_term] {
+            if let Some(loudness) = *loudness {
+                let y = get_y(get_level_ratio(loudness, LUFS_FLOOR));
+                cr.rectangle(x, y, bar_width, bars_height - y);
+                cr.fill();
+            }
+
+            x += bar_width + BAR_SPACING;
+        }
+
+        // Loudness target
+        cr.set_source_rgb(1f64, 1f64, 1f64);
+        cr.set_line_width(1f64);
+        let target_y = get_y(get_level_ratio(LUFS_TARGET, LUFS_FLOOR)).round() + 0.5f64;
+        cr.move_to(loudness_x, target_y);
+        cr.line_to(x - BAR_SPACING, target_y);
+        cr.stroke();
+
+        // Loudness values
+        let mut text_base = bars_height + 1.1f64 * FONT_SIZE;
+        for (label, loudness) in &[("M", levels.momentary), ("S", levels.short_term)] {
+            let text = match *loudness {
+                Some(loudness) if loudness > LUFS_FLOOR => {
+                    format!("{} {:.1} LUFS", label, loudness)
+                }
+                _ => format!("{} -∞ LUFS", label),
+            };
+            cr.move_to(BAR_SPACING, text_base);
+            cr.show_text(&text);
+            text_base += 1.2f64 * FONT_SIZE;
+        }
+
+        Inhibit(true)
+    }
+}
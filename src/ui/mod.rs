@@ -4,6 +4,9 @@ use self::audio_controller::AudioController;
 pub mod chapters_boundaries;
 pub use self::chapters_boundaries::ChaptersBoundaries;
 
+mod chapter_tags_editor;
+use self::chapter_tags_editor::ChapterTagsEditor;
+
 mod chapter_tree_manager;
 use self::chapter_tree_manager::ChapterTreeManager;
 
@@ -19,12 +22,27 @@ use self::image_surface::ImageSurface;
 pub mod main_controller;
 pub use self::main_controller::{ControllerState, MainController};
 
+mod meter_controller;
+use self::meter_controller::MeterController;
+
 mod output_base_controller;
 use self::output_base_controller::OutputBaseController;
 
+mod overview_controller;
+use self::overview_controller::OverviewController;
+
 mod perspective_controller;
 use self::perspective_controller::PerspectiveController;
 
+mod scene_controller;
+use self::scene_controller::SceneController;
+
+mod silence_controller;
+use self::silence_controller::SilenceController;
+
+pub mod spectrogram_buffer;
+pub use self::spectrogram_buffer::{SpectrogramBuffer, SpectrogramSettings};
+
 mod streams_controller;
 use self::streams_controller::StreamsController;
 
@@ -35,7 +53,12 @@ mod video_controller;
 use self::video_controller::VideoController;
 
 pub mod waveform_buffer;
-pub use self::waveform_buffer::{DoubleWaveformBuffer, ImagePositions, WaveformBuffer};
+pub use self::waveform_buffer::{DoubleWaveformBuffer, ImageLayout, ImagePositions,
+                                WaveformBuffer};
 
 pub mod waveform_image;
-pub use self::waveform_image::{WaveformImage, BACKGROUND_COLOR};
+pub use self::waveform_image::{get_amplitude_ratio, AmplitudeMode, ChannelLane, WaveformImage,
+                               BACKGROUND_COLOR};
+
+pub mod waveform_renderer;
+pub use self::waveform_renderer::WaveformRenderer;
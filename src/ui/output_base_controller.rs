@@ -80,6 +80,11 @@ impl OutputBaseController {
         self.show_message(gtk::MessageType::Error, error);
     }
 
+    pub fn show_chapters_loudness(&self, loudness: &[(u64, String)]) {
+        let main_ctrl_rc = self.main_ctrl.as_ref().unwrap().upgrade().unwrap();
+        main_ctrl_rc.borrow().show_chapters_loudness(loudness);
+    }
+
     pub fn restore_context(&mut self) {
         let context = self.playback_ctx.take().unwrap();
         let main_ctrl_rc = self.main_ctrl.as_ref().unwrap().upgrade().unwrap();
@@ -0,0 +1,384 @@
+use cairo;
+use gettextrs::gettext;
+use glib;
+use gtk;
+use gtk::prelude::*;
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+
+use application::{PeaksCacheKey, PEAKS_CACHE};
+use media::ContextMessage::*;
+use media::{Peaks, PeaksContext, PlaybackContext};
+use metadata::MediaInfo;
+
+use super::{ChaptersBoundaries, MainController, BACKGROUND_COLOR};
+
+const LISTENER_PERIOD: u32 = 500; // 500 ms (2 Hz)
+
+// Displays the peaks of the whole audio stream with the chapters boundaries
+// and the range displayed in the waveform
+pub struct OverviewController {
+    drawingarea: gtk::DrawingArea,
+    boundaries: Rc<RefCell<ChaptersBoundaries>>,
+
+    media_path: Option<PathBuf>,
+    audio_stream_id: Option<String>,
+    duration: u64,
+    position: u64,
+    visible_range: Option<(u64, u64)>,
+    // Offset between the pointer and the playback position while dragging the viewport
+    drag_offset: Option<i64>,
+
+    peaks: Option<Arc<Mutex<Peaks>>>,
+    // Identifies the peaks being computed in the cache
+    peaks_key: Option<PeaksCacheKey>,
+    peaks_ctx: Option<PeaksContext>,
+    listener_src: Option<glib::SourceId>,
+
+    main_ctrl: Option<Weak<RefCell<MainController>>>,
+    this_opt: Option<Rc<RefCell<OverviewController>>>,
+}
+
+impl OverviewController {
+    pub fn new(
+        builder: &gtk::Builder,
+        boundaries: Rc<RefCell<ChaptersBoundaries>>,
+    ) -> Rc<RefCell<Self>> {
+        let this = Rc::new(RefCell::new(OverviewController {
+            drawingarea: builder.get_object("overview-drawingarea").unwrap(),
+            boundaries,
+
+            media_path: None,
+            audio_stream_id: None,
+            duration: 0,
+            position: 0,
+            visible_range: None,
+            drag_offset: None,
+
+            peaks: None,
+            peaks_key: None,
+            peaks_ctx: None,
+            listener_src: None,
+
+            main_ctrl: None,
+            this_opt: None,
+        }));
+
+        {
+            let mut this_mut = this.borrow_mut();
+            let this_rc = Rc::clone(&this);
+            this_mut.this_opt = Some(this_rc);
+        }
+
+        this
+    }
+
+    pub fn register_callbacks(
+        this_rc: &Rc<RefCell<Self>>,
+        main_ctrl: &Rc<RefCell<MainController>>,
+    ) {
+        let mut this = this_rc.borrow_mut();
+        this.main_ctrl = Some(Rc::downgrade(main_ctrl));
+
+        // draw
+        let this_clone = Rc::clone(this_rc);
+        this.drawingarea
+            .connect_draw(move |drawingarea, cairo_ctx| {
+                this_clone.borrow().draw(drawingarea, cairo_ctx)
+            });
+
+        // button press: seek or start dragging the viewport
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        this.drawingarea
+            .connect_button_press_event(move |_, event_button| {
+                if 1 == event_button.get_button() {
+                    // left button
+                    let target = {
+                        let mut this = this_clone.borrow_mut();
+                        if this.duration == 0 {
+                            return Inhibit(true);
+                        }
+
+                        let pointer_pos = this.get_position_at(event_button.get_position().0);
+                        let is_in_viewport = this.visible_range
+                            .map_or(false, |(first, last)| pointer_pos >= first && pointer_pos <= last);
+                        if is_in_viewport {
+                            this.drag_offset = Some(pointer_pos as i64 - this.position as i64);
+                            None
+                        } else {
+                            this.drag_offset = Some(0);
+                            Some(pointer_pos)
+                        }
+                    };
+
+                    if let Some(target) = target {
+                        main_ctrl_clone.borrow_mut().seek(target, true); // accurate (slow)
+                    }
+                }
+                Inhibit(true)
+            });
+
+        // drag the viewport
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        this.drawingarea
+            .connect_motion_notify_event(move |_, event_motion| {
+                let target = this_clone
+                    .borrow()
+                    .get_drag_target(event_motion.get_position().0);
+                if let Some(target) = target {
+                    main_ctrl_clone.borrow_mut().seek(target, false); // approximate (fast)
+                }
+                Inhibit(true)
+            });
+
+        // stop dragging
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        this.drawingarea
+            .connect_button_release_event(move |_, event_button| {
+                if 1 == event_button.get_button() {
+                    let target = {
+                        let mut this = this_clone.borrow_mut();
+                        let target = this.get_drag_target(event_button.get_position().0);
+                        this.drag_offset = None;
+                        target
+                    };
+                    if let Some(target) = target {
+                        main_ctrl_clone.borrow_mut().seek(target, true); // accurate (slow)
+                    }
+                }
+                Inhibit(true)
+            });
+    }
+
+    pub fn new_media(&mut self, context: &PlaybackContext) {
+        let info = context.info.read().unwrap();
+        self.media_path = Some(info.path.clone());
+        self.duration = info.duration;
+        self.streams_changed(&info);
+    }
+
+    pub fn streams_changed(&mut self, info: &MediaInfo) {
+        let audio_stream_id = info.streams
+            .selected_audio()
+            .map(|stream| stream.id.to_string());
+        if audio_stream_id != self.audio_stream_id {
+            self.audio_stream_id = audio_stream_id;
+            self.compute_peaks();
+        }
+    }
+
+    pub fn tick(&mut self, position: u64, visible_range: Option<(u64, u64)>) {
+        if position != self.position || visible_range != self.visible_range {
+            self.position = position;
+            self.visible_range = visible_range;
+            self.drawingarea.queue_draw();
+        }
+    }
+
+    pub fn cleanup(&mut self) {
+        self.cancel_computation();
+        self.peaks = None;
+        self.peaks_key = None;
+        self.media_path = None;
+        self.audio_stream_id = None;
+        self.duration = 0;
+        self.position = 0;
+        self.visible_range = None;
+        self.drag_offset = None;
+        self.drawingarea.queue_draw();
+    }
+
+    fn compute_peaks(&mut self) {
+        self.cancel_computation();
+        self.peaks = None;
+        self.drawingarea.queue_draw();
+
+        let (media_path, stream_id) = match (self.media_path.clone(), self.audio_stream_id.clone())
+        {
+            (Some(media_path), Some(stream_id)) => (media_path, stream_id),
+            _ => return,
+        };
+
+        self.peaks_key = PeaksCacheKey::new(&media_path, &stream_id);
+        if let Some(ref peaks_key) = self.peaks_key {
+            if let Some(peaks) = PEAKS_CACHE.lock().unwrap().load(peaks_key) {
+                debug!("using cached peaks for {:?}", media_path);
+                self.peaks = Some(Arc::new(Mutex::new(peaks)));
+                return;
+            }
+        }
+
+        let (ctx_tx, ui_rx) = channel();
+        let peaks_ctx = match PeaksContext::new(&media_path, &stream_id, ctx_tx) {
+            Ok(peaks_ctx) => peaks_ctx,
+            Err(err) => {
+                error!("{}", err);
+                return;
+            }
+        };
+        self.peaks = Some(peaks_ctx.get_peaks());
+        self.peaks_ctx = Some(peaks_ctx);
+
+        let this_rc = Rc::clone(self.this_opt.as_ref().unwrap());
+        let main_ctrl_weak = Weak::clone(self.main_ctrl.as_ref().unwrap());
+        self.listener_src = Some(gtk::timeout_add(LISTENER_PERIOD, move || {
+            let mut keep_going = true;
+            for message in ui_rx.try_iter() {
+                match message {
+                    Eos => {
+                        this_rc.borrow().store_peaks();
+                        keep_going = false;
+                    }
+                    FailedToAnalyze(err) => {
+                        let msg = gettext("Failed to compute the audio overview. {}")
+                            .replacen("{}", &err, 1);
+                        error!("{}", msg);
+                        if let Some(main_ctrl) = main_ctrl_weak.upgrade() {
+                            main_ctrl
+                                .borrow()
+                                .show_message(gtk::MessageType::Warning, &msg);
+                        }
+                        keep_going = false;
+                    }
+                    _ => (),
+                }
+
+                if !keep_going {
+                    break;
+                }
+            }
+
+            let mut this = this_rc.borrow_mut();
+            if !keep_going {
+                // The listener is removed when returning
+                this.listener_src = None;
+                this.cancel_computation();
+            }
+            // Show the peaks computed so far
+            this.drawingarea.queue_draw();
+
+            glib::Continue(keep_going)
+        }));
+    }
+
+    fn store_peaks(&self) {
+        if let (Some(peaks), Some(peaks_key)) = (self.peaks.as_ref(), self.peaks_key.as_ref()) {
+            let peaks = peaks.lock().unwrap();
+            if peaks.is_complete() {
+                if let Err(err) = PEAKS_CACHE.lock().unwrap().store(peaks_key, &peaks) {
+                    warn!("{}", err);
+                }
+            }
+        }
+    }
+
+    fn cancel_computation(&mut self) {
+        if let Some(source_id) = self.listener_src.take() {
+            glib::source_remove(source_id);
+        }
+        if let Some(mut peaks_ctx) = self.peaks_ctx.take() {
+            peaks_ctx.cancel();
+        }
+    }
+
+    fn get_position_at(&self, x: f64) -> u64 {
+        let width = f64::from(self.drawingarea.get_allocated_width());
+        if width <= 0f64 {
+            return 0;
+        }
+
+        (x.max(0f64).min(width) / width * self.duration as f64) as u64
+    }
+
+    fn get_drag_target(&self, x: f64) -> Option<u64> {
+        self.drag_offset.map(|drag_offset| {
+            let target = self.get_position_at(x) as i64 - drag_offset;
+            target.max(0).min(self.duration as i64) as u64
+        })
+    }
+
+    fn draw(&self, drawingarea: &gtk::DrawingArea, cr: &cairo::Context) -> Inhibit {
+        let allocation = drawingarea.get_allocation();
+        let width = f64::from(allocation.width);
+        let height = f64::from(allocation.height);
+
+        cr.set_source_rgb(BACKGROUND_COLOR.0, BACKGROUND_COLOR.1, BACKGROUND_COLOR.2);
+        cr.paint();
+
+        if self.duration == 0 || allocation.width <= 0 {
+            return Inhibit(true);
+        }
+
+        let half_height = height / 2f64;
+        let duration = self.duration;
+        let get_x = |position: u64| position as f64 / duration as f64 * width;
+
+        // Peaks
+        if let Some(ref peaks) = self.peaks {
+            let peaks = peaks.lock().unwrap();
+            let column_count = allocation.width as u64;
+            let mut column_peaks = Vec::with_capacity(allocation.width as usize);
+            for column in 0..column_count {
+                let start = duration * column / column_count;
+                let end = duration * (column + 1) / column_count;
+                match peaks.get_peak(start, end) {
+                    Some(peak) => column_peaks.push(peak),
+                    None => break,
+                }
+            }
+
+            cr.set_line_width(1f64);
+            cr.set_source_rgb(0.55f64, 0.6f64, 0.75f64);
+            for (column, peak) in column_peaks.iter().enumerate() {
+                let x = column as f64 + 0.5f64;
+                cr.move_to(x, half_height * (1f64 - peak.max));
+                cr.line_to(x, half_height * (1f64 - peak.min) + 1f64);
+            }
+            cr.stroke();
+
+            cr.set_source_rgb(0.75f64, 0.8f64, 0.95f64);
+            for (column, peak) in column_peaks.iter().enumerate() {
+                let x = column as f64 + 0.5f64;
+                cr.move_to(x, half_height * (1f64 - peak.rms));
+                cr.line_to(x, half_height * (1f64 + peak.rms));
+            }
+            cr.stroke();
+        }
+
+        // Chapters boundaries
+        cr.set_source_rgba(1f64, 1f64, 1f64, 0.6f64);
+        for boundary in self.boundaries.borrow().keys() {
+            let x = get_x(*boundary).round() + 0.5f64;
+            cr.move_to(x, 0f64);
+            cr.line_to(x, height);
+        }
+        cr.stroke();
+
+        // Range displayed in the waveform
+        if let Some((first, last)) = self.visible_range {
+            let x = get_x(first);
+            let viewport_width = (get_x(last) - x).max(2f64);
+            cr.rectangle(x, 0.5f64, viewport_width, height - 1f64);
+            cr.set_source_rgba(1f64, 1f64, 1f64, 0.15f64);
+            cr.fill_preserve();
+            cr.set_source_rgba(1f64, 1f64, 1f64, 0.6f64);
+            cr.stroke();
+        }
+
+        // Playback position
+        let x = get_x(self.position).round() + 0.5f64;
+        cr.set_source_rgb(1f64, 0f64, 0f64);
+        cr.move_to(x, 0f64);
+        cr.line_to(x, height);
+        cr.stroke();
+
+        Inhibit(true)
+    }
+}
@@ -0,0 +1,387 @@
+use gettextrs::{gettext, ngettext};
+use gio;
+use gio::prelude::*;
+use glib;
+use gtk;
+use gtk::prelude::*;
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::channel;
+
+use application::CONFIG;
+use media::ContextMessage::*;
+use media::{PlaybackContext, SceneCandidate, SceneChange, SceneContext, SceneDetector};
+use metadata::{MediaInfo, Timestamp};
+
+use super::{ImageSurface, MainController};
+
+const LISTENER_PERIOD: u32 = 250; // 250 ms (4 Hz)
+
+// A proposed chapter start in the review list
+struct SceneRow {
+    position: u64,
+    check_btn: gtk::CheckButton,
+}
+
+pub struct SceneController {
+    detect_scenes_btn: gtk::ToolButton,
+    candidates_btn: gtk::MenuButton,
+    candidates_list: gtk::ListBox,
+    status_lbl: gtk::Label,
+    insert_btn: gtk::Button,
+    black_level_spin: gtk::SpinButton,
+    cut_threshold_spin: gtk::SpinButton,
+    min_interval_spin: gtk::SpinButton,
+
+    media_path: Option<PathBuf>,
+    video_stream_id: Option<String>,
+    duration: u64,
+    rows: Vec<SceneRow>,
+    scene_ctx: Option<SceneContext>,
+    listener_src: Option<glib::SourceId>,
+}
+
+impl SceneController {
+    pub fn new(builder: &gtk::Builder) -> Rc<RefCell<Self>> {
+        let this_rc = Rc::new(RefCell::new(SceneController {
+            detect_scenes_btn: builder.get_object("detect_scenes-toolbutton").unwrap(),
+            candidates_btn: builder.get_object("scene_candidates-menubutton").unwrap(),
+            candidates_list: builder.get_object("scene_candidates-listbox").unwrap(),
+            status_lbl: builder.get_object("scene_status-lbl").unwrap(),
+            insert_btn: builder.get_object("insert_scenes-btn").unwrap(),
+            black_level_spin: builder
+                .get_object("scene_detection_black_level-spin")
+                .unwrap(),
+            cut_threshold_spin: builder
+                .get_object("scene_detection_cut_threshold-spin")
+                .unwrap(),
+            min_interval_spin: builder
+                .get_object("scene_detection_min_interval-spin")
+                .unwrap(),
+
+            media_path: None,
+            video_stream_id: None,
+            duration: 0,
+            rows: Vec::new(),
+            scene_ctx: None,
+            listener_src: None,
+        }));
+
+        {
+            let mut this = this_rc.borrow_mut();
+            this.cleanup();
+        }
+
+        this_rc
+    }
+
+    pub fn register_callbacks(
+        this_rc: &Rc<RefCell<Self>>,
+        gtk_app: &gtk::Application,
+        main_ctrl: &Rc<RefCell<MainController>>,
+    ) {
+        let this = this_rc.borrow();
+
+        {
+            let config = CONFIG.read().unwrap();
+            this.black_level_spin
+                .set_value(f64::from(config.scene_detection.black_level));
+            this.cut_threshold_spin
+                .set_value(f64::from(config.scene_detection.cut_threshold));
+            this.min_interval_spin
+                .set_value(f64::from(config.scene_detection.min_interval_ms));
+        }
+        this.black_level_spin.connect_value_changed(|spin_btn| {
+            CONFIG.write().unwrap().scene_detection.black_level = spin_btn.get_value_as_int() as u32;
+        });
+        this.cut_threshold_spin.connect_value_changed(|spin_btn| {
+            CONFIG.write().unwrap().scene_detection.cut_threshold =
+                spin_btn.get_value_as_int() as u32;
+        });
+        this.min_interval_spin.connect_value_changed(|spin_btn| {
+            CONFIG.write().unwrap().scene_detection.min_interval_ms =
+                spin_btn.get_value_as_int() as u32;
+        });
+
+        // Register Detect scenes action
+        let detect_scenes = gio::SimpleAction::new("detect_scenes", None);
+        gtk_app.add_action(&detect_scenes);
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        detect_scenes.connect_activate(move |_, _| {
+            SceneController::detect_scenes(&this_clone, &main_ctrl_clone);
+        });
+
+        // Review a candidate
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        this.candidates_list
+            .connect_row_activated(move |_, list_row| {
+                let position = this_clone
+                    .borrow()
+                    .rows
+                    .get(list_row.get_index() as usize)
+                    .map(|row| row.position);
+                if let Some(position) = position {
+                    main_ctrl_clone.borrow_mut().seek(position, true); // accurate (slow)
+                }
+            });
+
+        // Insert the checked candidates in the chapters list
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        this.insert_btn.connect_clicked(move |_| {
+            let positions = this_clone
+                .borrow()
+                .rows
+                .iter()
+                .filter(|row| row.check_btn.get_active())
+                .map(|row| row.position)
+                .collect::<Vec<u64>>();
+            if positions.is_empty() {
+                return;
+            }
+
+            let added_count = {
+                let mut main_ctrl = main_ctrl_clone.borrow_mut();
+                let added_count = main_ctrl.add_chapter_boundaries(&positions);
+                // reflect the new chapters in the UI (audio waveform)
+                main_ctrl.refresh();
+                added_count
+            };
+
+            this_clone.borrow_mut().remove_rows(&positions);
+
+            let msg = ngettext("{} chapter added", "{} chapters added", added_count as u32)
+                .replacen("{}", &format!("{}", added_count), 1);
+            main_ctrl_clone
+                .borrow()
+                .show_message(gtk::MessageType::Info, &msg);
+        });
+    }
+
+    pub fn new_media(&mut self, context: &PlaybackContext) {
+        let info = context.info.read().unwrap();
+        self.media_path = Some(info.path.clone());
+        self.duration = info.duration;
+        self.streams_changed(&info);
+    }
+
+    pub fn streams_changed(&mut self, info: &MediaInfo) {
+        self.video_stream_id = info.streams
+            .selected_video()
+            .map(|stream| stream.id.to_string());
+        self.detect_scenes_btn
+            .set_sensitive(self.video_stream_id.is_some() && self.scene_ctx.is_none());
+    }
+
+    pub fn cleanup(&mut self) {
+        self.cancel_detection();
+        self.media_path = None;
+        self.video_stream_id = None;
+        self.duration = 0;
+        self.remove_rows(&[]);
+        self.detect_scenes_btn.set_sensitive(false);
+    }
+
+    fn detect_scenes(
+        this_rc: &Rc<RefCell<SceneController>>,
+        main_ctrl: &Rc<RefCell<MainController>>,
+    ) {
+        let mut this = this_rc.borrow_mut();
+        if this.scene_ctx.is_some() {
+            return;
+        }
+
+        let (media_path, stream_id) = match (this.media_path.clone(), this.video_stream_id.clone())
+        {
+            (Some(media_path), Some(stream_id)) => (media_path, stream_id),
+            _ => return,
+        };
+
+        let detector = {
+            let config = CONFIG.read().unwrap();
+            SceneDetector::new(
+                f64::from(config.scene_detection.black_level) / 100f64,
+                f64::from(config.scene_detection.cut_threshold) / 100f64,
+                u64::from(config.scene_detection.min_interval_ms) * 1_000_000,
+            )
+        };
+
+        let (ctx_tx, ui_rx) = channel();
+        match SceneContext::new(&media_path, &stream_id, detector, ctx_tx) {
+            Ok(scene_ctx) => this.scene_ctx = Some(scene_ctx),
+            Err(err) => {
+                main_ctrl
+                    .borrow()
+                    .show_message(gtk::MessageType::Error, &err);
+                return;
+            }
+        }
+        this.detect_scenes_btn.set_sensitive(false);
+        this.status_lbl.set_text(&gettext("Detecting scene changes..."));
+
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        this.listener_src = Some(gtk::timeout_add(LISTENER_PERIOD, move || {
+            let mut keep_going = true;
+            for message in ui_rx.try_iter() {
+                match message {
+                    Eos => {
+                        let candidate_count = this_clone.borrow_mut().scenes_detected();
+                        let msg = if candidate_count > 0 {
+                            ngettext(
+                                "{} chapter start proposed",
+                                "{} chapter starts proposed",
+                                candidate_count as u32,
+                            ).replacen("{}", &format!("{}", candidate_count), 1)
+                        } else {
+                            gettext("No scene changes found")
+                        };
+                        main_ctrl_clone
+                            .borrow()
+                            .show_message(gtk::MessageType::Info, &msg);
+                        keep_going = false;
+                    }
+                    FailedToAnalyze(err) => {
+                        {
+                            let mut this = this_clone.borrow_mut();
+                            // The listener is removed when returning
+                            this.listener_src = None;
+                            this.cancel_detection();
+                        }
+                        let msg =
+                            gettext("Failed to detect scene changes. {}").replacen("{}", &err, 1);
+                        error!("{}", msg);
+                        main_ctrl_clone
+                            .borrow()
+                            .show_message(gtk::MessageType::Error, &msg);
+                        keep_going = false;
+                    }
+                    _ => (),
+                }
+
+                if !keep_going {
+                    break;
+                }
+            }
+
+            if keep_going {
+                this_clone.borrow_mut().update_progress();
+            }
+
+            glib::Continue(keep_going)
+        }));
+    }
+
+    fn update_progress(&mut self) {
+        if self.duration == 0 {
+            return;
+        }
+
+        if let Some(position) = self.scene_ctx
+            .as_mut()
+            .map(|scene_ctx| scene_ctx.get_position())
+        {
+            let progress = (100 * position / self.duration).min(100);
+            self.status_lbl.set_text(
+                &gettext("Detecting scene changes... {}%").replacen(
+                    "{}",
+                    &format!("{}", progress),
+                    1,
+                ),
+            );
+        }
+    }
+
+    // Returns the number of proposed chapter starts
+    fn scenes_detected(&mut self) -> usize {
+        // The listener is removed when returning
+        self.listener_src = None;
+        let candidates = match self.scene_ctx.take() {
+            Some(scene_ctx) => scene_ctx.take_candidates(),
+            None => Vec::new(),
+        };
+
+        self.remove_rows(&[]);
+        for candidate in &candidates {
+            self.add_row(candidate);
+        }
+
+        self.status_lbl.set_text("");
+        self.detect_scenes_btn
+            .set_sensitive(self.video_stream_id.is_some());
+        self.insert_btn.set_sensitive(!self.rows.is_empty());
+        if !self.rows.is_empty() {
+            // Show the candidates for review
+            self.candidates_btn.set_active(true);
+        }
+
+        self.rows.len()
+    }
+
+    fn add_row(&mut self, candidate: &SceneCandidate) {
+        let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+
+        let check_btn = gtk::CheckButton::new();
+        check_btn.set_active(true);
+        row_box.pack_start(&check_btn, false, false, 0);
+
+        let thumbnail = &candidate.thumbnail;
+        if let Ok(surface) = ImageSurface::create_from_rgb(
+            thumbnail.width as i32,
+            thumbnail.height as i32,
+            &thumbnail.data,
+        ) {
+            row_box.pack_start(&gtk::Image::new_from_surface(&*surface), false, false, 0);
+        }
+
+        let change = match candidate.change {
+            SceneChange::BlackFrames => gettext("Black frames"),
+            SceneChange::Cut => gettext("Cut"),
+        };
+        let text = format!("{}\n{}", Timestamp::format(candidate.position, false), change);
+        let label = gtk::Label::new(Some(text.as_str()));
+        label.set_halign(gtk::Align::Start);
+        row_box.pack_start(&label, true, true, 0);
+
+        row_box.show_all();
+        self.candidates_list.add(&row_box);
+
+        self.rows.push(SceneRow {
+            position: candidate.position,
+            check_btn,
+        });
+    }
+
+    // Removes the rows at the given positions, or all the rows if `positions` is empty
+    fn remove_rows(&mut self, positions: &[u64]) {
+        let candidates_list = self.candidates_list.clone();
+        let list_rows = candidates_list.get_children();
+        let mut index = 0;
+        self.rows.retain(|row| {
+            let must_remove = positions.is_empty() || positions.contains(&row.position);
+            if must_remove {
+                candidates_list.remove(&list_rows[index]);
+            }
+            index += 1;
+            !must_remove
+        });
+
+        self.insert_btn.set_sensitive(!self.rows.is_empty());
+    }
+
+    fn cancel_detection(&mut self) {
+        if let Some(source_id) = self.listener_src.take() {
+            glib::source_remove(source_id);
+        }
+        if let Some(mut scene_ctx) = self.scene_ctx.take() {
+            scene_ctx.cancel();
+        }
+        self.status_lbl.set_text("");
+        self.detect_scenes_btn
+            .set_sensitive(self.video_stream_id.is_some());
+    }
+}
@@ -0,0 +1,291 @@
+use gettextrs::{gettext, ngettext};
+use gio;
+use gio::prelude::*;
+use glib;
+use gtk;
+use gtk::prelude::*;
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::channel;
+
+use application::CONFIG;
+use media::ContextMessage::*;
+use media::{PlaybackContext, SilenceContext, SilenceDetector};
+use metadata::MediaInfo;
+
+use super::{ChaptersBoundaries, MainController};
+
+const LISTENER_PERIOD: u32 = 250; // 250 ms (4 Hz)
+
+// Detects the silences in the selected audio stream and proposes
+// chapters boundaries which are displayed in the waveform (see `AudioController`)
+pub struct SilenceController {
+    drawingarea: gtk::DrawingArea,
+    detect_silences_btn: gtk::ToolButton,
+    accept_candidates_btn: gtk::ToolButton,
+    threshold_spin: gtk::SpinButton,
+    min_duration_spin: gtk::SpinButton,
+    per_channel_chk: gtk::CheckButton,
+
+    media_path: Option<PathBuf>,
+    audio_stream_id: Option<String>,
+    boundaries: Rc<RefCell<ChaptersBoundaries>>,
+    candidates: Vec<u64>,
+    silence_ctx: Option<SilenceContext>,
+    listener_src: Option<glib::SourceId>,
+}
+
+impl SilenceController {
+    pub fn new(
+        builder: &gtk::Builder,
+        boundaries: Rc<RefCell<ChaptersBoundaries>>,
+    ) -> Rc<RefCell<Self>> {
+        let this_rc = Rc::new(RefCell::new(SilenceController {
+            drawingarea: builder.get_object("audio-drawingarea").unwrap(),
+            detect_silences_btn: builder
+                .get_object("audio_detect_silences-toolbutton")
+                .unwrap(),
+            accept_candidates_btn: builder
+                .get_object("audio_accept_candidates-toolbutton")
+                .unwrap(),
+            threshold_spin: builder
+                .get_object("silence_detection_threshold-spin")
+                .unwrap(),
+            min_duration_spin: builder
+                .get_object("silence_detection_min_duration-spin")
+                .unwrap(),
+            per_channel_chk: builder
+                .get_object("silence_detection_per_channel-check")
+                .unwrap(),
+
+            media_path: None,
+            audio_stream_id: None,
+            boundaries,
+            candidates: Vec::new(),
+            silence_ctx: None,
+            listener_src: None,
+        }));
+
+        {
+            let mut this = this_rc.borrow_mut();
+            this.cleanup();
+        }
+
+        this_rc
+    }
+
+    pub fn register_callbacks(
+        this_rc: &Rc<RefCell<Self>>,
+        gtk_app: &gtk::Application,
+        main_ctrl: &Rc<RefCell<MainController>>,
+    ) {
+        let this = this_rc.borrow();
+
+        {
+            let config = CONFIG.read().unwrap();
+            this.threshold_spin
+                .set_value(f64::from(config.silence_detection.threshold));
+            this.min_duration_spin
+                .set_value(f64::from(config.silence_detection.min_duration_ms));
+            this.per_channel_chk
+                .set_active(config.silence_detection.is_per_channel);
+        }
+        this.threshold_spin.connect_value_changed(|spin_btn| {
+            CONFIG.write().unwrap().silence_detection.threshold = spin_btn.get_value_as_int();
+        });
+        this.min_duration_spin.connect_value_changed(|spin_btn| {
+            CONFIG.write().unwrap().silence_detection.min_duration_ms =
+                spin_btn.get_value_as_int() as u32;
+        });
+        this.per_channel_chk.connect_toggled(|check_btn| {
+            CONFIG.write().unwrap().silence_detection.is_per_channel = check_btn.get_active();
+        });
+
+        // Register Detect silences action
+        let detect_silences = gio::SimpleAction::new("detect_silences", None);
+        gtk_app.add_action(&detect_silences);
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        detect_silences.connect_activate(move |_, _| {
+            SilenceController::detect_silences(&this_clone, &main_ctrl_clone);
+        });
+
+        // Register Accept candidates action
+        let accept_candidates = gio::SimpleAction::new("accept_candidates", None);
+        gtk_app.add_action(&accept_candidates);
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        accept_candidates.connect_activate(move |_, _| {
+            let candidates = this_clone.borrow().candidates.clone();
+            SilenceController::accept_candidates(&this_clone, &main_ctrl_clone, &candidates);
+        });
+    }
+
+    pub fn new_media(&mut self, context: &PlaybackContext) {
+        let info = context.info.read().unwrap();
+        self.media_path = Some(info.path.clone());
+        self.streams_changed(&info);
+    }
+
+    pub fn streams_changed(&mut self, info: &MediaInfo) {
+        self.audio_stream_id = info.streams
+            .selected_audio()
+            .map(|stream| stream.id.to_string());
+        self.detect_silences_btn
+            .set_sensitive(self.audio_stream_id.is_some() && self.silence_ctx.is_none());
+    }
+
+    pub fn cleanup(&mut self) {
+        self.cancel_detection();
+        self.media_path = None;
+        self.audio_stream_id = None;
+        self.candidates.clear();
+        self.drawingarea.queue_draw();
+        self.detect_silences_btn.set_sensitive(false);
+        self.accept_candidates_btn.set_sensitive(false);
+    }
+
+    // Proposed chapters boundaries
+    pub fn get_candidates(&self) -> &[u64] {
+        &self.candidates
+    }
+
+    // Adds chapters boundaries at the given candidates positions
+    pub fn accept_candidates(
+        this_rc: &Rc<RefCell<SilenceController>>,
+        main_ctrl: &Rc<RefCell<MainController>>,
+        candidates: &[u64],
+    ) {
+        if candidates.is_empty() {
+            return;
+        }
+
+        main_ctrl.borrow_mut().add_chapter_boundaries(candidates);
+
+        let mut this = this_rc.borrow_mut();
+        this.candidates
+            .retain(|candidate| !candidates.contains(candidate));
+        this.accept_candidates_btn
+            .set_sensitive(!this.candidates.is_empty());
+        this.drawingarea.queue_draw();
+    }
+
+    fn detect_silences(
+        this_rc: &Rc<RefCell<SilenceController>>,
+        main_ctrl: &Rc<RefCell<MainController>>,
+    ) {
+        let mut this = this_rc.borrow_mut();
+        if this.silence_ctx.is_some() {
+            return;
+        }
+
+        let (media_path, stream_id) = match (this.media_path.clone(), this.audio_stream_id.clone())
+        {
+            (Some(media_path), Some(stream_id)) => (media_path, stream_id),
+            _ => return,
+        };
+
+        let detector = {
+            let config = CONFIG.read().unwrap();
+            SilenceDetector::new(
+                f64::from(config.silence_detection.threshold),
+                u64::from(config.silence_detection.min_duration_ms) * 1_000_000,
+                config.silence_detection.is_per_channel,
+            )
+        };
+
+        let (ctx_tx, ui_rx) = channel();
+        match SilenceContext::new(&media_path, &stream_id, detector, ctx_tx) {
+            Ok(silence_ctx) => this.silence_ctx = Some(silence_ctx),
+            Err(err) => {
+                main_ctrl
+                    .borrow()
+                    .show_message(gtk::MessageType::Error, &err);
+                return;
+            }
+        }
+        this.detect_silences_btn.set_sensitive(false);
+
+        let this_clone = Rc::clone(this_rc);
+        let main_ctrl_clone = Rc::clone(main_ctrl);
+        this.listener_src = Some(gtk::timeout_add(LISTENER_PERIOD, move || {
+            let mut keep_going = true;
+            for message in ui_rx.try_iter() {
+                match message {
+                    Eos => {
+                        let candidate_count = this_clone.borrow_mut().silences_detected();
+                        let msg = if candidate_count > 0 {
+                            ngettext(
+                                "{} chapter boundary proposed",
+                                "{} chapter boundaries proposed",
+                                candidate_count as u32,
+                            ).replacen("{}", &format!("{}", candidate_count), 1)
+                        } else {
+                            gettext("No silences found")
+                        };
+                        main_ctrl_clone
+                            .borrow()
+                            .show_message(gtk::MessageType::Info, &msg);
+                        keep_going = false;
+                    }
+                    FailedToAnalyze(err) => {
+                        {
+                            let mut this = this_clone.borrow_mut();
+                            // The listener is removed when returning
+                            this.listener_src = None;
+                            this.cancel_detection();
+                        }
+                        let msg =
+                            gettext("Failed to detect silences. {}").replacen("{}", &err, 1);
+                        error!("{}", msg);
+                        main_ctrl_clone
+                            .borrow()
+                            .show_message(gtk::MessageType::Error, &msg);
+                        keep_going = false;
+                    }
+                    _ => (),
+                }
+
+                if !keep_going {
+                    break;
+                }
+            }
+
+            glib::Continue(keep_going)
+        }));
+    }
+
+    // Returns the number of proposed boundaries
+    fn silences_detected(&mut self) -> usize {
+        // The listener is removed when returning
+        self.listener_src = None;
+        if let Some(silence_ctx) = self.silence_ctx.take() {
+            self.candidates = silence_ctx.get_boundary_candidates();
+            // Boundaries which already exist don't need to be proposed
+            let boundaries = self.boundaries.borrow();
+            self.candidates
+                .retain(|candidate| !boundaries.contains_key(candidate));
+        }
+
+        self.detect_silences_btn
+            .set_sensitive(self.audio_stream_id.is_some());
+        self.accept_candidates_btn
+            .set_sensitive(!self.candidates.is_empty());
+        self.drawingarea.queue_draw();
+
+        self.candidates.len()
+    }
+
+    fn cancel_detection(&mut self) {
+        if let Some(source_id) = self.listener_src.take() {
+            glib::source_remove(source_id);
+        }
+        if let Some(mut silence_ctx) = self.silence_ctx.take() {
+            silence_ctx.cancel();
+        }
+        self.detect_silences_btn
+            .set_sensitive(self.audio_stream_id.is_some());
+    }
+}
@@ -0,0 +1,492 @@
+use cairo;
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::mem;
+
+use media::sample_extractor::SampleExtractionState;
+use media::{AudioBuffer, AudioChannel, SampleExtractor, Stft};
+
+use super::{ImageLayout, BACKGROUND_COLOR};
+
+const MIN_WINDOW_SIZE: usize = 128;
+const MAX_WINDOW_SIZE: usize = 16_384;
+const MAX_OVERLAP: usize = 90; // %
+
+// Levels below this are displayed with the first color of the colormap
+const MIN_DISPLAYED_LEVEL: f64 = -100f64; // dBFS
+// Lowest frequency displayed with the logarithmic scale
+const MIN_LOG_FREQUENCY: f64 = 20f64; // Hz
+
+// Colors from the lowest to the highest level
+const COLORMAP_STEPS: [(f64, f64, f64); 5] = [
+    (0f64, 0f64, 0.016f64),
+    (0.318f64, 0.071f64, 0.486f64),
+    (0.718f64, 0.216f64, 0.475f64),
+    (0.988f64, 0.537f64, 0.380f64),
+    (0.988f64, 0.992f64, 0.749f64),
+];
+const COLORMAP_SIZE: usize = 256;
+
+// Cairo's Rgb24 format
+const BYTES_PER_PIXEL: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpectrogramSettings {
+    pub window_size: usize,
+    pub overlap: usize, // % of the window
+    pub is_log_scale: bool,
+}
+
+impl SpectrogramSettings {
+    // The window size is rounded to the next power of 2
+    pub fn new(window_size: usize, overlap: usize, is_log_scale: bool) -> Self {
+        SpectrogramSettings {
+            window_size: window_size
+                .max(MIN_WINDOW_SIZE)
+                .min(MAX_WINDOW_SIZE)
+                .next_power_of_two(),
+            overlap: overlap.min(MAX_OVERLAP),
+            is_log_scale,
+        }
+    }
+
+    // Number of samples between the start of two consecutive windows
+    fn get_hop(&self) -> usize {
+        self.window_size * (100 - self.overlap) / 100
+    }
+}
+
+impl Default for SpectrogramSettings {
+    fn default() -> Self {
+        SpectrogramSettings::new(2048, 75, true)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct ColumnsConditions {
+    settings: SpectrogramSettings,
+    height: usize,
+    rate: u64,
+}
+
+// Colored STFT frames, ready to be copied in the image
+struct Columns {
+    conditions: Option<ColumnsConditions>,
+    // Range of frequency bins displayed in each row, from top to bottom
+    row_bins: Vec<(usize, usize)>,
+    // Indexed by frame number
+    frames: BTreeMap<usize, Vec<u8>>,
+}
+
+impl Columns {
+    fn new() -> Self {
+        Columns {
+            conditions: None,
+            row_bins: Vec::new(),
+            frames: BTreeMap::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.conditions = None;
+        self.row_bins.clear();
+        self.frames.clear();
+    }
+}
+
+// A SpectrogramBuffer is a SampleExtractor which renders the spectrogram
+// of the samples covered by the waveform image.
+// It is hosted by the WaveformBuffer which keeps driving the positions,
+// the cursor and the seeks, so that both representations share the same
+// horizontal layout (see `set_image_layout`).
+// The spectrogram is only computed when it is visible.
+pub struct SpectrogramBuffer {
+    state: SampleExtractionState,
+    layout: Option<ImageLayout>,
+
+    is_visible: bool,
+    settings: SpectrogramSettings,
+    settings_changed: bool,
+
+    stft: Stft,
+    colormap: Vec<[u8; 4]>,
+    columns: Columns,
+
+    image: Option<cairo::ImageSurface>,
+    image_conditions: Option<(ImageLayout, SpectrogramSettings)>,
+}
+
+impl SpectrogramBuffer {
+    pub fn new() -> Self {
+        let settings = SpectrogramSettings::default();
+        SpectrogramBuffer {
+            state: SampleExtractionState::new(),
+            layout: None,
+
+            is_visible: false,
+            stft: Stft::new(settings.window_size),
+            settings,
+            settings_changed: false,
+
+            colormap: build_colormap(),
+            columns: Columns::new(),
+
+            image: None,
+            image_conditions: None,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.is_visible
+    }
+
+    pub fn set_settings(&mut self, is_visible: bool, settings: SpectrogramSettings) {
+        if is_visible != self.is_visible || settings != self.settings {
+            self.is_visible = is_visible;
+            self.settings = settings;
+            self.settings_changed = true;
+        }
+    }
+
+    pub fn get_half_window_size(&self) -> usize {
+        self.settings.window_size / 2
+    }
+
+    // Layout of the waveform image the spectrogram must be aligned on
+    pub fn set_image_layout(&mut self, layout: Option<ImageLayout>) {
+        self.layout = layout;
+    }
+
+    // The spectrogram image, when it is visible
+    pub fn get_image(&self) -> Option<&cairo::ImageSurface> {
+        if self.is_visible {
+            self.image.as_ref()
+        } else {
+            None
+        }
+    }
+
+    fn clear_image(&mut self) {
+        self.image = None;
+        self.image_conditions = None;
+    }
+
+    fn update_columns_conditions(&mut self, height: usize) -> bool {
+        let duration_per_1000_samples = self.state.duration_per_1000_samples;
+        if duration_per_1000_samples <= 0f64 {
+            return false;
+        }
+
+        let conditions = ColumnsConditions {
+            settings: self.settings.clone(),
+            height,
+            rate: (1_000_000_000_000f64 / duration_per_1000_samples).round() as u64,
+        };
+        // Columns might come from the other buffer
+        if self.stft.get_window_size() != self.settings.window_size {
+            self.stft = Stft::new(self.settings.window_size);
+        }
+
+        if self.columns.conditions.as_ref() == Some(&conditions) {
+            return true;
+        }
+
+        self.columns.frames.clear();
+        self.columns.row_bins = get_row_bins(
+            &self.settings,
+            height,
+            conditions.rate,
+            self.stft.get_bin_count(),
+        );
+        self.columns.conditions = Some(conditions);
+
+        true
+    }
+
+    // Returns the colored column for the window centered on `frame * hop`
+    // or `None` if the samples are not available
+    fn get_column(&mut self, audio_buffer: &AudioBuffer, frame: usize) -> Option<&[u8]> {
+        if !self.columns.frames.contains_key(&frame) {
+            let window_size = self.settings.window_size;
+            let first = (frame * self.settings.get_hop()) as i64 - (window_size / 2) as i64;
+
+            let mut samples = Vec::with_capacity(window_size);
+            for index in first..first + window_size as i64 {
+                let is_outside =
+                    index < 0 || (audio_buffer.eos && index >= audio_buffer.upper as i64);
+                let value = if is_outside {
+                    // Outside of the stream
+                    0f64
+                } else {
+                    let values = audio_buffer.get(index as usize)?;
+                    // Samples are stored as `1.0 - value`
+                    1f64 - values.iter().sum::<f64>() / values.len() as f64
+                };
+                samples.push(value);
+            }
+
+            let levels = self.stft.compute(&samples);
+            let mut column = Vec::with_capacity(self.columns.row_bins.len() * BYTES_PER_PIXEL);
+            for &(first_bin, last_bin) in &self.columns.row_bins {
+                let level = levels[first_bin..last_bin + 1]
+                    .iter()
+                    .fold(MIN_DISPLAYED_LEVEL, |max, level| max.max(*level));
+                let color_idx = ((level - MIN_DISPLAYED_LEVEL) / -MIN_DISPLAYED_LEVEL
+                    * (COLORMAP_SIZE - 1) as f64)
+                    .round() as usize;
+                column.extend_from_slice(&self.colormap[color_idx.min(COLORMAP_SIZE - 1)]);
+            }
+
+            self.columns.frames.insert(frame, column);
+        }
+
+        self.columns.frames.get(&frame).map(|column| column.as_slice())
+    }
+
+    // Render the spectrogram for the samples of the waveform image
+    fn render(&mut self, audio_buffer: &AudioBuffer) {
+        if !self.is_visible {
+            self.clear_image();
+            return;
+        }
+
+        let layout = match self.layout {
+            Some(layout) => layout,
+            None => {
+                self.clear_image();
+                return;
+            }
+        };
+        if layout.width <= 0 || layout.height <= 0 || layout.sample_step == 0
+            || layout.x_step == 0
+        {
+            self.clear_image();
+            return;
+        }
+
+        let image_conditions = (layout, self.settings.clone());
+        if self.image.is_some() && self.image_conditions.as_ref() == Some(&image_conditions) {
+            // Nothing new
+            return;
+        }
+
+        let width = layout.width as usize;
+        let height = layout.height as usize;
+        if !self.update_columns_conditions(height) {
+            self.clear_image();
+            return;
+        }
+
+        let stride = width * BYTES_PER_PIXEL;
+        let background = [
+            (BACKGROUND_COLOR.2 * 255f64) as u8,
+            (BACKGROUND_COLOR.1 * 255f64) as u8,
+            (BACKGROUND_COLOR.0 * 255f64) as u8,
+            0u8,
+        ];
+        let mut data = Vec::with_capacity(stride * height);
+        for _ in 0..width * height {
+            data.extend_from_slice(&background);
+        }
+
+        let hop = self.settings.get_hop();
+        for x in 0..width {
+            let sample = layout.lower + x / layout.x_step * layout.sample_step;
+            if sample >= layout.upper {
+                break;
+            }
+
+            if let Some(column) = self.get_column(audio_buffer, (sample + hop / 2) / hop) {
+                let mut offset = x * BYTES_PER_PIXEL;
+                for pixel in column.chunks(BYTES_PER_PIXEL) {
+                    data[offset..offset + BYTES_PER_PIXEL].copy_from_slice(pixel);
+                    offset += stride;
+                }
+            }
+        }
+
+        // Drop the columns which are out of the image
+        let first_frame = layout.lower / hop;
+        let last_frame = layout.upper / hop + 1;
+        self.columns.frames = self.columns.frames.split_off(&first_frame);
+        let _ = self.columns.frames.split_off(&(last_frame + 1));
+
+        match cairo::ImageSurface::create_for_data(
+            data.into_boxed_slice(),
+            |_| {},
+            cairo::Format::Rgb24,
+            layout.width,
+            layout.height,
+            stride as i32,
+        ) {
+            Ok(image) => {
+                self.image = Some(image);
+                self.image_conditions = Some(image_conditions);
+            }
+            Err(err) => {
+                warn!("SpectrogramBuffer: couldn't create image: {:?}", err);
+                self.clear_image();
+            }
+        }
+    }
+}
+
+impl SampleExtractor for SpectrogramBuffer {
+    fn as_mut_any(&mut self) -> &mut Any {
+        self
+    }
+
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn get_extraction_state(&self) -> &SampleExtractionState {
+        &self.state
+    }
+
+    fn get_extraction_state_mut(&mut self) -> &mut SampleExtractionState {
+        &mut self.state
+    }
+
+    fn get_lower(&self) -> usize {
+        // Keep the samples for the first window
+        self.layout.map_or(0, |layout| {
+            layout.lower.saturating_sub(self.get_half_window_size())
+        })
+    }
+
+    fn get_requested_sample_window(&self) -> Option<usize> {
+        // The sample window is driven by the waveform
+        None
+    }
+
+    fn cleanup(&mut self) {
+        self.state.cleanup();
+        self.layout = None;
+        self.columns.clear();
+        self.clear_image();
+    }
+
+    fn set_sample_duration(&mut self, per_sample: u64, per_1000_samples: f64) {
+        self.state.sample_duration = per_sample;
+        self.state.duration_per_1000_samples = per_1000_samples;
+        self.columns.clear();
+        self.clear_image();
+    }
+
+    fn set_channels(&mut self, _channels: &[AudioChannel]) {
+        // Channels are mixed down
+    }
+
+    fn switch_to_paused(&mut self) {}
+
+    fn update_concrete_state(&mut self, other: &mut SampleExtractor) {
+        let other = other
+            .as_mut_any()
+            .downcast_mut::<SpectrogramBuffer>()
+            .unwrap();
+
+        if other.settings_changed {
+            self.is_visible = other.is_visible;
+            self.settings = other.settings.clone();
+            other.settings_changed = false;
+        }
+
+        // Reuse the most recent columns
+        mem::swap(&mut self.columns, &mut other.columns);
+    }
+
+    fn extract_samples(&mut self, audio_buffer: &AudioBuffer) {
+        self.render(audio_buffer);
+    }
+
+    fn refresh(&mut self, audio_buffer: &AudioBuffer) {
+        self.render(audio_buffer);
+    }
+}
+
+// Returns the colors of the colormap in Cairo's Rgb24 pixel format
+fn build_colormap() -> Vec<[u8; 4]> {
+    let last_step = (COLORMAP_STEPS.len() - 1) as f64;
+    (0..COLORMAP_SIZE)
+        .map(|index| {
+            let position = index as f64 / (COLORMAP_SIZE - 1) as f64 * last_step;
+            let step = (position.floor() as usize).min(COLORMAP_STEPS.len() - 2);
+            let ratio = position - step as f64;
+            let (red_0, green_0, blue_0) = COLORMAP_STEPS[step];
+            let (red_1, green_1, blue_1) = COLORMAP_STEPS[step + 1];
+            let interpolate = |value_0: f64, value_1: f64| {
+                ((value_0 + (value_1 - value_0) * ratio) * 255f64) as u8
+            };
+            [
+                interpolate(blue_0, blue_1),
+                interpolate(green_0, green_1),
+                interpolate(red_0, red_1),
+                0u8,
+            ]
+        })
+        .collect()
+}
+
+// Returns the range of frequency bins displayed in each row, from top to bottom
+fn get_row_bins(
+    settings: &SpectrogramSettings,
+    height: usize,
+    rate: u64,
+    bin_count: usize,
+) -> Vec<(usize, usize)> {
+    let nyquist = rate as f64 / 2f64;
+    let bin_width = rate as f64 / settings.window_size as f64;
+    let min_frequency = MIN_LOG_FREQUENCY.min(nyquist);
+    let last_bin = bin_count - 1;
+
+    let get_bin = |ratio: f64| {
+        let frequency = if settings.is_log_scale {
+            min_frequency * (nyquist / min_frequency).powf(ratio)
+        } else {
+            ratio * nyquist
+        };
+        ((frequency / bin_width).round() as usize).min(last_bin)
+    };
+
+    (0..height)
+        .map(|row| {
+            let first_bin = get_bin(1f64 - (row + 1) as f64 / height as f64);
+            let last_bin = get_bin(1f64 - row as f64 / height as f64).max(first_bin);
+            (first_bin, last_bin)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_row_bins, SpectrogramSettings};
+
+    #[test]
+    fn row_bins() {
+        // 1024 window => bins are 43 Hz wide at 44.1 kHz
+        let settings = SpectrogramSettings::new(1000, 50, false);
+        assert_eq!(settings.window_size, 1024);
+        assert_eq!(settings.get_hop(), 512);
+
+        let row_bins = get_row_bins(&settings, 4, 44_100, 513);
+        assert_eq!(
+            row_bins,
+            vec![(384, 512), (256, 384), (128, 256), (0, 128)]
+        );
+
+        let settings = SpectrogramSettings::new(1024, 50, true);
+        let row_bins = get_row_bins(&settings, 100, 44_100, 513);
+        assert_eq!(row_bins.len(), 100);
+        assert_eq!(row_bins[0].1, 512);
+        assert_eq!(row_bins[99].0, 0);
+        // Low frequencies use more rows with the logarithmic scale
+        let bins_in_bottom_half = row_bins[50].1;
+        assert!(bins_in_bottom_half < 513 / 10);
+        for rows in row_bins.windows(2) {
+            assert!(rows[1].1 <= rows[0].1);
+            assert!(rows[1].0 <= rows[0].0);
+        }
+    }
+}
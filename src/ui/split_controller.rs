@@ -6,20 +6,35 @@ use gtk;
 use gtk::prelude::*;
 
 use std::cell::RefCell;
+use std::cmp;
+use std::collections::{HashSet, VecDeque};
+use std::fs::{create_dir_all, File};
+use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
 use std::rc::Rc;
-use std::sync::mpsc::{channel, Receiver};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+use application::CONFIG;
 
 use media::ContextMessage::*;
-use media::{ContextMessage, PlaybackContext, SplitterContext};
+use media::loudness_meter::REPLAYGAIN_REFERENCE_LEVEL;
+use media::{ContextMessage, Loudness, LoudnessContext, PlaybackContext, SplitterContext};
 
 use metadata;
-use metadata::{get_default_chapter_title, Format, MediaInfo, Stream, TocVisitor};
+use metadata::chapter_gaps::PREGAP_KEY;
+use metadata::{deduplicate_names, get_chapter_gap, get_default_chapter_title,
+               get_split_boundaries, get_tag_value, merge_chapter_tags, Format, MediaInfo,
+               NamingTemplate, Playlist, PlaylistFormat, PlaylistTrack, PregapMode, SplitOptions,
+               Stream, TemplateValue, Timestamp, TocVisit, TocVisitor};
 
 use super::{MainController, OutputBaseController};
 
 const LISTENER_PERIOD: u32 = 250; // 250 ms (4 Hz)
+const MAX_ATTEMPTS: usize = 2;
+// Passthrough boundaries drifting further than this are reported to the user
+const DRIFT_WARNING_THRESHOLD: u64 = 100_000_000; // 100 ms
 
 macro_rules! add_tag_from(
     ($tags:expr, $original_tags:expr, $TagType:ty) => {
@@ -29,28 +44,160 @@ macro_rules! add_tag_from(
     };
 );
 
+// A chapter to split
+struct SplitJob {
+    idx: usize,
+    chapter: gst::TocEntry,
+    // Start of the chapter in the table of contents
+    original_start: u64,
+    options: SplitOptions,
+    output_path: PathBuf,
+    attempts: usize,
+    // The output of a previous split can be kept
+    is_complete: bool,
+}
+
+impl SplitJob {
+    fn get_start_duration(&self) -> (u64, u64) {
+        let (start, end) = self.chapter.get_start_stop_times().unwrap();
+        (start as u64, (end - start) as u64)
+    }
+}
+
+// Values of the file name template placeholders for a chapter
+struct ChapterNaming<'a> {
+    media_tags: &'a gst::TagList,
+    audio_tags: Option<&'a gst::TagList>,
+    chapter: &'a gst::TocEntry,
+    chapter_path: &'a [String],
+    track_nb: Option<usize>,
+    track_count: usize,
+}
+
+impl<'a> ChapterNaming<'a> {
+    fn resolve(&self, name: &str) -> Option<TemplateValue> {
+        match name {
+            "track" => self.track_nb
+                .map(|track_nb| TemplateValue::Number(track_nb as u64)),
+            "tracks" => Some(TemplateValue::Number(self.track_count as u64)),
+            "title" => Some(TemplateValue::Text(get_chapter_title(self.chapter))),
+            "chapter_path" => Some(TemplateValue::Path(self.chapter_path.to_vec())),
+            "artist" => self.get_tag_value("artist")
+                .or_else(|| self.get_tag_value("album-artist")),
+            "album" => self.get_tag_value("album")
+                .or_else(|| get_tag_value(self.media_tags, "title")),
+            "year" => self.get_tag_value("datetime"),
+            "language" => self.audio_tags.and_then(|tags| {
+                get_tag_value(tags, "language-name").or_else(|| get_tag_value(tags, "language-code"))
+            }),
+            _ => self.get_tag_value(name),
+        }
+    }
+
+    // Chapter tags take precedence over media tags
+    fn get_tag_value(&self, name: &str) -> Option<TemplateValue> {
+        self.chapter
+            .get_tags()
+            .and_then(|tags| get_tag_value(&tags, name))
+            .or_else(|| get_tag_value(self.media_tags, name))
+    }
+}
+
+// Media data used to preview the file name template
+struct NamingPreview {
+    media_tags: gst::TagList,
+    chapter: gst::TocEntry,
+    chapter_path: Vec<String>,
+    track_nb: Option<usize>,
+    track_count: usize,
+    is_video_selected: bool,
+}
+
+// Loudness measurement of the chapters prior to the split
+struct LoudnessAnalysis {
+    loudness_ctx: LoudnessContext,
+    ui_rx: Receiver<ContextMessage>,
+    is_trimming: bool,
+}
+
+// A chapter being split
+struct SplitWorker {
+    job: SplitJob,
+    start: u64,
+    duration: u64,
+    splitter_ctx: SplitterContext,
+    ui_rx: Receiver<ContextMessage>,
+}
+
 pub struct SplitController {
     base: OutputBaseController,
 
     selected_audio: Option<Stream>,
-    toc_visitor: Option<TocVisitor>,
-    idx: usize,
+    streams: HashSet<String>,
+    passthrough_format: Option<Format>,
+    format: Format,
+    is_passthrough: bool,
+    max_drift: u64,
+    drifting_chapters: Vec<usize>,
+    pending_jobs: VecDeque<SplitJob>,
+    loudness_analysis: Option<LoudnessAnalysis>,
+    must_measure_loudness: bool,
+    workers: Vec<SplitWorker>,
+    total_duration: u64,
+    done_duration: u64,
+    is_resuming: bool,
+    // Outputs of a previous split being checked in a separate thread
+    resume_check_rx: Option<Receiver<Vec<(usize, bool)>>>,
+    skipped_count: usize,
+    redone_chapters: Vec<usize>,
+    failed_chapters: Vec<usize>,
+    last_error: String,
+    naming_preview: Option<NamingPreview>,
+    playlist_tracks: Vec<(usize, PlaylistTrack)>,
+    // Range to export instead of the chapters
+    range: Option<(u64, u64)>,
 
     split_list: gtk::ListBox,
     split_to_flac_row: gtk::ListBoxRow,
     flac_warning_lbl: gtk::Label,
     split_to_wave_row: gtk::ListBoxRow,
     wave_warning_lbl: gtk::Label,
+    split_to_wavpack_row: gtk::ListBoxRow,
+    wavpack_warning_lbl: gtk::Label,
+    split_to_aiff_row: gtk::ListBoxRow,
+    aiff_warning_lbl: gtk::Label,
     split_to_opus_row: gtk::ListBoxRow,
     opus_warning_lbl: gtk::Label,
     split_to_vorbis_row: gtk::ListBoxRow,
     vorbis_warning_lbl: gtk::Label,
     split_to_mp3_row: gtk::ListBoxRow,
     mp3_warning_lbl: gtk::Label,
+    split_to_aac_row: gtk::ListBoxRow,
+    aac_warning_lbl: gtk::Label,
+    split_to_alac_row: gtk::ListBoxRow,
+    alac_warning_lbl: gtk::Label,
+    split_to_matroska_row: gtk::ListBoxRow,
+    matroska_warning_lbl: gtk::Label,
+    split_to_matroska_keyframes_row: gtk::ListBoxRow,
+    matroska_keyframes_warning_lbl: gtk::Label,
+    split_to_passthrough_row: gtk::ListBoxRow,
+    passthrough_warning_lbl: gtk::Label,
+    split_concurrency_spin: gtk::SpinButton,
+    split_resume_chk: gtk::CheckButton,
+    split_template_entry: gtk::Entry,
+    split_template_preview_lbl: gtk::Label,
+    split_m3u8_chk: gtk::CheckButton,
+    split_pls_chk: gtk::CheckButton,
+    split_xspf_chk: gtk::CheckButton,
+    split_loudness_chk: gtk::CheckButton,
+    split_fade_in_spin: gtk::SpinButton,
+    split_fade_out_spin: gtk::SpinButton,
+    split_trim_silence_chk: gtk::CheckButton,
+    split_silence_threshold_spin: gtk::SpinButton,
+    split_pregap_combo: gtk::ComboBoxText,
     split_progress_bar: gtk::ProgressBar,
     split_btn: gtk::Button,
 
-    splitter_ctx: Option<SplitterContext>,
     this_opt: Option<Rc<RefCell<SplitController>>>,
 }
 
@@ -60,24 +207,77 @@ impl SplitController {
             base: OutputBaseController::new(builder),
 
             selected_audio: None,
-            toc_visitor: None,
-            idx: 0,
+            streams: HashSet::new(),
+            passthrough_format: None,
+            format: Format::Flac,
+            is_passthrough: false,
+            max_drift: 0,
+            drifting_chapters: Vec::new(),
+            pending_jobs: VecDeque::new(),
+            loudness_analysis: None,
+            must_measure_loudness: false,
+            workers: Vec::new(),
+            total_duration: 0,
+            done_duration: 0,
+            is_resuming: false,
+            resume_check_rx: None,
+            skipped_count: 0,
+            redone_chapters: Vec::new(),
+            failed_chapters: Vec::new(),
+            last_error: String::new(),
+            naming_preview: None,
+            playlist_tracks: Vec::new(),
+            range: None,
 
             split_list: builder.get_object("split-list-box").unwrap(),
             split_to_flac_row: builder.get_object("flac_split-row").unwrap(),
             flac_warning_lbl: builder.get_object("flac_warning-lbl").unwrap(),
             split_to_wave_row: builder.get_object("wave_split-row").unwrap(),
             wave_warning_lbl: builder.get_object("wave_warning-lbl").unwrap(),
+            split_to_wavpack_row: builder.get_object("wavpack_split-row").unwrap(),
+            wavpack_warning_lbl: builder.get_object("wavpack_warning-lbl").unwrap(),
+            split_to_aiff_row: builder.get_object("aiff_split-row").unwrap(),
+            aiff_warning_lbl: builder.get_object("aiff_warning-lbl").unwrap(),
             split_to_opus_row: builder.get_object("opus_split-row").unwrap(),
             opus_warning_lbl: builder.get_object("opus_warning-lbl").unwrap(),
             split_to_vorbis_row: builder.get_object("vorbis_split-row").unwrap(),
             vorbis_warning_lbl: builder.get_object("vorbis_warning-lbl").unwrap(),
             split_to_mp3_row: builder.get_object("mp3_split-row").unwrap(),
             mp3_warning_lbl: builder.get_object("mp3_warning-lbl").unwrap(),
+            split_to_aac_row: builder.get_object("aac_split-row").unwrap(),
+            aac_warning_lbl: builder.get_object("aac_warning-lbl").unwrap(),
+            split_to_alac_row: builder.get_object("alac_split-row").unwrap(),
+            alac_warning_lbl: builder.get_object("alac_warning-lbl").unwrap(),
+            split_to_matroska_row: builder.get_object("matroska_split-row").unwrap(),
+            matroska_warning_lbl: builder.get_object("matroska_warning-lbl").unwrap(),
+            split_to_matroska_keyframes_row: builder
+                .get_object("matroska_keyframes_split-row")
+                .unwrap(),
+            matroska_keyframes_warning_lbl: builder
+                .get_object("matroska_keyframes_warning-lbl")
+                .unwrap(),
+            split_to_passthrough_row: builder.get_object("passthrough_split-row").unwrap(),
+            passthrough_warning_lbl: builder.get_object("passthrough_warning-lbl").unwrap(),
+            split_concurrency_spin: builder.get_object("split_concurrency-spin").unwrap(),
+            split_resume_chk: builder.get_object("split_resume-check").unwrap(),
+            split_template_entry: builder.get_object("split_template-entry").unwrap(),
+            split_template_preview_lbl: builder
+                .get_object("split_template_preview-lbl")
+                .unwrap(),
+            split_m3u8_chk: builder.get_object("split_m3u8-check").unwrap(),
+            split_pls_chk: builder.get_object("split_pls-check").unwrap(),
+            split_xspf_chk: builder.get_object("split_xspf-check").unwrap(),
+            split_loudness_chk: builder.get_object("split_loudness-check").unwrap(),
+            split_fade_in_spin: builder.get_object("split_fade_in-spin").unwrap(),
+            split_fade_out_spin: builder.get_object("split_fade_out-spin").unwrap(),
+            split_trim_silence_chk: builder.get_object("split_trim_silence-check").unwrap(),
+            split_silence_threshold_spin: builder
+                .get_object("split_silence_threshold-spin")
+                .unwrap(),
+            split_pregap_combo: builder.get_object("split_pregap-combo").unwrap(),
             split_progress_bar: builder.get_object("split-progress").unwrap(),
             split_btn: builder.get_object("split-btn").unwrap(),
 
-            splitter_ctx: None,
             this_opt: None,
         }));
 
@@ -102,26 +302,130 @@ impl SplitController {
 
         this.check_requirements();
 
+        this.split_concurrency_spin
+            .set_value(f64::from(CONFIG.read().unwrap().split.concurrency));
+        this.split_concurrency_spin.connect_value_changed(|spin_btn| {
+            CONFIG.write().unwrap().split.concurrency = spin_btn.get_value_as_int() as u32;
+        });
+
+        {
+            let config = CONFIG.read().unwrap();
+            this.split_m3u8_chk.set_active(config.split.is_m3u8_enabled);
+            this.split_pls_chk.set_active(config.split.is_pls_enabled);
+            this.split_xspf_chk.set_active(config.split.is_xspf_enabled);
+            this.split_loudness_chk
+                .set_active(config.split.is_loudness_enabled);
+            this.split_fade_in_spin
+                .set_value(f64::from(config.split.fade_in_ms));
+            this.split_fade_out_spin
+                .set_value(f64::from(config.split.fade_out_ms));
+            this.split_trim_silence_chk
+                .set_active(config.split.is_silence_trimmed);
+            this.split_silence_threshold_spin
+                .set_value(f64::from(config.split.silence_threshold));
+            this.split_silence_threshold_spin
+                .set_sensitive(config.split.is_silence_trimmed);
+            this.split_pregap_combo
+                .set_active_id(get_pregap_mode_id(config.split.pregap_mode));
+        }
+        this.split_m3u8_chk.connect_toggled(|check_btn| {
+            CONFIG.write().unwrap().split.is_m3u8_enabled = check_btn.get_active();
+        });
+        this.split_pls_chk.connect_toggled(|check_btn| {
+            CONFIG.write().unwrap().split.is_pls_enabled = check_btn.get_active();
+        });
+        this.split_xspf_chk.connect_toggled(|check_btn| {
+            CONFIG.write().unwrap().split.is_xspf_enabled = check_btn.get_active();
+        });
+        this.split_loudness_chk.connect_toggled(|check_btn| {
+            CONFIG.write().unwrap().split.is_loudness_enabled = check_btn.get_active();
+        });
+        this.split_fade_in_spin.connect_value_changed(|spin_btn| {
+            CONFIG.write().unwrap().split.fade_in_ms = spin_btn.get_value_as_int() as u32;
+        });
+        this.split_fade_out_spin.connect_value_changed(|spin_btn| {
+            CONFIG.write().unwrap().split.fade_out_ms = spin_btn.get_value_as_int() as u32;
+        });
+        let threshold_spin = this.split_silence_threshold_spin.clone();
+        this.split_trim_silence_chk.connect_toggled(move |check_btn| {
+            let is_silence_trimmed = check_btn.get_active();
+            CONFIG.write().unwrap().split.is_silence_trimmed = is_silence_trimmed;
+            threshold_spin.set_sensitive(is_silence_trimmed);
+        });
+        this.split_silence_threshold_spin
+            .connect_value_changed(|spin_btn| {
+                CONFIG.write().unwrap().split.silence_threshold = spin_btn.get_value_as_int();
+            });
+        this.split_pregap_combo.connect_changed(|combo| {
+            if let Some(pregap_mode) = combo.get_active_id().and_then(|id| get_pregap_mode(&id)) {
+                CONFIG.write().unwrap().split.pregap_mode = pregap_mode;
+            }
+        });
+
+        this.split_template_entry
+            .set_text(&CONFIG.read().unwrap().split.naming_template);
+        this.update_naming_preview();
+
+        let this_clone = Rc::clone(this_rc);
+        this.split_template_entry.connect_changed(move |_| {
+            this_clone.borrow().template_changed();
+        });
+
+        let this_clone = Rc::clone(this_rc);
+        this.split_list.connect_row_selected(move |_, row| {
+            // The extension depends on the selected format
+            // Note: the row can also be selected programmatically
+            // while the controller is borrowed
+            if let Ok(this) = this_clone.try_borrow() {
+                this.update_naming_preview();
+                if row.is_some() {
+                    this.update_options_sensitivity();
+                }
+            }
+        });
+
         let this_clone = Rc::clone(this_rc);
         let main_ctrl_clone = Rc::clone(main_ctrl);
         this.split_btn.connect_clicked(move |_| {
-            let this_clone = Rc::clone(&this_clone);
+            if this_clone.borrow().listener_src.is_some() {
+                this_clone.borrow_mut().cancel();
+                return;
+            }
+
+            this_clone.borrow_mut().range = None;
             main_ctrl_clone
                 .borrow_mut()
-                .request_context(Box::new(move |context| {
-                    {
-                        this_clone.borrow_mut().playback_ctx = Some(context);
-                    }
-                    // launch export asynchronoulsy so that main_ctrl is no longer borrowed
-                    let this_clone = Rc::clone(&this_clone);
-                    gtk::idle_add(move || {
-                        this_clone.borrow_mut().split();
-                        glib::Continue(false)
-                    });
-                }));
+                .request_context(SplitController::get_split_cb(&this_clone));
         });
     }
 
+    // Returns the callback which takes the context and launches the split
+    pub fn get_split_cb(this_rc: &Rc<RefCell<Self>>) -> Box<FnMut(PlaybackContext)> {
+        let this_rc = Rc::clone(this_rc);
+        Box::new(move |context| {
+            {
+                this_rc.borrow_mut().playback_ctx = Some(context);
+            }
+            // launch export asynchronoulsy so that main_ctrl is no longer borrowed
+            let this_rc = Rc::clone(&this_rc);
+            gtk::idle_add(move || {
+                this_rc.borrow_mut().split();
+                glib::Continue(false)
+            });
+        })
+    }
+
+    // Restricts the next split to the range [start, end]
+    // Returns false if a split can't be started
+    pub fn set_range(&mut self, start: u64, end: u64) -> bool {
+        if self.listener_src.is_some() || !self.split_btn.get_sensitive() || start >= end {
+            return false;
+        }
+
+        self.range = Some((start, end));
+        true
+    }
+
     pub fn new_media(&mut self, context: &PlaybackContext) {
         let info = context.info.read().unwrap();
         self.streams_changed(&info);
@@ -130,12 +434,113 @@ impl SplitController {
     #[cfg_attr(feature = "cargo-clippy", allow(map_clone))]
     pub fn streams_changed(&mut self, info: &MediaInfo) {
         self.selected_audio = info.streams.selected_audio().map(|stream| stream.clone());
-        self.split_btn.set_sensitive(self.selected_audio.is_some());
+        self.naming_preview = Some(get_naming_preview(info));
+        self.split_btn
+            .set_sensitive(self.selected_audio.is_some() || info.streams.is_video_selected());
+
+        let passthrough_format = match self.selected_audio {
+            Some(ref stream) => match SplitterContext::get_passthrough_format(&stream.caps) {
+                Some(format) => {
+                    SplitterContext::check_passthrough_requirements(format).map(|_| format)
+                }
+                None => Err(gettext("Can't copy codec `{}`").replacen(
+                    "{}",
+                    &stream.codec_printable,
+                    1,
+                )),
+            },
+            None => Err(String::new()),
+        };
+
+        // Video can only be split to Matroska when it can be re-encoded
+        if !info.streams.video.is_empty() && self.split_to_matroska_row.get_sensitive() {
+            let _ = SplitterContext::check_video_requirements().map_err(|err| {
+                warn!("{}", err);
+                self.matroska_warning_lbl.set_label(&err);
+            });
+        } else if self.split_to_matroska_row.get_sensitive() {
+            self.matroska_warning_lbl.set_label("");
+        }
+
+        match passthrough_format {
+            Ok(format) => {
+                self.passthrough_format = Some(format);
+                self.passthrough_warning_lbl.set_label("");
+                self.split_to_passthrough_row.set_sensitive(true);
+            }
+            Err(err) => self.disable_passthrough(&err),
+        }
+
+        self.update_naming_preview();
     }
 
     pub fn cleanup(&mut self) {
         self.split_btn.set_sensitive(false);
         self.split_progress_bar.set_fraction(0f64);
+        self.disable_passthrough("");
+        self.naming_preview = None;
+        self.update_naming_preview();
+    }
+
+    fn template_changed(&self) {
+        let template = self.split_template_entry.get_text().unwrap_or_default();
+        if NamingTemplate::parse(&template).is_ok() {
+            CONFIG.write().unwrap().split.naming_template = template;
+        }
+        self.update_naming_preview();
+    }
+
+    fn update_naming_preview(&self) {
+        let template = self.split_template_entry.get_text().unwrap_or_default();
+        let template = match NamingTemplate::parse(&template) {
+            Ok(template) => template,
+            Err(err) => {
+                self.split_template_preview_lbl.set_label(&err);
+                return;
+            }
+        };
+
+        match self.naming_preview {
+            Some(ref preview) => {
+                let (format, _) = self.get_selection();
+                let extension = if format == Format::Matroska {
+                    if preview.is_video_selected {
+                        "mkv"
+                    } else {
+                        "mka"
+                    }
+                } else {
+                    metadata::Factory::get_extension(&format, true)
+                };
+
+                let audio_tags = self.selected_audio
+                    .as_ref()
+                    .and_then(|stream| stream.tags.as_ref());
+                let naming = ChapterNaming {
+                    media_tags: &preview.media_tags,
+                    audio_tags,
+                    chapter: &preview.chapter,
+                    chapter_path: &preview.chapter_path,
+                    track_nb: preview.track_nb,
+                    track_count: preview.track_count,
+                };
+                self.split_template_preview_lbl.set_label(&format!(
+                    "{}.{}",
+                    get_split_name(&template, &naming),
+                    extension
+                ));
+            }
+            None => self.split_template_preview_lbl.set_label(""),
+        }
+    }
+
+    fn disable_passthrough(&mut self, reason: &str) {
+        self.passthrough_format = None;
+        self.passthrough_warning_lbl.set_label(reason);
+        if self.split_to_passthrough_row.is_selected() {
+            self.split_list.select_row(&self.split_to_flac_row);
+        }
+        self.split_to_passthrough_row.set_sensitive(false);
     }
 
     fn check_requirements(&self) {
@@ -149,6 +554,25 @@ impl SplitController {
             self.wave_warning_lbl.set_label(&err);
             self.split_to_wave_row.set_sensitive(false);
         });
+        let _ = SplitterContext::check_requirements(Format::WavPack).map_err(|err| {
+            warn!("{}", err);
+            self.wavpack_warning_lbl.set_label(&err);
+            self.split_to_wavpack_row.set_sensitive(false);
+        });
+        match SplitterContext::check_requirements(Format::AIFF) {
+            Ok(()) => {
+                if !SplitterContext::are_tags_supported(Format::AIFF) {
+                    let msg = gettext("Tags can't be written to AIFF files");
+                    warn!("{}", msg);
+                    self.aiff_warning_lbl.set_label(&msg);
+                }
+            }
+            Err(err) => {
+                warn!("{}", err);
+                self.aiff_warning_lbl.set_label(&err);
+                self.split_to_aiff_row.set_sensitive(false);
+            }
+        }
         let _ = SplitterContext::check_requirements(Format::Opus).map_err(|err| {
             warn!("{}", err);
             self.opus_warning_lbl.set_label(&err);
@@ -164,152 +588,692 @@ impl SplitController {
             self.mp3_warning_lbl.set_label(&err);
             self.split_to_mp3_row.set_sensitive(false);
         });
+        let _ = SplitterContext::check_requirements(Format::AAC).map_err(|err| {
+            warn!("{}", err);
+            self.aac_warning_lbl.set_label(&err);
+            self.split_to_aac_row.set_sensitive(false);
+        });
+        let _ = SplitterContext::check_requirements(Format::ALAC).map_err(|err| {
+            warn!("{}", err);
+            self.alac_warning_lbl.set_label(&err);
+            self.split_to_alac_row.set_sensitive(false);
+        });
+        let _ = SplitterContext::check_requirements(Format::Matroska).map_err(|err| {
+            warn!("{}", err);
+            self.matroska_warning_lbl.set_label(&err);
+            self.split_to_matroska_row.set_sensitive(false);
+        });
+        let _ = SplitterContext::check_passthrough_requirements(Format::Matroska).map_err(|err| {
+            warn!("{}", err);
+            self.matroska_keyframes_warning_lbl.set_label(&err);
+            self.split_to_matroska_keyframes_row.set_sensitive(false);
+        });
     }
 
     fn split(&mut self) {
-        // Split button is not sensible when no audio
-        // stream is selected (see `streams_changed`)
-        debug_assert!(self.selected_audio.is_some());
+        // The range only applies to the split it was requested for
+        let range = self.range.take();
 
-        let format = self.get_selection();
-        self.prepare_process(&format, true);
+        let template = match NamingTemplate::parse(&CONFIG.read().unwrap().split.naming_template) {
+            Ok(template) => template,
+            Err(err) => {
+                self.restore_context();
+                self.show_error(&gettext("Invalid file name template. {}").replacen("{}", &err, 1));
+                return;
+            }
+        };
 
-        self.toc_visitor = self.base
-            .playback_ctx
-            .as_ref()
-            .unwrap()
-            .info
-            .read()
-            .unwrap()
-            .toc
-            .as_ref()
-            .map(|toc| TocVisitor::new(toc));
-        self.idx = 0;
+        let (format, is_passthrough) = self.get_selection();
+        let (streams, is_audio_only) = self.get_streams(format);
+        if streams.is_empty() {
+            let msg = if format == Format::Matroska {
+                gettext("No streams selected for export")
+            } else {
+                gettext("No audio stream selected")
+            };
+            self.restore_context();
+            self.show_error(&msg);
+            return;
+        }
 
-        if let Err(err) = self.build_context(format) {
+        if format == Format::Matroska && !is_passthrough && self.has_video_stream(&streams) {
+            if let Err(err) = SplitterContext::check_video_requirements() {
+                self.restore_context();
+                self.show_error(&err);
+                return;
+            }
+        }
+
+        self.streams = streams;
+        self.format = format;
+        self.is_passthrough = is_passthrough;
+        self.max_drift = 0;
+        self.drifting_chapters.clear();
+        self.prepare_process(&format, is_audio_only);
+        if format == Format::Matroska {
+            // `Factory` provides the extension for a media with a full TOC
+            self.extension = if is_audio_only { "mka" } else { "mkv" }.to_owned();
+        }
+
+        // Jobs are defined beforehand so that file names and track numbers
+        // don't depend on the order in which the workers complete
+        self.range = range;
+        self.pending_jobs = self.get_jobs(&template);
+        if !is_reencoding_audio(format, is_passthrough) {
+            // Chapters can still define fades and silence trimming in their tags
+            let chapters = self.pending_jobs
+                .iter()
+                .filter(|job| job.options != SplitOptions::default())
+                .map(|job| job.idx)
+                .collect::<Vec<usize>>();
+            if !chapters.is_empty() {
+                self.pending_jobs.clear();
+                self.range = None;
+                self.restore_context();
+                self.show_error(
+                    &gettext("Fades and silence trimming require an audio format. Chapters: {}")
+                        .replacen("{}", &join_chapters(&chapters), 1),
+                );
+                return;
+            }
+        }
+        self.update_jobs_duration();
+        self.done_duration = 0;
+        self.is_resuming = self.split_resume_chk.get_active();
+        self.skipped_count = 0;
+        self.redone_chapters.clear();
+        self.failed_chapters.clear();
+        self.last_error.clear();
+
+        self.switch_to_busy();
+        // The listener completes the process when no workers could be spawned
+        self.register_listener(LISTENER_PERIOD);
+
+        if let Err(err) = self.start_processing() {
+            self.stop();
             self.show_error(&err);
         }
     }
 
-    fn build_context(&mut self, format: metadata::Format) -> Result<bool, String> {
-        let mut chapter = match self.next_chapter() {
-            Some(chapter) => chapter,
-            None => {
-                if self.toc_visitor.is_none() && self.idx < 2 {
-                    // No chapter => build a fake chapter corresponding to the whole file
-                    let mut toc_entry =
-                        gst::TocEntry::new(gst::TocEntryType::Chapter, &"".to_owned());
-                    toc_entry
-                        .get_mut()
-                        .unwrap()
-                        .set_start_stop_times(0, self.duration as i64);
-
-                    let mut tag_list = gst::TagList::new();
-                    tag_list.get_mut().unwrap().add::<gst::tags::Title>(
-                        &self.media_path.file_stem().unwrap().to_str().unwrap(),
-                        gst::TagMergeMode::Replace,
-                    );
-                    toc_entry.get_mut().unwrap().set_tags(tag_list);
+    // Measures the loudness if required and starts the workers
+    fn start_processing(&mut self) -> Result<(), String> {
+        // Gains and silences must be known before the tracks are written
+        // Note: when silences are trimmed, the gains are measured
+        // on the trimmed chapters (see `register_listener`)
+        self.must_measure_loudness = CONFIG.read().unwrap().split.is_loudness_enabled;
+        let must_trim_silence = self.pending_jobs.iter().any(|job| job.options.trim_silence);
+        if (self.must_measure_loudness || must_trim_silence) && self.selected_audio.is_some() {
+            self.start_loudness_analysis(must_trim_silence)
+        } else {
+            self.start_splitting();
+            Ok(())
+        }
+    }
 
-                    toc_entry
-                } else {
-                    return Ok(false);
+    // Note: outputs are checked once the chapters boundaries are final
+    fn start_splitting(&mut self) {
+        if self.is_resuming {
+            // The listener spawns the workers when the check is complete
+            self.start_resume_check();
+        } else {
+            self.spawn_workers();
+        }
+    }
+
+    // Checks the outputs of a previous split without blocking the UI
+    fn start_resume_check(&mut self) {
+        let outputs = self.pending_jobs
+            .iter()
+            .filter(|job| job.output_path.exists())
+            .map(|job| (job.idx, job.output_path.clone(), job.get_start_duration().1))
+            .collect::<Vec<(usize, PathBuf, u64)>>();
+
+        let (check_tx, check_rx) = channel();
+        thread::spawn(move || {
+            let results = outputs
+                .into_iter()
+                .map(|(idx, output_path, duration)| {
+                    (idx, SplitterContext::is_output_complete(&output_path, duration))
+                })
+                .collect::<Vec<(usize, bool)>>();
+            // The receiver is dropped if the split is cancelled
+            let _ = check_tx.send(results);
+        });
+        self.resume_check_rx = Some(check_rx);
+    }
+
+    fn apply_resume_check(&mut self, results: &[(usize, bool)]) {
+        for &(idx, is_complete) in results {
+            if is_complete {
+                if let Some(job) = self.pending_jobs.iter_mut().find(|job| job.idx == idx) {
+                    job.is_complete = true;
                 }
+            } else {
+                warn!(
+                    "{}",
+                    gettext("Chapter {}: the existing file is incomplete")
+                        .replacen("{}", &format!("{}", idx), 1)
+                );
+                self.redone_chapters.push(idx);
             }
-        };
-        // Unfortunately, we need to make a copy here
-        // because the chapter is also owned by the self.toc
-        // and the TocVisitor so the chapters entries ref_count is > 1
-        let chapter = self.update_tags(&mut chapter);
-        let output_path = self.get_split_path(&chapter);
+        }
+    }
+
+    // Computes the split durations and the playlist from the jobs boundaries
+    fn update_jobs_duration(&mut self) {
+        self.playlist_tracks = self.pending_jobs
+            .iter()
+            .map(|job| (job.idx, get_playlist_track(job)))
+            .collect();
+        self.total_duration = self.pending_jobs
+            .iter()
+            .map(|job| job.get_start_duration().1)
+            .sum();
+    }
+
+    fn start_loudness_analysis(&mut self, is_trimming: bool) -> Result<(), String> {
+        let stream_id = self.selected_audio.as_ref().unwrap().id.to_string();
+        let boundaries = self.pending_jobs
+            .iter()
+            .map(|job| {
+                let (start, duration) = job.get_start_duration();
+                (start, start + duration)
+            })
+            .collect();
+        let fades = self.pending_jobs
+            .iter()
+            .map(|job| (job.options.fade_in, job.options.fade_out))
+            .collect::<Vec<(u64, u64)>>();
+
+        let silence_threshold = f64::from(CONFIG.read().unwrap().split.silence_threshold);
+
         let (ctx_tx, ui_rx) = channel();
-        self.register_listener(format, LISTENER_PERIOD, ui_rx);
-        match SplitterContext::new(
+        let loudness_ctx = LoudnessContext::new(
             &self.media_path,
-            &output_path,
-            &self.selected_audio.as_ref().unwrap().id,
-            format,
-            chapter,
+            &stream_id,
+            boundaries,
+            &fades,
+            silence_threshold,
             ctx_tx,
-        ) {
-            Ok(splitter_ctx) => {
-                self.switch_to_busy();
-                self.splitter_ctx = Some(splitter_ctx);
-                Ok(true)
-            }
-            Err(error) => {
-                self.remove_listener();
-                self.switch_to_available();
-                self.restore_context();
-                let msg = gettext("Failed to prepare for split. {}").replacen("{}", &error, 1);
+        ).map_err(|err| {
+                let msg = gettext("Failed to measure the loudness. {}").replacen("{}", &err, 1);
                 error!("{}", msg);
-                Err(msg)
-            }
+                msg
+            })?;
+        self.loudness_analysis = Some(LoudnessAnalysis {
+            loudness_ctx,
+            ui_rx,
+            is_trimming,
+        });
+
+        Ok(())
+    }
+
+    // Adds the gains to the tags of the chapters and displays the loudness
+    fn apply_loudness(&mut self, analysis: &mut LoudnessAnalysis) {
+        let (tracks_loudness, album_loudness) = analysis.loudness_ctx.take_loudness();
+
+        let mut chapters_loudness = Vec::<(u64, String)>::new();
+        for (job, track_loudness) in self.pending_jobs.iter_mut().zip(tracks_loudness) {
+            let mut tags = job.chapter.get_tags().unwrap_or_else(gst::TagList::new);
+            add_gain_tags(
+                tags.make_mut(),
+                self.format,
+                track_loudness.as_ref(),
+                album_loudness.as_ref(),
+            );
+            job.chapter.make_mut().set_tags(tags);
+
+            chapters_loudness.push((
+                job.original_start,
+                track_loudness.map_or_else(
+                    || "-".to_owned(),
+                    |loudness| format!("{:.1} LUFS", loudness.integrated),
+                ),
+            ));
+        }
+
+        if let Some(album_loudness) = album_loudness {
+            info!(
+                "{}",
+                gettext("Album loudness: {} LUFS, peak: {}")
+                    .replacen("{}", &format!("{:.1}", album_loudness.integrated), 1)
+                    .replacen("{}", &format!("{:.6}", album_loudness.peak), 1)
+            );
         }
+        self.show_chapters_loudness(&chapters_loudness);
     }
 
-    fn next_chapter(&mut self) -> Option<gst::TocEntry> {
-        if self.toc_visitor.is_none() {
-            self.idx += 1;
-            return None;
+    // Moves the chapters boundaries to the first and last audible frames
+    // Returns `true` if at least one chapter was trimmed
+    fn trim_silences(&mut self, analysis: &LoudnessAnalysis) -> bool {
+        let mut is_trimmed = false;
+        let audible_bounds = analysis.loudness_ctx.get_audible_bounds();
+        for (job, bounds) in self.pending_jobs.iter_mut().zip(audible_bounds) {
+            if !job.options.trim_silence {
+                continue;
+            }
+
+            let (start, duration) = job.get_start_duration();
+            let (first, last) = match bounds {
+                Some(bounds) => bounds,
+                None => {
+                    warn!(
+                        "{}",
+                        gettext("Chapter {} is silent, keeping it as is")
+                            .replacen("{}", &format!("{}", job.idx), 1)
+                    );
+                    continue;
+                }
+            };
+            let end = start + duration;
+            let first = cmp::max(start, first);
+            let last = cmp::min(end, last);
+            if first == start && last == end {
+                continue;
+            }
+
+            let mut tags = job.chapter.get_tags().unwrap_or_else(gst::TagList::new);
+            tags.make_mut().add::<gst::tags::Duration>(
+                &gst::ClockTime::from_nseconds(last - first),
+                gst::TagMergeMode::Replace,
+            );
+            let chapter = job.chapter.make_mut();
+            chapter.set_start_stop_times(first as i64, last as i64);
+            chapter.set_tags(tags);
+            is_trimmed = true;
         }
 
-        let chapter = self.toc_visitor.as_mut().unwrap().next_chapter();
+        is_trimmed
+    }
 
-        if chapter.is_some() {
-            self.idx += 1;
+    fn spawn_workers(&mut self) {
+        let concurrency = cmp::max(CONFIG.read().unwrap().split.concurrency, 1);
+        for _ in 0..concurrency {
+            if !self.spawn_worker() {
+                break;
+            }
         }
+    }
 
-        chapter
+    fn cancel(&mut self) {
+        self.stop();
+        self.show_info(&gettext("Split cancelled"));
     }
 
-    fn get_split_path(&self, chapter: &gst::TocEntry) -> PathBuf {
-        let mut split_name = String::new();
+    fn get_jobs(&self, template: &NamingTemplate) -> VecDeque<SplitJob> {
+        let (chapters, has_toc) = match self.range {
+            Some((start, end)) => {
+                // Range export => build a fake chapter spanning the range
+                let title = format!(
+                    "{} {} - {}",
+                    self.media_path.file_stem().unwrap().to_str().unwrap(),
+                    Timestamp::format(start, false),
+                    Timestamp::format(end, false),
+                );
+                (
+                    vec![(build_media_chapter(&title, start, end), Vec::new())],
+                    false,
+                )
+            }
+            None => {
+                let info = self.playback_ctx.as_ref().unwrap().info.read().unwrap();
+                match info.toc {
+                    Some(ref toc) => (get_chapters(toc), true),
+                    None => {
+                        // No chapter => build a fake chapter corresponding to the whole file
+                        let title = self.media_path.file_stem().unwrap().to_str().unwrap();
+                        (
+                            vec![(build_media_chapter(title, 0, self.duration), Vec::new())],
+                            false,
+                        )
+                    }
+                }
+            }
+        };
+
+        let (default_options, pregap_mode) = {
+            let config = CONFIG.read().unwrap();
+            // Fades and silence trimming are disabled in the UI
+            // when the audio stream is not re-encoded
+            let default_options = if is_reencoding_audio(self.format, self.is_passthrough) {
+                SplitOptions::new(
+                    config.split.fade_in_ms,
+                    config.split.fade_out_ms,
+                    config.split.is_silence_trimmed,
+                )
+            } else {
+                SplitOptions::default()
+            };
+            (default_options, config.split.pregap_mode)
+        };
 
-        let info = self.playback_ctx.as_ref().unwrap().info.read().unwrap();
+        let chapters_bounds = chapters
+            .iter()
+            .map(|&(ref chapter, _)| {
+                let (start, end) = chapter.get_start_stop_times().unwrap();
+                (start as u64, end as u64, get_chapter_gap(chapter, PREGAP_KEY))
+            })
+            .collect::<Vec<(u64, u64, u64)>>();
+        let split_bounds = get_split_boundaries(pregap_mode, &chapters_bounds);
+
+        let track_count = chapters.len();
+        let (mut jobs, mut split_names): (VecDeque<SplitJob>, Vec<String>) = chapters
+            .into_iter()
+            .zip(split_bounds)
+            .enumerate()
+            .map(|(index, ((mut chapter, chapter_path), (start, end)))| {
+                let idx = index + 1;
+                let options = match chapter.get_tags() {
+                    Some(tags) => default_options.with_overrides(&tags),
+                    None => default_options,
+                };
+                let original_start = chapter.get_start_stop_times().unwrap().0 as u64;
+                if pregap_mode != PregapMode::AppendToPrevious {
+                    chapter
+                        .make_mut()
+                        .set_start_stop_times(start as i64, end as i64);
+                }
+                // Unfortunately, we need to make a copy here
+                // because the chapter is also owned by the self.toc
+                // and the TocVisitor so the chapters entries ref_count is > 1
+                let chapter = self.update_tags(&mut chapter, idx);
+                let track_nb = if has_toc { Some(idx) } else { None };
+                let split_name = self.get_chapter_split_name(
+                    template,
+                    &chapter,
+                    &chapter_path,
+                    track_nb,
+                    track_count,
+                );
+                let job = SplitJob {
+                    idx,
+                    chapter,
+                    original_start,
+                    options,
+                    output_path: PathBuf::new(),
+                    attempts: 0,
+                    is_complete: false,
+                };
+                (job, split_name)
+            })
+            .unzip();
 
-        // TODO: make format customisable
-        if let Some(artist) = info.get_artist() {
-            split_name += &format!("{} - ", artist);
+        // Templates which don't depend on the chapter would result in the same file
+        deduplicate_names(&mut split_names);
+        for (job, split_name) in jobs.iter_mut().zip(split_names) {
+            job.output_path = self.target_path
+                .with_file_name(format!("{}.{}", split_name, self.extension));
         }
-        if let Some(album_title) = info.get_title() {
-            split_name += &format!("{} - ", album_title);
+
+        jobs
+    }
+
+    // Writes the selected playlists with the chapters which were split
+    fn write_playlists(&mut self) -> Result<(), String> {
+        let formats = {
+            let config = CONFIG.read().unwrap();
+            let mut formats = Vec::<PlaylistFormat>::new();
+            if config.split.is_m3u8_enabled {
+                formats.push(PlaylistFormat::M3U8);
+            }
+            if config.split.is_pls_enabled {
+                formats.push(PlaylistFormat::PLS);
+            }
+            if config.split.is_xspf_enabled {
+                formats.push(PlaylistFormat::XSPF);
+            }
+            formats
+        };
+
+        let tracks = mem::replace(&mut self.playlist_tracks, Vec::new());
+        // The playlists of the media don't apply to a range
+        if formats.is_empty() || self.range.is_some() {
+            return Ok(());
         }
 
-        let track_title = chapter
-            .get_tags()
-            .and_then(|tags| {
-                tags.get::<gst::tags::Title>()
-                    .map(|tag| tag.get().unwrap().to_owned())
-            })
-            .unwrap_or_else(get_default_chapter_title);
+        let mut playlist = Playlist::new(self.target_path.parent().unwrap());
+        for (idx, track) in tracks {
+            if !self.failed_chapters.contains(&idx) {
+                playlist.add_track(track);
+            }
+        }
+        if playlist.is_empty() {
+            return Ok(());
+        }
 
-        if self.toc_visitor.is_some() {
-            split_name += &format!("{:02}. ", self.idx);
+        for format in formats {
+            let playlist_path = self.target_path.with_extension(format.get_extension());
+            let mut playlist_file = File::create(&playlist_path).map_err(|_| {
+                let msg = gettext("Failed to create the file for the playlist {}").replacen(
+                    "{}",
+                    playlist_path.to_str().unwrap(),
+                    1,
+                );
+                error!("{}", msg);
+                msg
+            })?;
+            playlist.write(format, &mut playlist_file)?;
         }
 
-        split_name += &track_title;
+        Ok(())
+    }
 
-        if let Some(ref stream) = self.selected_audio {
-            if let Some(ref tags) = stream.tags {
-                match tags.get_index::<gst::tags::LanguageName>(0) {
-                    Some(ref language) => split_name += &format!(" ({})", language.get().unwrap()),
-                    None => {
-                        if let Some(ref code) = tags.get_index::<gst::tags::LanguageCode>(0) {
-                            split_name += &format!(" ({})", code.get().unwrap());
-                        }
-                    }
+    // Starts splitting the next pending chapter if any
+    fn spawn_worker(&mut self) -> bool {
+        while let Some(mut job) = self.pending_jobs.pop_front() {
+            let (start, duration) = job.get_start_duration();
+
+            if job.is_complete {
+                info!(
+                    "{}",
+                    gettext("Skipping {}: already complete")
+                        .replacen("{}", job.output_path.to_str().unwrap(), 1)
+                );
+                self.done_duration += duration;
+                self.skipped_count += 1;
+                continue;
+            }
+
+            job.attempts += 1;
+
+            if let Some(parent) = job.output_path.parent() {
+                if let Err(err) = create_dir_all(parent) {
+                    let msg = gettext("Failed to create directory {}. {}")
+                        .replacen("{}", parent.to_str().unwrap(), 1)
+                        .replacen("{}", &format!("{}", err), 1);
+                    error!("{}", msg);
+                    self.fail_job(job, msg);
+                    continue;
                 }
             }
+
+            let (ctx_tx, ui_rx) = channel();
+            match SplitterContext::new(
+                &self.media_path,
+                &job.output_path,
+                self.streams.clone(),
+                self.format,
+                self.is_passthrough,
+                job.chapter.clone(),
+                &job.options,
+                ctx_tx,
+            ) {
+                Ok(splitter_ctx) => {
+                    self.workers.push(SplitWorker {
+                        job,
+                        start,
+                        duration,
+                        splitter_ctx,
+                        ui_rx,
+                    });
+                    return true;
+                }
+                Err(error) => {
+                    let msg = gettext("Failed to prepare for split. {}").replacen("{}", &error, 1);
+                    error!("{}", msg);
+                    self.fail_job(job, msg);
+                }
+            }
+        }
+
+        false
+    }
+
+    // Retries the job if possible or registers the chapter as failed
+    fn fail_job(&mut self, job: SplitJob, error: String) {
+        if job.attempts < MAX_ATTEMPTS {
+            warn!(
+                "{}",
+                gettext("Retrying chapter {}").replacen("{}", &format!("{}", job.idx), 1)
+            );
+            self.pending_jobs.push_back(job);
+        } else {
+            self.done_duration += job.get_start_duration().1;
+            self.failed_chapters.push(job.idx);
+            self.last_error = error;
+        }
+    }
+
+    // Stops all the workers and restores the playback context
+    fn stop(&mut self) {
+        self.resume_check_rx = None;
+        if let Some(mut analysis) = self.loudness_analysis.take() {
+            analysis.loudness_ctx.cancel();
+        }
+        for worker in &mut self.workers {
+            worker.splitter_ctx.cancel();
+        }
+        self.workers.clear();
+        self.pending_jobs.clear();
+        self.playlist_tracks.clear();
+        self.range = None;
+
+        self.remove_listener();
+        self.switch_to_available();
+        self.restore_context();
+    }
+
+    fn update_progress(&mut self) {
+        // The loudness analysis decodes the whole media before the split starts
+        let analysis_position = self.loudness_analysis
+            .as_mut()
+            .map(|analysis| analysis.loudness_ctx.get_position());
+        if let Some(position) = analysis_position {
+            if self.duration > 0 {
+                self.split_progress_bar
+                    .set_fraction(cmp::min(position, self.duration) as f64 / self.duration as f64);
+            }
+            return;
         }
 
-        split_name += &format!(".{}", self.extension);
+        if self.total_duration == 0 {
+            return;
+        }
 
-        self.target_path.with_file_name(split_name)
+        let mut position = self.done_duration;
+        for worker in &mut self.workers {
+            let worker_position = worker.splitter_ctx.get_position();
+            position += cmp::min(worker_position.saturating_sub(worker.start), worker.duration);
+        }
+        self.split_progress_bar
+            .set_fraction(position as f64 / self.total_duration as f64);
+    }
+
+    // Returns the ids of the streams to split and whether they are all audio streams
+    fn get_streams(&self, format: metadata::Format) -> (HashSet<String>, bool) {
+        let mut streams = HashSet::<String>::new();
+
+        if format != Format::Matroska {
+            if let Some(ref stream) = self.selected_audio {
+                streams.insert(stream.id.to_string());
+            }
+            return (streams, true);
+        }
+
+        let mut has_other = false;
+        let info = self.playback_ctx.as_ref().unwrap().info.read().unwrap();
+        for (ref stream_id, ref stream) in &info.streams.video {
+            if stream.must_export {
+                streams.insert(stream_id.to_string());
+                has_other = true;
+            }
+        }
+        for (ref stream_id, ref stream) in &info.streams.audio {
+            if stream.must_export {
+                streams.insert(stream_id.to_string());
+            }
+        }
+        for (ref stream_id, ref stream) in &info.streams.text {
+            if stream.must_export {
+                streams.insert(stream_id.to_string());
+                has_other = true;
+            }
+        }
+
+        (streams, !has_other)
+    }
+
+    fn append_redone_chapters(&mut self, message: &mut String) {
+        if !self.redone_chapters.is_empty() {
+            self.redone_chapters.sort();
+            *message += "\n";
+            *message += &gettext("Incomplete files split again for chapters: {}")
+                .replacen("{}", &join_chapters(&self.redone_chapters), 1);
+        }
+    }
+
+    fn has_video_stream(&self, streams: &HashSet<String>) -> bool {
+        let info = self.playback_ctx.as_ref().unwrap().info.read().unwrap();
+        info.streams
+            .video
+            .keys()
+            .any(|stream_id| streams.contains(&stream_id.to_string()))
+    }
+
+    fn report_drift(&mut self, worker: &SplitWorker) {
+        if let Some((start_drift, end_drift)) = worker.splitter_ctx.get_drift() {
+            info!(
+                "{}",
+                gettext("Chapter {}: start drift {} ms, end drift {} ms")
+                    .replacen("{}", &format!("{}", worker.job.idx), 1)
+                    .replacen("{}", &format_drift(start_drift), 1)
+                    .replacen("{}", &format_drift(end_drift), 1)
+            );
+            let drift = cmp::max(start_drift.abs() as u64, end_drift.abs() as u64);
+            self.max_drift = cmp::max(self.max_drift, drift);
+            if drift > DRIFT_WARNING_THRESHOLD {
+                self.drifting_chapters.push(worker.job.idx);
+            }
+        }
+    }
+
+    fn get_chapter_split_name(
+        &self,
+        template: &NamingTemplate,
+        chapter: &gst::TocEntry,
+        chapter_path: &[String],
+        track_nb: Option<usize>,
+        track_count: usize,
+    ) -> String {
+        let info = self.playback_ctx.as_ref().unwrap().info.read().unwrap();
+        let audio_tags = self.selected_audio
+            .as_ref()
+            .and_then(|stream| stream.tags.as_ref());
+        let naming = ChapterNaming {
+            media_tags: &info.tags,
+            audio_tags,
+            chapter,
+            chapter_path,
+            track_nb,
+            track_count,
+        };
+
+        get_split_name(template, &naming)
     }
 
     #[cfg_attr(feature = "cargo-clippy", allow(cyclomatic_complexity))]
-    fn update_tags(&self, chapter: &mut gst::TocEntry) -> gst::TocEntry {
+    fn update_tags(&self, chapter: &mut gst::TocEntry, idx: usize) -> gst::TocEntry {
         let mut tags = gst::TagList::new();
         {
             let tags = tags.get_mut().unwrap();
@@ -345,9 +1309,8 @@ impl SplitController {
                 add_tag_from!(tags, info.tags, gst::tags::LicenseUri);
                 add_tag_from!(tags, info.tags, gst::tags::Performer);
                 add_tag_from!(tags, info.tags, gst::tags::Contact);
-                add_tag_from!(tags, info.tags, gst::tags::AlbumGain);
-                add_tag_from!(tags, info.tags, gst::tags::AlbumPeak);
-                add_tag_from!(tags, info.tags, gst::tags::ReferenceLevel);
+                // Note: the gains of the media don't apply to the tracks
+                // (see `add_gain_tags`)
                 add_tag_from!(tags, info.tags, gst::tags::LanguageCode);
                 add_tag_from!(tags, info.tags, gst::tags::LanguageName);
                 add_tag_from!(tags, info.tags, gst::tags::BeatsPerMinute);
@@ -383,18 +1346,16 @@ impl SplitController {
             };
 
             // Add track specific tags
-            let title = chapter
-                .get_tags()
-                .and_then(|tags| {
-                    tags.get::<gst::tags::Title>()
-                        .map(|tag| tag.get().unwrap().to_owned())
-                })
-                .unwrap_or_else(get_default_chapter_title);
+            if let Some(chapter_tags) = chapter.get_tags() {
+                merge_chapter_tags(tags, &chapter_tags);
+            }
+
+            let title = get_chapter_title(chapter);
             tags.add::<gst::tags::Title>(&title.as_str(), gst::TagMergeMode::Replace);
 
             let (start, end) = chapter.get_start_stop_times().unwrap();
 
-            tags.add::<gst::tags::TrackNumber>(&(self.idx as u32), gst::TagMergeMode::Replace);
+            tags.add::<gst::tags::TrackNumber>(&(idx as u32), gst::TagMergeMode::Replace);
             tags.add::<gst::tags::TrackCount>(&(chapter_count as u32), gst::TagMergeMode::Replace);
             tags.add::<gst::tags::Duration>(
                 &gst::ClockTime::from_nseconds((end - start) as u64),
@@ -408,105 +1369,447 @@ impl SplitController {
         chapter.to_owned()
     }
 
-    fn get_selection(&self) -> metadata::Format {
+    // Returns the selected format and whether the stream must be copied as is
+    fn get_selection(&self) -> (metadata::Format, bool) {
         if self.split_to_flac_row.is_selected() {
-            Format::Flac
+            (Format::Flac, false)
         } else if self.split_to_wave_row.is_selected() {
-            Format::Wave
+            (Format::Wave, false)
+        } else if self.split_to_wavpack_row.is_selected() {
+            (Format::WavPack, false)
+        } else if self.split_to_aiff_row.is_selected() {
+            (Format::AIFF, false)
         } else if self.split_to_opus_row.is_selected() {
-            Format::Opus
+            (Format::Opus, false)
         } else if self.split_to_vorbis_row.is_selected() {
-            Format::Vorbis
+            (Format::Vorbis, false)
         } else if self.split_to_mp3_row.is_selected() {
-            Format::MP3
+            (Format::MP3, false)
+        } else if self.split_to_aac_row.is_selected() {
+            (Format::AAC, false)
+        } else if self.split_to_alac_row.is_selected() {
+            (Format::ALAC, false)
+        } else if self.split_to_matroska_row.is_selected() {
+            (Format::Matroska, false)
+        } else if self.split_to_matroska_keyframes_row.is_selected() {
+            (Format::Matroska, true)
+        } else if self.split_to_passthrough_row.is_selected() {
+            // Passthrough row is not sensitive when the codec can't be copied
+            // (see `streams_changed`)
+            (self.passthrough_format.unwrap(), true)
         } else {
             unreachable!("ExportController::get_split_selection unknown split type");
         }
     }
 
+    fn update_options_sensitivity(&self) {
+        let (format, is_passthrough) = self.get_selection();
+        let is_reencoding_audio = is_reencoding_audio(format, is_passthrough);
+        self.split_fade_in_spin.set_sensitive(is_reencoding_audio);
+        self.split_fade_out_spin.set_sensitive(is_reencoding_audio);
+        self.split_trim_silence_chk.set_sensitive(is_reencoding_audio);
+        self.split_silence_threshold_spin
+            .set_sensitive(is_reencoding_audio && self.split_trim_silence_chk.get_active());
+    }
+
     fn switch_to_busy(&self) {
-        // TODO: allow cancelling split
         self.base.switch_to_busy();
 
         self.split_list.set_sensitive(false);
-        self.split_btn.set_sensitive(false);
+        self.split_concurrency_spin.set_sensitive(false);
+        self.split_resume_chk.set_sensitive(false);
+        self.split_loudness_chk.set_sensitive(false);
+        self.split_fade_in_spin.set_sensitive(false);
+        self.split_fade_out_spin.set_sensitive(false);
+        self.split_trim_silence_chk.set_sensitive(false);
+        self.split_silence_threshold_spin.set_sensitive(false);
+        self.split_pregap_combo.set_sensitive(false);
+        // Split button is used to cancel the split
+        self.split_btn.set_label(&gettext("Cancel"));
     }
 
     fn switch_to_available(&self) {
         self.base.switch_to_available();
 
         self.split_progress_bar.set_fraction(0f64);
+        self.split_btn.set_label(&gettext("Split"));
         self.split_btn.set_sensitive(true);
+        self.split_concurrency_spin.set_sensitive(true);
+        self.split_resume_chk.set_sensitive(true);
+        self.split_loudness_chk.set_sensitive(true);
+        self.update_options_sensitivity();
+        self.split_pregap_combo.set_sensitive(true);
         self.split_list.set_sensitive(true);
     }
 
-    fn register_listener(
-        &mut self,
-        format: metadata::Format,
-        period: u32,
-        ui_rx: Receiver<ContextMessage>,
-    ) {
+    fn register_listener(&mut self, period: u32) {
         let this_rc = Rc::clone(self.this_opt.as_ref().unwrap());
 
         self.listener_src = Some(gtk::timeout_add(period, move || {
-            let mut keep_going = true;
-            let mut process_done = false;
-
             let mut this = this_rc.borrow_mut();
 
-            if this.duration > 0 {
-                let position = match this.splitter_ctx.as_mut() {
-                    Some(splitter_ctx) => splitter_ctx.get_position(),
-                    None => 0,
+            this.update_progress();
+
+            if let Some(resume_check_rx) = this.resume_check_rx.take() {
+                let results = match resume_check_rx.try_recv() {
+                    Ok(results) => results,
+                    Err(TryRecvError::Empty) => {
+                        this.resume_check_rx = Some(resume_check_rx);
+                        return glib::Continue(true);
+                    }
+                    // Outputs couldn't be checked => split all the chapters again
+                    Err(TryRecvError::Disconnected) => Vec::new(),
                 };
-                this.split_progress_bar
-                    .set_fraction(position as f64 / this.duration as f64);
-            }
-
-            for message in ui_rx.try_iter() {
-                match message {
-                    Eos => {
-                        process_done = match this.build_context(format) {
-                            Ok(true) => false, // more chapters
-                            Ok(false) => {
-                                this.show_info(&gettext("Media split succesfully"));
-                                true
-                            }
-                            Err(err) => {
+
+                this.apply_resume_check(&results);
+                this.spawn_workers();
+            }
+
+            if let Some(mut analysis) = this.loudness_analysis.take() {
+                let mut result = None;
+                for message in analysis.ui_rx.try_iter() {
+                    match message {
+                        Eos => {
+                            result = Some(Ok(()));
+                            break;
+                        }
+                        FailedToAnalyze(error) => {
+                            result = Some(Err(error));
+                            break;
+                        }
+                        _ => (),
+                    }
+                }
+
+                match result {
+                    None => {
+                        this.loudness_analysis = Some(analysis);
+                        return glib::Continue(true);
+                    }
+                    Some(Ok(())) => {
+                        let is_trimmed = analysis.is_trimming && this.trim_silences(&analysis);
+                        this.update_jobs_duration();
+                        if is_trimmed && this.must_measure_loudness {
+                            // Measure the gains on the trimmed chapters
+                            if let Err(err) = this.start_loudness_analysis(false) {
+                                this.listener_src = None;
+                                this.stop();
                                 this.show_error(&err);
-                                true
+                                return glib::Continue(false);
                             }
-                        };
+                            return glib::Continue(true);
+                        }
 
-                        keep_going = false;
+                        if this.must_measure_loudness {
+                            this.apply_loudness(&mut analysis);
+                        }
+                        this.start_splitting();
                     }
-                    FailedToExport(error) => {
-                        this.listener_src = None;
-                        keep_going = false;
-                        process_done = true;
-                        let message =
-                            gettext("Failed to split media. {}").replacen("{}", &error, 1);
+                    Some(Err(error)) => {
+                        let message = gettext("Failed to measure the loudness. {}")
+                            .replacen("{}", &error, 1);
                         error!("{}", message);
+                        this.listener_src = None;
+                        this.stop();
                         this.show_error(&message);
+                        return glib::Continue(false);
                     }
-                    _ => (),
-                };
+                }
+            }
 
-                if !keep_going {
-                    break;
+            if this.resume_check_rx.is_some() {
+                // Wait for the check of the previous outputs
+                return glib::Continue(true);
+            }
+
+            // Workers which are done with the optional failure
+            let mut done_workers = Vec::<(usize, Option<String>)>::new();
+            for (index, worker) in this.workers.iter().enumerate() {
+                for message in worker.ui_rx.try_iter() {
+                    match message {
+                        Eos => {
+                            done_workers.push((index, None));
+                            break;
+                        }
+                        FailedToExport(error) => {
+                            done_workers.push((index, Some(error)));
+                            break;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+
+            // Remove from the end so that the indices remain valid
+            for (index, failure) in done_workers.into_iter().rev() {
+                let mut worker = this.workers.remove(index);
+                match failure {
+                    None => {
+                        this.report_drift(&worker);
+                        this.done_duration += worker.duration;
+                    }
+                    Some(error) => {
+                        // Remove the partial output
+                        worker.splitter_ctx.cancel();
+                        let message = gettext("Failed to split chapter {}. {}")
+                            .replacen("{}", &format!("{}", worker.job.idx), 1)
+                            .replacen("{}", &error, 1);
+                        error!("{}", message);
+                        this.fail_job(worker.job, message);
+                    }
                 }
+
+                this.spawn_worker();
+            }
+
+            if !this.workers.is_empty() {
+                return glib::Continue(true);
             }
 
-            if !keep_going && process_done {
-                this.switch_to_available();
-                this.restore_context();
+            if this.failed_chapters.is_empty() {
+                let mut message = if this.range.is_some() {
+                    gettext("Selection exported succesfully")
+                } else if this.is_passthrough && this.format != Format::Matroska {
+                    let max_drift = this.max_drift as i64;
+                    gettext("Media split succesfully\nMaximum boundary drift: {} ms")
+                        .replacen("{}", &format_drift(max_drift), 1)
+                } else {
+                    gettext("Media split succesfully")
+                };
+                if this.skipped_count > 0 {
+                    message += "\n";
+                    message += &gettext("Complete files skipped: {}").replacen(
+                        "{}",
+                        &format!("{}", this.skipped_count),
+                        1,
+                    );
+                }
+                this.append_redone_chapters(&mut message);
+
+                let message_type = if this.drifting_chapters.is_empty() {
+                    gtk::MessageType::Info
+                } else {
+                    this.drifting_chapters.sort();
+                    message += "\n";
+                    message += &gettext("Boundaries drift by more than {} ms for chapters: {}")
+                        .replacen("{}", &format_drift(DRIFT_WARNING_THRESHOLD as i64), 1)
+                        .replacen("{}", &join_chapters(&this.drifting_chapters), 1);
+                    gtk::MessageType::Warning
+                };
+
+                match this.write_playlists() {
+                    Ok(()) => this.show_message(message_type, &message),
+                    Err(err) => this.show_error(&err),
+                }
+            } else {
+                this.failed_chapters.sort();
+                let mut message = gettext("Failed to split chapters: {}\n{}")
+                    .replacen("{}", &join_chapters(&this.failed_chapters), 1)
+                    .replacen("{}", &this.last_error, 1);
+                this.append_redone_chapters(&mut message);
+                if let Err(err) = this.write_playlists() {
+                    message += "\n";
+                    message += &err;
+                }
+                this.show_error(&message);
             }
 
-            glib::Continue(keep_going)
+            this.listener_src = None;
+            this.range = None;
+            this.switch_to_available();
+            this.restore_context();
+
+            glib::Continue(false)
         }));
     }
 }
 
+// Opus defines its own gain tags relative to -23 LUFS (RFC 7845)
+// while other formats use ReplayGain 2.0 which targets -18 LUFS
+fn add_gain_tags(
+    tags: &mut gst::TagListRef,
+    format: Format,
+    track_loudness: Option<&Loudness>,
+    album_loudness: Option<&Loudness>,
+) {
+    if format == Format::Opus {
+        if let Some(track_loudness) = track_loudness {
+            tags.add::<gst::tags::ExtendedComment>(
+                &format!("R128_TRACK_GAIN={}", track_loudness.get_r128_gain()).as_str(),
+                gst::TagMergeMode::Append,
+            );
+        }
+        if let Some(album_loudness) = album_loudness {
+            tags.add::<gst::tags::ExtendedComment>(
+                &format!("R128_ALBUM_GAIN={}", album_loudness.get_r128_gain()).as_str(),
+                gst::TagMergeMode::Append,
+            );
+        }
+        return;
+    }
+
+    if let Some(track_loudness) = track_loudness {
+        tags.add::<gst::tags::TrackGain>(
+            &track_loudness.get_replaygain(),
+            gst::TagMergeMode::Replace,
+        );
+        tags.add::<gst::tags::TrackPeak>(&track_loudness.peak, gst::TagMergeMode::Replace);
+    }
+    if let Some(album_loudness) = album_loudness {
+        tags.add::<gst::tags::AlbumGain>(
+            &album_loudness.get_replaygain(),
+            gst::TagMergeMode::Replace,
+        );
+        tags.add::<gst::tags::AlbumPeak>(&album_loudness.peak, gst::TagMergeMode::Replace);
+    }
+    if track_loudness.is_some() || album_loudness.is_some() {
+        tags.add::<gst::tags::ReferenceLevel>(
+            &REPLAYGAIN_REFERENCE_LEVEL,
+            gst::TagMergeMode::Replace,
+        );
+    }
+}
+
+fn get_playlist_track(job: &SplitJob) -> PlaylistTrack {
+    let artist = job.chapter.get_tags().and_then(|tags| {
+        tags.get::<gst::tags::Artist>()
+            .map(|tag| tag.get().unwrap().to_owned())
+    });
+
+    PlaylistTrack {
+        path: job.output_path.clone(),
+        title: get_chapter_title(&job.chapter),
+        artist,
+        duration: job.get_start_duration().1,
+    }
+}
+
+fn get_chapter_title(chapter: &gst::TocEntry) -> String {
+    chapter
+        .get_tags()
+        .and_then(|tags| {
+            tags.get::<gst::tags::Title>()
+                .map(|tag| tag.get().unwrap().to_owned())
+        })
+        .unwrap_or_else(get_default_chapter_title)
+}
+
+// Returns the chapters in order with the titles of their parent chapters
+fn get_chapters(toc: &gst::Toc) -> Vec<(gst::TocEntry, Vec<String>)> {
+    let mut chapters = Vec::new();
+
+    // Title of the entries whose children are being visited
+    let mut parents = Vec::<Option<String>>::new();
+    let mut last_title = None;
+
+    let mut toc_visitor = TocVisitor::new(toc);
+    while let Some(toc_visit) = toc_visitor.next() {
+        match toc_visit {
+            TocVisit::EnteringChildren => parents.push(last_title.take()),
+            TocVisit::LeavingChildren => {
+                parents.pop();
+            }
+            TocVisit::Node(entry) => {
+                if let gst::TocEntryType::Chapter = entry.get_entry_type() {
+                    last_title = Some(get_chapter_title(&entry));
+                    let chapter_path = parents.iter().filter_map(|title| title.clone()).collect();
+                    chapters.push((entry, chapter_path));
+                } else {
+                    last_title = None;
+                }
+            }
+        }
+    }
+
+    chapters
+}
+
+fn get_pregap_mode_id(pregap_mode: PregapMode) -> &'static str {
+    match pregap_mode {
+        PregapMode::AppendToPrevious => "append",
+        PregapMode::PrependToCurrent => "prepend",
+        PregapMode::Discard => "discard",
+    }
+}
+
+fn get_pregap_mode(id: &str) -> Option<PregapMode> {
+    match id {
+        "append" => Some(PregapMode::AppendToPrevious),
+        "prepend" => Some(PregapMode::PrependToCurrent),
+        "discard" => Some(PregapMode::Discard),
+        _ => None,
+    }
+}
+
+fn build_media_chapter(title: &str, start: u64, end: u64) -> gst::TocEntry {
+    let mut toc_entry = gst::TocEntry::new(gst::TocEntryType::Chapter, &"".to_owned());
+    toc_entry
+        .get_mut()
+        .unwrap()
+        .set_start_stop_times(start as i64, end as i64);
+
+    let mut tag_list = gst::TagList::new();
+    tag_list
+        .get_mut()
+        .unwrap()
+        .add::<gst::tags::Title>(&title, gst::TagMergeMode::Replace);
+    toc_entry.get_mut().unwrap().set_tags(tag_list);
+
+    toc_entry
+}
+
+fn get_naming_preview(info: &MediaInfo) -> NamingPreview {
+    let (chapter, chapter_path, track_nb, track_count) = match info.toc {
+        Some(ref toc) => {
+            let mut chapters = get_chapters(toc);
+            let track_count = chapters.len();
+            if track_count > 0 {
+                let (chapter, chapter_path) = chapters.swap_remove(0);
+                (chapter, chapter_path, Some(1), track_count)
+            } else {
+                (build_media_chapter(&info.name, 0, info.duration), Vec::new(), None, 1)
+            }
+        }
+        None => (build_media_chapter(&info.name, 0, info.duration), Vec::new(), None, 1),
+    };
+
+    NamingPreview {
+        media_tags: info.tags.clone(),
+        chapter,
+        chapter_path,
+        track_nb,
+        track_count,
+        is_video_selected: info.streams.is_video_selected(),
+    }
+}
+
+// Returns the path of the split file relative to the media, without extension
+fn get_split_name(template: &NamingTemplate, naming: &ChapterNaming) -> String {
+    let split_name = template.render(|name| naming.resolve(name));
+    if split_name.is_empty() {
+        get_default_chapter_title()
+    } else {
+        split_name
+    }
+}
+
+// Fades and silence trimming are only applied when the audio stream is re-encoded
+fn is_reencoding_audio(format: metadata::Format, is_passthrough: bool) -> bool {
+    format != Format::Matroska && !is_passthrough
+}
+
+fn join_chapters(chapters: &[usize]) -> String {
+    chapters
+        .iter()
+        .map(|idx| format!("{}", idx))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn format_drift(drift: i64) -> String {
+    format!("{:.1}", drift as f64 / 1_000_000f64)
+}
+
 impl Deref for SplitController {
     type Target = OutputBaseController;
 
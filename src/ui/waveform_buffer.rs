@@ -5,9 +5,9 @@ use std::boxed::Box;
 use std::sync::{Arc, Mutex};
 
 use media::sample_extractor::SampleExtractionState;
-use media::{AudioBuffer, AudioChannel, DoubleAudioBuffer, SampleExtractor};
+use media::{AudioBuffer, AudioChannel, ChannelMix, DoubleAudioBuffer, SampleExtractor};
 
-use super::WaveformImage;
+use super::{AmplitudeMode, ChannelLane, SpectrogramBuffer, SpectrogramSettings, WaveformImage};
 
 pub struct DoubleWaveformBuffer {}
 
@@ -34,8 +34,21 @@ pub struct ImagePositions {
     pub sample_step: f64,
 }
 
+// Sample range covered by the waveform image and its dimensions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageLayout {
+    pub lower: usize,
+    pub upper: usize,
+    pub sample_step: usize,
+    pub x_step: usize,
+    pub width: i32,
+    pub height: i32,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum LockState {
+    // The user moved the window, which no longer follows the cursor
+    Panned,
     Playing,
     PlayingRange,
     RestoringInitialPos,
@@ -55,6 +68,8 @@ pub struct WaveformBuffer {
     conditions_changed: bool,
 
     image: WaveformImage,
+    // Replaces the waveform image when visible
+    spectrogram: SpectrogramBuffer,
 
     previous_sample: usize,
     current_sample: usize,
@@ -87,6 +102,7 @@ impl WaveformBuffer {
             conditions_changed: false,
 
             image: WaveformImage::new(id),
+            spectrogram: SpectrogramBuffer::new(),
 
             previous_sample: 0,
             current_sample: 0,
@@ -146,6 +162,39 @@ impl WaveformBuffer {
         )
     }
 
+    // Returns the layout of the waveform image so that other representations
+    // of the samples can be aligned on it
+    pub fn get_image_layout(&self) -> Option<ImageLayout> {
+        if !self.image.is_ready {
+            return None;
+        }
+
+        let image = self.image.get_image();
+        Some(ImageLayout {
+            lower: self.image.lower,
+            upper: self.image.upper,
+            sample_step: self.image.sample_step,
+            x_step: self.image.x_step,
+            width: image.get_width(),
+            height: image.get_height(),
+        })
+    }
+
+    pub fn is_spectrogram_visible(&self) -> bool {
+        self.spectrogram.is_visible()
+    }
+
+    pub fn set_spectrogram_settings(&mut self, is_visible: bool, settings: SpectrogramSettings) {
+        self.spectrogram.set_settings(is_visible, settings);
+    }
+
+    // Render the spectrogram for the samples of the waveform image
+    fn render_spectrogram(&mut self, audio_buffer: &AudioBuffer) {
+        let layout = self.get_image_layout();
+        self.spectrogram.set_image_layout(layout);
+        self.spectrogram.extract_samples(audio_buffer);
+    }
+
     pub fn get_half_window_duration(&self) -> u64 {
         self.half_req_sample_window as u64 * self.state.sample_duration
     }
@@ -169,7 +218,9 @@ impl WaveformBuffer {
             self.image.contains_eos,
         );
 
-        if is_playing {
+        if self.is_panned() {
+            // the window was moved by the user => keep it where it is
+        } else if is_playing {
             // stream is playing => let the cursor jump from current position
             // to the sought position without shifting the waveform if possible
 
@@ -212,7 +263,7 @@ impl WaveformBuffer {
     }
 
     pub fn start_play_range(&mut self) {
-        if self.image.sample_step == 0 {
+        if self.image.sample_step == 0 || self.is_panned() {
             return;
         }
 
@@ -222,6 +273,79 @@ impl WaveformBuffer {
         }
     }
 
+    fn is_panned(&self) -> bool {
+        match self.first_visible_sample_lock {
+            Some((_, LockState::Panned)) => true,
+            _ => false,
+        }
+    }
+
+    // Move the window by `delta_x` pixels without changing the playback position.
+    // The window no longer follows the cursor until `follow_playhead` is called.
+    pub fn pan(&mut self, delta_x: f64) {
+        let first_visible_sample = match self.first_visible_sample_lock {
+            Some((first_visible_sample, LockState::Panned)) => first_visible_sample,
+            _ => self.first_visible_sample.unwrap_or(self.image.lower) as i64,
+        };
+        let first_visible_sample =
+            first_visible_sample + (delta_x * self.image.sample_step_f).round() as i64;
+
+        self.first_visible_sample_lock = Some((first_visible_sample.max(0), LockState::Panned));
+    }
+
+    // Returns the position to seek to in order to decode the samples
+    // of a window moved by the user past the rendered samples
+    pub fn get_panned_refill_position(&self) -> Option<u64> {
+        let first_visible_sample = match self.first_visible_sample_lock {
+            Some((first_visible_sample, LockState::Panned)) => first_visible_sample as usize,
+            _ => return None,
+        };
+        if !self.image.is_ready {
+            return None;
+        }
+
+        let is_before = first_visible_sample < self.image.lower;
+        let is_after = first_visible_sample + self.req_sample_window > self.image.upper
+            && !self.image.contains_eos;
+        if is_before || is_after {
+            Some(
+                (first_visible_sample + self.half_req_sample_window) as u64
+                    * self.state.sample_duration,
+            )
+        } else {
+            None
+        }
+    }
+
+    // Have the window follow the cursor again after it was moved by the user
+    pub fn follow_playhead(&mut self) {
+        if self.is_panned() {
+            self.first_visible_sample = None;
+            self.first_visible_sample_lock = None;
+        }
+    }
+
+    // Returns the first visible sample of a window moved by the user
+    // constrained to the samples in the range [lower, upper)
+    fn constrain_panned_sample(
+        &self,
+        first_visible_sample: i64,
+        lower: usize,
+        upper: usize,
+    ) -> usize {
+        let max_first_visible_sample = if upper > lower + self.req_sample_window {
+            upper - self.req_sample_window
+        } else {
+            lower
+        };
+
+        if first_visible_sample < lower as i64 {
+            lower
+        } else {
+            (first_visible_sample as usize).min(max_first_visible_sample)
+        }
+    }
+
     fn refresh_position(&mut self, frame_time: u64) {
         let (position, mut sample) = self.get_current_sample(frame_time);
         if self.previous_sample != sample {
@@ -250,7 +374,16 @@ impl WaveformBuffer {
         self.first_visible_sample = if self.image.is_ready {
             self.refresh_position(frame_time);
 
-            if self.cursor_sample >= self.image.lower {
+            if let Some((first_visible_sample_lock, LockState::Panned)) =
+                self.first_visible_sample_lock
+            {
+                // the window was moved by the user => don't follow the cursor
+                Some(self.constrain_panned_sample(
+                    first_visible_sample_lock,
+                    self.image.lower,
+                    self.image.upper,
+                ))
+            } else if self.cursor_sample >= self.image.lower {
                 // current sample appears after first buffer sample
                 if let Some((first_visible_sample_lock, lock_state)) =
                     self.first_visible_sample_lock.take()
@@ -321,7 +454,7 @@ impl WaveformBuffer {
                                 Some(next_lower)
                             }
                         }
-                        LockState::PlayingRange => {
+                        LockState::Panned | LockState::PlayingRange => {
                             // keep origin on the first sample upon seek
                             self.first_visible_sample_lock =
                                 Some((first_visible_sample_lock, lock_state));
@@ -406,7 +539,22 @@ impl WaveformBuffer {
     }
 
     // Update rendering conditions
-    pub fn update_conditions(&mut self, duration_per_1000px: f64, width: i32, height: i32) {
+    // When the duration changes, the sample displayed at `zoom_anchor_x` keeps its position.
+    // The cursor is used as an anchor if no position is specified.
+    pub fn update_conditions(
+        &mut self,
+        duration_per_1000px: f64,
+        width: i32,
+        height: i32,
+        zoom_anchor_x: Option<f64>,
+    ) {
+        let anchor_sample = match (zoom_anchor_x, self.first_visible_sample) {
+            (Some(x), Some(first_visible_sample)) => {
+                first_visible_sample as i64 + (x * self.sample_step_f) as i64
+            }
+            _ => self.cursor_sample as i64,
+        };
+
         let (duration_changed, mut scale_factor) =
             if (duration_per_1000px - self.req_duration_per_1000px).abs() < 1f64 {
                 (false, 0f64)
@@ -451,7 +599,7 @@ impl WaveformBuffer {
                 self.first_visible_sample = match self.first_visible_sample {
                     Some(first_visible_sample) => {
                         let new_first_visible_sample = first_visible_sample as i64
-                            + ((self.cursor_sample as i64 - first_visible_sample as i64) as f64
+                            + ((anchor_sample - first_visible_sample as i64) as f64
                                 * (1f64 - scale_factor)) as i64;
 
                         if new_first_visible_sample > self.image.sample_step as i64 {
@@ -491,6 +639,9 @@ impl WaveformBuffer {
                             // first_visible_sample can be snapped to the beginning
                             // => have refresh and update_first_visible_sample
                             // compute the best range for this situation
+                            if self.is_panned() {
+                                self.first_visible_sample_lock = Some((0, LockState::Panned));
+                            }
                             None
                         }
                     }
@@ -500,6 +651,22 @@ impl WaveformBuffer {
         }
     }
 
+    pub fn update_amplitude_mode(&mut self, amplitude_mode: AmplitudeMode, db_floor: f64) {
+        self.image.update_amplitude_mode(amplitude_mode, db_floor);
+    }
+
+    pub fn update_lanes_layout(&mut self, is_lanes_layout: bool) {
+        self.image.update_lanes_layout(is_lanes_layout);
+    }
+
+    pub fn update_channel_mix(&mut self, channel_mix: &ChannelMix) {
+        self.image.update_channel_mix(channel_mix);
+    }
+
+    pub fn get_channel_lanes(&self) -> Vec<ChannelLane> {
+        self.image.get_channel_lanes()
+    }
+
     fn update_sample_step(&mut self) {
         // compute a sample step which will produce an integer number of
         // samples per pixel or an integer number of pixels per samples
@@ -578,10 +745,15 @@ impl WaveformBuffer {
                     None => None,
                 };
 
+                let image = match self.spectrogram.get_image() {
+                    Some(spectrogram) => spectrogram,
+                    None => self.image.get_image(),
+                };
+
                 (
                     self.cursor_position,
                     Some((
-                        self.image.get_image(),
+                        image,
                         ImagePositions {
                             first: SamplePosition {
                                 x: x_offset,
@@ -633,7 +805,9 @@ impl WaveformBuffer {
             );
 
             self.first_visible_sample = None;
-            self.first_visible_sample_lock = None;
+            if !self.is_panned() {
+                self.first_visible_sample_lock = None;
+            }
 
             (audio_buffer.segment_lower, audio_buffer.upper)
         };
@@ -649,9 +823,27 @@ impl WaveformBuffer {
             );
 
             self.first_visible_sample = None;
-            self.first_visible_sample_lock = None;
+            if !self.is_panned() {
+                self.first_visible_sample_lock = None;
+            }
 
             Some((lower, upper))
+        } else if let Some((first_visible_sample, LockState::Panned)) =
+            self.first_visible_sample_lock
+        {
+            // the window was moved by the user => keep it regardless of the cursor
+            // the requested origin is kept so that the window reaches it
+            // when its samples become available
+            let first_visible_sample =
+                self.constrain_panned_sample(first_visible_sample, lower, upper);
+            self.first_visible_sample = Some(first_visible_sample);
+
+            Some((
+                first_visible_sample,
+                upper.min(
+                    first_visible_sample + self.req_sample_window + self.half_req_sample_window,
+                ),
+            ))
         } else {
             match self.first_visible_sample {
                 Some(first_visible_sample) => {
@@ -694,7 +886,9 @@ impl WaveformBuffer {
                                     self.first_visible_sample = None;
                                     None
                                 }
-                                LockState::PlayingRange | LockState::RestoringInitialPos => {
+                                LockState::Panned
+                                | LockState::PlayingRange
+                                | LockState::RestoringInitialPos => {
                                     // keep position
                                     self.first_visible_sample_lock =
                                         Some((first_visible_sample, lock_state));
@@ -795,14 +989,21 @@ impl SampleExtractor for WaveformBuffer {
     }
 
     fn get_lower(&self) -> usize {
-        self.first_visible_sample
+        let lower = self.first_visible_sample
             .map_or(self.image.lower, |sample| {
                 if sample > self.half_req_sample_window {
                     sample - self.half_req_sample_window
                 } else {
                     sample
                 }
-            })
+            });
+
+        if self.spectrogram.is_visible() {
+            // Keep the samples for the first window of the spectrogram
+            lower.saturating_sub(self.spectrogram.get_half_window_size())
+        } else {
+            lower
+        }
     }
 
     fn get_requested_sample_window(&self) -> Option<usize> {
@@ -818,6 +1019,7 @@ impl SampleExtractor for WaveformBuffer {
 
         self.state.cleanup();
         self.reset();
+        self.spectrogram.cleanup();
     }
 
     fn set_sample_duration(&mut self, per_sample: u64, per_1000_samples: f64) {
@@ -831,6 +1033,9 @@ impl SampleExtractor for WaveformBuffer {
         self.state.duration_per_1000_samples = per_1000_samples;
         self.update_sample_step();
         self.update_sample_window();
+
+        self.spectrogram
+            .set_sample_duration(per_sample, per_1000_samples);
     }
 
     fn set_channels(&mut self, channels: &[AudioChannel]) {
@@ -842,7 +1047,7 @@ impl SampleExtractor for WaveformBuffer {
             None => self.first_visible_sample = None,
             Some((first_visible_sample, lock_state)) => match lock_state {
                 LockState::Playing => self.first_visible_sample = None,
-                LockState::PlayingRange | LockState::RestoringInitialPos =>
+                LockState::Panned | LockState::PlayingRange | LockState::RestoringInitialPos =>
                     // don't drop first_visible_sample & first_visible_sample_lock
                     self.first_visible_sample_lock =
                         Some((first_visible_sample, lock_state)),
@@ -882,6 +1087,8 @@ impl SampleExtractor for WaveformBuffer {
         self.state.last_pos = other.state.last_pos;
 
         self.image.update_from_other(&mut other.image);
+        self.spectrogram
+            .update_concrete_state(&mut other.spectrogram);
     }
 
     // This is the entry point for the waveform update.
@@ -899,6 +1106,7 @@ impl SampleExtractor for WaveformBuffer {
         // the waveform image and the AudioBuffer
         let (lower, upper) = self.get_sample_range(audio_buffer);
         self.image.render(audio_buffer, lower, upper);
+        self.render_spectrogram(audio_buffer);
 
         self.playback_needs_refresh = if audio_buffer.eos && !self.image.contains_eos {
             // there won't be any refresh on behalf of audio_buffer
@@ -926,6 +1134,7 @@ impl SampleExtractor for WaveformBuffer {
 
             let (lower, upper) = self.get_sample_range(audio_buffer);
             self.image.render(audio_buffer, lower, upper);
+            self.render_spectrogram(audio_buffer);
 
             self.playback_needs_refresh = {
                 if self.playback_needs_refresh && self.image.contains_eos {
@@ -8,19 +8,69 @@ use std::fs::{create_dir, File};
 #[cfg(feature = "dump-waveform")]
 use std::io::ErrorKind;
 
-use media::{AudioBuffer, AudioChannel, AudioChannelSide};
+use media::{AudioBuffer, AudioChannel, AudioChannelSide, ChannelMix};
 
 pub const BACKGROUND_COLOR: (f64, f64, f64) = (0.2f64, 0.2235f64, 0.2314f64);
 pub const AMPLITUDE_0_COLOR: (f64, f64, f64) = (0.5f64, 0.5f64, 0f64);
 
+const PEAK_ALPHA: f64 = 0.68f64;
+const RMS_ALPHA: f64 = 0.4f64;
+// Applied to the alpha of the channels which are not audible
+const SILENCED_ALPHA_FACTOR: f64 = 0.25f64;
+
 // Initial image dimensions
 // will dynamically adapt if needed
 const INIT_WIDTH: i32 = 2000;
 const INIT_HEIGHT: i32 = 500;
 
+const DEFAULT_DB_FLOOR: f64 = -60f64;
+
 #[cfg(feature = "dump-waveform")]
 const WAVEFORM_DUMP_DIR: &str = "target/waveforms";
 
+// How the amplitude of the samples is represented
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AmplitudeMode {
+    LinearPeak,
+    // The RMS of the samples represented by each column is drawn under the peaks
+    LinearRms,
+    // Amplitudes are displayed in dB down to a floor
+    Logarithmic,
+}
+
+impl Default for AmplitudeMode {
+    fn default() -> Self {
+        AmplitudeMode::LinearPeak
+    }
+}
+
+// Returns the ratio of the half height of the image where an `amplitude`
+// in the range [0.0, 1.0] is represented
+// `db_floor` is the lowest level represented in `AmplitudeMode::Logarithmic`
+pub fn get_amplitude_ratio(amplitude: f64, mode: AmplitudeMode, db_floor: f64) -> f64 {
+    match mode {
+        AmplitudeMode::LinearPeak | AmplitudeMode::LinearRms => amplitude.min(1f64),
+        AmplitudeMode::Logarithmic => {
+            if amplitude > 0f64 && db_floor < 0f64 {
+                ((20f64 * amplitude.log10() - db_floor) / -db_floor)
+                    .max(0f64)
+                    .min(1f64)
+            } else {
+                0f64
+            }
+        }
+    }
+}
+
+// Vertical area where a channel is drawn when the channels are displayed in lanes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelLane {
+    pub channel: usize,
+    pub label: &'static str,
+    pub top: f64,
+    pub height: f64,
+}
+
 #[derive(Debug, Clone)]
 pub struct WaveformSample {
     pub x: f64,
@@ -34,6 +84,11 @@ pub struct WaveformImage {
     pub shareable_state_changed: bool,
 
     channel_colors: Vec<(f64, f64, f64)>,
+    channel_labels: Vec<&'static str>,
+    channel_mix: ChannelMix,
+    is_lanes_layout: bool,
+    amplitude_mode: AmplitudeMode,
+    db_floor: f64,
 
     exposed_image: Option<cairo::ImageSurface>,
     secondary_image: Option<cairo::ImageSurface>,
@@ -79,6 +134,11 @@ impl WaveformImage {
             shareable_state_changed: false,
 
             channel_colors: Vec::new(),
+            channel_labels: Vec::new(),
+            channel_mix: ChannelMix::default(),
+            is_lanes_layout: false,
+            amplitude_mode: AmplitudeMode::default(),
+            db_floor: DEFAULT_DB_FLOOR,
 
             exposed_image: Some(
                 cairo::ImageSurface::create(cairo::Format::Rgb24, INIT_WIDTH, INIT_HEIGHT).unwrap(),
@@ -136,6 +196,7 @@ impl WaveformImage {
         self.force_redraw = false;
 
         self.channel_colors.clear();
+        self.channel_labels.clear();
 
         self.lower = 0;
         self.upper = 0;
@@ -161,6 +222,7 @@ impl WaveformImage {
                 AudioChannelSide::NotLocalized => (0f64, 0f64, channel.factor),
                 AudioChannelSide::Right => (channel.factor, 0f64, 0f64),
             });
+            self.channel_labels.push(channel.get_label());
         }
     }
 
@@ -190,6 +252,59 @@ impl WaveformImage {
         }
     }
 
+    pub fn update_amplitude_mode(&mut self, amplitude_mode: AmplitudeMode, db_floor: f64) {
+        if amplitude_mode != self.amplitude_mode || db_floor != self.db_floor {
+            debug!(
+                "{}_update_amplitude_mode {:?}, floor {}",
+                self.id, amplitude_mode, db_floor
+            );
+
+            self.amplitude_mode = amplitude_mode;
+            self.db_floor = db_floor;
+            self.force_redraw = true;
+            self.shareable_state_changed = true;
+        }
+    }
+
+    pub fn update_lanes_layout(&mut self, is_lanes_layout: bool) {
+        if is_lanes_layout != self.is_lanes_layout {
+            debug!("{}_update_lanes_layout {}", self.id, is_lanes_layout);
+
+            self.is_lanes_layout = is_lanes_layout;
+            self.force_redraw = true;
+            self.shareable_state_changed = true;
+        }
+    }
+
+    pub fn update_channel_mix(&mut self, channel_mix: &ChannelMix) {
+        if *channel_mix != self.channel_mix {
+            debug!("{}_update_channel_mix {:?}", self.id, channel_mix);
+
+            self.channel_mix = channel_mix.clone();
+            self.force_redraw = true;
+            self.shareable_state_changed = true;
+        }
+    }
+
+    // Returns the lanes of the channels, if the channels are displayed in lanes
+    pub fn get_channel_lanes(&self) -> Vec<ChannelLane> {
+        if !self.is_lanes_layout || self.half_range == 0f64 {
+            return Vec::new();
+        }
+
+        (0..self.get_lane_count())
+            .map(|channel| {
+                let (top, half_height) = self.get_lane(channel);
+                ChannelLane {
+                    channel,
+                    label: self.channel_labels.get(channel).cloned().unwrap_or("?"),
+                    top,
+                    height: 2f64 * half_height,
+                }
+            })
+            .collect()
+    }
+
     pub fn update_sample_step(&mut self, sample_step_f: f64) {
         self.force_redraw |= (self.sample_step_f - sample_step_f).abs() > 0.01f64;
         self.is_initialized = self.force_redraw && (self.req_width != 0);
@@ -230,6 +345,19 @@ impl WaveformImage {
                 self.req_height = other.req_height;
                 self.force_redraw = true;
             }
+            if self.amplitude_mode != other.amplitude_mode || self.db_floor != other.db_floor {
+                self.amplitude_mode = other.amplitude_mode;
+                self.db_floor = other.db_floor;
+                self.force_redraw = true;
+            }
+            if self.is_lanes_layout != other.is_lanes_layout {
+                self.is_lanes_layout = other.is_lanes_layout;
+                self.force_redraw = true;
+            }
+            if self.channel_mix != other.channel_mix {
+                self.channel_mix = other.channel_mix.clone();
+                self.force_redraw = true;
+            }
 
             other.shareable_state_changed = false;
         }
@@ -470,6 +598,79 @@ impl WaveformImage {
         self.is_ready = true;
     }
 
+    // Prepares the rendering of a `width` x `height` waveform on a surface
+    // which is not owned by the `WaveformImage`, e.g. for a file.
+    // Sample `lower` is drawn at x = 0 and the samples are spread by `sample_step_f`
+    // samples per pixel, which doesn't need to be an integer nor its inverse.
+    pub fn start_offscreen(
+        &mut self,
+        cr: &cairo::Context,
+        width: i32,
+        height: i32,
+        lower: usize,
+        sample_step_f: f64,
+    ) {
+        self.sample_step_f = sample_step_f;
+        self.sample_step = (sample_step_f as usize).max(1);
+        self.x_step_f = self.sample_step as f64 / sample_step_f;
+        self.x_step = (self.x_step_f as usize).max(1);
+
+        self.image_width = width;
+        self.image_width_f = f64::from(width);
+        self.image_height = height;
+        self.half_range = f64::from(height / 2);
+        self.full_range = f64::from(height);
+
+        self.lower = lower;
+        self.upper = lower;
+        self.first = None;
+        self.last = None;
+
+        cr.set_source_rgb(BACKGROUND_COLOR.0, BACKGROUND_COLOR.1, BACKGROUND_COLOR.2);
+        cr.paint();
+        self.set_scale(cr);
+    }
+
+    // Draws the samples from previous upper bound up to `upper`
+    // after the samples already drawn by `start_offscreen` or `draw_offscreen`.
+    // Returns the sample up to which the waveform is drawn.
+    pub fn draw_offscreen(
+        &mut self,
+        cr: &cairo::Context,
+        audio_buffer: &AudioBuffer,
+        upper: usize,
+    ) -> usize {
+        if audio_buffer.upper <= self.lower {
+            return self.upper;
+        }
+
+        // Steps are counted from `self.lower`, except for the last samples of the range
+        let step = self.sample_step;
+        let is_last = upper <= audio_buffer.upper;
+        let upper = if is_last {
+            upper
+        } else {
+            self.lower + (audio_buffer.upper - self.lower) / step * step
+        };
+        // Samples missing in the buffer are left blank
+        let lower = if self.upper < audio_buffer.lower {
+            self.lower + (audio_buffer.lower - self.lower + step - 1) / step * step
+        } else {
+            self.upper
+        };
+        if upper <= lower || (!is_last && upper <= lower + step) {
+            return self.upper;
+        }
+
+        let first_x = ((lower - self.lower) / step) as f64 * self.x_step_f;
+        if let Some((_first, last)) = self.draw_samples(cr, audio_buffer, lower, upper, first_x) {
+            self.last = Some(last);
+            self.upper = upper;
+        }
+
+        self.upper
+    }
+
     // Redraw the whole sample range on a clean image
     fn redraw(
         &mut self,
@@ -752,8 +953,17 @@ impl WaveformImage {
     }
 
     fn set_channel_color(&self, cr: &cairo::Context, channel: usize) {
+        self.set_channel_color_alpha(cr, channel, PEAK_ALPHA);
+    }
+
+    fn set_channel_color_alpha(&self, cr: &cairo::Context, channel: usize, alpha: f64) {
         if let Some(&(red, green, blue)) = self.channel_colors.get(channel) {
-            cr.set_source_rgba(red, green, blue, 0.68f64);
+            let alpha = if self.channel_mix.is_audible(channel) {
+                alpha
+            } else {
+                alpha * SILENCED_ALPHA_FACTOR
+            };
+            cr.set_source_rgba(red, green, blue, alpha);
         } else {
             warn!(
                 "{}_set_channel_color no color for channel {}",
@@ -776,14 +986,87 @@ impl WaveformImage {
         }
     }
 
-    fn convert_sample(&self, value: &f64) -> f64 {
-        value * self.half_range
+    fn get_lane_count(&self) -> usize {
+        if self.is_lanes_layout {
+            self.channel_colors.len().max(1)
+        } else {
+            1
+        }
+    }
+
+    // Returns the top and the half height of the lane where `channel` is drawn
+    fn get_lane(&self, channel: usize) -> (f64, f64) {
+        let lane_count = self.get_lane_count();
+        if lane_count > 1 {
+            let half_height = self.half_range / lane_count as f64;
+            (2f64 * half_height * channel.min(lane_count - 1) as f64, half_height)
+        } else {
+            (0f64, self.half_range)
+        }
+    }
+
+    fn convert_sample(&self, channel: usize, value: &f64) -> f64 {
+        let (top, half_height) = self.get_lane(channel);
+        match self.amplitude_mode {
+            AmplitudeMode::LinearPeak | AmplitudeMode::LinearRms => top + value * half_height,
+            AmplitudeMode::Logarithmic => {
+                // Samples are stored as `1.0 - value`
+                let sample = 1f64 - value;
+                let ratio = get_amplitude_ratio(sample.abs(), self.amplitude_mode, self.db_floor);
+                top + (1f64 - sample.signum() * ratio) * half_height
+            }
+        }
+    }
+
+    // Draw the RMS of each channel for the samples in the range [lower, upper)
+    fn draw_rms(
+        &self,
+        cr: &cairo::Context,
+        audio_buffer: &AudioBuffer,
+        lower: usize,
+        upper: usize,
+        x: f64,
+    ) {
+        let mut sums = vec![0f64; audio_buffer.channels];
+        let mut count = 0;
+        if let Some(sample_iter) = audio_buffer.iter(lower, upper.min(audio_buffer.upper), 1) {
+            for sample in sample_iter {
+                for (sum, value) in sums.iter_mut().zip(sample) {
+                    *sum += (1f64 - value).powi(2);
+                }
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return;
+        }
+
+        cr.set_line_width(self.x_step_f);
+        for (channel, sum) in sums.iter().enumerate() {
+            let rms = (sum / f64::from(count)).sqrt();
+            let (top, half_height) = self.get_lane(channel);
+            self.set_channel_color_alpha(cr, channel, RMS_ALPHA);
+            cr.move_to(x, top + (1f64 - rms) * half_height);
+            cr.line_to(x, top + (1f64 + rms) * half_height);
+            cr.stroke();
+        }
+        cr.set_line_width(self.get_line_width());
+    }
+
+    fn get_line_width(&self) -> f64 {
+        if self.x_step == 1 {
+            1f64
+        } else if self.x_step < 4 {
+            1.5f64
+        } else {
+            2f64
+        }
     }
 
     fn convert_sample_values(&self, values: &[f64]) -> Vec<f64> {
         let mut result: Vec<f64> = Vec::with_capacity(values.len());
-        for value in values {
-            result.push(self.convert_sample(value));
+        for (channel, value) in values.iter().enumerate() {
+            result.push(self.convert_sample(channel, value));
         }
         result
     }
@@ -798,13 +1081,7 @@ impl WaveformImage {
         upper: usize,
         first_x: f64,
     ) -> Option<(WaveformSample, WaveformSample)> {
-        if self.x_step == 1 {
-            cr.set_line_width(1f64);
-        } else if self.x_step < 4 {
-            cr.set_line_width(1.5f64);
-        } else {
-            cr.set_line_width(2f64);
-        }
+        cr.set_line_width(self.get_line_width());
 
         let sample_iter = audio_buffer.iter(lower, upper, self.sample_step);
         if sample_iter.is_none() {
@@ -852,12 +1129,23 @@ impl WaveformImage {
             cr.stroke();
         }
 
+        if self.amplitude_mode == AmplitudeMode::LinearRms {
+            // Draw the RMS first so that the peaks remain visible
+            let mut sample = lower;
+            let mut x = first_x;
+            while sample < upper {
+                self.draw_rms(cr, audio_buffer, sample, sample + self.sample_step, x);
+                sample += self.sample_step;
+                x += self.x_step_f;
+            }
+        }
+
         let mut first_values: Vec<f64> = Vec::with_capacity(audio_buffer.channels);
         let mut last_values: Vec<f64> = Vec::with_capacity(audio_buffer.channels);
 
         let sample = sample_iter.next();
-        for channel_value in sample.unwrap() {
-            let y = self.convert_sample(channel_value);
+        for (channel, channel_value) in sample.unwrap().iter().enumerate() {
+            let y = self.convert_sample(channel, channel_value);
             first_values.push(y);
             last_values.push(y);
         }
@@ -888,7 +1176,7 @@ impl WaveformImage {
                 self.set_channel_color(cr, channel);
                 cr.move_to(prev_x, last_values[channel]);
 
-                last_values[channel] = self.convert_sample(value);
+                last_values[channel] = self.convert_sample(channel, value);
                 cr.line_to(x, last_values[channel]);
                 cr.stroke();
             }
@@ -935,9 +1223,12 @@ impl WaveformImage {
             AMPLITUDE_0_COLOR.2,
         );
 
-        cr.move_to(first_x, self.half_range);
-        cr.line_to(last_x, self.half_range);
-        cr.stroke();
+        for lane in 0..self.get_lane_count() {
+            let (top, half_height) = self.get_lane(lane);
+            cr.move_to(first_x, top + half_height);
+            cr.line_to(last_x, top + half_height);
+            cr.stroke();
+        }
     }
 
     // clear samples previously rendered
@@ -960,8 +1251,8 @@ mod tests {
 
     use std::i16;
 
-    use media::{AudioBuffer, AudioChannel, AudioChannelSide};
-    use ui::WaveformImage;
+    use media::{AudioBuffer, AudioChannel, AudioChannelSide, ChannelMix};
+    use ui::{get_amplitude_ratio, AmplitudeMode, ChannelLane, WaveformImage};
 
     const OUT_DIR: &'static str = "target/test";
     const SAMPLE_RATE: u32 = 300;
@@ -992,10 +1283,12 @@ mod tests {
         let mut waveform = WaveformImage::new(0);
         waveform.set_channels(&[
             AudioChannel {
+                position: gst_audio::AudioChannelPosition::FrontLeft,
                 side: AudioChannelSide::Left,
                 factor: 1f64,
             },
             AudioChannel {
+                position: gst_audio::AudioChannelPosition::FrontRight,
                 side: AudioChannelSide::Right,
                 factor: 1f64,
             },
@@ -1279,4 +1572,110 @@ mod tests {
             true,
         );
     }
+
+    #[test]
+    fn amplitude_ratio() {
+        assert_eq!(get_amplitude_ratio(0.5f64, AmplitudeMode::LinearPeak, -60f64), 0.5f64);
+        assert_eq!(get_amplitude_ratio(1f64, AmplitudeMode::Logarithmic, -60f64), 1f64);
+        assert_eq!(get_amplitude_ratio(0.001f64, AmplitudeMode::Logarithmic, -60f64), 0f64);
+        assert_eq!(get_amplitude_ratio(0f64, AmplitudeMode::Logarithmic, -60f64), 0f64);
+        let ratio = get_amplitude_ratio(0.5f64, AmplitudeMode::Logarithmic, -60f64);
+        assert!((ratio - 0.9f64).abs() < 0.001f64);
+    }
+
+    #[test]
+    fn amplitude_modes() {
+        for &(prefix, amplitude_mode) in &[
+            ("rms", AmplitudeMode::LinearRms),
+            ("db", AmplitudeMode::Logarithmic),
+        ] {
+            let (mut audio_buffer, mut waveform) = init(3f64, 250);
+            waveform.update_amplitude_mode(amplitude_mode, -48f64);
+            let samples_window = SAMPLE_RATE as usize;
+
+            render_with_samples(
+                &format!("amplitude_{}_0", prefix),
+                &mut waveform,
+                &mut audio_buffer,
+                &build_buffer(100, 200),
+                100,
+                true,
+                samples_window,
+                true,
+            );
+            // append to the left
+            render_with_samples(
+                &format!("amplitude_{}_1", prefix),
+                &mut waveform,
+                &mut audio_buffer,
+                &build_buffer(50, 100),
+                50,
+                true,
+                samples_window,
+                true,
+            );
+            // append to the right
+            render_with_samples(
+                &format!("amplitude_{}_2", prefix),
+                &mut waveform,
+                &mut audio_buffer,
+                &build_buffer(200, 250),
+                200,
+                true,
+                samples_window,
+                true,
+            );
+        }
+    }
+
+    #[test]
+    fn lanes() {
+        let (mut audio_buffer, mut waveform) = init(3f64, 250);
+        waveform.update_lanes_layout(true);
+        let mut channel_mix = ChannelMix::default();
+        channel_mix.toggle_mute(1);
+        waveform.update_channel_mix(&channel_mix);
+        let samples_window = SAMPLE_RATE as usize;
+
+        render_with_samples(
+            "lanes_0",
+            &mut waveform,
+            &mut audio_buffer,
+            &build_buffer(100, 200),
+            100,
+            true,
+            samples_window,
+            true,
+        );
+        assert_eq!(
+            waveform.get_channel_lanes(),
+            vec![
+                ChannelLane {
+                    channel: 0,
+                    label: "FL",
+                    top: 0f64,
+                    height: 150f64,
+                },
+                ChannelLane {
+                    channel: 1,
+                    label: "FR",
+                    top: 150f64,
+                    height: 150f64,
+                },
+            ]
+        );
+
+        waveform.update_lanes_layout(false);
+        render_with_samples(
+            "lanes_1 overlaid",
+            &mut waveform,
+            &mut audio_buffer,
+            &build_buffer(200, 250),
+            200,
+            true,
+            samples_window,
+            true,
+        );
+        assert!(waveform.get_channel_lanes().is_empty());
+    }
 }
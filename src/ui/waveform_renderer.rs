@@ -0,0 +1,512 @@
+use cairo;
+use gettextrs::gettext;
+
+use gstreamer as gst;
+use gstreamer_audio as gst_audio;
+
+use std::fs::File;
+use std::path::Path;
+
+use media::{AudioBuffer, AudioChannel, DecodedData, RenderContext};
+use metadata::{self, MediaInfo, TocVisitor};
+
+use super::{AmplitudeMode, WaveformImage};
+
+const BUFFER_DURATION: u64 = 60_000_000_000; // 60 s
+const FONT_FAMILY: &str = "Sans";
+const FONT_SIZE: f64 = 12f64;
+
+enum OutputFormat {
+    Png,
+    Svg,
+}
+
+// Renders the waveform of a media file to an image file without displaying it
+pub struct WaveformRenderer {
+    pub width: i32,
+    pub height: i32,
+    pub start: u64,
+    pub end: Option<u64>,
+    pub with_chapters: bool,
+    pub amplitude_mode: AmplitudeMode,
+    pub db_floor: f64,
+    pub is_lanes_layout: bool,
+}
+
+impl WaveformRenderer {
+    pub fn render(&self, input_path: &Path, output_path: &Path) -> Result<(), String> {
+        let format = WaveformRenderer::get_format(output_path)?;
+        if self.width <= 0 || self.height <= 0 {
+            return Err(gettext("Invalid image dimensions"));
+        }
+
+        let mut context = RenderContext::new(input_path)?;
+
+        let end = match self.end {
+            Some(end) if context.duration > 0 => end.min(context.duration),
+            Some(end) => end,
+            None if context.duration > 0 => context.duration,
+            None => return Err(gettext("Could not determine the media duration")),
+        };
+        if self.start >= end {
+            return Err(gettext("The range to render is empty"));
+        }
+
+        let toc = if self.with_chapters {
+            WaveformRenderer::get_toc(input_path, &context)
+        } else {
+            None
+        };
+
+        context.start(self.start, Some(end))?;
+        self.render_data(&format, output_path, end, toc.as_ref(), || context.pop_data())
+    }
+
+    fn get_format(output_path: &Path) -> Result<OutputFormat, String> {
+        match output_path.extension().and_then(|ext| ext.to_str()) {
+            Some("png") => Ok(OutputFormat::Png),
+            Some("svg") => Ok(OutputFormat::Svg),
+            _ => Err(gettext("Unsupported image format for \"{}\"")
+                .replacen("{}", output_path.to_str().unwrap(), 1)),
+        }
+    }
+
+    // Renders the data returned by `pop_data` up to `end` to `output_path`
+    fn render_data<F>(
+        &self,
+        format: &OutputFormat,
+        output_path: &Path,
+        end: u64,
+        toc: Option<&gst::Toc>,
+        pop_data: F,
+    ) -> Result<(), String>
+    where
+        F: FnMut() -> Result<DecodedData, String>,
+    {
+        match *format {
+            OutputFormat::Png => {
+                let surface =
+                    cairo::ImageSurface::create(cairo::Format::Rgb24, self.width, self.height)
+                        .map_err(|_| gettext("Failed to create the image"))?;
+                self.draw(&cairo::Context::new(&surface), end, toc, pop_data)?;
+
+                let mut output_file = File::create(output_path).map_err(|_| {
+                    gettext("Failed to create the file \"{}\"")
+                        .replacen("{}", output_path.to_str().unwrap(), 1)
+                })?;
+                surface.write_to_png(&mut output_file).map_err(|_| {
+                    gettext("Failed to write the file \"{}\"")
+                        .replacen("{}", output_path.to_str().unwrap(), 1)
+                })
+            }
+            OutputFormat::Svg => {
+                let surface = cairo::svg::File::new(
+                    f64::from(self.width),
+                    f64::from(self.height),
+                    output_path,
+                );
+                self.draw(&cairo::Context::new(&surface), end, toc, pop_data)?;
+                surface.finish();
+
+                Ok(())
+            }
+        }
+    }
+
+    fn draw<F>(
+        &self,
+        cr: &cairo::Context,
+        end: u64,
+        toc: Option<&gst::Toc>,
+        pop_data: F,
+    ) -> Result<(), String>
+    where
+        F: FnMut() -> Result<DecodedData, String>,
+    {
+        let (first_position, duration_per_px) = self.draw_waveform(cr, end, pop_data)?;
+
+        if let Some(toc) = toc {
+            self.draw_chapters(cr, toc, first_position, duration_per_px, end);
+        }
+
+        Ok(())
+    }
+
+    // Returns the position of the first pixel and the duration represented by each pixel
+    fn draw_waveform<F>(
+        &self,
+        cr: &cairo::Context,
+        end: u64,
+        mut pop_data: F,
+    ) -> Result<(u64, f64), String>
+    where
+        F: FnMut() -> Result<DecodedData, String>,
+    {
+        let mut audio_buffer = AudioBuffer::new(BUFFER_DURATION);
+        let mut waveform = WaveformImage::new(0);
+        waveform.update_amplitude_mode(self.amplitude_mode, self.db_floor);
+        waveform.update_lanes_layout(self.is_lanes_layout);
+
+        let mut end_sample = 0;
+        let mut steps = (0, 0f64);
+        loop {
+            match pop_data()? {
+                DecodedData::Caps(caps) => {
+                    if audio_buffer.sample_duration != 0 {
+                        // already initialized
+                        continue;
+                    }
+
+                    let audio_info = match gst_audio::AudioInfo::from_caps(&caps) {
+                        Some(audio_info) => audio_info,
+                        None => return Err(gettext("Unsupported audio format")),
+                    };
+
+                    let mut channels: Vec<AudioChannel> = Vec::new();
+                    if let Some(positions) = audio_info.positions() {
+                        for position in positions {
+                            channels.push(AudioChannel::new(position));
+                        }
+                    };
+                    waveform.set_channels(&channels);
+                    audio_buffer.init(audio_info);
+
+                    let sample_duration = audio_buffer.sample_duration;
+                    let lower = (self.start / sample_duration) as usize;
+                    end_sample = (end / sample_duration) as usize;
+                    if end_sample <= lower {
+                        return Err(gettext("The range to render is empty"));
+                    }
+
+                    // The same fractional step is used for the samples and the chapters
+                    let sample_step_f = (end_sample - lower) as f64 / f64::from(self.width);
+                    waveform.start_offscreen(cr, self.width, self.height, lower, sample_step_f);
+                    steps = (
+                        lower as u64 * sample_duration,
+                        sample_step_f * sample_duration as f64,
+                    );
+                }
+                DecodedData::Segment(segment) => audio_buffer.have_gst_segment(&segment),
+                DecodedData::Buffer(buffer) => {
+                    if audio_buffer.sample_duration == 0 {
+                        continue;
+                    }
+
+                    audio_buffer.push_gst_buffer(&buffer, waveform.upper);
+                    if waveform.draw_offscreen(cr, &audio_buffer, end_sample) >= end_sample {
+                        break;
+                    }
+                }
+                DecodedData::Eos => {
+                    audio_buffer.handle_eos();
+                    waveform.draw_offscreen(cr, &audio_buffer, end_sample);
+                    break;
+                }
+            }
+        }
+
+        if audio_buffer.sample_duration == 0 {
+            Err(gettext("No audio stream found"))
+        } else {
+            Ok(steps)
+        }
+    }
+
+    // Prefers a toc file next to the media, as the info controller does
+    fn get_toc(input_path: &Path, context: &RenderContext) -> Option<gst::Toc> {
+        let info = MediaInfo::new(input_path);
+        let extern_toc = metadata::Factory::get_extensions()
+            .into_iter()
+            .filter_map(|(extension, format)| {
+                let path = info.path
+                    .with_file_name(&format!("{}.{}", info.name, extension));
+                if path.is_file() {
+                    Some((path, format))
+                } else {
+                    None
+                }
+            })
+            .next()
+            .and_then(|(toc_path, format)| match File::open(toc_path.clone()) {
+                Ok(mut toc_file) => {
+                    match metadata::Factory::get_reader(&format).read(&info, &mut toc_file) {
+                        Ok(toc) => toc,
+                        Err(err) => {
+                            warn!("{}", err);
+                            None
+                        }
+                    }
+                }
+                Err(_) => {
+                    warn!(
+                        "{}",
+                        gettext("Failed to open toc file \"{}\"")
+                            .replacen("{}", toc_path.to_str().unwrap(), 1)
+                    );
+                    None
+                }
+            });
+
+        extern_toc.or_else(|| context.toc.clone())
+    }
+
+    fn draw_chapters(
+        &self,
+        cr: &cairo::Context,
+        toc: &gst::Toc,
+        first_position: u64,
+        duration_per_px: f64,
+        end: u64,
+    ) {
+        cr.select_font_face(FONT_FAMILY, cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+        cr.set_font_size(FONT_SIZE);
+        cr.set_source_rgb(0.5f64, 0.6f64, 1f64);
+        cr.set_line_width(1f64);
+
+        let height = f64::from(self.height);
+        let text_base = height - 0.5f64 * FONT_SIZE;
+
+        let mut visitor = TocVisitor::new(toc);
+        while let Some(chapter) = visitor.next_chapter() {
+            let (chapter_start, chapter_end) = match chapter.get_start_stop_times() {
+                Some((start, stop)) => (start as u64, stop as u64),
+                None => continue,
+            };
+            if chapter_end <= first_position || chapter_start >= end {
+                continue;
+            }
+
+            let tags = chapter.get_tags().unwrap_or_else(gst::TagList::new);
+            let title = tags.get::<gst::tags::Title>()
+                .map(|tag| tag.get().unwrap().to_owned())
+                .unwrap_or_else(metadata::get_default_chapter_title);
+
+            let x = if chapter_start >= first_position {
+                let x = ((chapter_start - first_position) as f64 / duration_per_px).round();
+                cr.move_to(x, 0f64);
+                cr.line_to(x, height);
+                cr.stroke();
+                x
+            } else {
+                0f64
+            };
+
+            cr.move_to(x + 5f64, text_base);
+            cr.show_text(&title);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{ByteOrder, LittleEndian};
+    use cairo;
+    use gstreamer as gst;
+    use gstreamer_audio as gst_audio;
+    use gstreamer_audio::AUDIO_FORMAT_S16;
+
+    use std::fs::{create_dir, File};
+    use std::i16;
+    use std::io::{ErrorKind, Read};
+    use std::path::{Path, PathBuf};
+
+    use media::DecodedData;
+    use ui::AmplitudeMode;
+
+    use super::{OutputFormat, WaveformRenderer};
+
+    const OUT_DIR: &'static str = "target/test";
+    const SAMPLE_RATE: u32 = 8_000;
+    const DURATION: u64 = 1_000_000_000; // 1s
+    const WIDTH: i32 = 300;
+    const HEIGHT: i32 = 100;
+
+    // Rows of the positive and negative peaks of the sine wave
+    // in the lanes of the left and right channels
+    const PEAK_ROWS: [i32; 4] = [12, 37, 62, 87];
+    // Row in the first lane below the negative peak, only crossed by the chapters boundaries
+    const BOUNDARY_ROW: i32 = 45;
+
+    // Renders a stereo sine wave from `start` to 1s, which doesn't fit
+    // an integer number of samples per pixel
+    fn render(
+        format: &OutputFormat,
+        file_name: &str,
+        start: u64,
+        toc: Option<&gst::Toc>,
+    ) -> PathBuf {
+        gst::init().unwrap();
+        let _ = create_dir(&OUT_DIR).map_err(|err| match err.kind() {
+            ErrorKind::AlreadyExists => (),
+            _ => panic!("WaveformRenderer test: couldn't create directory {}", OUT_DIR),
+        });
+
+        let audio_info = gst_audio::AudioInfo::new(AUDIO_FORMAT_S16, SAMPLE_RATE, 2)
+            .build()
+            .unwrap();
+
+        // Samples are received from `start`, as after a seek
+        let mut segment = gst::Segment::new();
+        segment.set_format(gst::Format::Time);
+        segment.set_start(gst::ClockTime::from_nseconds(start));
+
+        let first_index = (start * u64::from(SAMPLE_RATE) / DURATION) as u32;
+        let mut samples_u8 = Vec::with_capacity(SAMPLE_RATE as usize * 2 * 2);
+        let mut buf_u8 = [0; 2];
+        for index in first_index..SAMPLE_RATE {
+            let value = ((f64::from(index) / 20f64).sin() * f64::from(i16::MAX / 2)) as i16;
+            for channel_value in &[value, -value] {
+                LittleEndian::write_i16(&mut buf_u8, *channel_value);
+                samples_u8.extend_from_slice(&buf_u8);
+            }
+        }
+        let mut buffer = gst::Buffer::with_size(samples_u8.len()).unwrap();
+        {
+            let buffer_mut = buffer.get_mut().unwrap();
+            buffer_mut.copy_from_slice(0, &samples_u8).unwrap();
+            buffer_mut.set_pts(gst::ClockTime::from_nseconds(start));
+        }
+
+        let mut data = vec![
+            DecodedData::Caps(audio_info.to_caps().unwrap()),
+            DecodedData::Segment(segment),
+            DecodedData::Buffer(buffer),
+        ].into_iter();
+
+        let renderer = WaveformRenderer {
+            width: WIDTH,
+            height: HEIGHT,
+            start,
+            end: Some(DURATION),
+            with_chapters: toc.is_some(),
+            amplitude_mode: AmplitudeMode::LinearRms,
+            db_floor: -60f64,
+            is_lanes_layout: true,
+        };
+
+        let output_path = Path::new(OUT_DIR).join(file_name);
+        renderer
+            .render_data(format, &output_path, DURATION, toc, || {
+                Ok(data.next().unwrap_or(DecodedData::Eos))
+            })
+            .unwrap();
+
+        output_path
+    }
+
+    // Chapters: [0, 250ms), [250ms, 750ms) and [750ms, 1s)
+    fn new_toc() -> gst::Toc {
+        let mut toc = gst::Toc::new(gst::TocScope::Global);
+        {
+            let mut edition = gst::TocEntry::new(gst::TocEntryType::Edition, "");
+            for &(nb, start, end) in &[
+                (1, 0, 250_000_000),
+                (2, 250_000_000, 750_000_000),
+                (3, 750_000_000, DURATION as i64),
+            ] {
+                let mut chapter =
+                    gst::TocEntry::new(gst::TocEntryType::Chapter, &format!("{:02}", nb));
+                chapter.get_mut().unwrap().set_start_stop_times(start, end);
+                edition.get_mut().unwrap().append_sub_entry(chapter);
+            }
+            toc.get_mut().unwrap().append_entry(edition);
+        }
+        toc
+    }
+
+    fn open_png(output_path: &Path) -> cairo::ImageSurface {
+        let image = cairo::ImageSurface::create_from_png(&mut File::open(output_path).unwrap())
+            .unwrap();
+        assert_eq!(image.get_width(), WIDTH);
+        assert_eq!(image.get_height(), HEIGHT);
+        image
+    }
+
+    // Returns the color of the pixel at (x, y) in Cairo's Rgb24 format
+    fn get_pixel(image: &mut cairo::ImageSurface, x: i32, y: i32) -> [u8; 3] {
+        let offset = (y * image.get_stride() + x * 4) as usize;
+        let data = image.get_data().unwrap();
+        [data[offset], data[offset + 1], data[offset + 2]]
+    }
+
+    // Checks that the sine peaks are drawn in both lanes
+    // and that a chapter boundary is drawn at each column of `boundaries` only
+    // Note: boundaries are drawn between two pixels, so they spread on the previous column
+    fn check_png(image: &mut cairo::ImageSurface, boundaries: &[i32]) {
+        let background = get_pixel(image, 10, BOUNDARY_ROW);
+
+        for &row in &PEAK_ROWS {
+            assert!(
+                (0..WIDTH).any(|x| get_pixel(image, x, row) != background),
+                "no peak drawn on row {}",
+                row
+            );
+        }
+
+        for &boundary in boundaries {
+            assert_ne!(
+                background,
+                get_pixel(image, boundary, BOUNDARY_ROW),
+                "no boundary drawn at column {}",
+                boundary
+            );
+        }
+
+        for x in 0..WIDTH {
+            if !boundaries.contains(&x) && !boundaries.contains(&(x + 1)) {
+                assert_eq!(
+                    background,
+                    get_pixel(image, x, BOUNDARY_ROW),
+                    "unexpected drawing at column {}",
+                    x
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn render_png() {
+        let output_path = render(&OutputFormat::Png, "waveform_renderer.png", 0, None);
+        check_png(&mut open_png(&output_path), &[]);
+    }
+
+    #[test]
+    fn render_png_chapters() {
+        let output_path = render(
+            &OutputFormat::Png,
+            "waveform_renderer_chapters.png",
+            0,
+            Some(&new_toc()),
+        );
+        // 1s in 300px
+        check_png(&mut open_png(&output_path), &[0, 75, 225]);
+    }
+
+    #[test]
+    fn render_png_range() {
+        let output_path = render(
+            &OutputFormat::Png,
+            "waveform_renderer_range.png",
+            500_000_000,
+            Some(&new_toc()),
+        );
+        // 500ms in 300px: the first chapter is out of the range
+        // and the second one starts before the first pixel
+        check_png(&mut open_png(&output_path), &[150]);
+    }
+
+    #[test]
+    fn render_svg() {
+        let output_path = render(&OutputFormat::Svg, "waveform_renderer.svg", 0, None);
+
+        let mut svg = String::new();
+        File::open(output_path)
+            .unwrap()
+            .read_to_string(&mut svg)
+            .unwrap();
+        assert!(svg.contains(&format!("width=\"{}pt\" height=\"{}pt\"", WIDTH, HEIGHT)));
+        // Background and samples
+        assert!(svg.contains("<path"));
+        assert!(svg.contains("stroke"));
+    }
+}